@@ -0,0 +1,171 @@
+//! Benchmarks of the paths object data takes through the provider, for objects of up to several
+//! megabytes: encoding files as secrets, cutting downloads into chunks and assembling uploads from
+//! them, hashing and compressing data, and coalescing concurrent metadata reads
+//!
+//! Run with `cargo bench`, or `cargo bench -- encode` for a single group.
+
+use std::sync::Arc;
+
+use blobstore_vault::{
+    cas,
+    client::File,
+    compress, object_path,
+    singleflight::Group,
+    upload::{UploadSession, UploadSessions},
+    wasmcloud_interface_blobstore::Chunk,
+};
+use bytes::Bytes;
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+
+/// Sizes of the objects benchmarked
+const SIZES: [usize; 3] = [64 * 1024, 1024 * 1024, 4 * 1024 * 1024];
+/// Size of the chunks objects are uploaded and downloaded in, below the default NATS message
+/// size
+const CHUNK_BYTES: usize = 256 * 1024;
+/// Number of reads of the same metadata waiting for each other
+const CONCURRENT_READS: usize = 64;
+
+/// Returns data that compresses about as well as typical text objects
+fn data(size: usize) -> Bytes {
+    (0..size)
+        .map(|i| b"blobstore-vault "[i % 16] ^ (i / 4096) as u8)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Returns the chunks an object of `data` is sent in
+fn chunks(data: &Bytes) -> Vec<Chunk> {
+    let mut rest = data.clone();
+    let mut offset = 0;
+    let mut chunks = Vec::with_capacity(data.len() / CHUNK_BYTES + 1);
+    while !rest.is_empty() {
+        let bytes = rest.split_to(CHUNK_BYTES.min(rest.len()));
+        let len = bytes.len() as u64;
+        chunks.push(Chunk {
+            object_id: "reports/2024-01/summary.json".to_string(),
+            container_id: "bench".to_string(),
+            bytes,
+            offset,
+            is_last: rest.is_empty(),
+        });
+        offset += len;
+    }
+    chunks
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for size in SIZES {
+        let file = File {
+            data: data(size),
+            content_type: Some("application/json".to_string()),
+            ..Default::default()
+        };
+        let encoded = serde_json::to_vec(&file).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("serialize", size), &file, |b, file| {
+            b.iter(|| serde_json::to_vec(black_box(file)).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("deserialize", size),
+            &encoded,
+            |b, encoded| b.iter(|| serde_json::from_slice::<File>(black_box(encoded)).unwrap()),
+        );
+    }
+    group.bench_function("object_path", |b| {
+        b.iter(|| object_path::decode(&object_path::encode(black_box("reports/2024 #1/a%b.json"))))
+    });
+    group.finish();
+}
+
+fn chunk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunk");
+    for size in SIZES {
+        let data = data(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("split", size), &data, |b, data| {
+            b.iter(|| chunks(black_box(data)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("assemble", size),
+            &chunks(&data),
+            |b, chunks| {
+                let uploads = UploadSessions::default();
+                b.iter_batched(
+                    || chunks.clone(),
+                    |chunks| {
+                        let mut chunks = chunks.into_iter();
+                        let first = chunks.next().unwrap();
+                        let mut session = UploadSession::new("actor", "bench", first.object_id);
+                        session.buffer.extend_from_slice(&first.bytes);
+                        let stream_id = uploads.start(session);
+                        for chunk in chunks {
+                            uploads.update(&stream_id, |session| {
+                                assert_eq!(chunk.offset, session.received());
+                                session.buffer.extend_from_slice(&chunk.bytes);
+                            });
+                        }
+                        uploads.remove(&stream_id).unwrap().buffer.freeze()
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn digest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("digest");
+    for size in SIZES {
+        let data = data(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("hash", size), &data, |b, data| {
+            b.iter(|| cas::hash(black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("gzip", size), &data, |b, data| {
+            b.iter(|| compress::gzip(black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+fn metadata(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("metadata");
+    group.throughput(Throughput::Elements(CONCURRENT_READS as u64));
+    // Reads of the same path share a single call, reads of different paths each make their own
+    for (name, shared) in [("same_path", true), ("distinct_paths", false)] {
+        group.bench_function(name, |b| {
+            let reads = Arc::new(Group::<Arc<str>>::default());
+            b.to_async(&runtime).iter(|| {
+                let reads = reads.clone();
+                async move {
+                    let calls = (0..CONCURRENT_READS).map(|i| {
+                        let reads = reads.clone();
+                        tokio::spawn(async move {
+                            let path = if shared {
+                                "bench/object".to_string()
+                            } else {
+                                format!("bench/object-{i}")
+                            };
+                            reads
+                                .work(&path.clone(), async move {
+                                    tokio::task::yield_now().await;
+                                    Ok(Arc::from(path))
+                                })
+                                .await
+                        })
+                    });
+                    futures::future::join_all(calls).await
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, chunk, digest, metadata);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+//! Small helpers for serving blobs over HTTP
+//!
+use std::collections::HashMap;
+
+/// Content type returned when the stored object has none
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Size of the parts objects are uploaded and downloaded in, which keeps every message well
+/// under the lattice's maximum message size (1MB by default for NATS)
+pub const CHUNK_BYTES: usize = 512 * 1024;
+
+/// An inclusive byte range requested with a `Range: bytes=...` header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=start-end`, or `bytes=start-` when `end` is None
+    FromTo { start: u64, end: Option<u64> },
+    /// `bytes=-n`, the last n bytes of the object
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parses the value of a `Range` header. Only single ranges in `bytes` units are supported,
+    /// anything else returns None so the caller can fall back to serving the whole object
+    pub fn parse(value: &str) -> Option<ByteRange> {
+        let spec = value.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            return end.parse().ok().map(ByteRange::Suffix);
+        }
+        let start = start.parse().ok()?;
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse().ok()?)
+        };
+        match end {
+            Some(end) if end < start => None,
+            _ => Some(ByteRange::FromTo { start, end }),
+        }
+    }
+
+    /// Resolves the range against an object of `len` bytes, returning the inclusive
+    /// `(start, end)` offsets, or None if the range cannot be satisfied
+    pub fn resolve(&self, len: u64) -> Option<(u64, u64)> {
+        if len == 0 {
+            return None;
+        }
+        match *self {
+            ByteRange::FromTo { start, .. } if start >= len => None,
+            ByteRange::FromTo { start, end } => {
+                Some((start, end.map(|e| e.min(len - 1)).unwrap_or(len - 1)))
+            }
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(n) => Some((len.saturating_sub(n), len - 1)),
+        }
+    }
+}
+
+/// Formats the value of a `Content-Range` header for a satisfied range
+pub fn content_range(start: u64, end: u64, len: u64) -> String {
+    format!("bytes {start}-{end}/{len}")
+}
+
+/// Splits the inclusive range from `start` to `end` into inclusive parts of at most `size` bytes
+pub fn parts(start: u64, end: u64, size: usize) -> impl Iterator<Item = (u64, u64)> {
+    (start..=end)
+        .step_by(size)
+        .map(move |part| (part, end.min(part + size as u64 - 1)))
+}
+
+/// Formats a weak ETag from the length and modification time of an object, so it changes
+/// whenever the object is written without the object having to be downloaded
+pub fn etag_of(len: u64, last_modified: Option<(i64, u32)>) -> String {
+    let (sec, nsec) = last_modified.unwrap_or_default();
+    format!("W/\"{len:x}-{sec:x}.{nsec:x}\"")
+}
+
+/// Returns true if an `If-None-Match` header value matches the given ETag. Tags are compared
+/// weakly, as `If-None-Match` requires
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == etag)
+}
+
+/// Case-insensitive lookup of the first value of a header
+pub fn header<'a>(headers: &'a HashMap<String, Vec<String>>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .and_then(|(_, v)| v.first())
+        .map(|v| v.as_str())
+}
+
+/// Content type to store for an upload, passed through from the request's `Content-Type`
+pub fn request_content_type(headers: &HashMap<String, Vec<String>>) -> Option<String> {
+    header(headers, "Content-Type").map(ToString::to_string)
+}
+
+/// Content type to send for a download, passed through from the stored object metadata
+pub fn response_content_type(stored: Option<&str>) -> String {
+    stored.unwrap_or(DEFAULT_CONTENT_TYPE).to_string()
+}
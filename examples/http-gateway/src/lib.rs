@@ -0,0 +1,202 @@
+//! Example actor that fronts the Vault blobstore provider over wasmcloud:httpserver
+//!
+//! * `GET /<container>/<object>` downloads an object, honoring `Range` and `If-None-Match`.
+//!   Only the requested range is fetched from the provider, in parts of [`http::CHUNK_BYTES`]
+//! * `PUT /<container>/<object>` uploads the request body, passing through `Content-Type`. Bodies
+//!   larger than [`http::CHUNK_BYTES`] are uploaded with `PutObject` followed by `PutChunk`
+//! * `DELETE /<container>/<object>` removes the object
+pub mod http;
+
+use wasmbus_rpc::actor::prelude::*;
+use wasmcloud_interface_blobstore::{
+    Blobstore, BlobstoreSender, Chunk, ContainerObject, GetObjectRequest, PutChunkRequest,
+    PutObjectRequest, RemoveObjectsRequest,
+};
+use wasmcloud_interface_httpserver::{
+    HeaderMap, HttpRequest, HttpResponse, HttpServer, HttpServerReceiver,
+};
+
+use crate::http::ByteRange;
+
+#[derive(Debug, Default, Actor, HealthResponder)]
+#[services(Actor, HttpServer)]
+struct HttpGatewayActor {}
+
+#[async_trait]
+impl HttpServer for HttpGatewayActor {
+    async fn handle_request(&self, ctx: &Context, req: &HttpRequest) -> RpcResult<HttpResponse> {
+        let object = match req.path.trim_start_matches('/').split_once('/') {
+            Some((container_id, object_id))
+                if !container_id.is_empty() && !object_id.is_empty() =>
+            {
+                ContainerObject {
+                    container_id: container_id.to_string(),
+                    object_id: object_id.to_string(),
+                }
+            }
+            _ => {
+                return Ok(HttpResponse::bad_request(
+                    "expected a path of the form /<container>/<object>",
+                ))
+            }
+        };
+        match req.method.as_str() {
+            "GET" => get(ctx, object, &req.header).await,
+            "PUT" => put(ctx, object, &req.header, &req.body).await,
+            "DELETE" => delete(ctx, object).await,
+            _ => Ok(response(405, HeaderMap::new(), Vec::new())),
+        }
+    }
+}
+
+async fn get(
+    ctx: &Context,
+    object: ContainerObject,
+    headers: &HeaderMap,
+) -> RpcResult<HttpResponse> {
+    let blobstore = BlobstoreSender::new();
+    if !blobstore.object_exists(ctx, &object).await? {
+        return Ok(HttpResponse::not_found());
+    }
+    // The ETag is derived from the object's metadata, so it is known without downloading the
+    // object and only the requested range is transferred
+    let info = blobstore.get_object_info(ctx, &object).await?;
+    let len = info.content_length;
+    let etag = http::etag_of(len, info.last_modified.as_ref().map(|t| (t.sec, t.nsec)));
+
+    let mut header = HeaderMap::new();
+    header.insert("ETag".to_string(), vec![etag.clone()]);
+    if http::header(headers, "If-None-Match").is_some_and(|inm| http::etag_matches(inm, &etag)) {
+        return Ok(response(304, header, Vec::new()));
+    }
+    header.insert(
+        "Content-Type".to_string(),
+        vec![http::response_content_type(info.content_type.as_deref())],
+    );
+    if let Some(encoding) = info.content_encoding {
+        header.insert("Content-Encoding".to_string(), vec![encoding]);
+    }
+    header.insert("Accept-Ranges".to_string(), vec!["bytes".to_string()]);
+
+    let (status, start, end) = match http::header(headers, "Range").and_then(ByteRange::parse) {
+        None if len == 0 => return Ok(response(200, header, Vec::new())),
+        None => (200, 0, len - 1),
+        Some(range) => match range.resolve(len) {
+            Some((start, end)) => {
+                header.insert(
+                    "Content-Range".to_string(),
+                    vec![http::content_range(start, end, len)],
+                );
+                (206, start, end)
+            }
+            None => {
+                header.insert("Content-Range".to_string(), vec![format!("bytes */{len}")]);
+                return Ok(response(416, header, Vec::new()));
+            }
+        },
+    };
+    // The range is fetched in parts, each small enough for a single lattice message
+    let mut body = Vec::with_capacity((end - start + 1) as usize);
+    for (part_start, part_end) in http::parts(start, end, http::CHUNK_BYTES) {
+        let resp = blobstore
+            .get_object(
+                ctx,
+                &GetObjectRequest {
+                    object_id: object.object_id.clone(),
+                    container_id: object.container_id.clone(),
+                    range_start: Some(part_start),
+                    range_end: Some(part_end),
+                },
+            )
+            .await?;
+        if !resp.success {
+            return Ok(HttpResponse::internal_server_error(
+                resp.error.unwrap_or_default(),
+            ));
+        }
+        body.extend(resp.initial_chunk.map(|c| c.bytes).unwrap_or_default());
+    }
+    Ok(response(status, header, body))
+}
+
+async fn put(
+    ctx: &Context,
+    object: ContainerObject,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> RpcResult<HttpResponse> {
+    // The body is sent in chunks, each small enough for a single lattice message. The provider
+    // writes the object once the last chunk arrived
+    let blobstore = BlobstoreSender::new();
+    let mut chunks = body.chunks(http::CHUNK_BYTES);
+    let chunk = |bytes: &[u8], offset: usize, is_last| Chunk {
+        object_id: object.object_id.clone(),
+        container_id: object.container_id.clone(),
+        bytes: bytes.to_vec(),
+        offset: offset as u64,
+        is_last,
+    };
+    let first = chunks.next().unwrap_or_default();
+    let resp = blobstore
+        .put_object(
+            ctx,
+            &PutObjectRequest {
+                chunk: chunk(first, 0, chunks.len() == 0),
+                content_type: http::request_content_type(headers),
+                content_encoding: http::header(headers, "Content-Encoding")
+                    .map(ToString::to_string),
+            },
+        )
+        .await?;
+    let mut offset = first.len();
+    while let Some(bytes) = chunks.next() {
+        let sent = blobstore
+            .put_chunk(
+                ctx,
+                &PutChunkRequest {
+                    chunk: chunk(bytes, offset, chunks.len() == 0),
+                    stream_id: resp.stream_id.clone(),
+                    cancel_and_remove: false,
+                },
+            )
+            .await;
+        if let Err(e) = sent {
+            // Discards what the provider received of the upload
+            let cancel = PutChunkRequest {
+                chunk: chunk(&[], offset, true),
+                stream_id: resp.stream_id.clone(),
+                cancel_and_remove: true,
+            };
+            let _ = blobstore.put_chunk(ctx, &cancel).await;
+            return Err(e);
+        }
+        offset += bytes.len();
+    }
+    Ok(response(201, HeaderMap::new(), Vec::new()))
+}
+
+async fn delete(ctx: &Context, object: ContainerObject) -> RpcResult<HttpResponse> {
+    let results = BlobstoreSender::new()
+        .remove_objects(
+            ctx,
+            &RemoveObjectsRequest {
+                container_id: object.container_id,
+                objects: vec![object.object_id],
+            },
+        )
+        .await?;
+    match results.into_iter().find(|r| !r.success) {
+        Some(failed) => Ok(HttpResponse::internal_server_error(
+            failed.error.unwrap_or_default(),
+        )),
+        None => Ok(response(204, HeaderMap::new(), Vec::new())),
+    }
+}
+
+fn response(status_code: u16, header: HeaderMap, body: Vec<u8>) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        header,
+        body,
+    }
+}
@@ -0,0 +1,34 @@
+//! Restrictions on the paths a link can access, checked before any request is sent to Vault
+//!
+//! These let operators constrain an actor to a subtree of a mount without writing a Vault policy
+//! for every actor. Paths are the object paths seen by the provider, before encoding.
+
+/// Prefixes of paths a link is allowed and denied access to
+#[derive(Clone, Debug, Default)]
+pub struct PathRules {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl PathRules {
+    pub fn new(allowed: Vec<String>, denied: Vec<String>) -> PathRules {
+        PathRules { allowed, denied }
+    }
+
+    /// Returns true if the path starts with an allowed prefix, or none are set, and with no
+    /// denied prefix
+    pub fn permits(&self, path: &str) -> bool {
+        (self.allowed.is_empty() || self.allowed.iter().any(|p| path.starts_with(p.as_str())))
+            && !self.denied.iter().any(|p| path.starts_with(p.as_str()))
+    }
+
+    /// Returns true if the folder at the path may be listed. A folder is treated like the path of
+    /// an object in it, so a prefix of `a/` permits listing `a`
+    pub fn permits_folder(&self, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() {
+            return self.permits("");
+        }
+        self.permits(&format!("{path}/"))
+    }
+}
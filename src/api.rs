@@ -0,0 +1,288 @@
+//! Requests to the Vault HTTP API
+//!
+//! The [backends](crate::backend) send their requests through a [`Connection`] rather than the
+//! vaultrs helpers, which only return the data of a response. A connection also keeps what the
+//! client needs from the rest of the response, such as the `request_id` Vault logs the request
+//! under in its audit log. Failures are reported as the same [`ClientError`]s vaultrs returns,
+//! so they are handled the same way whichever sent the request.
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::TryStreamExt;
+#[cfg(unix)]
+use hyper::body::HttpBody;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Method, StatusCode,
+};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use vaultrs::{client::VaultClient, error::ClientError};
+
+use crate::json::{self, Received};
+
+/// What the client keeps of the response to a request
+#[derive(Clone, Debug, Default)]
+pub struct Response {
+    /// Status of the response
+    pub status: Option<u16>,
+    /// ID of the request in Vault's audit log. Vault only returns it with successful responses
+    pub request_id: Option<String>,
+    /// `X-Vault-Index` of the response, the replication state of Vault Enterprise after a write
+    pub index: Option<String>,
+    /// Delay the server asked for with `Retry-After` before the request is sent again. Only
+    /// delays in seconds are read, which is the form Vault sends with rate limited requests
+    pub retry_after: Option<Duration>,
+}
+
+/// Envelope of Vault's responses
+#[derive(Deserialize)]
+struct Envelope<D> {
+    #[serde(default)]
+    request_id: Option<String>,
+    data: Option<D>,
+}
+
+/// Body of Vault's error responses
+#[derive(Deserialize)]
+struct Errors {
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// A Vault server to send a request to. The response to the request is recorded, so the client
+/// can read it once the request completed
+#[derive(Clone)]
+pub struct Connection {
+    client: Arc<VaultClient>,
+    /// Headers of the link, for requests not sent with the HTTP client that has them as defaults
+    headers: Arc<HeaderMap>,
+    /// Replication state the server must have reached to serve the request
+    index: Option<String>,
+    response: Arc<Mutex<Response>>,
+}
+
+impl AsRef<VaultClient> for Connection {
+    fn as_ref(&self) -> &VaultClient {
+        &self.client
+    }
+}
+
+impl Connection {
+    pub fn new(client: Arc<VaultClient>) -> Connection {
+        Connection {
+            client,
+            headers: Arc::default(),
+            index: None,
+            response: Arc::default(),
+        }
+    }
+
+    /// Sends the requests with the headers configured for the link. The HTTP client of the
+    /// connection already adds them, except to requests sent over a Unix socket
+    pub fn with_headers(mut self, headers: Arc<HeaderMap>) -> Connection {
+        self.headers = headers;
+        self
+    }
+
+    /// Sends the requests with an `X-Vault-Index` from the response to an earlier write, so a
+    /// performance standby that hasn't replicated the write yet forwards them to the active node
+    /// instead of serving stale data
+    pub fn with_index(mut self, index: Option<&str>) -> Connection {
+        self.index = index.map(ToString::to_string);
+        self
+    }
+
+    /// Returns what was recorded of the last response received
+    pub fn response(&self) -> Response {
+        self.response.lock().unwrap().clone()
+    }
+
+    /// Sends a request without a body to an API path such as `v1/secret/metadata/key`. Returns
+    /// the `data` of the response, or None if it has none
+    pub async fn send<D: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &str,
+        path: &str,
+    ) -> Result<Option<D>, ClientError> {
+        self.exchange(method, path, None).await
+    }
+
+    /// Sends a request like [`Connection::send`] with `body` serialized as JSON. Large bodies
+    /// are serialized while they are sent, see [`json`]
+    pub async fn send_json<D, T>(
+        &self,
+        method: &str,
+        path: &str,
+        body: T,
+    ) -> Result<Option<D>, ClientError>
+    where
+        D: DeserializeOwned + Send + 'static,
+        T: Serialize + Send + 'static,
+    {
+        let body = json::encode(body).map_err(|source| ClientError::JsonParseError { source })?;
+        self.exchange(method, path, Some(body)).await
+    }
+
+    async fn exchange<D: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<json::Body>,
+    ) -> Result<Option<D>, ClientError> {
+        let settings = &self.client.settings;
+        let url = format!("{}/{path}", settings.address.as_str().trim_end_matches('/'));
+        let method = Method::from_bytes(method.as_bytes()).expect("methods are valid tokens");
+        let failed = |source: anyhow::Error| {
+            ClientError::from(rustify::errors::ClientError::RequestError {
+                source,
+                url: url.clone(),
+                method: method.to_string(),
+            })
+        };
+        let mut headers = HeaderMap::new();
+        let mut header = |name: &'static str, value: &str| {
+            let value = HeaderValue::from_str(value).map_err(|e| failed(e.into()))?;
+            headers.insert(name, value);
+            Ok::<_, ClientError>(())
+        };
+        header("X-Vault-Request", "true")?;
+        if !settings.token.is_empty() {
+            header("X-Vault-Token", &settings.token)?;
+        }
+        if let Some(namespace) = &settings.namespace {
+            header("X-Vault-Namespace", namespace)?;
+        }
+        if let Some(index) = &self.index {
+            header("X-Vault-Index", index)?;
+            header("X-Vault-Inconsistent", "forward-active-node")?;
+        }
+        if body.is_some() {
+            header("Content-Type", "application/json")?;
+        }
+        let (status, headers, body) = self
+            .transfer(method.clone(), path, headers, body)
+            .await
+            .map_err(failed)?;
+        let index = headers
+            .get("X-Vault-Index")
+            .and_then(|index| index.to_str().ok())
+            .map(ToString::to_string);
+        let retry_after = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|delay| delay.to_str().ok())
+            .and_then(|delay| delay.trim().parse().ok())
+            .map(Duration::from_secs);
+        *self.response.lock().unwrap() = Response {
+            status: Some(status.as_u16()),
+            request_id: None,
+            index,
+            retry_after,
+        };
+        if !status.is_success() {
+            let body = body
+                .try_fold(Vec::new(), |mut whole, chunk| async move {
+                    whole.extend_from_slice(&chunk);
+                    Ok(whole)
+                })
+                .await
+                .map_err(failed)?;
+            // Like vaultrs, errors whose body isn't the usual list of errors have none
+            let errors = serde_json::from_slice::<Errors>(&body)
+                .map(|e| e.errors)
+                .unwrap_or_default();
+            return Err(ClientError::APIError {
+                code: status.as_u16(),
+                errors,
+            });
+        }
+        let body = match json::decode::<Envelope<D>, _>(body).await.map_err(failed)? {
+            Received::Whole(body) => body,
+            Received::Decoded(Ok(envelope)) => {
+                self.response.lock().unwrap().request_id = envelope.request_id;
+                return Ok(envelope.data);
+            }
+            Received::Decoded(Err(source)) => return Err(ClientError::JsonParseError { source }),
+        };
+        if body.is_empty() {
+            return Ok(None);
+        }
+        match serde_json::from_slice::<Envelope<D>>(&body) {
+            Ok(envelope) => {
+                self.response.lock().unwrap().request_id = envelope.request_id;
+                Ok(envelope.data)
+            }
+            Err(source) => {
+                // The data may be what failed to parse, but the request ID is still worth having
+                // to find out what Vault returned
+                let envelope = serde_json::from_slice::<Envelope<IgnoredAny>>(&body);
+                self.response.lock().unwrap().request_id =
+                    envelope.ok().and_then(|envelope| envelope.request_id);
+                Err(ClientError::JsonParseError { source })
+            }
+        }
+    }
+
+    /// Sends a request to the server of the connection and returns the status and headers of its
+    /// response, with its body as it is received. Servers with a `unix://` address are sent it
+    /// over their socket
+    async fn transfer(
+        &self,
+        method: Method,
+        path: &str,
+        headers: HeaderMap,
+        body: Option<json::Body>,
+    ) -> anyhow::Result<(StatusCode, HeaderMap, json::Chunks<anyhow::Error>)> {
+        let settings = &self.client.settings;
+        #[cfg(unix)]
+        if settings.address.scheme() == "unix" {
+            let mut headers = headers;
+            crate::unix::add_defaults(&mut headers, &self.headers);
+            let response = crate::unix::send(
+                settings.address.path(),
+                method,
+                path,
+                headers,
+                body,
+                settings.timeout,
+            )
+            .await?;
+            let (parts, body) = response.into_parts();
+            let body = futures::stream::unfold(body, |mut body| async move {
+                let chunk = body.data().await?.map_err(anyhow::Error::from);
+                Some((chunk, body))
+            });
+            return Ok((parts.status, parts.headers, Box::pin(body)));
+        }
+        let url = format!("{}/{path}", settings.address.as_str().trim_end_matches('/'));
+        let mut request = self.client.http.http.request(method, url).headers(headers);
+        if let Some(timeout) = settings.timeout {
+            request = request.timeout(timeout);
+        }
+        match body {
+            Some(json::Body::Whole(body)) => request = request.body(body),
+            Some(body) => request = request.body(reqwest::Body::wrap_stream(body.into_chunks())),
+            None => (),
+        }
+        let response = request.send().await?;
+        let (status, headers) = (response.status(), response.headers().clone());
+        let body = futures::stream::unfold(response, |mut response| async move {
+            let chunk = response
+                .chunk()
+                .await
+                .transpose()?
+                .map_err(anyhow::Error::from);
+            Some((chunk, response))
+        });
+        Ok((status, headers, Box::pin(body)))
+    }
+}
+
+/// Returns the data of a response, failing if it has none
+pub fn required<D>(data: Option<D>) -> Result<D, ClientError> {
+    data.ok_or(ClientError::ResponseDataEmptyError)
+}
@@ -0,0 +1,90 @@
+//! Audit events for blobstore operations
+//!
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::wasmcloud_interface_blobstore::Timestamp;
+
+/// Where audit events are written
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditSink {
+    /// Write each event as a line of JSON to stdout
+    Stdout,
+    /// Write each event as a secret under the given path of the link's mount
+    Vault(String),
+}
+
+impl FromStr for AuditSink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "stdout" => Ok(AuditSink::Stdout),
+            other => match other.strip_prefix("vault:") {
+                Some(path) if !path.trim_matches('/').is_empty() => {
+                    Ok(AuditSink::Vault(path.trim_matches('/').to_string()))
+                }
+                _ => Err(anyhow::anyhow!(
+                    "invalid audit sink '{other}', expected 'stdout' or 'vault:<path>'"
+                )),
+            },
+        }
+    }
+}
+
+/// A single audited blobstore operation
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEvent {
+    pub timestamp: Timestamp,
+    pub actor_id: String,
+    pub operation: &'static str,
+    pub container_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_id: Option<String>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of payload bytes read or written by the operation
+    pub bytes: u64,
+}
+
+impl AuditEvent {
+    /// Creates a successful event for the given operation. Use the builder methods to fill in
+    /// the object, byte count and result
+    pub fn new(
+        actor_id: impl Into<String>,
+        operation: &'static str,
+        container_id: impl Into<String>,
+    ) -> AuditEvent {
+        AuditEvent {
+            timestamp: Timestamp::now(),
+            actor_id: actor_id.into(),
+            operation,
+            container_id: container_id.into(),
+            object_id: None,
+            success: true,
+            error: None,
+            bytes: 0,
+        }
+    }
+
+    pub fn object(mut self, object_id: impl Into<String>) -> AuditEvent {
+        self.object_id = Some(object_id.into());
+        self
+    }
+
+    pub fn bytes(mut self, bytes: u64) -> AuditEvent {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Records the outcome of the operation
+    pub fn result<T, E: ToString>(mut self, result: &Result<T, E>) -> AuditEvent {
+        if let Err(e) = result {
+            self.success = false;
+            self.error = Some(e.to_string());
+        }
+        self
+    }
+}
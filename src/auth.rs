@@ -0,0 +1,113 @@
+//! Auth methods used to get a new token when the configured one expires
+//!
+use std::collections::HashMap;
+
+use vaultrs::{api::AuthInfo, client::VaultClient, error::ClientError};
+
+/// Default path of the service account token mounted into Kubernetes pods
+const DEFAULT_KUBERNETES_JWT_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// A Vault auth method and the credentials to log in with it
+#[derive(Clone, Debug)]
+pub enum AuthMethod {
+    AppRole {
+        mount: String,
+        role_id: String,
+        secret_id: String,
+    },
+    Kubernetes {
+        mount: String,
+        role: String,
+        /// Path of the service account token to log in with. It is read on every login, as
+        /// Kubernetes rotates it
+        jwt_path: String,
+    },
+    /// Logs in with the client certificate of the link's mTLS connection, see
+    /// [`Config::tls_client_cert`](crate::config::Config::tls_client_cert)
+    Cert {
+        mount: String,
+        /// Certificate role to log in with. Vault tries all roles of the mount if empty
+        name: String,
+    },
+}
+
+impl AuthMethod {
+    /// Parses the auth method from `auth_method` and the settings of that method in linkdef
+    /// values, removing them. Returns None if no auth method is set
+    pub fn from_values(values: &mut HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let Some(method) = take(values, "auth_method") else {
+            return Ok(None);
+        };
+        let required = |values: &mut HashMap<String, String>, key: &str| {
+            take(values, key).ok_or_else(|| {
+                anyhow::anyhow!("missing setting for '{key}', required by auth_method {method}")
+            })
+        };
+        let auth = match method.as_str() {
+            "approle" => AuthMethod::AppRole {
+                role_id: required(values, "role_id")?,
+                secret_id: required(values, "secret_id")?,
+                mount: take(values, "auth_mount").unwrap_or_else(|| "approle".to_string()),
+            },
+            "kubernetes" => AuthMethod::Kubernetes {
+                role: required(values, "role")?,
+                jwt_path: take(values, "jwt_path")
+                    .unwrap_or_else(|| DEFAULT_KUBERNETES_JWT_PATH.to_string()),
+                mount: take(values, "auth_mount").unwrap_or_else(|| "kubernetes".to_string()),
+            },
+            "cert" => AuthMethod::Cert {
+                name: take(values, "cert_role").unwrap_or_default(),
+                mount: take(values, "auth_mount").unwrap_or_else(|| "cert".to_string()),
+            },
+            _ => {
+                anyhow::bail!(
+                    "invalid auth_method '{method}', expected 'approle', 'kubernetes' or 'cert'"
+                )
+            }
+        };
+        Ok(Some(auth))
+    }
+
+    /// Returns the API path of the login endpoint
+    pub fn login_path(&self) -> String {
+        match self {
+            AuthMethod::AppRole { mount, .. }
+            | AuthMethod::Kubernetes { mount, .. }
+            | AuthMethod::Cert { mount, .. } => format!("v1/auth/{mount}/login"),
+        }
+    }
+
+    /// Logs in to Vault, returning the new token
+    pub async fn login(&self, client: &VaultClient) -> Result<AuthInfo, ClientError> {
+        match self {
+            AuthMethod::AppRole {
+                mount,
+                role_id,
+                secret_id,
+            } => vaultrs::auth::approle::login(client, mount, role_id, secret_id).await,
+            AuthMethod::Kubernetes {
+                mount,
+                role,
+                jwt_path,
+            } => {
+                let jwt = tokio::fs::read_to_string(jwt_path).await.map_err(|e| {
+                    ClientError::FileReadError {
+                        source: e,
+                        path: jwt_path.clone(),
+                    }
+                })?;
+                vaultrs::auth::kubernetes::login(client, mount, role, jwt.trim()).await
+            }
+            AuthMethod::Cert { mount, name } => {
+                vaultrs::auth::cert::login(client, mount, name).await
+            }
+        }
+    }
+}
+
+/// Removes a setting from linkdef values, in lower or upper case
+fn take(values: &mut HashMap<String, String>, key: &str) -> Option<String> {
+    values
+        .remove(key)
+        .or_else(|| values.remove(&key.to_uppercase()))
+}
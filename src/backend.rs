@@ -0,0 +1,626 @@
+//! Secrets engines objects can be stored in
+//!
+//! The [client](crate::client::Client) handles everything objects need on top of storing secrets:
+//! path encoding, content-addressed blobs, access rules, failover and metadata. A [`KvBackend`]
+//! only sends the requests of one engine, so the engines share that logic: [`Kv2`], and [`Kv1`]
+//! for KV v1 and cubbyhole mounts, picked for a mount by [`Engine`]. Engines without versions or
+//! metadata report what they can in the KV v2 response types, and refuse the requests that need
+//! them, like check-and-set writes.
+use std::{collections::HashMap, future::Future};
+
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use vaultrs::api::kv2::responses::{ReadSecretMetadataResponse, SecretVersionMetadata};
+use vaultrs::error::ClientError;
+
+use crate::api::{self, Connection};
+use crate::client::API_VERSION;
+
+/// Requests of a secrets engine, for the secret at `key` of the engine mounted at `mount`, sent
+/// through the connection to a Vault server. Keys are already encoded
+pub trait KvBackend: Send + Sync {
+    /// Returns the API path a request for the data of a secret is sent to, for tracing
+    fn data_path(&self, mount: &str, key: &str) -> String;
+
+    /// Returns the API path a request for the metadata of a secret, or for listing secrets, is
+    /// sent to, for tracing
+    fn metadata_path(&self, mount: &str, key: &str) -> String;
+
+    /// Returns the API path a request checking whether a secret exists is sent to, for tracing
+    fn exists_path(&self, mount: &str, key: &str) -> String;
+
+    /// Returns whether the current version of a secret exists, without transferring its data.
+    /// Fails if the server doesn't support the request, in which case the client reads the
+    /// metadata of the secret instead
+    fn exists(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<bool, ClientError>> + Send;
+
+    /// Reads the current version of a secret
+    fn read<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<D, ClientError>> + Send;
+
+    /// Reads a specific version of a secret
+    fn read_version<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        version: u64,
+    ) -> impl Future<Output = Result<D, ClientError>> + Send;
+
+    /// Writes a secret, if `cas` is given only when its current version is `cas`
+    fn write<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        value: &T,
+        cas: Option<u64>,
+    ) -> impl Future<Output = Result<SecretVersionMetadata, ClientError>> + Send;
+
+    /// Reads the metadata of a secret
+    fn read_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<ReadSecretMetadataResponse, ClientError>> + Send;
+
+    /// Replaces the custom metadata of a secret
+    fn set_custom_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        metadata: &HashMap<String, String>,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    /// Sets the maximum number of versions kept of a secret
+    fn set_max_versions(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        max_versions: u64,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    /// Deletes the current version of a secret
+    fn delete(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    /// Permanently deletes all versions and the metadata of a secret
+    fn purge(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    /// Lists the keys in a folder, with subfolders ending in `/`
+    fn list(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> impl Future<Output = Result<Vec<String>, ClientError>> + Send;
+}
+
+/// Response to a read of a KV v2 secret
+#[derive(Deserialize)]
+struct ReadSecretResponse<D> {
+    data: D,
+}
+
+/// Response to a listing
+#[derive(Deserialize)]
+struct ListResponse {
+    keys: Vec<String>,
+}
+
+/// Body of a KV v2 write
+#[derive(Serialize)]
+struct SetSecretRequest<T> {
+    data: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<SetSecretOptions>,
+}
+
+#[derive(Serialize)]
+struct SetSecretOptions {
+    cas: u64,
+}
+
+/// Body of a KV v2 metadata write. Fields left out keep their value
+#[derive(Default, Serialize)]
+struct SetSecretMetadataRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_versions: Option<u64>,
+}
+
+/// The KV v2 secrets engine
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Kv2;
+
+impl KvBackend for Kv2 {
+    fn data_path(&self, mount: &str, key: &str) -> String {
+        format!("v{API_VERSION}/{mount}/data/{key}")
+    }
+
+    fn metadata_path(&self, mount: &str, key: &str) -> String {
+        format!("v{API_VERSION}/{mount}/metadata/{key}")
+    }
+
+    fn exists_path(&self, mount: &str, key: &str) -> String {
+        format!("v{API_VERSION}/{mount}/subkeys/{key}")
+    }
+
+    async fn exists(&self, conn: &Connection, mount: &str, key: &str) -> Result<bool, ClientError> {
+        // The subkeys endpoint answers with the keys of the secret's data rather than their
+        // values, and with 404 if its current version is deleted. Only the top level is needed
+        let path = format!("{}?depth=1", self.exists_path(mount, key));
+        match conn.send::<IgnoredAny>("GET", &path).await {
+            Ok(_) => Ok(true),
+            Err(ClientError::APIError { code: 404, errors }) if !is_unsupported(&errors) => {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<D, ClientError> {
+        conn.send::<ReadSecretResponse<D>>("GET", &self.data_path(mount, key))
+            .await
+            .and_then(api::required)
+            .map(|res| res.data)
+    }
+
+    async fn read_version<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        version: u64,
+    ) -> Result<D, ClientError> {
+        let path = format!("{}?version={version}", self.data_path(mount, key));
+        conn.send::<ReadSecretResponse<D>>("GET", &path)
+            .await
+            .and_then(api::required)
+            .map(|res| res.data)
+    }
+
+    async fn write<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        value: &T,
+        cas: Option<u64>,
+    ) -> Result<SecretVersionMetadata, ClientError> {
+        let request = SetSecretRequest {
+            data: value.clone(),
+            options: cas.map(|cas| SetSecretOptions { cas }),
+        };
+        conn.send_json("POST", &self.data_path(mount, key), request)
+            .await
+            .and_then(api::required)
+    }
+
+    async fn read_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<ReadSecretMetadataResponse, ClientError> {
+        conn.send("GET", &self.metadata_path(mount, key))
+            .await
+            .and_then(api::required)
+    }
+
+    async fn set_custom_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<(), ClientError> {
+        let request = SetSecretMetadataRequest {
+            custom_metadata: Some(metadata.clone()),
+            ..Default::default()
+        };
+        conn.send_json::<IgnoredAny, _>("POST", &self.metadata_path(mount, key), request)
+            .await
+            .map(drop)
+    }
+
+    async fn set_max_versions(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        max_versions: u64,
+    ) -> Result<(), ClientError> {
+        let request = SetSecretMetadataRequest {
+            max_versions: Some(max_versions),
+            ..Default::default()
+        };
+        conn.send_json::<IgnoredAny, _>("POST", &self.metadata_path(mount, key), request)
+            .await
+            .map(drop)
+    }
+
+    async fn delete(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        conn.send::<IgnoredAny>("DELETE", &self.data_path(mount, key))
+            .await
+            .map(drop)
+    }
+
+    async fn purge(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        conn.send::<IgnoredAny>("DELETE", &self.metadata_path(mount, key))
+            .await
+            .map(drop)
+    }
+
+    async fn list(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        conn.send::<ListResponse>("LIST", &self.metadata_path(mount, key))
+            .await
+            .and_then(api::required)
+            .map(|res| res.keys)
+    }
+}
+
+/// Returns whether the errors of a 404 response mean the path isn't handled by the engine, as
+/// Vault answers requests to endpoints added in later versions, rather than that the secret
+/// doesn't exist
+pub fn is_unsupported(errors: &[String]) -> bool {
+    errors.iter().any(|e| e.contains("unsupported path"))
+}
+
+/// The KV v1 secrets engine, and the cubbyhole engine which has the same API. Secrets have no
+/// versions or metadata: a secret is reported as its only version, version 1, with no timestamps
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Kv1;
+
+impl Kv1 {
+    /// Returns the error of requests that need versions or metadata of secrets
+    fn unsupported(request: &str) -> ClientError {
+        ClientError::APIError {
+            code: 400,
+            errors: vec![format!(
+                "{request} isn't supported by KV v1 and cubbyhole mounts, their secrets have no \
+                 versions or metadata"
+            )],
+        }
+    }
+
+    /// Returns the metadata reported for the only version of a secret
+    fn version_metadata() -> SecretVersionMetadata {
+        SecretVersionMetadata {
+            created_time: String::new(),
+            custom_metadata: None,
+            deletion_time: String::new(),
+            destroyed: false,
+            version: 1,
+        }
+    }
+}
+
+impl KvBackend for Kv1 {
+    fn data_path(&self, mount: &str, key: &str) -> String {
+        format!("v{API_VERSION}/{mount}/{key}")
+    }
+
+    fn metadata_path(&self, mount: &str, key: &str) -> String {
+        self.data_path(mount, key)
+    }
+
+    fn exists_path(&self, mount: &str, key: &str) -> String {
+        self.data_path(mount, key)
+    }
+
+    async fn exists(&self, conn: &Connection, mount: &str, key: &str) -> Result<bool, ClientError> {
+        match conn
+            .send::<IgnoredAny>("GET", &self.exists_path(mount, key))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(ClientError::APIError { code: 404, .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<D, ClientError> {
+        // The data of the response is the secret itself
+        conn.send::<D>("GET", &self.data_path(mount, key))
+            .await
+            .and_then(api::required)
+    }
+
+    async fn read_version<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        version: u64,
+    ) -> Result<D, ClientError> {
+        match version {
+            1 => self.read(conn, mount, key).await,
+            _ => Err(ClientError::APIError {
+                code: 404,
+                errors: Vec::new(),
+            }),
+        }
+    }
+
+    async fn write<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        value: &T,
+        cas: Option<u64>,
+    ) -> Result<SecretVersionMetadata, ClientError> {
+        if cas.is_some() {
+            return Err(Self::unsupported(
+                "Writing only if a secret has a given version",
+            ));
+        }
+        conn.send_json::<IgnoredAny, _>("POST", &self.data_path(mount, key), value.clone())
+            .await?;
+        Ok(Self::version_metadata())
+    }
+
+    async fn read_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<ReadSecretMetadataResponse, ClientError> {
+        // A secret that can be read has one version
+        conn.send::<IgnoredAny>("GET", &self.data_path(mount, key))
+            .await?;
+        Ok(ReadSecretMetadataResponse {
+            cas_required: false,
+            created_time: String::new(),
+            current_version: 1,
+            delete_version_after: "0s".to_string(),
+            max_versions: 1,
+            oldest_version: 1,
+            updated_time: String::new(),
+            custom_metadata: None,
+            versions: HashMap::from([("1".to_string(), Self::version_metadata())]),
+        })
+    }
+
+    async fn set_custom_metadata(
+        &self,
+        _conn: &Connection,
+        _mount: &str,
+        _key: &str,
+        _metadata: &HashMap<String, String>,
+    ) -> Result<(), ClientError> {
+        Err(Self::unsupported("Custom metadata"))
+    }
+
+    async fn set_max_versions(
+        &self,
+        _conn: &Connection,
+        _mount: &str,
+        _key: &str,
+        _max_versions: u64,
+    ) -> Result<(), ClientError> {
+        // Only the current value of a secret is ever kept
+        Ok(())
+    }
+
+    async fn delete(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        conn.send::<IgnoredAny>("DELETE", &self.data_path(mount, key))
+            .await
+            .map(drop)
+    }
+
+    async fn purge(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        self.delete(conn, mount, key).await
+    }
+
+    async fn list(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        conn.send::<ListResponse>("LIST", &self.data_path(mount, key))
+            .await
+            .and_then(api::required)
+            .map(|res| res.keys)
+    }
+}
+
+/// The secrets engine of a mount
+#[derive(Clone, Copy, Debug)]
+pub enum Engine {
+    Kv2(Kv2),
+    Kv1(Kv1),
+}
+
+impl Engine {
+    /// Returns the engine of the mount at `mount` given the configured KV version. The cubbyhole
+    /// engine is always mounted at `cubbyhole`
+    pub fn new(mount: &str, kv_version: Option<u8>) -> Engine {
+        match (mount.trim_matches('/'), kv_version) {
+            ("cubbyhole", _) | (_, Some(1)) => Engine::Kv1(Kv1),
+            _ => Engine::Kv2(Kv2),
+        }
+    }
+
+    /// Returns the KV version whose API the engine has
+    pub fn kv_version(&self) -> u8 {
+        match self {
+            Engine::Kv2(_) => 2,
+            Engine::Kv1(_) => 1,
+        }
+    }
+}
+
+impl KvBackend for Engine {
+    fn data_path(&self, mount: &str, key: &str) -> String {
+        match self {
+            Engine::Kv2(b) => b.data_path(mount, key),
+            Engine::Kv1(b) => b.data_path(mount, key),
+        }
+    }
+
+    fn metadata_path(&self, mount: &str, key: &str) -> String {
+        match self {
+            Engine::Kv2(b) => b.metadata_path(mount, key),
+            Engine::Kv1(b) => b.metadata_path(mount, key),
+        }
+    }
+
+    fn exists_path(&self, mount: &str, key: &str) -> String {
+        match self {
+            Engine::Kv2(b) => b.exists_path(mount, key),
+            Engine::Kv1(b) => b.exists_path(mount, key),
+        }
+    }
+
+    async fn exists(&self, conn: &Connection, mount: &str, key: &str) -> Result<bool, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.exists(conn, mount, key).await,
+            Engine::Kv1(b) => b.exists(conn, mount, key).await,
+        }
+    }
+
+    async fn read<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<D, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.read(conn, mount, key).await,
+            Engine::Kv1(b) => b.read(conn, mount, key).await,
+        }
+    }
+
+    async fn read_version<D: DeserializeOwned + Send + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        version: u64,
+    ) -> Result<D, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.read_version(conn, mount, key, version).await,
+            Engine::Kv1(b) => b.read_version(conn, mount, key, version).await,
+        }
+    }
+
+    async fn write<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        value: &T,
+        cas: Option<u64>,
+    ) -> Result<SecretVersionMetadata, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.write(conn, mount, key, value, cas).await,
+            Engine::Kv1(b) => b.write(conn, mount, key, value, cas).await,
+        }
+    }
+
+    async fn read_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<ReadSecretMetadataResponse, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.read_metadata(conn, mount, key).await,
+            Engine::Kv1(b) => b.read_metadata(conn, mount, key).await,
+        }
+    }
+
+    async fn set_custom_metadata(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<(), ClientError> {
+        match self {
+            Engine::Kv2(b) => b.set_custom_metadata(conn, mount, key, metadata).await,
+            Engine::Kv1(b) => b.set_custom_metadata(conn, mount, key, metadata).await,
+        }
+    }
+
+    async fn set_max_versions(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+        max_versions: u64,
+    ) -> Result<(), ClientError> {
+        match self {
+            Engine::Kv2(b) => b.set_max_versions(conn, mount, key, max_versions).await,
+            Engine::Kv1(b) => b.set_max_versions(conn, mount, key, max_versions).await,
+        }
+    }
+
+    async fn delete(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        match self {
+            Engine::Kv2(b) => b.delete(conn, mount, key).await,
+            Engine::Kv1(b) => b.delete(conn, mount, key).await,
+        }
+    }
+
+    async fn purge(&self, conn: &Connection, mount: &str, key: &str) -> Result<(), ClientError> {
+        match self {
+            Engine::Kv2(b) => b.purge(conn, mount, key).await,
+            Engine::Kv1(b) => b.purge(conn, mount, key).await,
+        }
+    }
+
+    async fn list(
+        &self,
+        conn: &Connection,
+        mount: &str,
+        key: &str,
+    ) -> Result<Vec<String>, ClientError> {
+        match self {
+            Engine::Kv2(b) => b.list(conn, mount, key).await,
+            Engine::Kv1(b) => b.list(conn, mount, key).await,
+        }
+    }
+}
@@ -1,47 +1,1085 @@
-//! Nats implementation for wasmcloud:messaging.
+//! Vault implementation for wasmcloud:blobstore.
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use blobstore_vault::error::VaultError;
-use futures::FutureExt;
-use tokio::sync::{OwnedRwLockReadGuard, RwLock};
-use tracing::{debug, error, instrument};
+use blobstore_vault::audit::AuditEvent;
+#[cfg(feature = "smithy")]
+use blobstore_vault::config::DEFAULT_LIST_METADATA_CONCURRENCY;
+use blobstore_vault::config::{self, DEFAULT_MAX_QUEUED_REQUESTS};
+use blobstore_vault::deadline;
+use blobstore_vault::error::{ErrorCode, VaultError};
+use blobstore_vault::events::{ChangeOperation, ObjectEvent};
+use blobstore_vault::export::{self, ExportContainerRequest};
+use blobstore_vault::limit::{Limiter, MemoryBudget};
+use blobstore_vault::metrics::{ActorLabel, OperationMetrics, TokenTtlGauge};
+use blobstore_vault::objects::write_object;
+#[cfg(feature = "smithy")]
+use blobstore_vault::objects::{put_object_group, put_objects, read_current, Current};
+use blobstore_vault::progress::Progress;
+use blobstore_vault::upload::{JanitorSettings, UploadSession, UploadSessions};
+use blobstore_vault::{import, object_path, recording, selfcheck, vault_events};
+use futures::StreamExt;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, instrument, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use wasmcloud_provider_sdk::core::{HealthCheckRequest, HealthCheckResponse, LinkDefinition};
 use wasmcloud_provider_sdk::error::ProviderInvocationError;
 use wasmcloud_provider_sdk::ProviderHandler;
-use wasmcloud_provider_sdk::{core::LinkDefinition, start_provider, Context};
+use wasmcloud_provider_sdk::{start_provider, Context};
 
+#[cfg(feature = "smithy")]
+use blobstore_vault::client::live_version;
+#[cfg(feature = "smithy")]
+use blobstore_vault::compress;
+#[cfg(feature = "smithy")]
+use blobstore_vault::continuation::{Continuation, ContinuationKey};
+#[cfg(feature = "smithy")]
+use blobstore_vault::download::ChunkSender;
+#[cfg(feature = "smithy")]
+use blobstore_vault::glob::Pattern;
+#[cfg(feature = "smithy")]
+use blobstore_vault::lock::{LockOutcome, DEFAULT_LOCK_TTL};
+#[cfg(feature = "smithy")]
+use blobstore_vault::snapshot;
+#[cfg(feature = "smithy")]
+use blobstore_vault::usage::{self, Usage};
 use blobstore_vault::wasmcloud_interface_blobstore::*;
-use blobstore_vault::{client::Client, config::Config};
+use blobstore_vault::{
+    client::{Client, File},
+    config::Config,
+};
+#[cfg(feature = "smithy")]
+use bytes::Bytes;
+#[cfg(feature = "smithy")]
+use vaultrs::api::kv2::responses::{ReadSecretMetadataResponse, SecretVersionMetadata};
+
+/// Delay before resubscribing to Vault events after the subscription failed or ended
+const VAULT_EVENTS_RETRY: Duration = Duration::from_secs(5);
+/// Interval at which the time to live of each link's token is looked up
+const TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Time to live below which a link's token is logged as expiring soon
+const TOKEN_EXPIRY_WARNING: Duration = Duration::from_secs(3600);
+
+/// Number of names a listing returns at most, and if the request doesn't say
+#[cfg(feature = "smithy")]
+const MAX_LIST_ITEMS: usize = 1000;
+
+/// Interval at which watched objects are checked for changes
+#[cfg(feature = "smithy")]
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Time left for sending the response of a watch before the invocation would be abandoned
+#[cfg(feature = "smithy")]
+const WATCH_RESPONSE_MARGIN: Duration = Duration::from_millis(250);
+
+/// Times an append is attempted when other writes to the object race it
+#[cfg(feature = "smithy")]
+const APPEND_ATTEMPTS: usize = 5;
+
+/// Time after which an invocation is abandoned if neither the environment nor the host set one.
+/// This is the default RPC timeout of wasmCloud hosts
+const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time after which an invocation of a [bulk operation](is_bulk) is abandoned if the environment
+/// doesn't set one
+const DEFAULT_BULK_OPERATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[cfg(not(feature = "smithy"))]
+compile_error!("the `smithy` feature must be enabled to build the provider");
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--selfcheck") => {
+            let settings = selfcheck::settings(args)?;
+            let ok = tokio::runtime::Runtime::new()?.block_on(selfcheck::run(&settings));
+            std::process::exit(if ok { 0 } else { 1 });
+        }
+        Some("--import") => {
+            let (Some(dir), Some(container)) = (args.next(), args.next()) else {
+                return Err(
+                    "usage: --import <directory> <container> [--<setting> <value>...]".into(),
+                );
+            };
+            let settings = selfcheck::settings(args)?;
+            let (imported, failed) = tokio::runtime::Runtime::new()?.block_on(import::run(
+                &settings,
+                dir.as_ref(),
+                &container,
+            ))?;
+            println!("Imported {imported} files, {failed} failed");
+            std::process::exit(if failed == 0 { 0 } else { 1 });
+        }
+        Some("--export") => {
+            let (Some(container), Some(path)) = (args.next(), args.next()) else {
+                return Err(
+                    "usage: --export <container> <archive> [--<setting> <value>...]".into(),
+                );
+            };
+            let settings = selfcheck::settings(args)?;
+            let client = Client::new(Config::from_values(&settings)?)?;
+            let file = std::fs::File::create(&path)?;
+            let progress = Progress::printed("export", &container);
+            let (_, count) = tokio::runtime::Runtime::new()?.block_on(export::archive(
+                client.for_container(&container),
+                &container,
+                file,
+                &progress,
+            ))?;
+            println!("Exported {count} objects to {path}");
+            return Ok(());
+        }
+        Some("--record") => {
+            let (Some(listen), Some(fixtures)) = (args.next(), args.next()) else {
+                return Err(
+                    "usage: --record <listen address> <fixtures> [--<setting> <value>...]".into(),
+                );
+            };
+            let settings = selfcheck::settings(args)?;
+            let config = Config::from_values(&settings)?;
+            println!("Recording Vault interactions to {fixtures}, send requests to {listen}");
+            tokio::runtime::Runtime::new()?.block_on(recording::record(
+                &config,
+                listen.parse()?,
+                fixtures.into(),
+            ))?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // the host propagates trace context to us as W3C trace context headers
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
     // handle lattice control messages and forward rpc to the provider dispatch
     // returns when provider receives a shutdown control message
-    start_provider(
-        VaultBlobstoreProvider::default(),
-        Some("NATS Messaging Provider".to_string()),
-    )?;
+    let actor_label = actor_label()?;
+    let provider = VaultBlobstoreProvider {
+        limiter: shared_limiter()?,
+        memory_budget: memory_budget()?,
+        metrics: OperationMetrics::new(actor_label),
+        token_ttl: TokenTtlGauge::new(actor_label),
+        janitor: janitor_settings()?,
+        operation_timeout: operation_timeout()?,
+        bulk_operation_timeout: bulk_operation_timeout()?,
+        defaults: Arc::new(link_defaults()?),
+        #[cfg(feature = "smithy")]
+        continuation_key: Arc::new(continuation_key()),
+        ..Default::default()
+    };
+    start_provider(provider, Some("NATS Messaging Provider".to_string()))?;
 
     eprintln!("Vault Blobstore provider exiting");
     Ok(())
 }
 
-/// Nats implementation for wasmcloud:messaging
+/// Builds the limiter shared by all links from the `VAULT_MAX_CONCURRENT_REQUESTS` and
+/// `VAULT_MAX_QUEUED_REQUESTS` environment variables. Returns None if no limit is set
+fn shared_limiter() -> Result<Option<Arc<Limiter>>, Box<dyn std::error::Error>> {
+    let max_concurrent = match std::env::var("VAULT_MAX_CONCURRENT_REQUESTS") {
+        Ok(max) => max.parse()?,
+        Err(_) => return Ok(None),
+    };
+    let max_queued = match std::env::var("VAULT_MAX_QUEUED_REQUESTS") {
+        Ok(max) => max.parse()?,
+        Err(_) => DEFAULT_MAX_QUEUED_REQUESTS,
+    };
+    Ok(Some(Arc::new(Limiter::new(
+        "provider",
+        max_concurrent,
+        max_queued,
+    ))))
+}
+
+/// Builds the budget for object data written at once across all links from the
+/// `VAULT_MAX_WRITE_BUFFER_BYTES` environment variable. Returns None if it isn't set
+fn memory_budget() -> Result<Option<Arc<MemoryBudget>>, Box<dyn std::error::Error>> {
+    match std::env::var("VAULT_MAX_WRITE_BUFFER_BYTES") {
+        Ok(max) => Ok(Some(Arc::new(MemoryBudget::new(max.parse()?)))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns how metrics label the invoking actor, from the `VAULT_METRICS_ACTOR_LABEL`
+/// environment variable. Actors aren't labeled if it isn't set
+fn actor_label() -> Result<ActorLabel, Box<dyn std::error::Error>> {
+    match std::env::var("VAULT_METRICS_ACTOR_LABEL") {
+        Ok(label) => Ok(label.parse()?),
+        Err(_) => Ok(ActorLabel::default()),
+    }
+}
+
+/// Returns how long an invocation may take before it is abandoned. This is the
+/// `VAULT_OPERATION_TIMEOUT_MS` environment variable if set, and otherwise the host's RPC timeout,
+/// after which the calling actor stops waiting for the result anyway
+fn operation_timeout() -> Result<Duration, Box<dyn std::error::Error>> {
+    if let Ok(ms) = std::env::var("VAULT_OPERATION_TIMEOUT_MS") {
+        return Ok(Duration::from_millis(ms.parse()?));
+    }
+    let host_timeout = wasmcloud_provider_sdk::load_host_data()
+        .ok()
+        .and_then(|host_data| host_data.default_rpc_timeout_ms);
+    Ok(host_timeout
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_OPERATION_TIMEOUT))
+}
+
+/// Returns how long an invocation of a [bulk operation](is_bulk) may take before it is abandoned.
+/// This is the `VAULT_BULK_OPERATION_TIMEOUT_MS` environment variable if set. Callers waiting
+/// for these operations set their own, longer timeout, so the host's RPC timeout doesn't apply
+fn bulk_operation_timeout() -> Result<Duration, Box<dyn std::error::Error>> {
+    match std::env::var("VAULT_BULK_OPERATION_TIMEOUT_MS") {
+        Ok(ms) => Ok(Duration::from_millis(ms.parse()?)),
+        Err(_) => Ok(DEFAULT_BULK_OPERATION_TIMEOUT),
+    }
+}
+
+/// Returns whether an operation visits a whole container or mount, or waits for changes, so it
+/// runs within the timeout of bulk operations rather than the one of single requests
+fn is_bulk(method: &str) -> bool {
+    matches!(
+        method,
+        "Blobstore.SnapshotContainer"
+            | "Blobstore.RestoreSnapshot"
+            | "Blobstore.WatchObject"
+            | "VaultBlobstore.CollectGarbage"
+            | "VaultBlobstore.ExportContainer"
+    )
+}
+
+/// Loads the defaults for the values of every link from the config file at the path in the
+/// `VAULT_CONFIG_FILE` environment variable, or else the `config_file` field of the host's config
+/// JSON. Returns no defaults if neither is set
+fn link_defaults() -> Result<LinkValues, Box<dyn std::error::Error>> {
+    let path = match std::env::var("VAULT_CONFIG_FILE") {
+        Ok(path) => Some(path),
+        Err(_) => wasmcloud_provider_sdk::load_host_data()
+            .ok()
+            .and_then(|host_data| host_data.config_json.clone())
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+            .and_then(|config| config.get("config_file")?.as_str().map(ToString::to_string)),
+    };
+    match path {
+        Some(path) => {
+            let defaults = config::load_defaults(path.as_ref())?;
+            info!(%path, settings = defaults.len(), "Loaded link defaults from config file");
+            Ok(defaults)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Returns the key continuation tokens are signed with, from the `VAULT_CONTINUATION_KEY`
+/// environment variable or random if it isn't set
+#[cfg(feature = "smithy")]
+fn continuation_key() -> ContinuationKey {
+    match std::env::var("VAULT_CONTINUATION_KEY") {
+        Ok(key) if !key.is_empty() => ContinuationKey::new(key),
+        _ => ContinuationKey::default(),
+    }
+}
+
+/// Reads how stalled uploads and orphaned secrets are cleaned up from the
+/// `VAULT_UPLOAD_TIMEOUT_SECS`, `VAULT_UPLOAD_DELETE_PARTIAL` and `VAULT_GC_INTERVAL_SECS`
+/// environment variables
+fn janitor_settings() -> Result<JanitorSettings, Box<dyn std::error::Error>> {
+    let mut settings = JanitorSettings::default();
+    if let Ok(secs) = std::env::var("VAULT_UPLOAD_TIMEOUT_SECS") {
+        settings.timeout = Duration::from_secs(secs.parse()?);
+    }
+    if let Ok(delete) = std::env::var("VAULT_UPLOAD_DELETE_PARTIAL") {
+        settings.delete_partial = delete.parse()?;
+    }
+    if let Ok(secs) = std::env::var("VAULT_GC_INTERVAL_SECS") {
+        settings.gc_interval = Some(Duration::from_secs(secs.parse()?));
+    }
+    Ok(settings)
+}
+
+/// Values of a link definition
+type LinkValues = Vec<(String, String)>;
+
+/// Vault implementation for wasmcloud:blobstore
 #[derive(Default, Clone)]
 struct VaultBlobstoreProvider {
     // TODO: Make this an actual vault client type
     actors: Arc<RwLock<HashMap<String, Client>>>,
+    /// Limits Vault requests across all links
+    limiter: Option<Arc<Limiter>>,
+    /// Bounds the object data written at once across all links
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// In-progress multipart uploads
+    uploads: Arc<UploadSessions>,
+    /// Key continuation tokens of listings are signed with
+    #[cfg(feature = "smithy")]
+    continuation_key: Arc<ContinuationKey>,
+    /// Downloads whose chunks are being sent to actors
+    #[cfg(feature = "smithy")]
+    downloads: Arc<ChunkSender>,
+    janitor: JanitorSettings,
+    janitor_started: Arc<AtomicBool>,
+    /// Time after which an invocation is abandoned
+    operation_timeout: Duration,
+    /// Time after which an invocation of a bulk operation is abandoned
+    bulk_operation_timeout: Duration,
+    metrics: OperationMetrics,
+    token_ttl: TokenTtlGauge,
+    /// Provider-level defaults for the values of every link
+    defaults: Arc<LinkValues>,
+    /// Values each link was configured with, by actor ID
+    link_values: Arc<RwLock<HashMap<String, LinkValues>>>,
+    /// Background tasks of each link, forwarding Vault events and watching its token, by actor
+    /// ID
+    link_tasks: Arc<RwLock<HashMap<String, Vec<JoinHandle<()>>>>>,
 }
 
 impl VaultBlobstoreProvider {
-    /// Get a vault client for the actor
-    async fn get_client(
+    /// Get a vault client for the actor. The client is cloned out of the map of links, so a
+    /// link can be reconfigured while operations using its previous client are still running
+    async fn get_client(&self, ctx: &Context) -> Result<Client, String> {
+        self.actors
+            .read()
+            .await
+            .get(actor_id(ctx))
+            .cloned()
+            .ok_or_else(|| ErrorCode::Unauthorized.message("Actor is not linked"))
+    }
+
+    /// Get a vault client for the mount the container is stored in
+    async fn get_container_client(
         &self,
         ctx: &Context,
-    ) -> Result<OwnedRwLockReadGuard<HashMap<String, Client>, Client>, String> {
-        let actors = self.actors.clone().read_owned().await;
-        OwnedRwLockReadGuard::try_map(actors, |a| a.get(ctx.actor.as_deref().unwrap_or_default()))
-            .map_err(|_| "Actor is not linked".to_string())
+        container_id: &str,
+    ) -> Result<Client, String> {
+        Ok(self
+            .get_client(ctx)
+            .await?
+            .for_container(container_id)
+            .clone())
+    }
+
+    /// Periodically abandons uploads that stopped receiving chunks, freeing their buffers and
+    /// optionally deleting the secrets they already wrote
+    async fn run_upload_janitor(self) {
+        let mut interval =
+            tokio::time::interval((self.janitor.timeout / 4).max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            for (stream_id, session) in self.uploads.expire(self.janitor.timeout) {
+                warn!(
+                    %stream_id,
+                    actor_id = %session.actor_id,
+                    object_id = %session.object_id,
+                    received = session.received(),
+                    "Abandoning stalled upload"
+                );
+                if self.janitor.delete_partial {
+                    self.delete_partial_upload(&stream_id, session).await;
+                }
+            }
+        }
+    }
+
+    /// Deletes the manifest and chunks an upload that won't be completed wrote to Vault
+    async fn delete_partial_upload(&self, stream_id: &str, session: UploadSession) {
+        let client = self
+            .actors
+            .read()
+            .await
+            .get(&session.actor_id)
+            .map(|c| c.for_container(&session.container_id).clone());
+        let Some(client) = client else {
+            return;
+        };
+        if let Err(e) = client.purge_upload(stream_id, session.chunks.len()).await {
+            warn!(%stream_id, error = %e, "Failed to delete partial upload");
+        }
+    }
+
+    /// Periodically removes orphaned secrets from the mounts of all links
+    async fn run_gc(self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let clients: Vec<Client> = self.actors.read().await.values().cloned().collect();
+            for client in clients
+                .iter()
+                .flat_map(Client::mounts)
+                .filter(|client| client.content_addressed())
+            {
+                match client.collect_garbage().await {
+                    Ok(removed) if !removed.is_empty() => {
+                        info!(mount = %client.mount(), ?removed, "Removed orphaned secrets")
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        warn!(mount = %client.mount(), error = %e, "Garbage collection failed")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes an audit event using the actor's client, for operations that don't otherwise need
+    /// to talk to Vault
+    async fn audit(&self, ctx: &Context, event: AuditEvent) {
+        if let Ok(client) = self.get_client(ctx).await {
+            client.audit(event).await;
+        }
+    }
+}
+
+/// Returns the ID of the actor that sent the invocation
+fn actor_id(ctx: &Context) -> &str {
+    ctx.actor.as_deref().unwrap_or_default()
+}
+
+/// Removes orphaned secrets from the mounts of a link on behalf of its actor, if the link allows
+/// it. Returns the paths of the removed secrets
+async fn collect_garbage(client: &Client) -> Result<Vec<String>, String> {
+    if !client.allow_garbage_collection() {
+        return Err(VaultError::NotEnabled {
+            operation: "CollectGarbage",
+            setting: "allow_garbage_collection",
+        }
+        .to_rpc_string());
+    }
+    let mut removed = Vec::new();
+    for client in client.mounts() {
+        removed.extend(
+            client
+                .collect_garbage()
+                .await
+                .map_err(|e| e.to_rpc_string())?,
+        );
+    }
+    Ok(removed)
+}
+
+/// Writes the archive of a container to parts in another container on behalf of the link's actor,
+/// if the link allows it, publishing a change for each part. Returns the IDs of the parts with
+/// the number of bytes written
+async fn export_container(
+    source: &Client,
+    target: &Client,
+    actor_id: &str,
+    input: &ExportContainerRequest,
+) -> Result<(Vec<String>, u64), String> {
+    if !source.allow_export() {
+        return Err(VaultError::NotEnabled {
+            operation: "ExportContainer",
+            setting: "allow_export",
+        }
+        .to_rpc_string());
+    }
+    let progress = Progress::logged("export", &input.container_id);
+    let mut parts = Vec::new();
+    let mut written = 0;
+    export::archive_parts(
+        source,
+        &input.container_id,
+        export::PART_BYTES,
+        &progress,
+        |index, data| {
+            let object_id = export::part_id(&input.target_object_id, index);
+            written += data.len() as u64;
+            parts.push(object_id.clone());
+            async move {
+                let file = File {
+                    data: data.into(),
+                    content_type: Some("application/gzip".to_string()),
+                    ..Default::default()
+                };
+                let version = write_object(
+                    target,
+                    &input.target_container_id,
+                    &object_id,
+                    file,
+                    None,
+                    None,
+                    None,
+                )
+                .await?
+                .version;
+                publish_change(
+                    target,
+                    ObjectEvent::new(
+                        actor_id,
+                        ChangeOperation::Put,
+                        &input.target_container_id,
+                        object_id,
+                    )
+                    .version(version),
+                )
+                .await;
+                Ok(())
+            }
+        },
+    )
+    .await
+    .map_err(|e| match e.downcast_ref::<VaultError>() {
+        Some(e) => e.to_rpc_string(),
+        None => ErrorCode::Internal.message(e),
+    })?;
+    Ok((parts, written))
+}
+
+/// Appends bytes to the data of an object, creating it if it doesn't exist, and returns the
+/// version written with the length of its data. The object is written only if it is still at the
+/// version that was read, and read again if another write came in between, up to
+/// [`APPEND_ATTEMPTS`] times
+#[cfg(feature = "smithy")]
+async fn append_object(
+    client: &Client,
+    arg: &AppendObjectRequest,
+) -> Result<(SecretVersionMetadata, u64), String> {
+    let object_id = &arg.object_id;
+    let mut attempt = 1;
+    loop {
+        let Current { version, file, .. } = read_current(client, &arg.container_id, object_id)
+            .await
+            .map_err(|e| e.to_rpc_string())?;
+        // A deleted object is recreated with the appended bytes only
+        let file = match file {
+            Some(file) if file.content_encoding.is_some() => {
+                return Err(ErrorCode::Internal.message(format!(
+                    "can't append to {object_id}, its data is encoded with {}",
+                    file.content_encoding.unwrap_or_default()
+                )));
+            }
+            Some(file) => {
+                let mut data = Vec::with_capacity(file.data.len() + arg.bytes.len());
+                data.extend_from_slice(&file.data);
+                data.extend_from_slice(&arg.bytes);
+                File {
+                    data: data.into(),
+                    ..file
+                }
+            }
+            None => File {
+                data: arg.bytes.clone(),
+                content_type: arg.content_type.clone(),
+                ..Default::default()
+            },
+        };
+        let length = file.data.len() as u64;
+        match write_object(
+            client,
+            &arg.container_id,
+            object_id,
+            file,
+            Some(version),
+            None,
+            None,
+        )
+        .await
+        {
+            Err(VaultError::VersionMismatch { .. }) if attempt < APPEND_ATTEMPTS => {
+                debug!(%object_id, attempt, "Object changed while appending, retrying");
+                attempt += 1;
+            }
+            res => {
+                return res
+                    .map(|written| (written, length))
+                    .map_err(|e| e.to_rpc_string())
+            }
+        }
+    }
+}
+
+/// Returns the session of a multipart upload started by `PutObject`, before its first chunk
+#[cfg(feature = "smithy")]
+fn upload_session(actor_id: &str, arg: PutObjectRequest) -> UploadSession {
+    let mut session = UploadSession::new(actor_id, arg.chunk.container_id, arg.chunk.object_id);
+    session.content_type = arg.content_type;
+    session.content_encoding = arg.content_encoding;
+    session.if_version = arg.if_version;
+    session.metadata = arg.metadata;
+    session.idempotency_key = arg.idempotency_key;
+    session
+}
+
+/// Returns the information about a container, with its usage if it was counted
+#[cfg(feature = "smithy")]
+fn container_info(container_id: String, usage: Option<Usage>) -> ContainerMetadata {
+    let timestamp = |sec| Timestamp { sec, nsec: 0 };
+    match usage {
+        Some(usage) => ContainerMetadata {
+            container_id,
+            created_at: Some(timestamp(usage.created_at)),
+            object_count: Some(usage.object_count),
+            total_bytes: Some(usage.total_bytes),
+            last_modified: Some(timestamp(usage.last_write)),
+        },
+        None => ContainerMetadata {
+            container_id,
+            ..Default::default()
+        },
+    }
+}
+
+/// Lists the containers of all mounts of a link, with their usage if the link accounts it
+#[cfg(feature = "smithy")]
+async fn list_containers(client: &Client) -> Result<ContainersInfo, VaultError> {
+    let mut containers = Vec::new();
+    for mount in client.mounts() {
+        // Folders of an additional mount that don't match its prefix aren't reachable as
+        // containers
+        containers.extend(
+            mount
+                .list_containers()
+                .await?
+                .into_iter()
+                .filter(|container| client.for_container(container).mount() == mount.mount()),
+        );
+    }
+    containers.sort();
+    containers.dedup();
+    if !client.usage_accounting() {
+        return Ok(containers
+            .into_iter()
+            .map(|container_id| container_info(container_id, None))
+            .collect());
+    }
+    let concurrency = client
+        .list_metadata()
+        .unwrap_or(DEFAULT_LIST_METADATA_CONCURRENCY);
+    futures::stream::iter(containers)
+        .map(|container_id| async move {
+            let usage = client
+                .for_container(&container_id)
+                .read_usage(&container_id)
+                .await?;
+            Ok(container_info(container_id, usage))
+        })
+        .buffered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect()
+}
+
+/// Returns the listing entry of an object without any metadata
+#[cfg(feature = "smithy")]
+fn bare_object(container_id: &str, object_id: String) -> ObjectMetadata {
+    ObjectMetadata {
+        object_id,
+        container_id: container_id.to_string(),
+        content_length: 0,
+        content_type: None,
+        content_encoding: None,
+        last_modified: None,
+        version: None,
+        metadata: None,
+    }
+}
+
+/// Returns the listing entry of an object with its metadata if its user metadata contains all of
+/// the tags, or None if it doesn't
+#[cfg(feature = "smithy")]
+fn tagged_object(
+    container_id: &str,
+    object_id: String,
+    metadata: &ReadSecretMetadataResponse,
+    tags: &HashMap<String, String>,
+) -> Option<ObjectMetadata> {
+    let custom = metadata.custom_metadata.as_ref();
+    let matches = tags
+        .iter()
+        .all(|(key, value)| custom.and_then(|c| c.get(key)) == Some(value));
+    matches.then(|| ObjectMetadata {
+        last_modified: Timestamp::parse_rfc3339(&metadata.updated_time),
+        version: live_version(metadata),
+        metadata: metadata.custom_metadata.clone(),
+        ..bare_object(container_id, object_id)
+    })
+}
+
+/// Lists the names in a container starting with a prefix, including those of subfolders ending
+/// in `/`. Only the folder the prefix is in is listed, not its subfolders
+#[cfg(feature = "smithy")]
+async fn list_prefix(
+    client: &Client,
+    container_id: &str,
+    prefix: Option<&str>,
+) -> Result<Vec<String>, VaultError> {
+    // Vault can only list a folder, so list the folder the prefix is in and filter by the rest
+    // of the prefix
+    let prefix = prefix.unwrap_or_default();
+    let (folder, partial) = prefix.split_at(prefix.rfind('/').map_or(0, |i| i + 1));
+    let path = if folder.is_empty() {
+        container_id.to_string()
+    } else {
+        format!("{}/{folder}", container_id.trim_end_matches('/'))
+    };
+    Ok(client
+        .list_files(&path)
+        .await?
+        .into_iter()
+        .filter(|key| key.starts_with(partial))
+        .map(|key| format!("{folder}{key}"))
+        .collect())
+}
+
+/// Lists the IDs of the objects in a container matching a glob pattern and starting with a
+/// prefix. Only the folder before the first wildcard of the pattern is walked, with its
+/// subfolders
+#[cfg(feature = "smithy")]
+async fn list_pattern(
+    client: &Client,
+    container_id: &str,
+    prefix: Option<&str>,
+    pattern: &str,
+) -> Result<Vec<String>, VaultError> {
+    let pattern = Pattern::new(pattern);
+    let prefix = prefix.unwrap_or_default();
+    let container = container_id.trim_end_matches('/');
+    Ok(export::walk_folder(client, container, pattern.folder())
+        .await?
+        .into_iter()
+        .filter(|id| id.starts_with(prefix) && pattern.matches(id))
+        .collect())
+}
+
+/// A name of a listing, with the time its object was last modified if the listing is ordered by
+/// it
+#[cfg(feature = "smithy")]
+struct Listed {
+    name: String,
+    /// Seconds and nanoseconds since the Unix epoch, None for folders
+    modified: Option<(u64, u32)>,
+}
+
+/// Reads the metadata of objects of a container like [`Client::get_metadata_many`], pairing each
+/// result with its object ID rather than its path
+#[cfg(feature = "smithy")]
+async fn metadata_of(
+    client: &Client,
+    container_id: &str,
+    object_ids: impl IntoIterator<Item = String>,
+    concurrency: usize,
+) -> Vec<(String, Result<ReadSecretMetadataResponse, VaultError>)> {
+    let paths = object_ids
+        .into_iter()
+        .map(|object_id| object_path::join(container_id, &object_id));
+    let folder = object_path::join(container_id, "");
+    client
+        .get_metadata_many(paths, concurrency)
+        .await
+        .into_iter()
+        .map(|(path, res)| (path[folder.len()..].to_string(), res))
+        .collect()
+}
+
+/// Returns the names of a listing of a container, with the modification times of their objects
+/// if `modified` is set. Fetching the times needs the link to allow fetching metadata for
+/// listings
+#[cfg(feature = "smithy")]
+async fn with_modified(
+    client: &Client,
+    container_id: &str,
+    names: Vec<String>,
+    modified: bool,
+) -> Result<Vec<Listed>, String> {
+    if !modified {
+        return Ok(names
+            .into_iter()
+            .map(|name| Listed {
+                name,
+                modified: None,
+            })
+            .collect());
+    }
+    let Some(concurrency) = client.list_metadata() else {
+        return Err(ErrorCode::Internal.message(
+            "ordering or filtering by modification time requires the link to set list_metadata",
+        ));
+    };
+    let (folders, objects): (Vec<_>, Vec<_>) =
+        names.into_iter().partition(|name| name.ends_with('/'));
+    let mut entries: Vec<_> = metadata_of(client, container_id, objects, concurrency)
+        .await
+        .into_iter()
+        .map(|(name, res)| Listed {
+            modified: res
+                .ok()
+                .and_then(|metadata| Timestamp::parse_rfc3339(&metadata.updated_time))
+                .map(|time| (time.sec, time.nsec)),
+            name,
+        })
+        .collect();
+    entries.extend(folders.into_iter().map(|name| Listed {
+        name,
+        modified: None,
+    }));
+    Ok(entries)
+}
+
+/// Compares two names of a listing in the order it asks for. Names without a modification time
+/// come last when ordering by it, and names modified at the same time are ordered by name
+#[cfg(feature = "smithy")]
+fn compare_listed(sort: ListSort, a: &Listed, b: &Listed) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let by_modified = |descending: bool| {
+        match (a.modified, b.modified) {
+            (Some(a), Some(b)) if descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| a.name.cmp(&b.name))
+    };
+    match sort {
+        ListSort::Name => a.name.cmp(&b.name),
+        ListSort::NameDesc => b.name.cmp(&a.name),
+        ListSort::LastModified => by_modified(false),
+        ListSort::LastModifiedDesc => by_modified(true),
+    }
+}
+
+/// Returns the page of `entries` a listing asks for, in the order it asks for, along with the
+/// continuation token of the next page if there is one
+#[cfg(feature = "smithy")]
+fn page(
+    key: &ContinuationKey,
+    arg: &ListObjectsRequest,
+    mut entries: Vec<Listed>,
+) -> Result<(Vec<Listed>, Option<String>), String> {
+    let sort = arg.sort.unwrap_or_default();
+    let prefix = arg.prefix.clone().unwrap_or_default();
+    // The bounds of the request are on names, whatever the order
+    let start_with = arg
+        .start_with
+        .as_ref()
+        .filter(|_| arg.continuation.is_none());
+    let after = arg
+        .modified_after
+        .as_ref()
+        .map(|time| (time.sec, time.nsec));
+    let before = arg
+        .modified_before
+        .as_ref()
+        .map(|time| (time.sec, time.nsec));
+    entries.retain(|entry| {
+        let name = &entry.name;
+        // Objects whose modification time couldn't be read can't match a filter on it
+        let modified_out = match entry.modified {
+            _ if name.ends_with('/') => false,
+            Some(modified) => {
+                after.is_some_and(|after| modified <= after)
+                    || before.is_some_and(|before| modified >= before)
+            }
+            None => after.is_some() || before.is_some(),
+        };
+        !(modified_out
+            || start_with.is_some_and(|start| name < start)
+            || arg.end_with.as_ref().is_some_and(|last| name > last)
+            || arg.end_before.as_ref().is_some_and(|before| name >= before))
+    });
+    entries.sort_by(|a, b| compare_listed(sort, a, b));
+    let start = match &arg.continuation {
+        Some(token) => {
+            let continuation = key
+                .decode(token)
+                .filter(|c| c.is_for(&arg.container_id, &prefix, sort))
+                .ok_or_else(|| ErrorCode::Internal.message("invalid continuation token"))?;
+            let after = Listed {
+                name: continuation.after,
+                modified: continuation.after_modified,
+            };
+            entries.partition_point(|entry| compare_listed(sort, entry, &after).is_le())
+        }
+        None => 0,
+    };
+    let max_items = arg.max_items.map_or(MAX_LIST_ITEMS, |max| {
+        (max as usize).clamp(1, MAX_LIST_ITEMS)
+    });
+    let stop = entries
+        .len()
+        .min(start.saturating_add(max_items))
+        .max(start);
+    let continuation = (stop < entries.len()).then(|| {
+        let last = &entries[stop - 1];
+        key.encode(&Continuation {
+            container: arg.container_id.clone(),
+            prefix,
+            sort,
+            after: last.name.clone(),
+            after_modified: last.modified,
+        })
+    });
+    entries.truncate(stop);
+    entries.drain(..start);
+    Ok((entries, continuation))
+}
+
+/// Returns the listing entry of an object with its metadata fetched from Vault
+#[cfg(feature = "smithy")]
+async fn listed_object(client: &Client, container_id: &str, object_id: String) -> ObjectMetadata {
+    match client
+        .read_with_metadata(&object_path::join(container_id, &object_id))
+        .await
+    {
+        Ok((metadata, file)) => object_metadata(container_id, object_id, metadata, file),
+        // Objects can be removed between the listing and the lookup, so list them without
+        // metadata rather than failing the whole listing
+        Err(e) => {
+            debug!(error = %e, object = %object_id, "Failed to fetch metadata of listed object");
+            bare_object(container_id, object_id)
+        }
+    }
+}
+
+/// Returns the metadata of an object read along with its data. The content length is that of
+/// the data, so it is 0 only for empty objects
+#[cfg(feature = "smithy")]
+fn object_metadata(
+    container_id: &str,
+    object_id: String,
+    metadata: ReadSecretMetadataResponse,
+    file: File,
+) -> ObjectMetadata {
+    ObjectMetadata {
+        content_length: file.data.len() as u64,
+        content_type: file.content_type,
+        content_encoding: file.content_encoding,
+        last_modified: Timestamp::parse_rfc3339(&metadata.updated_time),
+        version: live_version(&metadata),
+        metadata: metadata.custom_metadata.filter(|m| !m.is_empty()),
+        ..bare_object(container_id, object_id)
+    }
+}
+
+/// Compresses the data of a response with gzip if it has at least `min_bytes` bytes, isn't
+/// encoded already, and compressing makes it smaller
+#[cfg(feature = "smithy")]
+async fn compress_response(mut response: GetObjectResponse, min_bytes: usize) -> GetObjectResponse {
+    let Some(chunk) = response.initial_chunk.as_mut() else {
+        return response;
+    };
+    if chunk.bytes.len() < min_bytes || response.content_encoding.is_some() {
+        return response;
+    }
+    let data = std::mem::take(&mut chunk.bytes);
+    let (data, compressed) = tokio::task::spawn_blocking(move || {
+        let compressed = compress::gzip(&data);
+        (data, compressed)
+    })
+    .await
+    .expect("Compression task panicked");
+    match compressed {
+        Some(compressed) => {
+            response.content_length = compressed.len() as u64;
+            response.content_encoding = Some(compress::GZIP.to_string());
+            chunk.bytes = compressed;
+        }
+        None => chunk.bytes = data,
+    }
+    response
+}
+
+/// Cuts the data of a response down to its first `chunk_bytes` bytes if it has more, returning
+/// the rest of the data as the final chunk
+#[cfg(feature = "smithy")]
+fn split_response(response: &mut GetObjectResponse, chunk_bytes: usize) -> Option<Chunk> {
+    let chunk = response.initial_chunk.as_mut()?;
+    if chunk.bytes.len() <= chunk_bytes {
+        return None;
+    }
+    let rest = chunk.bytes.split_off(chunk_bytes);
+    chunk.is_last = false;
+    Some(Chunk {
+        object_id: chunk.object_id.clone(),
+        container_id: chunk.container_id.clone(),
+        bytes: rest,
+        offset: chunk.offset + chunk_bytes as u64,
+        is_last: true,
+    })
+}
+
+/// Publishes a change made by an actor on the link's events subject, if one is configured and
+/// changes aren't taken from Vault events instead
+async fn publish_change(client: &Client, event: ObjectEvent) {
+    if !client.vault_events() {
+        publish(client, event).await
+    }
+}
+
+/// Looks up the time to live of a link's token periodically for the token TTL gauge, warning
+/// while it is about to expire
+async fn watch_token(actor_id: String, client: Client, gauge: TokenTtlGauge) {
+    let mut interval = tokio::time::interval(TOKEN_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        match client.token_ttl().await {
+            Ok(ttl) => {
+                if ttl.is_some_and(|ttl| ttl < TOKEN_EXPIRY_WARNING) {
+                    warn!(%actor_id, ?ttl, "Vault token of link expires soon");
+                }
+                gauge.record(&actor_id, ttl);
+            }
+            Err(e) => debug!(%actor_id, error = %e, "Failed to look up Vault token of link"),
+        }
+    }
+}
+
+/// Forwards the changes Vault reports for the mount of a client to the link's events subject,
+/// resubscribing with the current token whenever the subscription fails or ends
+async fn forward_vault_events(client: Client) {
+    loop {
+        let subscription = match client.connection() {
+            Some((addr, token)) => {
+                vault_events::subscribe(&addr, &token, client.headers(), client.mount()).await
+            }
+            None => Err(anyhow::anyhow!("no Vault address configured")),
+        };
+        match subscription {
+            Ok(changes) => {
+                debug!(mount = %client.mount(), "Subscribed to Vault events");
+                let mut changes = std::pin::pin!(changes);
+                while let Some(change) = changes.next().await {
+                    // Secrets outside of folders aren't objects of any container
+                    let Some((container, object)) = change.path.split_once('/') else {
+                        continue;
+                    };
+                    let mut event = ObjectEvent::new("", change.operation, container, object);
+                    event.version = change.version;
+                    publish(&client, event).await;
+                }
+                warn!(mount = %client.mount(), "Vault event subscription ended");
+            }
+            Err(e) => {
+                warn!(mount = %client.mount(), error = %e, "Failed to subscribe to Vault events")
+            }
+        }
+        tokio::time::sleep(VAULT_EVENTS_RETRY).await;
+    }
+}
+
+/// Publishes a change to an object on the link's events subject, if one is configured.
+/// Failures are only logged, as the change itself already succeeded
+async fn publish(client: &Client, event: ObjectEvent) {
+    let Some(subject) = client.events_subject() else {
+        return;
+    };
+    let payload = match serde_json::to_vec(&event) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!(error = %e, "Failed to serialize object event");
+            return;
+        }
+    };
+    if let Err(e) = wasmcloud_provider_sdk::get_connection()
+        .get_rpc_client()
+        .client()
+        .publish(subject.to_string(), payload.into())
+        .await
+    {
+        warn!(error = %e, %subject, "Failed to publish object event");
     }
 }
 
@@ -54,25 +1092,93 @@ impl ProviderHandler for VaultBlobstoreProvider {
     /// If the link is allowed, return true, otherwise return false to deny the link.
     #[instrument(level = "debug", skip(self, ld), fields(actor_id = %ld.actor_id))]
     async fn put_link(&self, ld: &LinkDefinition) -> bool {
-        let config = match Config::from_values(&ld.values) {
+        // A link put again is reconfigured in place. Operations already running finish with the
+        // previous client, and if the new values are invalid the previous client is kept
+        match self.link_values.read().await.get(&ld.actor_id) {
+            Some(values) if *values == ld.values => {
+                debug!("Link values unchanged, keeping current client");
+                return true;
+            }
+            Some(_) => info!("Link values changed, reconfiguring link"),
+            None => (),
+        }
+        let config = match Config::from_values(&config::with_defaults(&self.defaults, &ld.values)) {
             Ok(c) => c,
             Err(e) => {
                 error!("Failed to parse values: {e:?}");
                 return false;
             }
         };
+        let validate = config.validate_on_link;
+        let require_reachable = config.require_reachable_on_link;
+        let prewarm = config.prewarm_on_link;
         let client = match Client::new(config) {
-            Ok(c) => c,
+            Ok(c) => match &self.limiter {
+                Some(limiter) => c.with_shared_limiter(limiter.clone()),
+                None => c,
+            },
             Err(e) => {
                 error!("Failed to connect to Vault: {e:?}");
                 return false;
             }
         };
+        let client = match &self.memory_budget {
+            Some(budget) => client.with_memory_budget(budget.clone()),
+            None => client,
+        }
+        .with_actor(&ld.actor_id);
+
+        if prewarm {
+            client.prewarm().await;
+        }
+        if require_reachable {
+            if let Err(e) = client.probe().await {
+                error!("Vault is unreachable, rejecting link: {e}");
+                return false;
+            }
+        }
+        if validate {
+            if let Err(e) = client.validate().await {
+                error!("Failed to validate connection to Vault, rejecting link: {e}");
+                return false;
+            }
+        }
+
+        let mut tasks = vec![tokio::spawn(watch_token(
+            ld.actor_id.clone(),
+            client.clone(),
+            self.token_ttl.clone(),
+        ))];
+        if client.vault_events() && client.events_subject().is_some() {
+            tasks.extend(
+                client
+                    .mounts()
+                    .map(|mount| tokio::spawn(forward_vault_events(mount.clone()))),
+            );
+        }
+        let replaced = self
+            .link_tasks
+            .write()
+            .await
+            .insert(ld.actor_id.clone(), tasks);
+        replaced.into_iter().flatten().for_each(|task| task.abort());
 
         self.actors
             .write()
             .await
             .insert(ld.actor_id.clone(), client);
+        self.link_values
+            .write()
+            .await
+            .insert(ld.actor_id.clone(), ld.values.clone());
+
+        // The janitor can only be started from within the runtime the SDK sets up
+        if !self.janitor_started.swap(true, Ordering::AcqRel) {
+            tokio::spawn(self.clone().run_upload_janitor());
+            if let Some(period) = self.janitor.gc_interval {
+                tokio::spawn(self.clone().run_gc(period));
+            }
+        }
 
         true
     }
@@ -80,6 +1186,11 @@ impl ProviderHandler for VaultBlobstoreProvider {
     /// Handle notification that a link is dropped: close the connection
     #[instrument(level = "info", skip(self))]
     async fn delete_link(&self, actor_id: &str) {
+        self.link_values.write().await.remove(actor_id);
+        if let Some(tasks) = self.link_tasks.write().await.remove(actor_id) {
+            tasks.iter().for_each(JoinHandle::abort);
+        }
+        self.token_ttl.remove(actor_id);
         let mut aw = self.actors.write().await;
 
         if let Some(_client) = aw.remove(actor_id) {
@@ -91,6 +1202,38 @@ impl ProviderHandler for VaultBlobstoreProvider {
         }
     }
 
+    /// Reports the provider as unhealthy when Vault is unreachable for any linked actor
+    async fn health_request(&self, _arg: &HealthCheckRequest) -> HealthCheckResponse {
+        let actors = self.actors.read().await;
+        let sealed: Vec<&str> = actors
+            .iter()
+            .filter(|(_, client)| client.is_sealed())
+            .map(|(actor_id, _)| actor_id.as_str())
+            .collect();
+        let unhealthy: Vec<&str> = actors
+            .iter()
+            .filter(|(_, client)| !client.is_healthy() && !client.is_sealed())
+            .map(|(actor_id, _)| actor_id.as_str())
+            .collect();
+        let mut problems = Vec::new();
+        if !sealed.is_empty() {
+            problems.push(format!(
+                "Vault is sealed for linked actors: {}",
+                sealed.join(", ")
+            ));
+        }
+        if !unhealthy.is_empty() {
+            problems.push(format!(
+                "Vault is unreachable for linked actors: {}",
+                unhealthy.join(", ")
+            ));
+        }
+        HealthCheckResponse {
+            healthy: problems.is_empty(),
+            message: (!problems.is_empty()).then(|| problems.join("; ")),
+        }
+    }
+
     /// Handle shutdown request by closing all connections
     async fn shutdown(&self) {
         let mut aw = self.actors.write().await;
@@ -101,17 +1244,28 @@ impl ProviderHandler for VaultBlobstoreProvider {
     }
 }
 
+#[cfg(feature = "smithy")]
 #[async_trait::async_trait]
 impl Blobstore for VaultBlobstoreProvider {
     /// Returns whether the container exists
-    async fn container_exists(&self, _ctx: Context, _arg: ContainerId) -> Result<bool, String> {
+    async fn container_exists(&self, ctx: Context, arg: ContainerId) -> Result<bool, String> {
+        self.audit(
+            &ctx,
+            AuditEvent::new(actor_id(&ctx), "ContainerExists", arg),
+        )
+        .await;
         // If we were doing this for real, we'd probably make this tied to a secrets namespace
         Ok(true)
     }
     /// Creates a container by name, returning success if it worked
     /// Note that container names may not be globally unique - just unique within the
     /// "namespace" of the connecting actor and linkdef
-    async fn create_container(&self, _ctx: Context, _arg: ContainerId) -> Result<(), String> {
+    async fn create_container(&self, ctx: Context, arg: ContainerId) -> Result<(), String> {
+        self.audit(
+            &ctx,
+            AuditEvent::new(actor_id(&ctx), "CreateContainer", arg),
+        )
+        .await;
         // We don't actually need to create a container because it is purely contained in the path
         // name of the secret, so just noop here
         Ok(())
@@ -120,18 +1274,34 @@ impl Blobstore for VaultBlobstoreProvider {
     /// Returns error if the container id is invalid or not found.
     async fn get_container_info(
         &self,
-        _ctx: Context,
+        ctx: Context,
         arg: ContainerId,
     ) -> Result<ContainerMetadata, String> {
-        Ok(ContainerMetadata {
-            container_id: arg,
-            created_at: Some(Timestamp::now()),
-        })
+        let client = self.get_container_client(&ctx, &arg).await?;
+        let res = if client.usage_accounting() {
+            client.read_usage(&arg).await.map_err(|e| e.to_rpc_string())
+        } else {
+            Ok(None)
+        };
+        client
+            .audit(AuditEvent::new(actor_id(&ctx), "GetContainerInfo", &arg).result(&res))
+            .await;
+        let mut info = container_info(arg, res?);
+        info.created_at.get_or_insert_with(Timestamp::now);
+        Ok(info)
     }
 
-    /// Returns list of container ids
-    async fn list_containers(&self, _ctx: Context) -> Result<ContainersInfo, String> {
-        Ok(Vec::with_capacity(0))
+    /// Returns list of container ids. If the link accounts usage, each container includes the
+    /// number and bytes of its objects
+    async fn list_containers(&self, ctx: Context) -> Result<ContainersInfo, String> {
+        let client = self.get_client(&ctx).await?;
+        let res = list_containers(&client)
+            .await
+            .map_err(|e| e.to_rpc_string());
+        client
+            .audit(AuditEvent::new(actor_id(&ctx), "ListContainers", "").result(&res))
+            .await;
+        res
     }
     /// Empty and remove the container(s)
     /// The MultiResult list contains one entry for each container
@@ -139,40 +1309,60 @@ impl Blobstore for VaultBlobstoreProvider {
     /// If the MultiResult list is empty, all container removals succeeded.
     async fn remove_containers(
         &self,
-        _ctx: Context,
-        _arg: ContainerIds,
+        ctx: Context,
+        arg: ContainerIds,
     ) -> Result<MultiResult, String> {
+        for container in arg {
+            self.audit(
+                &ctx,
+                AuditEvent::new(actor_id(&ctx), "RemoveContainers", container),
+            )
+            .await;
+        }
         Ok(Vec::with_capacity(0))
     }
     /// Returns whether the object exists
     async fn object_exists(&self, ctx: Context, arg: ContainerObject) -> Result<bool, String> {
-        let client = self.get_client(&ctx).await?;
-        match client.get_metadata(&arg.object_id).await {
-            Ok(_) => Ok(true),
-            Err(VaultError::NotFound { .. }) => Ok(false),
-            Err(e) => Err(e.to_string()),
-        }
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = client
+            .exists(object_path::join(&arg.container_id, &arg.object_id))
+            .await
+            .map_err(|e| e.to_rpc_string());
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "ObjectExists", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
     }
     /// Retrieves information about the object.
     /// Returns error if the object id is invalid or not found.
+    ///
+    /// Vault metadata doesn't record the size of a secret, so the object is read to return its
+    /// content length, which is 0 only for empty objects.
     async fn get_object_info(
         &self,
         ctx: Context,
         arg: ContainerObject,
     ) -> Result<ObjectMetadata, String> {
-        let client = self.get_client(&ctx).await?;
-        client
-            .get_metadata(&arg.object_id)
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = client
+            .read_with_metadata(&object_path::join(&arg.container_id, &arg.object_id))
             .await
-            .map_err(|e| e.to_string())
-            .map(|_| ObjectMetadata {
-                object_id: arg.object_id,
-                container_id: arg.container_id,
-                content_length: 0,
-                content_type: None,
-                content_encoding: None,
-                last_modified: None,
-            })
+            .map_err(|e| e.to_rpc_string())
+            .map(|(metadata, file)| {
+                object_metadata(&arg.container_id, arg.object_id.clone(), metadata, file)
+            });
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "GetObjectInfo", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
     }
 
     /// Lists the objects in the container.
@@ -185,31 +1375,74 @@ impl Blobstore for VaultBlobstoreProvider {
     ///
     /// Optional object metadata fields (i.e., `contentType` and `contentEncoding`) may not be
     /// filled in for ListObjects response. To get complete object metadata, use GetObjectInfo.
+    ///
+    /// Vault stores secrets in folders separated by `/`, and only the folder containing `prefix`
+    /// is listed. Objects in subfolders are returned as the subfolder name ending in `/`, or in
+    /// `commonPrefixes` if `delimiter` is set. With a `pattern`, the objects of subfolders whose
+    /// IDs match it are returned instead, and no folders.
     async fn list_objects(
         &self,
         ctx: Context,
         arg: ListObjectsRequest,
     ) -> Result<ListObjectsResponse, String> {
-        let client = self.get_client(&ctx).await?;
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        if arg.delimiter.as_deref().is_some_and(|d| d != "/") {
+            let res = Err(ErrorCode::Internal.message("Only '/' is supported as delimiter"));
+            client
+                .audit(
+                    AuditEvent::new(actor_id(&ctx), "ListObjects", arg.container_id).result(&res),
+                )
+                .await;
+            return res;
+        }
+        let modified = arg.sort.unwrap_or_default().by_modified()
+            || arg.modified_after.is_some()
+            || arg.modified_before.is_some();
+        let names = match &arg.pattern {
+            Some(pattern) => {
+                list_pattern(&client, &arg.container_id, arg.prefix.as_deref(), pattern).await
+            }
+            None => list_prefix(&client, &arg.container_id, arg.prefix.as_deref()).await,
+        };
+        let listed = match names {
+            Ok(names) => with_modified(&client, &arg.container_id, names, modified).await,
+            Err(e) => Err(e.to_rpc_string()),
+        }
+        .and_then(|entries| page(&self.continuation_key, &arg, entries));
+        let res = match listed {
+            Ok((entries, continuation)) => {
+                let names = entries.into_iter().map(|entry| entry.name);
+                let (common_prefixes, objs): (Vec<_>, Vec<_>) = if arg.delimiter.is_some() {
+                    names.partition(|name| name.ends_with('/'))
+                } else {
+                    (Vec::new(), names.collect())
+                };
+                let objects = match client.list_metadata() {
+                    Some(concurrency) => {
+                        futures::stream::iter(objs)
+                            .map(|o| listed_object(&client, &arg.container_id, o))
+                            .buffered(concurrency)
+                            .collect()
+                            .await
+                    }
+                    None => objs
+                        .into_iter()
+                        .map(|o| bare_object(&arg.container_id, o))
+                        .collect(),
+                };
+                Ok(ListObjectsResponse {
+                    objects,
+                    common_prefixes,
+                    is_last: continuation.is_none(),
+                    continuation,
+                })
+            }
+            Err(e) => Err(e),
+        };
         client
-            .list_files(&arg.container_id)
-            .await
-            .map_err(|e| e.to_string())
-            .map(|objs| ListObjectsResponse {
-                objects: objs
-                    .into_iter()
-                    .map(|o| ObjectMetadata {
-                        object_id: o,
-                        container_id: arg.container_id.clone(),
-                        content_length: 0,
-                        content_type: None,
-                        content_encoding: None,
-                        last_modified: None,
-                    })
-                    .collect(),
-                is_last: true,
-                continuation: None,
-            })
+            .audit(AuditEvent::new(actor_id(&ctx), "ListObjects", arg.container_id).result(&res))
+            .await;
+        res
     }
     /// Removes the objects. In the event any of the objects cannot be removed,
     /// the operation continues until all requested deletions have been attempted.
@@ -220,23 +1453,49 @@ impl Blobstore for VaultBlobstoreProvider {
         ctx: Context,
         arg: RemoveObjectsRequest,
     ) -> Result<MultiResult, String> {
-        let client = self.get_client(&ctx).await?;
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
         let futs = arg.objects.into_iter().map(|key| {
-            let cloned_key = key.clone();
-            client.delete_file(key).map(|res| match res {
-                Ok(_) => ItemResult {
-                    key: cloned_key,
-                    error: None,
-                    success: true,
-                },
-                Err(e) => ItemResult {
-                    key: cloned_key,
-                    error: Some(e.to_string()),
-                    success: false,
-                },
-            })
+            let (client, container_id) = (&client, &arg.container_id);
+            async move {
+                let path = object_path::join(container_id, &key);
+                let before = usage::size_before(client, &path).await;
+                match client.delete_file(&path).await {
+                    Ok(_) => {
+                        usage::record(client, container_id, before, None).await;
+                        ItemResult {
+                            key,
+                            error: None,
+                            success: true,
+                        }
+                    }
+                    Err(e) => ItemResult {
+                        key,
+                        error: Some(e.to_rpc_string()),
+                        success: false,
+                    },
+                }
+            }
         });
         let results = futures::future::join_all(futs).await;
+        for item in results.iter() {
+            let mut event = AuditEvent::new(actor_id(&ctx), "RemoveObjects", &arg.container_id)
+                .object(&item.key);
+            event.success = item.success;
+            event.error = item.error.clone();
+            client.audit(event).await;
+            if item.success {
+                publish_change(
+                    &client,
+                    ObjectEvent::new(
+                        actor_id(&ctx),
+                        ChangeOperation::Remove,
+                        &arg.container_id,
+                        &item.key,
+                    ),
+                )
+                .await;
+            }
+        }
         Ok(results)
     }
     /// Requests to start upload of a file/blob to the Blobstore.
@@ -246,12 +1505,60 @@ impl Blobstore for VaultBlobstoreProvider {
         ctx: Context,
         arg: PutObjectRequest,
     ) -> Result<PutObjectResponse, String> {
-        let client = self.get_client(&ctx).await?;
-        client
-            .write_file(arg.chunk.object_id, arg.chunk.bytes)
-            .await
-            .map_err(|e| e.to_string())
-            .map(|_| PutObjectResponse { stream_id: None })
+        let client = self
+            .get_container_client(&ctx, &arg.chunk.container_id)
+            .await?;
+        let event = AuditEvent::new(actor_id(&ctx), "PutObject", &arg.chunk.container_id)
+            .object(&arg.chunk.object_id)
+            .bytes(arg.chunk.bytes.len() as u64);
+        // The rest of the object follows in PutChunk requests, it is written once all arrived
+        if !arg.chunk.is_last {
+            let data = arg.chunk.bytes.clone();
+            let stream_id = self.uploads.start(upload_session(actor_id(&ctx), arg));
+            debug!(%stream_id, "Started multipart upload");
+            let res = self
+                .store_chunk(&client, &stream_id, 0, data)
+                .await
+                .map(|()| PutObjectResponse {
+                    stream_id: Some(stream_id.clone()),
+                });
+            if res.is_err() {
+                self.uploads.remove(&stream_id);
+            }
+            client.audit(event.result(&res)).await;
+            return res;
+        }
+        let file = File {
+            data: arg.chunk.bytes,
+            content_type: arg.content_type,
+            content_encoding: arg.content_encoding,
+            ..Default::default()
+        };
+        let res = write_object(
+            &client,
+            &arg.chunk.container_id,
+            &arg.chunk.object_id,
+            file,
+            arg.if_version,
+            arg.idempotency_key.as_deref(),
+            arg.metadata,
+        )
+        .await
+        .map_err(|e| e.to_rpc_string());
+        client.audit(event.result(&res)).await;
+        let written = res?;
+        publish_change(
+            &client,
+            ObjectEvent::new(
+                actor_id(&ctx),
+                ChangeOperation::Put,
+                arg.chunk.container_id,
+                arg.chunk.object_id,
+            )
+            .version(written.version),
+        )
+        .await;
+        Ok(PutObjectResponse { stream_id: None })
     }
     /// Requests to retrieve an object. If the object is large, the provider
     /// may split the response into multiple parts
@@ -261,148 +1568,1043 @@ impl Blobstore for VaultBlobstoreProvider {
         ctx: Context,
         arg: GetObjectRequest,
     ) -> Result<GetObjectResponse, String> {
-        let client = self.get_client(&ctx).await?;
-        client
-            .read_file(&arg.object_id)
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let event =
+            AuditEvent::new(actor_id(&ctx), "GetObject", &arg.container_id).object(&arg.object_id);
+        // Compressing part of an object would make the offsets of its chunks meaningless
+        let ranged = arg.range_start.is_some() || arg.range_end.is_some();
+        let accepts_gzip = !ranged
+            && arg
+                .accept_encoding
+                .as_deref()
+                .is_some_and(compress::accepts_gzip);
+        // The metadata is read before the data, so the version returned is never newer than the
+        // data and conditional writes based on it can't overwrite unseen changes
+        let res = client
+            .read_with_metadata(&object_path::join(&arg.container_id, &arg.object_id))
             .await
-            .map_err(|e| e.to_string())
-            .map(|data| GetObjectResponse {
-                success: true,
-                error: None,
-                initial_chunk: Some(Chunk {
-                    object_id: arg.object_id,
-                    container_id: arg.container_id,
-                    bytes: data,
-                    is_last: true,
-                    offset: 0,
-                }),
-                ..Default::default()
-            })
+            .map_err(|e| e.to_rpc_string())
+            .map(|(metadata, mut file)| {
+                let range = arg.range(file.data.len() as u64);
+                GetObjectResponse {
+                    success: true,
+                    error: None,
+                    content_length: file.data.len() as u64,
+                    content_type: file.content_type,
+                    content_encoding: file.content_encoding,
+                    version: live_version(&metadata),
+                    not_modified: false,
+                    initial_chunk: Some(Chunk {
+                        object_id: arg.object_id,
+                        container_id: arg.container_id,
+                        bytes: file
+                            .data
+                            .split_off(range.start as usize)
+                            .split_to((range.end - range.start) as usize),
+                        is_last: true,
+                        offset: range.start,
+                    }),
+                }
+            });
+        let bytes = res
+            .as_ref()
+            .ok()
+            .and_then(|r| r.initial_chunk.as_ref())
+            .map(|c| c.bytes.len() as u64)
+            .unwrap_or_default();
+        client.audit(event.bytes(bytes).result(&res)).await;
+        let res = match (res, client.compress_min_bytes()) {
+            (Ok(response), Some(min_bytes)) if accepts_gzip => {
+                Ok(compress_response(response, min_bytes).await)
+            }
+            (res, _) => res,
+        };
+        match (res, client.download_chunk_bytes()) {
+            (Ok(mut response), Some(chunk_bytes)) => {
+                if let Some(rest) = split_response(&mut response, chunk_bytes) {
+                    let downloads = self.downloads.clone();
+                    let actor_id = actor_id(&ctx).to_string();
+                    tokio::spawn(async move { downloads.send(actor_id, rest, chunk_bytes).await });
+                }
+                Ok(response)
+            }
+            (res, _) => res,
+        }
     }
     /// Uploads a file chunk to a blobstore. This must be called AFTER PutObject
     /// It is recommended to keep chunks under 1MB to avoid exceeding nats default message size
-    async fn put_chunk(&self, _ctx: Context, _arg: PutChunkRequest) -> Result<(), String> {
-        Err("Chunking not supported".to_string())
+    async fn put_chunk(&self, ctx: Context, arg: PutChunkRequest) -> Result<(), String> {
+        let event = AuditEvent::new(actor_id(&ctx), "PutChunk", &arg.chunk.container_id)
+            .object(&arg.chunk.object_id)
+            .bytes(arg.chunk.bytes.len() as u64);
+        let res = self.receive_chunk(&ctx, arg).await;
+        self.audit(&ctx, event.result(&res)).await;
+        res
+    }
+}
+
+/// Extensions of the `Blobstore` interface
+#[cfg(feature = "smithy")]
+impl VaultBlobstoreProvider {
+    /// Adds a chunk to a multipart upload started by `put_object`, writing the object once the
+    /// last chunk arrived. Chunks must arrive in order, and each is written to Vault with the
+    /// manifest of the upload before it is acknowledged, so an upload the provider doesn't know
+    /// is resumed from Vault. A chunk sent again after it was received is acknowledged without
+    /// being added twice. With `cancel_and_remove` the upload is discarded instead, along with
+    /// what it wrote to Vault
+    async fn receive_chunk(&self, ctx: &Context, arg: PutChunkRequest) -> Result<(), String> {
+        let Some(stream_id) = arg.stream_id else {
+            return Err(ErrorCode::Internal.message("PutChunk requires the streamId of PutObject"));
+        };
+        let chunk = arg.chunk;
+        let client = self.get_container_client(ctx, &chunk.container_id).await?;
+        if arg.cancel_and_remove {
+            return self.cancel_upload(ctx, &client, &stream_id).await;
+        }
+        let not_found =
+            || ErrorCode::NotFound.message(format!("no upload {stream_id}, it may have expired"));
+        if self.uploads.update(&stream_id, |_| ()).is_none() {
+            let session = match client.read_upload(&stream_id).await {
+                // Uploads of other actors are left alone, as if they didn't exist
+                Ok(session) if session.actor_id == actor_id(ctx) => session,
+                Ok(_) | Err(VaultError::NotFound { .. }) => return Err(not_found()),
+                Err(e) => return Err(e.to_rpc_string()),
+            };
+            debug!(%stream_id, received = session.received(), "Resumed upload");
+            self.uploads.resume(&stream_id, session);
+        }
+        let received = self.uploads.update(&stream_id, |session| {
+            if session.actor_id != actor_id(ctx)
+                || session.container_id != chunk.container_id
+                || session.object_id != chunk.object_id
+            {
+                return Err(ErrorCode::Internal.message(format!(
+                    "chunk of {}/{} doesn't belong to upload {stream_id}",
+                    chunk.container_id, chunk.object_id
+                )));
+            }
+            if session.has_chunk(chunk.offset, &chunk.bytes) {
+                return Ok(None);
+            }
+            if chunk.offset != session.received() {
+                return Err(ErrorCode::Internal.message(format!(
+                    "chunk at offset {} of upload {stream_id}, expected {}",
+                    chunk.offset,
+                    session.received()
+                )));
+            }
+            Ok(Some(session.chunks.len()))
+        });
+        let index = match received {
+            None => return Err(not_found()),
+            Some(res) => res?,
+        };
+        let Some(index) = index else {
+            // The chunk was already received, but the response to it may have been lost
+            return Ok(());
+        };
+        if !chunk.is_last {
+            return self
+                .store_chunk(&client, &stream_id, index, chunk.bytes)
+                .await;
+        }
+        let Some(mut session) = self.uploads.remove(&stream_id) else {
+            return Err(ErrorCode::NotFound.message(format!("upload {stream_id} expired")));
+        };
+        // The last chunk goes straight into the object, only the ones before it were stored
+        let stored = session.chunks.len();
+        session.append(&chunk.bytes);
+        let file = File {
+            data: session.buffer.freeze(),
+            content_type: session.content_type,
+            content_encoding: session.content_encoding,
+            ..Default::default()
+        };
+        let written = write_object(
+            &client,
+            &session.container_id,
+            &session.object_id,
+            file,
+            session.if_version,
+            session.idempotency_key.as_deref(),
+            session.metadata,
+        )
+        .await
+        .map_err(|e| e.to_rpc_string())?;
+        if let Err(e) = client.purge_upload(&stream_id, stored).await {
+            warn!(%stream_id, error = %e, "Failed to delete completed upload");
+        }
+        publish_change(
+            &client,
+            ObjectEvent::new(
+                actor_id(ctx),
+                ChangeOperation::Put,
+                session.container_id,
+                session.object_id,
+            )
+            .version(written.version),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Writes a chunk of an upload to Vault with the manifest of the upload, then adds it to the
+    /// session. Chunks are only acknowledged once stored, so they aren't lost if the provider
+    /// restarts before the upload completes
+    async fn store_chunk(
+        &self,
+        client: &Client,
+        stream_id: &str,
+        index: usize,
+        data: Bytes,
+    ) -> Result<(), String> {
+        let not_found =
+            || ErrorCode::NotFound.message(format!("no upload {stream_id}, it may have expired"));
+        let (manifest, next) = self
+            .uploads
+            .update(stream_id, |session| session.manifest_with(&data))
+            .ok_or_else(not_found)?;
+        if next != index {
+            return Err(ErrorCode::Conflict.message(format!(
+                "chunk {index} of upload {stream_id} was received concurrently with another"
+            )));
+        }
+        client
+            .write_upload_chunk(stream_id, &manifest, index, data.clone())
+            .await
+            .map_err(|e| e.to_rpc_string())?;
+        self.uploads
+            .update(stream_id, |session| {
+                if session.chunks.len() == index {
+                    session.append(&data);
+                }
+            })
+            .ok_or_else(not_found)
+    }
+
+    /// Discards an upload of the calling actor and what it wrote to Vault. Uploads of other
+    /// actors are left alone, and cancelling one that already expired or completed has nothing
+    /// left to do
+    async fn cancel_upload(
+        &self,
+        ctx: &Context,
+        client: &Client,
+        stream_id: &str,
+    ) -> Result<(), String> {
+        let owned = self
+            .uploads
+            .update(stream_id, |session| session.actor_id == actor_id(ctx));
+        match owned {
+            Some(true) => {
+                if let Some(session) = self.uploads.remove(stream_id) {
+                    debug!(%stream_id, received = session.received(), "Cancelled upload");
+                    self.delete_partial_upload(stream_id, session).await;
+                }
+            }
+            Some(false) => (),
+            // The provider may have restarted since the upload started
+            None => match client.read_upload_manifest(stream_id).await {
+                Ok(manifest) if manifest.actor_id == actor_id(ctx) => {
+                    debug!(%stream_id, received = manifest.received, "Cancelled upload");
+                    if let Err(e) = client.purge_upload(stream_id, manifest.chunks.len()).await {
+                        warn!(%stream_id, error = %e, "Failed to delete partial upload");
+                    }
+                }
+                Ok(_) | Err(VaultError::NotFound { .. }) => (),
+                Err(e) => return Err(e.to_rpc_string()),
+            },
+        }
+        Ok(())
+    }
+
+    /// Returns the object like `get_object`, unless its version is still the one the caller has,
+    /// in which case only `not_modified` is set
+    async fn get_object_if_changed(
+        &self,
+        ctx: Context,
+        arg: GetObjectIfChangedRequest,
+    ) -> Result<GetObjectResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let path = object_path::join(&arg.container_id, &arg.object_id);
+        let res = match client.get_metadata(&path).await {
+            Ok(metadata) => Ok(live_version(&metadata)),
+            Err(VaultError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.to_rpc_string()),
+        };
+        if res
+            .as_ref()
+            .is_ok_and(|version| *version != Some(arg.version))
+        {
+            // get_object returns the current data, or reports the object as missing
+            let req = GetObjectRequest {
+                object_id: arg.object_id,
+                container_id: arg.container_id,
+                range_start: None,
+                range_end: None,
+                accept_encoding: None,
+            };
+            return Blobstore::get_object(self, ctx, req).await;
+        }
+        let res = res.map(|version| GetObjectResponse {
+            success: true,
+            version,
+            not_modified: true,
+            ..Default::default()
+        });
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "GetObjectIfChanged", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Lists the objects whose user metadata contains all of the requested tags, fetching the
+    /// metadata of as many objects at once as the link's `list_metadata_concurrency`
+    async fn list_objects_by_tag(
+        &self,
+        ctx: Context,
+        arg: ListObjectsByTagRequest,
+    ) -> Result<ListObjectsResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let concurrency = client
+            .list_metadata()
+            .unwrap_or(DEFAULT_LIST_METADATA_CONCURRENCY);
+        let res = match list_prefix(&client, &arg.container_id, arg.prefix.as_deref()).await {
+            Ok(names) => Ok(ListObjectsResponse {
+                objects: metadata_of(
+                    &client,
+                    &arg.container_id,
+                    names.into_iter().filter(|n| !n.ends_with('/')),
+                    concurrency,
+                )
+                .await
+                .into_iter()
+                .filter_map(|(object_id, res)| match res {
+                    Ok(metadata) => {
+                        tagged_object(&arg.container_id, object_id, &metadata, &arg.tags)
+                    }
+                    // Objects can be removed between the listing and the lookup
+                    Err(e) => {
+                        debug!(
+                            error = %e,
+                            object = %object_id,
+                            "Failed to fetch metadata of listed object"
+                        );
+                        None
+                    }
+                })
+                .collect(),
+                common_prefixes: Vec::new(),
+                is_last: true,
+                continuation: None,
+            }),
+            Err(e) => Err(e.to_rpc_string()),
+        };
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "ListObjectsByTag", arg.container_id).result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Returns the versions of an object Vault still keeps, oldest first
+    async fn get_object_versions(
+        &self,
+        ctx: Context,
+        arg: ContainerObject,
+    ) -> Result<Vec<ObjectVersion>, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = client
+            .get_metadata(object_path::join(&arg.container_id, &arg.object_id))
+            .await
+            .map_err(|e| e.to_rpc_string())
+            .map(|metadata| {
+                let mut versions: Vec<ObjectVersion> = metadata
+                    .versions
+                    .iter()
+                    .filter_map(|(version, v)| {
+                        Some(ObjectVersion {
+                            version: version.parse().ok()?,
+                            created_at: Timestamp::parse_rfc3339(&v.created_time),
+                            deleted_at: Timestamp::parse_rfc3339(&v.deletion_time),
+                            destroyed: v.destroyed,
+                        })
+                    })
+                    .collect();
+                versions.sort_by_key(|v| v.version);
+                versions
+            });
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "GetObjectVersions", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Writes an older version of an object as its latest version, returning the new version
+    async fn restore_object(&self, ctx: Context, arg: RestoreObjectRequest) -> Result<u64, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = client
+            .restore_file(
+                &object_path::join(&arg.container_id, &arg.object_id),
+                arg.version,
+            )
+            .await
+            .map(|written| written.version)
+            .map_err(|e| e.to_rpc_string());
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "RestoreObject", &arg.container_id)
+                    .object(&arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        let version = res?;
+        publish_change(
+            &client,
+            ObjectEvent::new(
+                actor_id(&ctx),
+                ChangeOperation::Put,
+                arg.container_id,
+                arg.object_id,
+            )
+            .version(version),
+        )
+        .await;
+        Ok(version)
+    }
+
+    /// Waits until the version of an object differs from the one the caller has, or the timeout
+    /// passes, checking the object's metadata periodically
+    async fn watch_object(
+        &self,
+        ctx: Context,
+        arg: WatchObjectRequest,
+    ) -> Result<WatchObjectResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        // Answer before the invocation is abandoned, so the caller gets a response. Without a
+        // timeout of its own, the watch ends within the RPC timeout of the host
+        let max_timeout = deadline::remaining()
+            .unwrap_or(self.operation_timeout)
+            .saturating_sub(WATCH_RESPONSE_MARGIN);
+        let timeout = match arg.timeout_ms {
+            0 => self
+                .operation_timeout
+                .saturating_sub(WATCH_RESPONSE_MARGIN)
+                .min(max_timeout),
+            ms => Duration::from_millis(ms).min(max_timeout),
+        };
+        let deadline = tokio::time::Instant::now() + timeout;
+        let path = object_path::join(&arg.container_id, &arg.object_id);
+        let res = loop {
+            let version = match client.get_metadata(&path).await {
+                Ok(metadata) => live_version(&metadata),
+                Err(VaultError::NotFound { .. }) => None,
+                Err(e) => break Err(e.to_rpc_string()),
+            };
+            let changed = version != arg.version;
+            if changed || tokio::time::Instant::now() + WATCH_POLL_INTERVAL > deadline {
+                break Ok(WatchObjectResponse { changed, version });
+            }
+            tokio::time::sleep(WATCH_POLL_INTERVAL).await;
+        };
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "WatchObject", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Takes the advisory lock of an object for the calling actor, unless it is held
+    async fn acquire_lock(
+        &self,
+        ctx: Context,
+        arg: AcquireLockRequest,
+    ) -> Result<AcquireLockResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let ttl = match arg.ttl_ms {
+            0 => DEFAULT_LOCK_TTL,
+            // Expiry is stored in whole seconds
+            ms => Duration::from_secs(ms.div_ceil(1000)),
+        };
+        let res = client
+            .acquire_lock(
+                &object_path::join(&arg.container_id, &arg.object_id),
+                actor_id(&ctx),
+                ttl,
+            )
+            .await
+            .map_err(|e| e.to_rpc_string())
+            .map(|outcome| match outcome {
+                LockOutcome::Acquired { token, expires_at } => AcquireLockResponse {
+                    acquired: true,
+                    token: Some(token),
+                    owner: actor_id(&ctx).to_string(),
+                    expires_at: Timestamp {
+                        sec: expires_at,
+                        nsec: 0,
+                    },
+                },
+                LockOutcome::Held(lock) => AcquireLockResponse {
+                    acquired: false,
+                    token: None,
+                    owner: lock.owner,
+                    expires_at: Timestamp {
+                        sec: lock.expires_at,
+                        nsec: 0,
+                    },
+                },
+            });
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "AcquireLock", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Releases a lock the calling actor took
+    async fn release_lock(&self, ctx: Context, arg: ReleaseLockRequest) -> Result<(), String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = client
+            .release_lock(
+                &object_path::join(&arg.container_id, &arg.object_id),
+                arg.token,
+            )
+            .await
+            .map_err(|e| e.to_rpc_string());
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "ReleaseLock", arg.container_id)
+                    .object(arg.object_id)
+                    .result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Records the current version of every object of a container as a named snapshot
+    async fn snapshot_container(
+        &self,
+        ctx: Context,
+        arg: SnapshotContainerRequest,
+    ) -> Result<SnapshotContainerResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = if arg.name.is_empty() || arg.name.contains('/') {
+            Err(ErrorCode::Internal.message("Snapshot names must be non-empty and not contain '/'"))
+        } else {
+            snapshot::take(&client, &arg.container_id, &arg.name)
+                .await
+                .map(|manifest| SnapshotContainerResponse {
+                    object_count: manifest.objects.len() as u64,
+                    created_at: Timestamp {
+                        sec: manifest.created_at,
+                        nsec: 0,
+                    },
+                })
+                .map_err(|e| e.to_rpc_string())
+        };
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "SnapshotContainer", arg.container_id).result(&res),
+            )
+            .await;
+        res
+    }
+
+    /// Rewrites the objects of a container to their versions in a snapshot, or only lists the
+    /// objects that would be rewritten
+    async fn restore_snapshot(
+        &self,
+        ctx: Context,
+        arg: RestoreSnapshotRequest,
+    ) -> Result<RestoreSnapshotResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let res = snapshot::restore(&client, &arg.container_id, &arg.name, arg.dry_run)
+            .await
+            .map(|changed| RestoreSnapshotResponse { changed })
+            .map_err(|e| e.to_rpc_string());
+        client
+            .audit(
+                AuditEvent::new(actor_id(&ctx), "RestoreSnapshot", &arg.container_id).result(&res),
+            )
+            .await;
+        if let (Ok(response), false) = (&res, arg.dry_run) {
+            for object_id in &response.changed {
+                publish_change(
+                    &client,
+                    ObjectEvent::new(
+                        actor_id(&ctx),
+                        ChangeOperation::Put,
+                        &arg.container_id,
+                        object_id,
+                    ),
+                )
+                .await;
+            }
+        }
+        res
+    }
+
+    /// Writes several objects of a container, so actors writing many small objects don't need an
+    /// invocation for each
+    async fn put_objects(
+        &self,
+        ctx: Context,
+        arg: PutObjectsRequest,
+    ) -> Result<Vec<ObjectWriteResult>, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let sizes: HashMap<_, _> = arg
+            .objects
+            .iter()
+            .map(|object| (object.object_id.clone(), object.bytes.len() as u64))
+            .collect();
+        let results = put_objects(&client, &arg.container_id, arg.objects).await;
+        for item in &results {
+            let mut event = AuditEvent::new(actor_id(&ctx), "PutObjects", &arg.container_id)
+                .object(&item.object_id)
+                .bytes(sizes.get(&item.object_id).copied().unwrap_or_default());
+            event.success = item.success;
+            event.error = item.error.clone();
+            client.audit(event).await;
+            if let Some(version) = item.version {
+                publish_change(
+                    &client,
+                    ObjectEvent::new(
+                        actor_id(&ctx),
+                        ChangeOperation::Put,
+                        &arg.container_id,
+                        &item.object_id,
+                    )
+                    .version(version),
+                )
+                .await;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Writes all objects of a group or, as far as possible, none of them
+    async fn put_object_group(
+        &self,
+        ctx: Context,
+        arg: PutObjectGroupRequest,
+    ) -> Result<PutObjectGroupResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let response = put_object_group(&client, &arg.container_id, arg.objects).await;
+        let rolled_back: HashMap<_, _> = response
+            .rolled_back
+            .iter()
+            .map(|item| (item.key.as_str(), item.success))
+            .collect();
+        for item in &response.results {
+            let mut event = AuditEvent::new(actor_id(&ctx), "PutObjectGroup", &arg.container_id)
+                .object(&item.object_id);
+            event.success = item.success;
+            event.error = item.error.clone();
+            client.audit(event).await;
+            // Objects rolled back are as they were, so only the writes that stayed are changes
+            let stayed = rolled_back.get(item.object_id.as_str()) != Some(&true);
+            if let Some(version) = item.version.filter(|_| stayed) {
+                publish_change(
+                    &client,
+                    ObjectEvent::new(
+                        actor_id(&ctx),
+                        ChangeOperation::Put,
+                        &arg.container_id,
+                        &item.object_id,
+                    )
+                    .version(version),
+                )
+                .await;
+            }
+        }
+        Ok(response)
+    }
+
+    /// Appends bytes to an object, so actors writing logs don't race each other with their own
+    /// read-modify-write cycles
+    async fn append_object(
+        &self,
+        ctx: Context,
+        arg: AppendObjectRequest,
+    ) -> Result<AppendObjectResponse, String> {
+        let client = self.get_container_client(&ctx, &arg.container_id).await?;
+        let event = AuditEvent::new(actor_id(&ctx), "AppendObject", &arg.container_id)
+            .object(&arg.object_id)
+            .bytes(arg.bytes.len() as u64);
+        let res = append_object(&client, &arg).await;
+        client.audit(event.result(&res)).await;
+        let (written, content_length) = res?;
+        publish_change(
+            &client,
+            ObjectEvent::new(
+                actor_id(&ctx),
+                ChangeOperation::Put,
+                arg.container_id,
+                arg.object_id,
+            )
+            .version(written.version),
+        )
+        .await;
+        Ok(AppendObjectResponse {
+            version: written.version,
+            content_length,
+        })
     }
 }
 
+/// Sets the parent of the current span to the trace context sent along with the invocation, so
+/// spans around Vault requests show up inside the calling actor's trace
+fn propagate_trace(ctx: &Context) {
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&ctx.tracing)
+    });
+    tracing::Span::current().set_parent(parent);
+}
+
 #[async_trait::async_trait]
 impl wasmcloud_provider_sdk::MessageDispatch for VaultBlobstoreProvider {
+    #[instrument(level = "debug", skip(self, ctx, body), fields(actor_id = ?ctx.actor))]
     async fn dispatch<'a>(
         &'a self,
         ctx: Context,
         method: String,
         body: std::borrow::Cow<'a, [u8]>,
     ) -> Result<Vec<u8>, ProviderInvocationError> {
-        match method.as_str() {
+        propagate_trace(&ctx);
+        let actor = actor_id(&ctx).to_string();
+        let timeout = if is_bulk(&method) {
+            self.bulk_operation_timeout
+        } else {
+            self.operation_timeout
+        };
+        let start = Instant::now();
+        // Retries and walks give up at the deadline on their own, the timeout only catches
+        // requests still in flight
+        let operation = deadline::scope(
+            start + timeout,
+            self.dispatch_operation(ctx, &method, &body),
+        );
+        let res = tokio::time::timeout(timeout, operation)
+            .await
+            .unwrap_or_else(|_| {
+                warn!(%method, ?timeout, "Invocation timed out");
+                self.metrics.record_timeout(&method);
+                Err(ProviderInvocationError::Provider(
+                    ErrorCode::Unavailable
+                        .message(format!("{method} did not complete within {timeout:?}")),
+                ))
+            });
+        let elapsed = start.elapsed();
+        self.metrics.record(&method, &actor, elapsed, res.is_ok());
+        debug!(%method, ?elapsed, ok = res.is_ok(), "Invocation finished");
+        res
+    }
+}
+
+impl VaultBlobstoreProvider {
+    /// Runs the operation an invocation is for. Dropping the returned future cancels any Vault
+    /// requests the operation still has to send
+    // Every arm binds the result the same way, including those of operations returning nothing
+    #[allow(clippy::let_unit_value)]
+    async fn dispatch_operation(
+        &self,
+        ctx: Context,
+        method: &str,
+        body: &[u8],
+    ) -> Result<Vec<u8>, ProviderInvocationError> {
+        match method {
+            #[cfg(feature = "smithy")]
             "Blobstore.ContainerExists" => {
-                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.container_exists(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::container_exists(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.CreateContainer" => {
-                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.create_container(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::create_container(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.GetContainerInfo" => {
-                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.get_container_info(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerId = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::get_container_info(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.ListContainers" => {
-                let _input: () = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.list_containers(ctx).await.map_err(|e| {
+                let _input: () = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::list_containers(self, ctx).await.map_err(|e| {
                     ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
                         e.to_string(),
                     )
                 })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.RemoveContainers" => {
-                let input: ContainerIds = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.remove_containers(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerIds = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::remove_containers(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.ObjectExists" => {
-                let input: ContainerObject = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.object_exists(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerObject = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::object_exists(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.GetObjectInfo" => {
-                let input: ContainerObject = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.get_object_info(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ContainerObject = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::get_object_info(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.ListObjects" => {
-                let input: ListObjectsRequest = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.list_objects(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: ListObjectsRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::list_objects(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.RemoveObjects" => {
-                let input: RemoveObjectsRequest = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.remove_objects(ctx, input).await.map_err(|e| {
-                    ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
-                        e.to_string(),
-                    )
-                })?;
+                let input: RemoveObjectsRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::remove_objects(self, ctx, input)
+                    .await
+                    .map_err(|e| {
+                        ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
+                            e.to_string(),
+                        )
+                    })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.PutObject" => {
-                let input: PutObjectRequest = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.put_object(ctx, input).await.map_err(|e| {
+                let input: PutObjectRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::put_object(self, ctx, input).await.map_err(|e| {
                     ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
                         e.to_string(),
                     )
                 })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.GetObject" => {
-                let input: GetObjectRequest = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.get_object(ctx, input).await.map_err(|e| {
+                let input: GetObjectRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::get_object(self, ctx, input).await.map_err(|e| {
                     ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
                         e.to_string(),
                     )
                 })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
             "Blobstore.PutChunk" => {
-                let input: PutChunkRequest = ::wasmcloud_provider_sdk::deserialize(&body)?;
-                let result = self.put_chunk(ctx, input).await.map_err(|e| {
+                let input: PutChunkRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = Blobstore::put_chunk(self, ctx, input).await.map_err(|e| {
                     ::wasmcloud_provider_sdk::error::ProviderInvocationError::Provider(
                         e.to_string(),
                     )
                 })?;
                 Ok(::wasmcloud_provider_sdk::serialize(&result)?)
             }
+            #[cfg(feature = "smithy")]
+            "Blobstore.GetObjectIfChanged" => {
+                let input: GetObjectIfChangedRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .get_object_if_changed(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.GetObjectVersions" => {
+                let input: ContainerObject = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .get_object_versions(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.ListObjectsByTag" => {
+                let input: ListObjectsByTagRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .list_objects_by_tag(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.RestoreObject" => {
+                let input: RestoreObjectRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .restore_object(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.WatchObject" => {
+                let input: WatchObjectRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .watch_object(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.AcquireLock" => {
+                let input: AcquireLockRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .acquire_lock(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.ReleaseLock" => {
+                let input: ReleaseLockRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .release_lock(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.SnapshotContainer" => {
+                let input: SnapshotContainerRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .snapshot_container(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.RestoreSnapshot" => {
+                let input: RestoreSnapshotRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .restore_snapshot(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.PutObjects" => {
+                let input: PutObjectsRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .put_objects(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.PutObjectGroup" => {
+                let input: PutObjectGroupRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .put_object_group(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            #[cfg(feature = "smithy")]
+            "Blobstore.AppendObject" => {
+                let input: AppendObjectRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let result = self
+                    .append_object(ctx, input)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&result)?)
+            }
+            "VaultBlobstore.CollectGarbage" => {
+                let client = self
+                    .get_client(&ctx)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                let res = collect_garbage(&client).await;
+                client
+                    .audit(AuditEvent::new(actor_id(&ctx), "CollectGarbage", "").result(&res))
+                    .await;
+                let removed = res.map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&removed)?)
+            }
+            "VaultBlobstore.ExportContainer" => {
+                let input: ExportContainerRequest = ::wasmcloud_provider_sdk::deserialize(body)?;
+                let source = self
+                    .get_container_client(&ctx, &input.container_id)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                let target = self
+                    .get_container_client(&ctx, &input.target_container_id)
+                    .await
+                    .map_err(ProviderInvocationError::Provider)?;
+                let res = export_container(&source, &target, actor_id(&ctx), &input).await;
+                let event = AuditEvent::new(
+                    actor_id(&ctx),
+                    "ExportContainer",
+                    &input.target_container_id,
+                )
+                .object(&input.target_object_id)
+                .bytes(res.as_ref().map_or(0, |(_, written)| *written));
+                target.audit(event.result(&res)).await;
+                let (parts, _) = res.map_err(ProviderInvocationError::Provider)?;
+                Ok(::wasmcloud_provider_sdk::serialize(&parts)?)
+            }
             _ => Err(
                 ::wasmcloud_provider_sdk::error::InvocationError::Malformed(format!(
                     "Invalid method name {method}",
@@ -0,0 +1,91 @@
+//! Content-addressed storage of object data
+//!
+//! In content-addressed mode the data of an object is stored once per distinct content, in a blob
+//! secret named after the SHA-256 hash of the data. The object's own secret only points to the
+//! blob, so identical objects written by many actors share a single blob, and copies only write a
+//! new pointer. Each blob has a reference count of the pointers to it, and is deleted once the last
+//! one is removed.
+//!
+//! Reference counts are updated with check-and-set writes, retried when several providers sharing
+//! the mount race. Releasing the last reference marks the count as deleting before the blob is
+//! deleted, and is done once the count is written back as zero, so another provider adding a
+//! reference meanwhile waits rather than pointing to data that is about to be deleted. The count
+//! secret is kept once the blob is gone, so its versions keep increasing.
+//!
+//! NOTE: A deletion that fails halfway is taken over by the next writer of the same content once
+//! it is older than [`DELETION_TIMEOUT`], which relies on the clocks of the providers being
+//! synchronized.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Folder of the mount holding blobs and their reference counts. Object IDs starting with it are
+/// reserved in content-addressed mode
+pub const CAS_PREFIX: &str = "_cas";
+
+/// Time garbage collection of a mount holds its lock for. Another provider may start collecting
+/// once it expired
+pub const GC_LOCK_TTL: Duration = Duration::from_secs(600);
+
+/// Time after which the deletion of a blob is considered to have failed. Garbage collection also
+/// leaves blobs younger than this alone, as their reference count may not be written yet
+pub const DELETION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time a writer waits between reads of the count of a blob being deleted
+pub const DELETION_WAIT: Duration = Duration::from_millis(200);
+
+/// Number of objects pointing to a blob
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct RefCount {
+    pub count: u64,
+    /// Seconds since the Unix epoch at which the deletion of the blob started, if it is being
+    /// deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleting_since: Option<u64>,
+}
+
+impl RefCount {
+    /// Creates the count of a blob whose last reference was released, marking it as deleting
+    pub fn deleting() -> RefCount {
+        RefCount {
+            count: 0,
+            deleting_since: Some(now().as_secs()),
+        }
+    }
+
+    /// Returns whether the blob is being deleted by another writer, which hasn't timed out
+    pub fn is_deleting(&self) -> bool {
+        self.deleting_since
+            .is_some_and(|since| now().as_secs() < since.saturating_add(DELETION_TIMEOUT.as_secs()))
+    }
+}
+
+/// Returns the hex-encoded SHA-256 hash blobs are named after
+pub fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Returns the path of the blob holding the data with the given hash
+pub fn blob_path(hash: &str) -> String {
+    format!("{CAS_PREFIX}/blobs/{hash}")
+}
+
+/// Returns the path of the lock held while collecting garbage of the mount
+pub fn gc_lock_path() -> String {
+    format!("{CAS_PREFIX}/gc")
+}
+
+/// Returns the path of the reference count of the blob with the given hash
+pub fn refs_path(hash: &str) -> String {
+    format!("{CAS_PREFIX}/refs/{hash}")
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+}
@@ -1,27 +1,224 @@
 //! Hashicorp vault client
 //!
-use std::{string::ToString, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    string::ToString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
 
-use serde::{Deserialize, Serialize};
+use bytes::{Bytes, BytesMut};
+use futures::{future::Either, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, error, field, info, warn, Instrument, Span};
 use vaultrs::api::kv2::responses::{ReadSecretMetadataResponse, SecretVersionMetadata};
 use vaultrs::client::{VaultClient, VaultClientSettings};
+use vaultrs::error::ClientError;
 
-use crate::{config::Config, error::VaultError};
+use crate::{
+    access::PathRules,
+    api::{self, Connection},
+    audit::{AuditEvent, AuditSink},
+    auth::AuthMethod,
+    backend::{Engine, KvBackend},
+    cas::{self, RefCount},
+    config::Config,
+    deadline, discovery,
+    error::VaultError,
+    export::ListLimits,
+    failover::{self, Node, Nodes},
+    fault::FaultInjection,
+    hedge::Hedge,
+    idempotency::Writes,
+    limit::{Limiter, MemoryBudget},
+    lock::{self, Lock, LockOutcome},
+    metrics::{self, ErrorCounter, RenewalCounter},
+    object_path,
+    singleflight::Group,
+    snapshot::{self, Manifest},
+    throttle::Throttle,
+    upload::{self, UploadSession},
+    usage::{self, Usage},
+    wasmcloud_interface_blobstore::Timestamp,
+};
 
 /// Vault HTTP api version. As of Vault 1.9.x (Feb 2022), all http api calls use version 1
-const API_VERSION: u8 = 1;
+pub(crate) const API_VERSION: u8 = 1;
+
+/// Delay before the first retry of a request while Vault is sealed, doubled on each retry
+const SEALED_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+/// Maximum delay between retries of a request while Vault is sealed
+const SEALED_MAX_BACKOFF: Duration = Duration::from_secs(2);
+/// Delay before the first retry of a rate limited request that didn't say when to retry with
+/// `Retry-After`, doubled on each retry. Requests sent through vaultrs don't expose the header,
+/// so this is the one second interval rate limit quotas are counted in
+const RATE_LIMIT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Maximum delay between retries of a rate limited request without `Retry-After`
+const RATE_LIMIT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Maximum number of characters of a secret path recorded in spans
+const MAX_TRACED_PATH_LEN: usize = 128;
+/// Size allowed for the parts of a write request other than the file, such as the JSON envelope
+/// and the `cas` option
+const REQUEST_OVERHEAD_BYTES: usize = 1024;
+/// Time before the metadata of a secret written recently is read again while waiting for a
+/// replica to serve the written version. Doubles with every attempt
+const READ_YOUR_WRITES_BACKOFF: Duration = Duration::from_millis(25);
+
+/// Version and time of the last write of each secret, by path
+type WrittenVersions = HashMap<String, (u64, Instant)>;
+
+/// What the client reads of a token lookup
+#[derive(Deserialize)]
+struct TokenLookup {
+    expire_time: Option<String>,
+    #[serde(default)]
+    ttl: u64,
+}
+
+/// What the client reads of a mount listed by `sys/mounts`
+#[derive(Deserialize)]
+struct MountInfo {
+    #[serde(rename = "type")]
+    mount_type: String,
+    #[serde(default)]
+    options: Option<HashMap<String, String>>,
+}
 
 /// Vault client connection information.
 #[derive(Clone)]
 pub struct Client {
-    inner: Arc<vaultrs::client::VaultClient>,
+    nodes: Arc<Nodes>,
+    /// Servers read-only requests are sent to before falling back to `nodes`, if configured
+    read_nodes: Option<Arc<Nodes>>,
+    /// How long reads of a secret written through this client wait for `read_nodes` to serve
+    /// the written version, if they do
+    read_after_write: Duration,
+    /// `X-Vault-Index` of the last write sent through this client that returned one. Reads send
+    /// it, so performance standbys that haven't replicated the write yet forward them to the
+    /// active node rather than serving stale data
+    replication_index: Arc<Mutex<Option<String>>>,
+    /// Whether the backend can tell if a secret exists without reading its metadata. Cleared
+    /// the first time the server doesn't support it
+    existence_checks: Arc<AtomicBool>,
+    /// Version and time of the last write of each secret written within `read_after_write`, if
+    /// reads wait for `read_nodes` to serve them
+    written: Option<Arc<Mutex<WrittenVersions>>>,
+    /// How long requests are retried while Vault is sealed
+    sealed_retry: Duration,
+    /// How long requests rejected by a rate limit quota are retried
+    rate_limit_retry: Duration,
+    /// Time above which requests log a warning, if any
+    slow_request: Option<Duration>,
+    /// Tracks read latencies to hedge slow reads, if enabled
+    hedge: Option<Arc<Hedge>>,
+    /// Faults injected into requests, only for testing
+    faults: Option<Arc<FaultInjection>>,
+    /// Actor the client's link is for, if known, to attribute logs
+    actor_id: Option<Arc<str>>,
+    errors: ErrorCounter,
+    renewals: RenewalCounter,
+    /// Whether object data is stored in content-addressed blobs
+    content_addressed: bool,
+    /// Whether actors may collect garbage of the link's mounts
+    allow_garbage_collection: bool,
+    /// Whether actors may export containers of the link
+    allow_export: bool,
+    /// Whether writes of the content a file already has are skipped
+    skip_unchanged_writes: bool,
+    /// Whether the usage of containers is kept in their markers
+    usage_accounting: bool,
+    /// Maximum number of versions kept of the files in a container, by container
+    container_max_versions: Arc<HashMap<String, u64>>,
+    /// Held while updating blob reference counts, so concurrent writes of the same content don't
+    /// lose references
+    cas_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Auth method used to get a new token when requests are denied
+    auth: Option<Arc<AuthMethod>>,
+    /// Primary and secondary token requests switch between when denied, if there's no `auth`
+    tokens: Option<Arc<(String, String)>>,
+    /// Time of the last login with `auth`, or switch between `tokens`. Held while changing the
+    /// token so concurrent requests denied with the same expired token only change it once
+    last_login: Arc<tokio::sync::Mutex<Option<Instant>>>,
     namespace: String,
+    audit: Option<AuditSink>,
+    /// Mount a Vault audit sink writes to, which is the same for the clients of all mounts
+    audit_mount: String,
+    /// Limiters a request must get a slot from before being sent, in acquisition order
+    limiters: Vec<Arc<Limiter>>,
+    /// Bound on the object data written at once, shared with other links
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Results of writes with idempotency keys, None if keys are ignored
+    idempotent_writes: Option<Arc<Writes<SecretVersionMetadata>>>,
+    /// Headers added to every request, also needed for connections vaultrs can't make
+    headers: Arc<reqwest::header::HeaderMap>,
+    /// Maximum size of a request to Vault, 0 if unlimited
+    max_request_bytes: usize,
+    /// Bandwidth limits for object data read and written by the link
+    read_throttle: Option<Arc<Throttle>>,
+    write_throttle: Option<Arc<Throttle>>,
+    /// Number of objects whose metadata is fetched at once for listings, if listings include it
+    list_metadata: Option<usize>,
+    /// Bounds of listings of all objects of a container
+    list_limits: ListLimits,
+    /// Number of objects of a batch written at once
+    batch_concurrency: usize,
+    /// Minimum size of object data compressed for actors that accept it, if enabled
+    compress_min_bytes: Option<usize>,
+    /// Size of the chunks object data is sent to actors in, if sent in chunks
+    download_chunk_bytes: Option<usize>,
+    /// Subject changes to objects are published on, if any
+    events_subject: Option<Arc<str>>,
+    /// Whether changes are reported by Vault events instead of the provider
+    vault_events: bool,
+    /// Paths the link may access
+    access: Arc<PathRules>,
+    /// Clients for the additional mounts of the link, by container prefix
+    routes: Arc<Vec<(String, Client)>>,
+    /// Version of the KV engine of the mount, once configured or detected, which selects the
+    /// [`Engine`] requests are sent with. None if it couldn't be detected
+    kv_version: Arc<tokio::sync::OnceCell<Option<u8>>>,
+    reads: Arc<Group<File>>,
+    metadata: Arc<Group<ReadSecretMetadataResponse>>,
 }
 
-/// A representation of a file that can be serialized and deserialized
-#[derive(Serialize, Deserialize)]
-struct File {
-    data: Vec<u8>,
+/// A representation of a file that can be serialized and deserialized. The data is shared
+/// rather than copied when a file is cloned, e.g. for concurrent reads of the same path
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct File {
+    /// Data of the file. Secrets without it, such as markers written with only a content type,
+    /// are read as empty files
+    #[serde(default)]
+    pub data: Bytes,
+    /// MIME type of the data, if given when the file was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Encodings applied to the data, if given when the file was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// Hash of the blob holding the data, for files written in content-addressed mode. The data
+    /// of such files is empty until read from the blob
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+impl From<Vec<u8>> for File {
+    fn from(data: Vec<u8>) -> Self {
+        Bytes::from(data).into()
+    }
+}
+
+impl From<Bytes> for File {
+    fn from(data: Bytes) -> Self {
+        File {
+            data,
+            ..Default::default()
+        }
+    }
 }
 
 impl Client {
@@ -30,98 +227,2276 @@ impl Client {
     /// Note that this constructor does not attempt to connect to the vault server,
     /// so the vault server does not need to be running at the time a LinkDefinition to this provider is created.
     pub fn new(config: Config) -> Result<Self, VaultError> {
-        Ok(Client {
-            inner: Arc::new(VaultClient::new(VaultClientSettings {
-                token: config.token,
-                address: config.addr,
-                ca_certs: config.certs,
-                verify: false,
-                version: API_VERSION,
-                wrapping: false,
-                timeout: None,
-                namespace: Some(config.mount.clone()),
-            })?),
+        let nodes = Arc::new(build_nodes(&config, &config.addrs)?);
+        let read_nodes = if config.read_addrs.is_empty() {
+            None
+        } else {
+            Some(Arc::new(build_nodes(&config, &config.read_addrs)?))
+        };
+        if !config.seal_poll_interval.is_zero() {
+            let watched = std::iter::once(&nodes)
+                .chain(&read_nodes)
+                .map(Arc::downgrade)
+                .collect();
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(poll_seal_status(config.seal_poll_interval, watched));
+                }
+                Err(_) => warn!("Not polling the seal status outside of a Tokio runtime"),
+            }
+        }
+        if let Some(discovery) = config.discovery.clone() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(discovery::run(
+                        discovery,
+                        config.discovery_interval,
+                        Arc::downgrade(&nodes),
+                    ));
+                }
+                Err(_) => warn!("Not discovering Vault servers outside of a Tokio runtime"),
+            }
+        }
+        if config.tls_client_cert.is_some() && !config.tls_client_reload.is_zero() {
+            let watched = std::iter::once(&nodes)
+                .chain(&read_nodes)
+                .map(Arc::downgrade)
+                .collect();
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(reload_client_cert(config.clone(), watched));
+                }
+                Err(_) => warn!("Not reloading the client certificate outside of a Tokio runtime"),
+            }
+        }
+        let audit_mount = config
+            .audit_mount
+            .clone()
+            .unwrap_or_else(|| config.mount.clone());
+        let client = Client {
+            nodes,
+            read_nodes,
+            read_after_write: config.read_after_write,
+            replication_index: Default::default(),
+            existence_checks: Arc::new(AtomicBool::new(true)),
+            written: (config.read_your_writes && !config.read_addrs.is_empty())
+                .then(Default::default),
+            sealed_retry: config.sealed_retry,
+            rate_limit_retry: config.rate_limit_retry,
+            slow_request: config.slow_request,
+            hedge: config.hedge_percentile.map(|p| Arc::new(Hedge::new(p))),
+            faults: config.fault_injection.map(|faults| {
+                warn!(
+                    ?faults,
+                    "Injecting faults into Vault requests, this must never be used in production"
+                );
+                Arc::new(faults)
+            }),
+            actor_id: None,
+            errors: ErrorCounter::default(),
+            renewals: RenewalCounter::default(),
+            content_addressed: config.content_addressed,
+            allow_garbage_collection: config.allow_garbage_collection,
+            allow_export: config.allow_export,
+            skip_unchanged_writes: config.skip_unchanged_writes,
+            usage_accounting: config.usage_accounting,
+            container_max_versions: Arc::new(config.container_max_versions),
+            cas_lock: Default::default(),
+            tokens: config
+                .secondary_token
+                .filter(|_| config.auth.is_none())
+                .map(|secondary| Arc::new((config.token.clone(), secondary))),
+            auth: config.auth.map(Arc::new),
+            last_login: Default::default(),
+            kv_version: known_kv_version(&config.mount, config.kv_version),
             namespace: config.mount,
+            audit: config.audit,
+            audit_mount,
+            limiters: config
+                .max_concurrent_requests
+                .map(|max| Arc::new(Limiter::new("link", max, config.max_queued_requests)))
+                .into_iter()
+                .collect(),
+            memory_budget: None,
+            max_request_bytes: config.max_request_bytes,
+            headers: Arc::new(config.headers.clone()),
+            idempotent_writes: (!config.idempotency_window.is_zero())
+                .then(|| Arc::new(Writes::new(config.idempotency_window))),
+            read_throttle: config
+                .max_read_bytes_per_sec
+                .map(|max| Arc::new(Throttle::new("read", max))),
+            write_throttle: config
+                .max_write_bytes_per_sec
+                .map(|max| Arc::new(Throttle::new("write", max))),
+            compress_min_bytes: config.compress_min_bytes,
+            download_chunk_bytes: config.download_chunk_bytes,
+            list_metadata: config
+                .list_metadata
+                .then_some(config.list_metadata_concurrency),
+            list_limits: ListLimits {
+                concurrency: config.list_concurrency,
+                max_folder_keys: config.list_max_folder_keys,
+                max_keys: config.list_max_keys,
+            },
+            batch_concurrency: config.batch_concurrency,
+            events_subject: config.events_subject.map(Into::into),
+            vault_events: config.vault_events,
+            access: Arc::new(PathRules::new(
+                config.allowed_prefixes,
+                config.denied_prefixes,
+            )),
+            routes: Default::default(),
+            reads: Default::default(),
+            metadata: Default::default(),
+        };
+        let routes = config
+            .mounts
+            .into_iter()
+            .map(|(prefix, mount)| {
+                let route = Client {
+                    kv_version: known_kv_version(&mount, config.kv_version),
+                    namespace: mount,
+                    reads: Default::default(),
+                    metadata: Default::default(),
+                    ..client.clone()
+                };
+                (prefix, route)
+            })
+            .collect();
+        Ok(Client {
+            routes: Arc::new(routes),
+            ..client
         })
     }
 
-    /// Reads value of secret using namespace and key path
-    pub async fn read_file(&self, path: impl AsRef<str>) -> Result<Vec<u8>, VaultError> {
-        match vaultrs::kv2::read::<File>(self.inner.as_ref(), &self.namespace, path.as_ref()).await
-        {
-            Err(vaultrs::error::ClientError::APIError { code, errors: _ }) if code == 404 => {
-                Err(VaultError::NotFound {
-                    namespace: self.namespace.clone(),
-                    path: path.as_ref().to_string(),
+    /// Returns the client for the mount a container is stored in: the additional mount with the
+    /// longest prefix of the container ID, or this client if none match
+    pub fn for_container(&self, container_id: &str) -> &Client {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| container_id.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, route)| route)
+            .unwrap_or(self)
+    }
+
+    /// Adds a limiter shared with other clients to this client and the clients of its additional
+    /// mounts, e.g. to bound requests across all links. It is acquired after the client's own
+    /// limiter so requests queued on a busy link don't hold shared slots
+    pub fn with_shared_limiter(self, limiter: Arc<Limiter>) -> Self {
+        self.with_routes(|client| client.limiters.push(limiter.clone()))
+    }
+
+    /// Bounds the object data written through this client, and the clients of its additional
+    /// mounts, by a budget shared with other clients
+    pub fn with_memory_budget(self, budget: Arc<MemoryBudget>) -> Self {
+        self.with_routes(|client| client.memory_budget = Some(budget.clone()))
+    }
+
+    /// Sets the actor the link is for, which is included in logs of slow requests
+    pub fn with_actor(self, actor_id: &str) -> Self {
+        let actor_id: Arc<str> = actor_id.into();
+        self.with_routes(|client| client.actor_id = Some(actor_id.clone()))
+    }
+
+    /// Applies `update` to this client and the clients of its additional mounts
+    fn with_routes(mut self, update: impl Fn(&mut Client)) -> Self {
+        self.routes = Arc::new(
+            self.routes
+                .iter()
+                .map(|(prefix, route)| {
+                    let mut route = route.clone();
+                    update(&mut route);
+                    (prefix.clone(), route)
                 })
-            }
-            Err(e) => Err(e.into()),
-            Ok(val) => Ok(val.data),
+                .collect(),
+        );
+        update(&mut self);
+        self
+    }
+
+    /// Returns false if none of the configured Vault servers could handle the last requests sent
+    /// to them
+    pub fn is_healthy(&self) -> bool {
+        self.nodes.any_healthy()
+    }
+
+    /// Returns true if all of the configured Vault servers reported being sealed
+    pub fn is_sealed(&self) -> bool {
+        self.nodes.all_sealed()
+    }
+
+    /// Returns this client and the clients of its additional mounts
+    pub fn mounts(&self) -> impl Iterator<Item = &Client> {
+        std::iter::once(self).chain(self.routes.iter().map(|(_, route)| route))
+    }
+
+    /// Returns the mount the client stores objects in
+    pub fn mount(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns whether the usage of containers is kept in their markers
+    pub fn usage_accounting(&self) -> bool {
+        self.usage_accounting
+    }
+
+    /// Returns whether object data is stored in content-addressed blobs
+    pub fn content_addressed(&self) -> bool {
+        self.content_addressed
+    }
+
+    /// Returns whether actors of the link may collect garbage of its mounts
+    pub fn allow_garbage_collection(&self) -> bool {
+        self.allow_garbage_collection
+    }
+
+    /// Returns whether actors of the link may export its containers
+    pub fn allow_export(&self) -> bool {
+        self.allow_export
+    }
+
+    /// Returns the bounds of listings of all objects of a container
+    pub fn list_limits(&self) -> ListLimits {
+        self.list_limits
+    }
+
+    /// Returns the number of objects of a batch that should be written at once
+    pub fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency
+    }
+
+    /// Returns the number of objects whose metadata should be fetched at once when listing
+    /// objects, or None if listings shouldn't include metadata
+    pub fn list_metadata(&self) -> Option<usize> {
+        self.list_metadata
+    }
+
+    /// Returns the minimum size of object data compressed for actors that accept it, or None if
+    /// data is never compressed
+    pub fn compress_min_bytes(&self) -> Option<usize> {
+        self.compress_min_bytes
+    }
+
+    /// Returns the size of the chunks object data is sent to actors in, or None if it is always
+    /// returned whole
+    pub fn download_chunk_bytes(&self) -> Option<usize> {
+        self.download_chunk_bytes
+    }
+
+    /// Returns the subject changes to objects should be published on, if any
+    pub fn events_subject(&self) -> Option<&str> {
+        self.events_subject.as_deref()
+    }
+
+    /// Returns true if changes to objects should be taken from Vault events rather than published
+    /// by the provider as it makes them
+    pub fn vault_events(&self) -> bool {
+        self.vault_events
+    }
+
+    /// Returns the address and current token of the server requests would be sent to first, for
+    /// connections vaultrs can't make such as event subscriptions
+    pub fn connection(&self) -> Option<(url::Url, String)> {
+        self.nodes.candidates().first().map(|node| {
+            let client = node.client();
+            (
+                client.settings.address.clone(),
+                client.settings.token.clone(),
+            )
+        })
+    }
+
+    /// Returns the headers added to every request to Vault
+    pub fn headers(&self) -> &reqwest::header::HeaderMap {
+        &self.headers
+    }
+
+    /// Reads value of secret using namespace and key path. Concurrent reads of the same path
+    /// share a single request to Vault
+    pub async fn read_file(&self, path: impl AsRef<str>) -> Result<File, VaultError> {
+        self.check_access(path.as_ref())?;
+        let client = self.clone();
+        let path = path.as_ref().to_string();
+        let file = self
+            .reads
+            .work(&path.clone(), async move { client.fetch_file(path).await })
+            .await?;
+        if let Some(throttle) = &self.read_throttle {
+            throttle.consume(file.data.len() as u64).await;
+        }
+        Ok(file)
+    }
+
+    async fn fetch_file(&self, path: impl AsRef<str>) -> Result<File, VaultError> {
+        self.await_written(path.as_ref()).await?;
+        let mut file: File = self.get_secret(path.as_ref()).await?;
+        // Files written in content-addressed mode only point to the blob holding their data
+        if let Some(hash) = file.blob.take() {
+            let blob: File = self.get_secret(&cas::blob_path(&hash)).await?;
+            file.data = blob.data;
+        }
+        if let Some(faults) = &self.faults {
+            faults.truncate(path.as_ref(), &mut file.data);
         }
+        Ok(file)
     }
 
     pub async fn read_with_metadata(
         &self,
         path: impl AsRef<str>,
-    ) -> Result<(ReadSecretMetadataResponse, Vec<u8>), VaultError> {
+    ) -> Result<(ReadSecretMetadataResponse, File), VaultError> {
         // We need to read both because the metadata doesn't contain the size of the secret
         let metadata = self.get_metadata(path.as_ref()).await?;
 
-        self.read_file(path).await.map(|data| (metadata, data))
+        self.read_file(path).await.map(|file| (metadata, file))
     }
 
+    /// Reads the metadata of a secret. Concurrent reads of the same path share a single request
+    /// to Vault
     pub async fn get_metadata(
         &self,
         path: impl AsRef<str>,
     ) -> Result<ReadSecretMetadataResponse, VaultError> {
-        match vaultrs::kv2::read_metadata(self.inner.as_ref(), &self.namespace, path.as_ref()).await
-        {
-            Err(vaultrs::error::ClientError::APIError { code, errors: _ }) if code == 404 => {
-                Err(VaultError::NotFound {
-                    namespace: self.namespace.clone(),
-                    path: path.as_ref().to_string(),
-                })
+        self.check_access(path.as_ref())?;
+        let client = self.clone();
+        let path = path.as_ref().to_string();
+        self.metadata
+            .work(
+                &path.clone(),
+                async move { client.fetch_metadata(path).await },
+            )
+            .await
+    }
+
+    /// Reads the metadata of many secrets, `concurrency` at a time. Results are in the order of
+    /// `paths`, each paired with its path so a failed read doesn't fail the others
+    pub async fn get_metadata_many(
+        &self,
+        paths: impl IntoIterator<Item = String>,
+        concurrency: usize,
+    ) -> Vec<(String, Result<ReadSecretMetadataResponse, VaultError>)> {
+        futures::stream::iter(paths)
+            .map(|path| async move {
+                let res = self.get_metadata(&path).await;
+                (path, res)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Returns whether a file exists. A secret whose current version was deleted doesn't count
+    /// as existing. The engine is asked without transferring any data, using the KV v2
+    /// `subkeys` endpoint, and the metadata of the secret is read instead on servers older than
+    /// Vault 1.10 that don't have it, or tokens that aren't allowed to use it
+    pub async fn exists(&self, path: impl AsRef<str>) -> Result<bool, VaultError> {
+        let path = path.as_ref();
+        self.check_access(path)?;
+        // A replica that may not have caught up with a write needs its metadata to be awaited
+        if self.existence_checks.load(Ordering::Relaxed) && self.written_version(path).is_none() {
+            let key = &object_path::encode(path);
+            let backend = self.engine().await;
+            match self
+                .traced(
+                    "GET",
+                    &backend.exists_path(&self.namespace, key),
+                    |c| async move { backend.exists(&c, &self.namespace, key).await },
+                )
+                .await
+            {
+                // Policies written before the endpoint existed may not allow it for this token
+                Err(VaultError::Client {
+                    source: ClientError::APIError { code: 403, .. },
+                    ..
+                }) => {}
+                Err(VaultError::Client {
+                    source:
+                        ClientError::APIError {
+                            code: 404 | 405, ..
+                        },
+                    ..
+                }) => {
+                    debug!(mount = %self.namespace, "Vault has no subkeys endpoint, reading metadata to check existence");
+                    self.existence_checks.store(false, Ordering::Relaxed);
+                }
+                res => return res,
+            }
+        }
+        match self.get_metadata(path).await {
+            Ok(metadata) => Ok(live_version(&metadata).is_some()),
+            Err(VaultError::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn fetch_metadata(
+        &self,
+        path: impl AsRef<str>,
+    ) -> Result<ReadSecretMetadataResponse, VaultError> {
+        match self.await_written(path.as_ref()).await? {
+            Some(metadata) => Ok(metadata),
+            None => self.read_metadata(path.as_ref()).await,
+        }
+    }
+
+    /// Waits until the servers reads are sent to serve at least the version of a secret written
+    /// through this client within the read-after-write window, polling its metadata. Returns
+    /// the metadata read last, or None if there was nothing to wait for. Gives up once the
+    /// window passed, so reads don't hang on a replica that stopped replicating
+    async fn await_written(
+        &self,
+        path: &str,
+    ) -> Result<Option<ReadSecretMetadataResponse>, VaultError> {
+        let Some(version) = self.written_version(path) else {
+            return Ok(None);
+        };
+        let deadline = deadline::cap(Instant::now() + self.read_after_write);
+        let mut backoff = READ_YOUR_WRITES_BACKOFF;
+        loop {
+            let metadata = match self.read_metadata(path).await {
+                Ok(metadata) => metadata,
+                // The replica doesn't have the secret at all yet
+                Err(VaultError::NotFound { .. }) if Instant::now() + backoff < deadline => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if metadata.current_version >= version {
+                return Ok(Some(metadata));
+            }
+            if Instant::now() + backoff >= deadline {
+                debug!(
+                    %path,
+                    version,
+                    served = metadata.current_version,
+                    "Replica didn't catch up with write in time, reading stale version"
+                );
+                return Ok(Some(metadata));
             }
-            Err(e) => Err(e.into()),
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    async fn read_metadata(&self, path: &str) -> Result<ReadSecretMetadataResponse, VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        match self
+            .traced(
+                "GET",
+                &backend.metadata_path(&self.namespace, key),
+                |c| async move { backend.read_metadata(&c, &self.namespace, key).await },
+            )
+            .await
+        {
+            Err(VaultError::Client {
+                source: ClientError::APIError { code: 404, .. },
+                ..
+            }) => Err(VaultError::NotFound {
+                namespace: self.namespace.clone(),
+                path: path.to_string(),
+            }),
+            Err(e) => Err(e),
             Ok(val) => Ok(val),
         }
     }
 
-    /// Writes value of secret using namespace and key path
+    /// Writes value of secret using namespace and key path. In content-addressed mode the data
+    /// is stored in a blob shared by all files with the same content, and the secret at the path
+    /// only points to it
     pub async fn write_file(
         &self,
         path: impl AsRef<str>,
-        data: Vec<u8>,
+        file: impl Into<File>,
     ) -> Result<SecretVersionMetadata, VaultError> {
-        vaultrs::kv2::set(
-            self.inner.as_ref(),
-            &self.namespace,
-            path.as_ref(),
-            &File { data },
-        )
-        .await
-        .map_err(VaultError::from)
+        self.write_file_with_cas(path.as_ref(), file.into(), None)
+            .await
+    }
+
+    /// Writes a file only if the current version of its secret is `version`, or if it doesn't
+    /// exist yet when `version` is 0. Fails with [`VaultError::VersionMismatch`] otherwise
+    pub async fn write_file_if_version(
+        &self,
+        path: impl AsRef<str>,
+        file: impl Into<File>,
+        version: u64,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        self.write_file_with_cas(path.as_ref(), file.into(), Some(version))
+            .await
+    }
+
+    /// Writes a file like [`Client::write_file`], or only if its current version is `version`
+    /// like [`Client::write_file_if_version`], unless the same file was already written with
+    /// `idempotency_key` within the idempotency window. The result of that write is returned
+    /// instead, so retries don't write another version
+    pub async fn write_file_once(
+        &self,
+        path: impl AsRef<str>,
+        file: impl Into<File>,
+        version: Option<u64>,
+        idempotency_key: &str,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let (path, file) = (path.as_ref().to_string(), file.into());
+        let Some(writes) = &self.idempotent_writes else {
+            return self.write_file_with_cas(&path, file, version).await;
+        };
+        let fingerprint = format!(
+            "{path}\n{}\n{:?}\n{:?}\n{version:?}",
+            cas::hash(&file.data),
+            file.content_type,
+            file.content_encoding
+        );
+        let client = self.clone();
+        writes
+            .write(idempotency_key, fingerprint, async move {
+                client.write_file_with_cas(&path, file, version).await
+            })
+            .await
+    }
+
+    async fn write_file_with_cas(
+        &self,
+        path: &str,
+        file: File,
+        cas: Option<u64>,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        self.check_access(path)?;
+        let size = request_size(&file);
+        if self.max_request_bytes > 0 && size > self.max_request_bytes {
+            return Err(VaultError::TooLarge {
+                path: path.to_string(),
+                size,
+                max: self.max_request_bytes,
+            });
+        }
+        if self.skip_unchanged_writes {
+            if let Some(current) = self.unchanged_version(path, &file, cas).await? {
+                debug!(
+                    path,
+                    version = current.version,
+                    "Skipping write of unchanged file"
+                );
+                return Ok(current);
+            }
+        }
+        let _reservation = match &self.memory_budget {
+            Some(budget) => Some(budget.reserve(file.data.len()).await),
+            None => None,
+        };
+        if let Some(throttle) = &self.write_throttle {
+            throttle.consume(file.data.len() as u64).await;
+        }
+        if !self.content_addressed {
+            let res = self.put_secret_with_cas(path, &file, cas).await;
+            self.limit_versions(path, &res).await;
+            return res;
+        }
+        let _guard = self.cas_lock.lock().await;
+        let previous = self.blob_of(path).await?;
+        let File {
+            data,
+            content_type,
+            content_encoding,
+            ..
+        } = file;
+        let hash = cas::hash(&data);
+        self.retain_blob(&hash, Some(data)).await?;
+        let pointer = File {
+            data: Bytes::new(),
+            content_type,
+            content_encoding,
+            blob: Some(hash.clone()),
+        };
+        let res = self.put_secret_with_cas(path, &pointer, cas).await;
+        // Drop the reference that is no longer held: the replaced blob if the pointer was
+        // written, or the new one if it wasn't
+        self.release_blob(if res.is_ok() { previous } else { Some(hash) })
+            .await;
+        self.limit_versions(path, &res).await;
+        res
+    }
+
+    /// Sets the maximum number of versions kept of a file that was written, if its container has
+    /// one configured. It is set when the secret is created, and again every `max_versions`
+    /// writes so secrets created before the limit was configured get it too. Failures are only
+    /// logged, as the write itself succeeded
+    async fn limit_versions(
+        &self,
+        path: &str,
+        written: &Result<SecretVersionMetadata, VaultError>,
+    ) {
+        let Ok(written) = written else {
+            return;
+        };
+        let container = path.split('/').next().unwrap_or_default();
+        let Some(&max_versions) = self.container_max_versions.get(container) else {
+            return;
+        };
+        if written.version != 1 && written.version % max_versions.max(1) != 0 {
+            return;
+        }
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        let res = self
+            .traced(
+                "POST",
+                &backend.metadata_path(&self.namespace, key),
+                |c| async move {
+                    backend
+                        .set_max_versions(&c, &self.namespace, key, max_versions)
+                        .await
+                },
+            )
+            .await;
+        if let Err(e) = res {
+            warn!(path, max_versions, error = %e, "Failed to limit versions of file");
+        }
+    }
+
+    /// Returns the metadata of the current version of a file if it already has the content of
+    /// `file`, and matches `cas` if given, so writing it again can be skipped
+    async fn unchanged_version(
+        &self,
+        path: &str,
+        file: &File,
+        cas: Option<u64>,
+    ) -> Result<Option<SecretVersionMetadata>, VaultError> {
+        let metadata = match self.fetch_metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(VaultError::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let Some(version) = live_version(&metadata).filter(|v| cas.unwrap_or(*v) == *v) else {
+            return Ok(None);
+        };
+        let current: File = match self.get_secret_version(path, version).await {
+            Err(VaultError::NotFound { .. }) => return Ok(None),
+            res => res?,
+        };
+        let same_data = match &current.blob {
+            Some(hash) => *hash == cas::hash(&file.data),
+            None => current.data == file.data,
+        };
+        let unchanged = same_data
+            && current.content_type == file.content_type
+            && current.content_encoding == file.content_encoding;
+        Ok(unchanged
+            .then(|| metadata.versions.get(&version.to_string()).cloned())
+            .flatten())
+    }
+
+    /// Replaces the user metadata of a file, stored in the custom metadata of its secret
+    pub async fn set_custom_metadata(
+        &self,
+        path: impl AsRef<str>,
+        metadata: HashMap<String, String>,
+    ) -> Result<(), VaultError> {
+        let path = path.as_ref();
+        self.check_access(path)?;
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        let metadata = &metadata;
+        let res = self
+            .traced(
+                "POST",
+                &backend.metadata_path(&self.namespace, key),
+                |c| async move {
+                    backend
+                        .set_custom_metadata(&c, &self.namespace, key, metadata)
+                        .await
+                },
+            )
+            .await;
+        res
+    }
+
+    /// Copies a file along with its user metadata. In content-addressed mode only a new pointer
+    /// to the same blob is written
+    pub async fn copy_file(
+        &self,
+        src: impl AsRef<str>,
+        dest: impl AsRef<str>,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let (src, dest) = (src.as_ref(), dest.as_ref());
+        self.check_access(src)?;
+        self.check_access(dest)?;
+        let metadata = self.get_metadata(src).await?;
+        let written = self.copy_data(src, dest).await?;
+        self.copy_custom_metadata(metadata, dest).await?;
+        Ok(written)
+    }
+
+    /// Copies the data of a file, leaving the user metadata of `dest` as it was
+    async fn copy_data(&self, src: &str, dest: &str) -> Result<SecretVersionMetadata, VaultError> {
+        if !self.content_addressed {
+            let file = self.read_file(src).await?;
+            return self.write_file(dest, file).await;
+        }
+        let guard = self.cas_lock.lock().await;
+        let file: File = self.get_secret(src).await?;
+        if file.blob.is_none() {
+            // Written before content-addressed mode was enabled, so store its data as a blob
+            drop(guard);
+            return self.write_file(dest, file).await;
+        }
+        self.put_pointer(dest, &file).await
+    }
+
+    /// Copies a file to a path of another client, such as the client of another mount. Across
+    /// mounts the data is read and written again through `dest`, so its limits and
+    /// content-addressed mode apply, and the user metadata of the file is copied along. Within
+    /// the same mount this is [`Client::copy_file`]
+    pub async fn copy_file_to(
+        &self,
+        src: impl AsRef<str>,
+        dest: &Client,
+        dest_path: impl AsRef<str>,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let dest_path = dest_path.as_ref();
+        if self.namespace == dest.namespace {
+            return dest.copy_file(src, dest_path).await;
+        }
+        dest.check_access(dest_path)?;
+        let (metadata, file) = self.read_with_metadata(src).await?;
+        let written = dest.write_file(dest_path, file).await?;
+        dest.copy_custom_metadata(metadata, dest_path).await?;
+        Ok(written)
+    }
+
+    /// Gives a copied file the user metadata of its source, if it has any
+    async fn copy_custom_metadata(
+        &self,
+        metadata: ReadSecretMetadataResponse,
+        path: &str,
+    ) -> Result<(), VaultError> {
+        match metadata.custom_metadata.filter(|m| !m.is_empty()) {
+            Some(custom) => self.set_custom_metadata(path, custom).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Rewrites an older version of a file as its latest version. If that version points to a
+    /// content-addressed blob that was removed since, this fails with `NotFound`
+    pub async fn restore_file(
+        &self,
+        path: impl AsRef<str>,
+        version: u64,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let path = path.as_ref();
+        self.check_access(path)?;
+        let guard = self.cas_lock.lock().await;
+        let file: File = self.get_secret_version(path, version).await?;
+        if file.blob.is_none() {
+            drop(guard);
+            return self.write_file(path, file).await;
+        }
+        self.put_pointer(path, &file).await
+    }
+
+    /// Writes a file pointing to a blob that already exists, moving the file's reference from
+    /// the blob it pointed to before. Must be called with `cas_lock` held
+    async fn put_pointer(
+        &self,
+        path: &str,
+        file: &File,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let Some(hash) = file.blob.clone() else {
+            return self.put_secret(path, file).await;
+        };
+        let previous = self.blob_of(path).await?;
+        self.retain_blob(&hash, None).await?;
+        let res = self.put_secret(path, file).await;
+        self.release_blob(if res.is_ok() { previous } else { Some(hash) })
+            .await;
+        res
     }
 
     /// Deletes the latest version of the secret. Note that if versions are in use, only the latest is deleted
     /// Returns Ok if the key was deleted, or Err for any other error including key not found
     pub async fn delete_file(&self, path: impl AsRef<str>) -> Result<(), VaultError> {
-        vaultrs::kv2::delete_latest(self.inner.as_ref(), &self.namespace, path.as_ref())
+        self.remove_file(path.as_ref(), false).await
+    }
+
+    /// Permanently deletes all versions and the metadata of the secret
+    pub async fn purge_file(&self, path: impl AsRef<str>) -> Result<(), VaultError> {
+        self.remove_file(path.as_ref(), true).await
+    }
+
+    /// Deletes or purges a file, dropping its reference to the blob holding its data in
+    /// content-addressed mode
+    async fn remove_file(&self, path: &str, purge: bool) -> Result<(), VaultError> {
+        self.check_access(path)?;
+        let (_guard, previous) = if self.content_addressed {
+            (Some(self.cas_lock.lock().await), self.blob_of(path).await?)
+        } else {
+            (None, None)
+        };
+        if purge {
+            self.purge_secret(path).await?;
+        } else {
+            self.delete_secret(path).await?;
+        }
+        self.release_blob(previous).await;
+        Ok(())
+    }
+
+    /// Removes content-addressed blobs without a reference count, and reference counts without a
+    /// blob. These are left behind when writing or releasing a blob fails halfway. Of several
+    /// providers sharing the mount only one collects at a time, holding a lock in Vault, and the
+    /// others return without removing anything. Returns the paths of the removed secrets
+    pub async fn collect_garbage(&self) -> Result<Vec<String>, VaultError> {
+        if !self.content_addressed {
+            return Err(VaultError::NotEnabled {
+                operation: "Garbage collection",
+                setting: "content_addressed",
+            });
+        }
+        let lock_path = cas::gc_lock_path();
+        let owner = format!("gc-{:016x}", rand::random::<u64>());
+        let token = match self.take_lock(&lock_path, &owner, cas::GC_LOCK_TTL).await? {
+            LockOutcome::Acquired { token, .. } => token,
+            LockOutcome::Held(lock) => {
+                info!(mount = %self.namespace, owner = %lock.owner, "Garbage collection is already running");
+                return Ok(Vec::new());
+            }
+        };
+        let res = self.remove_orphans().await;
+        if let Err(e) = self
+            .put_secret_with_cas(&lock_path, &Lock::default(), Some(token))
             .await
-            .map_err(VaultError::from)
+        {
+            warn!(mount = %self.namespace, error = %e, "Failed to release garbage collection lock");
+        }
+        res
     }
 
-    /// Lists keys at the path
-    pub async fn list_files(&self, path: impl AsRef<str>) -> Result<Vec<String>, VaultError> {
-        match vaultrs::kv2::list(self.inner.as_ref(), &self.namespace, path.as_ref()).await {
-            Err(vaultrs::error::ClientError::APIError { code, errors: _ }) if code == 404 => {
-                Err(VaultError::NotFound {
+    /// Removes orphaned blobs and reference counts. Must be called with the lock of garbage
+    /// collection held
+    async fn remove_orphans(&self) -> Result<Vec<String>, VaultError> {
+        let _guard = self.cas_lock.lock().await;
+        let mut listings = Vec::with_capacity(2);
+        for folder in [cas::blob_path(""), cas::refs_path("")] {
+            listings.push(match self.list_keys(&folder).await {
+                Ok(hashes) => hashes.into_iter().collect::<HashSet<_>>(),
+                Err(VaultError::NotFound { .. }) => HashSet::new(),
+                Err(e) => return Err(e),
+            });
+        }
+        let (blobs, refs) = (&listings[0], &listings[1]);
+        let mut removed = Vec::new();
+        for hash in blobs.difference(refs) {
+            deadline::check()?;
+            // Writers create the blob before its reference count
+            let path = cas::blob_path(hash);
+            let metadata = match self.fetch_metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(VaultError::NotFound { .. }) => continue,
+                Err(e) => return Err(e),
+            };
+            let settled = Timestamp::parse_rfc3339(&metadata.updated_time).is_some_and(|updated| {
+                Timestamp::now().sec.saturating_sub(updated.sec) >= cas::DELETION_TIMEOUT.as_secs()
+            });
+            if !settled {
+                continue;
+            }
+            self.purge_secret(&path).await?;
+            removed.push(path);
+        }
+        for hash in refs.difference(blobs) {
+            deadline::check()?;
+            if self.read_refs(hash).await?.1.is_deleting() {
+                continue;
+            }
+            let path = cas::refs_path(hash);
+            self.purge_secret(&path).await?;
+            removed.push(path);
+        }
+        Ok(removed)
+    }
+
+    /// Takes the lock of a file for `owner` for `ttl`, unless it is held, including by `owner`
+    /// itself. See [`lock`] for how locks are stored
+    pub async fn acquire_lock(
+        &self,
+        path: impl AsRef<str>,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<LockOutcome, VaultError> {
+        let path = path.as_ref();
+        self.check_access(path)?;
+        self.take_lock(&lock::lock_path(path), owner, ttl).await
+    }
+
+    /// Takes the lock at `lock_path` for `owner` for `ttl`, unless it is held
+    async fn take_lock(
+        &self,
+        lock_path: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<LockOutcome, VaultError> {
+        let version = match self.fetch_metadata(lock_path).await {
+            Ok(metadata) => metadata.current_version,
+            Err(VaultError::NotFound { .. }) => 0,
+            Err(e) => return Err(e),
+        };
+        if version > 0 {
+            let current = match self.get_secret_version::<Lock>(lock_path, version).await {
+                Err(VaultError::NotFound { .. }) => Lock::default(),
+                res => res?,
+            };
+            if current.is_held() {
+                return Ok(LockOutcome::Held(current));
+            }
+        }
+        let lock = Lock::new(owner, ttl);
+        match self
+            .put_secret_with_cas(lock_path, &lock, Some(version))
+            .await
+        {
+            Ok(written) => Ok(LockOutcome::Acquired {
+                token: written.version,
+                expires_at: lock.expires_at,
+            }),
+            // Another owner took the lock after it was read
+            Err(VaultError::VersionMismatch { .. }) => {
+                Ok(LockOutcome::Held(self.get_secret(lock_path).await?))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Releases the lock of a file taken with `token`. Fails with
+    /// [`VaultError::VersionMismatch`] if it was released already, or taken by another owner
+    /// after it expired
+    pub async fn release_lock(&self, path: impl AsRef<str>, token: u64) -> Result<(), VaultError> {
+        let path = path.as_ref();
+        self.check_access(path)?;
+        // The released lock is written rather than deleted, so tokens keep increasing
+        self.put_secret_with_cas(&lock::lock_path(path), &Lock::default(), Some(token))
+            .await
+            .map(|_| ())
+    }
+
+    /// Writes the manifest of a container's snapshot, failing with
+    /// [`VaultError::VersionMismatch`] if a snapshot with that name exists. See [`snapshot`] for
+    /// how snapshots are stored
+    pub async fn write_snapshot(
+        &self,
+        container: &str,
+        name: &str,
+        manifest: &Manifest,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        if !self.access.permits_folder(container) {
+            return Err(VaultError::Denied {
+                path: container.to_string(),
+            });
+        }
+        self.put_secret_with_cas(&snapshot::snapshot_path(container, name), manifest, Some(0))
+            .await
+    }
+
+    /// Reads the manifest of a container's snapshot
+    pub async fn read_snapshot(&self, container: &str, name: &str) -> Result<Manifest, VaultError> {
+        if !self.access.permits_folder(container) {
+            return Err(VaultError::Denied {
+                path: container.to_string(),
+            });
+        }
+        self.get_secret(&snapshot::snapshot_path(container, name))
+            .await
+    }
+
+    /// Writes a chunk of a multipart upload, then the manifest of the upload including it. See
+    /// [`upload`] for how uploads are stored
+    pub async fn write_upload_chunk(
+        &self,
+        stream_id: &str,
+        manifest: &upload::Manifest,
+        index: usize,
+        data: Bytes,
+    ) -> Result<(), VaultError> {
+        if !self.access.permits_folder(&manifest.container_id) {
+            return Err(VaultError::Denied {
+                path: manifest.container_id.clone(),
+            });
+        }
+        let chunk = File {
+            data,
+            ..Default::default()
+        };
+        self.put_secret(&upload::chunk_path(stream_id, index), &chunk)
+            .await?;
+        self.put_secret(&upload::manifest_path(stream_id), manifest)
+            .await
+            .map(drop)
+    }
+
+    /// Reads the manifest of a multipart upload
+    pub async fn read_upload_manifest(
+        &self,
+        stream_id: &str,
+    ) -> Result<upload::Manifest, VaultError> {
+        let manifest: upload::Manifest = self.get_secret(&upload::manifest_path(stream_id)).await?;
+        if !self.access.permits_folder(&manifest.container_id) {
+            return Err(VaultError::Denied {
+                path: manifest.container_id,
+            });
+        }
+        Ok(manifest)
+    }
+
+    /// Reads the session of a multipart upload back from its manifest and chunks. Fails with
+    /// [`VaultError::Corrupted`] if a chunk doesn't match the checksum in the manifest
+    pub async fn read_upload(&self, stream_id: &str) -> Result<UploadSession, VaultError> {
+        let manifest = self.read_upload_manifest(stream_id).await?;
+        let mut buffer = BytesMut::with_capacity(manifest.received as usize);
+        for (index, chunk) in manifest.chunks.iter().enumerate() {
+            let path = upload::chunk_path(stream_id, index);
+            let file: File = self.get_secret(&path).await?;
+            if chunk.offset != buffer.len() as u64 || chunk.sha256 != cas::hash(&file.data) {
+                return Err(VaultError::Corrupted { path });
+            }
+            buffer.extend_from_slice(&file.data);
+        }
+        Ok(UploadSession::resume(manifest, buffer))
+    }
+
+    /// Permanently deletes the manifest and the first `chunks` chunks of a multipart upload
+    pub async fn purge_upload(&self, stream_id: &str, chunks: usize) -> Result<(), VaultError> {
+        for index in 0..chunks {
+            self.purge_secret(&upload::chunk_path(stream_id, index))
+                .await?;
+        }
+        self.purge_secret(&upload::manifest_path(stream_id)).await
+    }
+
+    /// Reads the usage of a container from its marker. Returns None if nothing was counted for
+    /// the container yet
+    pub async fn read_usage(&self, container: &str) -> Result<Option<Usage>, VaultError> {
+        if !self.access.permits_folder(container) {
+            return Err(VaultError::Denied {
+                path: container.to_string(),
+            });
+        }
+        match self.get_secret(&usage::container_path(container)).await {
+            Ok(usage) => Ok(Some(usage)),
+            Err(VaultError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Counts the change of an object of a container from `before` to `after` bytes in the
+    /// container's marker, creating it if needed
+    pub async fn update_usage(
+        &self,
+        container: &str,
+        before: Option<u64>,
+        after: Option<u64>,
+    ) -> Result<(), VaultError> {
+        let path = usage::container_path(container);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let version = match self.fetch_metadata(&path).await {
+                Ok(metadata) => metadata.current_version,
+                Err(VaultError::NotFound { .. }) => 0,
+                Err(e) => return Err(e),
+            };
+            let mut current = if version > 0 {
+                self.get_secret_version(&path, version).await?
+            } else {
+                Usage::new()
+            };
+            current.apply(before, after);
+            match self
+                .put_secret_with_cas(&path, &current, Some(version))
+                .await
+            {
+                // Another change updated the marker after it was read
+                Err(VaultError::VersionMismatch { .. }) if attempts < usage::UPDATE_ATTEMPTS => {
+                    continue
+                }
+                res => return res.map(|_| ()),
+            }
+        }
+    }
+
+    /// Fails if the link's path rules don't allow accessing the path, or it is in one of the
+    /// folders this client keeps its own secrets in
+    fn check_access(&self, path: &str) -> Result<(), VaultError> {
+        if self.access.permits(path) && !self.is_reserved(path) {
+            Ok(())
+        } else {
+            Err(VaultError::Denied {
+                path: path.to_string(),
+            })
+        }
+    }
+
+    /// Returns whether the path is in a folder at the top of the mount holding blobs, locks,
+    /// snapshots, uploads or container markers, or in the audit path, which aren't objects
+    fn is_reserved(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        let folder = path.split('/').next();
+        folder == Some(lock::LOCK_PREFIX)
+            || folder == Some(snapshot::SNAPSHOT_PREFIX)
+            || folder == Some(upload::UPLOAD_PREFIX)
+            || folder == Some(usage::CONTAINER_PREFIX)
+            || self.content_addressed && folder == Some(cas::CAS_PREFIX)
+            || self.audit_path().is_some_and(|audit| {
+                path.strip_prefix(audit)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+
+    /// Returns the path audit events are written under in this client's mount, if any
+    fn audit_path(&self) -> Option<&str> {
+        match &self.audit {
+            Some(AuditSink::Vault(path)) if self.audit_mount == self.namespace => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the hash of the blob the file at the path points to, if any
+    async fn blob_of(&self, path: &str) -> Result<Option<String>, VaultError> {
+        match self.get_secret::<File>(path).await {
+            Ok(file) => Ok(file.blob),
+            Err(VaultError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the reference count of a blob with the version it was read at, which is 0 if the
+    /// blob has none
+    async fn read_refs(&self, hash: &str) -> Result<(u64, RefCount), VaultError> {
+        let refs_path = cas::refs_path(hash);
+        let version = match self.fetch_metadata(&refs_path).await {
+            Ok(metadata) => metadata.current_version,
+            Err(VaultError::NotFound { .. }) => return Ok((0, RefCount::default())),
+            Err(e) => return Err(e),
+        };
+        match self.get_secret_version(&refs_path, version).await {
+            Ok(refs) => Ok((version, refs)),
+            Err(VaultError::NotFound { .. }) => Ok((version, RefCount::default())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds a reference to a blob. If the blob doesn't exist yet, it is created with `data`
+    async fn retain_blob(&self, hash: &str, data: Option<Bytes>) -> Result<(), VaultError> {
+        let refs_path = cas::refs_path(hash);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (version, refs) = self.read_refs(hash).await?;
+            if refs.is_deleting() {
+                // Another writer released the last reference, wait for it to delete the blob
+                if attempts >= usage::UPDATE_ATTEMPTS {
+                    return Err(VaultError::VersionMismatch { path: refs_path });
+                }
+                tokio::time::sleep(cas::DELETION_WAIT).await;
+                continue;
+            }
+            if refs.count == 0 {
+                let data = data.clone().ok_or_else(|| VaultError::NotFound {
                     namespace: self.namespace.clone(),
-                    path: path.as_ref().to_string(),
+                    path: cas::blob_path(hash),
+                })?;
+                self.put_secret(&cas::blob_path(hash), &File::from(data))
+                    .await?;
+            }
+            let retained = RefCount {
+                count: refs.count + 1,
+                deleting_since: None,
+            };
+            match self
+                .put_secret_with_cas(&refs_path, &retained, Some(version))
+                .await
+            {
+                // Another writer updated the count after it was read
+                Err(VaultError::VersionMismatch { .. }) if attempts < usage::UPDATE_ATTEMPTS => {
+                    continue
+                }
+                res => return res.map(|_| ()),
+            }
+        }
+    }
+
+    /// Drops a reference to a blob, deleting it once no file points to it anymore. Failures leave
+    /// the blob behind and are only logged, as the operation that released it already succeeded
+    async fn release_blob(&self, hash: Option<String>) {
+        let Some(hash) = hash else {
+            return;
+        };
+        if let Err(e) = self.try_release_blob(&hash).await {
+            warn!(%hash, error = %e, "Failed to release content-addressed blob");
+        }
+    }
+
+    async fn try_release_blob(&self, hash: &str) -> Result<(), VaultError> {
+        let refs_path = cas::refs_path(hash);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (version, refs) = self.read_refs(hash).await?;
+            if version == 0 || refs.deleting_since.is_some() {
+                // Nothing to release. A blob left without a count is removed by garbage collection
+                return Ok(());
+            }
+            let released = if refs.count > 1 {
+                RefCount {
+                    count: refs.count - 1,
+                    deleting_since: None,
+                }
+            } else {
+                RefCount::deleting()
+            };
+            let written = match self
+                .put_secret_with_cas(&refs_path, &released, Some(version))
+                .await
+            {
+                Err(VaultError::VersionMismatch { .. }) if attempts < usage::UPDATE_ATTEMPTS => {
+                    continue
+                }
+                res => res?,
+            };
+            if released.count > 0 {
+                return Ok(());
+            }
+            self.purge_secret(&cas::blob_path(hash)).await?;
+            return self
+                .put_secret_with_cas(&refs_path, &RefCount::default(), Some(written.version))
+                .await
+                .map(|_| ());
+        }
+    }
+
+    /// Reads a secret at the given path
+    async fn get_secret<D: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+    ) -> Result<D, VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        match self
+            .traced(
+                "GET",
+                &backend.data_path(&self.namespace, key),
+                |c| async move { backend.read::<D>(&c, &self.namespace, key).await },
+            )
+            .await
+        {
+            Err(VaultError::Client {
+                source: ClientError::APIError { code: 404, .. },
+                ..
+            }) => Err(VaultError::NotFound {
+                namespace: self.namespace.clone(),
+                path: path.to_string(),
+            }),
+            Err(e) => Err(e),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    /// Reads a specific version of a secret
+    async fn get_secret_version<D: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+        version: u64,
+    ) -> Result<D, VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        match self
+            .traced(
+                "GET",
+                &backend.data_path(&self.namespace, key),
+                |c| async move {
+                    backend
+                        .read_version::<D>(&c, &self.namespace, key, version)
+                        .await
+                },
+            )
+            .await
+        {
+            Err(VaultError::Client {
+                source: ClientError::APIError { code: 404, .. },
+                ..
+            }) => Err(VaultError::NotFound {
+                namespace: self.namespace.clone(),
+                path: path.to_string(),
+            }),
+            res => res,
+        }
+    }
+
+    /// Writes a secret at the given path
+    async fn put_secret<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        path: &str,
+        value: &T,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        self.put_secret_with_cas(path, value, None).await
+    }
+
+    /// Writes a secret, if given only when its current version is `cas`, using Vault's
+    /// check-and-set
+    async fn put_secret_with_cas<T: Serialize + Clone + Send + Sync + 'static>(
+        &self,
+        path: &str,
+        value: &T,
+        cas: Option<u64>,
+    ) -> Result<SecretVersionMetadata, VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        let res = self
+            .traced(
+                "POST",
+                &backend.data_path(&self.namespace, key),
+                |c| async move { backend.write(&c, &self.namespace, key, value, cas).await },
+            )
+            .await;
+        if let (Some(written), Ok(metadata)) = (&self.written, &res) {
+            let mut written = written.lock().unwrap();
+            written.retain(|_, (_, at)| at.elapsed() < self.read_after_write);
+            written.insert(path.to_string(), (metadata.version, Instant::now()));
+        }
+        match res {
+            Err(VaultError::Client {
+                source: ClientError::APIError { code: 400, errors },
+                ..
+            }) if errors.iter().any(|e| e.contains("check-and-set")) => {
+                Err(VaultError::VersionMismatch {
+                    path: path.to_string(),
                 })
             }
-            Err(e) => Err(e.into()),
-            Ok(secret_list) => Ok(secret_list),
+            res => res,
+        }
+    }
+
+    /// Deletes the latest version of a secret
+    async fn delete_secret(&self, path: &str) -> Result<(), VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        let res = self
+            .traced(
+                "DELETE",
+                &backend.data_path(&self.namespace, key),
+                |c| async move { backend.delete(&c, &self.namespace, key).await },
+            )
+            .await;
+        res
+    }
+
+    /// Permanently deletes all versions and the metadata of a secret
+    async fn purge_secret(&self, path: &str) -> Result<(), VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        let res = self
+            .traced(
+                "DELETE",
+                &backend.metadata_path(&self.namespace, key),
+                |c| async move { backend.purge(&c, &self.namespace, key).await },
+            )
+            .await;
+        res
+    }
+
+    /// Lists keys at the path, leaving out those the link may not access
+    pub async fn list_files(&self, path: impl AsRef<str>) -> Result<Vec<String>, VaultError> {
+        let path = path.as_ref();
+        if !self.access.permits_folder(path) || self.is_reserved(path) {
+            return Err(VaultError::Denied {
+                path: path.to_string(),
+            });
+        }
+        let folder = match path.trim_end_matches('/') {
+            "" => String::new(),
+            folder => format!("{folder}/"),
+        };
+        Ok(self
+            .list_keys(path)
+            .await?
+            .into_iter()
+            .filter(|key| {
+                let path = format!("{folder}{key}");
+                match key.strip_suffix('/') {
+                    Some(_) => self.access.permits_folder(&path),
+                    None => self.access.permits(&path),
+                }
+            })
+            .collect())
+    }
+
+    /// Lists the containers stored in this client's mount, which are the folders at its top.
+    /// Containers the link's path rules don't permit listing are left out
+    pub async fn list_containers(&self) -> Result<Vec<String>, VaultError> {
+        let keys = match self.list_keys("").await {
+            Ok(keys) => keys,
+            Err(VaultError::NotFound { .. }) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| key.strip_suffix('/').map(ToString::to_string))
+            .filter(|container| self.access.permits_folder(container))
+            .collect())
+    }
+
+    /// Lists keys at the path, regardless of the link's path rules
+    async fn list_keys(&self, path: &str) -> Result<Vec<String>, VaultError> {
+        let key = &object_path::encode(path);
+        let backend = self.engine().await;
+        match self
+            .traced(
+                "LIST",
+                &backend.metadata_path(&self.namespace, key),
+                |c| async move { backend.list(&c, &self.namespace, key).await },
+            )
+            .await
+        {
+            Err(VaultError::Client {
+                source: ClientError::APIError { code: 404, .. },
+                ..
+            }) => Err(VaultError::NotFound {
+                namespace: self.namespace.clone(),
+                path: path.to_string(),
+            }),
+            Err(e) => Err(e),
+            Ok(secret_list) => {
+                // Reserved folders are hidden from listings of objects, but not from listings of
+                // the reserved folders themselves
+                let reserved = self.is_reserved(path);
+                let folder = match path.trim_end_matches('/') {
+                    "" => String::new(),
+                    folder => format!("{folder}/"),
+                };
+                Ok(secret_list
+                    .iter()
+                    .map(|key| object_path::decode(key))
+                    .filter(|key| {
+                        reserved
+                            || !key
+                                .strip_suffix('/')
+                                .is_some_and(|key| self.is_reserved(&format!("{folder}{key}")))
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Opens a connection to each configured server by looking up the token with it, logging in
+    /// first if the link has no token yet. Servers that can't be reached are marked unhealthy.
+    /// Failures are logged rather than returned, as requests can still succeed later
+    pub async fn prewarm(&self) {
+        if let Some(auth) = &self.auth {
+            let has_token = self
+                .nodes
+                .candidates()
+                .first()
+                .is_some_and(|node| !node.client().settings.token.is_empty());
+            if !has_token {
+                if let Err(e) = self.reauthenticate(auth, Instant::now()).await {
+                    warn!(error = %e, "Failed to log in to Vault while prewarming connections");
+                }
+            }
+        }
+        let nodes = self
+            .nodes
+            .candidates()
+            .into_iter()
+            .chain(self.read_nodes.iter().flat_map(|nodes| nodes.candidates()));
+        futures::future::join_all(nodes.map(|node| async move {
+            match lookup_token(&node.connection()).await {
+                Ok(_) => node.mark_healthy(),
+                Err(e) => {
+                    if failover::should_failover(&e) {
+                        node.mark_unhealthy(&e);
+                    }
+                    warn!(addr = %node.addr, error = %e, "Failed to prewarm connection to Vault");
+                }
+            }
+        }))
+        .await;
+    }
+
+    /// Checks that at least one Vault server can be reached and isn't sealed. Any response to a
+    /// token lookup counts, so an invalid token doesn't fail the probe
+    pub async fn probe(&self) -> Result<(), VaultError> {
+        match self
+            .traced(
+                "GET",
+                &format!("v{API_VERSION}/auth/token/lookup-self"),
+                |c| async move { lookup_token(&c).await },
+            )
+            .await
+        {
+            Err(
+                e @ (VaultError::Sealed
+                | VaultError::Client {
+                    source:
+                        ClientError::RestClientError { .. } | ClientError::APIError { code: 503, .. },
+                    ..
+                }),
+            ) => Err(e),
+            _ => Ok(()),
         }
     }
+
+    /// Looks up the time to live of the token, None if it doesn't expire
+    pub async fn token_ttl(&self) -> Result<Option<Duration>, VaultError> {
+        let token = self
+            .traced(
+                "GET",
+                &format!("v{API_VERSION}/auth/token/lookup-self"),
+                |c| async move { lookup_token(&c).await },
+            )
+            .await?;
+        // Tokens that don't expire, such as root tokens, have no expire time and a TTL of 0
+        Ok(token
+            .expire_time
+            .is_some()
+            .then(|| Duration::from_secs(token.ttl)))
+    }
+
+    /// Checks that the token is valid and that the mount can be listed, returning a description
+    /// of the first problem found
+    pub async fn validate(&self) -> anyhow::Result<()> {
+        self.traced(
+            "GET",
+            &format!("v{API_VERSION}/auth/token/lookup-self"),
+            |c| async move { lookup_token(&c).await },
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("token lookup failed, is the token valid? {e:?}"))?;
+        for mount in self.mounts() {
+            if mount.kv_version().await == Some(0) {
+                anyhow::bail!("mount '{}' is not a KV engine", mount.namespace);
+            }
+        }
+        match self.list_keys("").await {
+            // An empty mount has nothing to list
+            Ok(_) | Err(VaultError::NotFound { .. }) => Ok(()),
+            Err(e) => Err(anyhow::anyhow!(
+                "listing mount '{}' failed, does it exist and does the token have list \
+                 permissions on it? {e:?}",
+                self.namespace
+            )),
+        }
+    }
+
+    /// Returns the version of the KV engine of the mount, detecting it from `sys/mounts` on first
+    /// use if it wasn't configured. Returns None if the token may not read the mounts or the
+    /// mount isn't listed. Failures to reach Vault aren't remembered, so detection is tried again
+    /// by the next request
+    pub async fn kv_version(&self) -> Option<u8> {
+        let detected = self
+            .kv_version
+            .get_or_try_init(|| async {
+                let mounts = match self
+                    .traced(
+                        "GET",
+                        &format!("v{API_VERSION}/sys/mounts"),
+                        |c| async move {
+                            c.send::<HashMap<String, MountInfo>>(
+                                "GET",
+                                &format!("v{API_VERSION}/sys/mounts"),
+                            )
+                            .await
+                            .and_then(api::required)
+                        },
+                    )
+                    .await
+                {
+                    Ok(mounts) => mounts,
+                    // Vault answered, so the token won't be allowed to read the mounts later either
+                    Err(
+                        e @ (VaultError::NotFound { .. }
+                        | VaultError::Client {
+                            source: ClientError::APIError { .. },
+                            ..
+                        }),
+                    ) => {
+                        debug!(error = %e, mount = %self.namespace, "Cannot detect KV version");
+                        return Ok(None);
+                    }
+                    Err(e) => return Err(e),
+                };
+                let Some(mount) = mounts.get(&format!("{}/", self.namespace.trim_matches('/')))
+                else {
+                    return Ok(None);
+                };
+                let version = match mount.mount_type.as_str() {
+                    // KV mounts without a version option are version 1
+                    "kv" => mount
+                        .options
+                        .as_ref()
+                        .and_then(|options| options.get("version"))
+                        .and_then(|version| version.parse().ok())
+                        .unwrap_or(1),
+                    // Cubbyhole mounts have the API of KV v1
+                    "generic" | "cubbyhole" => 1,
+                    other => {
+                        warn!(mount = %self.namespace, engine = other, "Mount is not a KV engine");
+                        0
+                    }
+                };
+                debug!(mount = %self.namespace, version, "Detected KV version");
+                Ok::<_, VaultError>(Some(version))
+            })
+            .await;
+        match detected {
+            Ok(version) => *version,
+            Err(e) => {
+                debug!(error = %e, mount = %self.namespace, "Failed to detect KV version");
+                None
+            }
+        }
+    }
+
+    /// Returns the engine requests to the mount are sent with, for the KV version configured or
+    /// detected by [`Client::kv_version`]. KV v2 is assumed if the version is unknown
+    async fn engine(&self) -> Engine {
+        Engine::new(&self.namespace, self.kv_version().await)
+    }
+
+    /// Writes an audit event to the configured sink, if any. Failures to write the event are
+    /// logged rather than returned so they don't fail the audited operation
+    pub async fn audit(&self, event: AuditEvent) {
+        match &self.audit {
+            None => (),
+            Some(AuditSink::Stdout) => match serde_json::to_string(&event) {
+                Ok(line) => println!("{line}"),
+                Err(e) => error!(error = %e, "Failed to serialize audit event"),
+            },
+            Some(AuditSink::Vault(prefix)) => {
+                let path = format!(
+                    "{prefix}/{}/{}-{:09}",
+                    event.actor_id, event.timestamp.sec, event.timestamp.nsec
+                );
+                let (mount, path, event) = (&self.audit_mount, path.as_str(), &event);
+                let backend = self.engine().await;
+                if let Err(e) = self
+                    .traced("POST", &backend.data_path(mount, path), |c| async move {
+                        backend.write(&c, mount, path, event, None).await
+                    })
+                    .await
+                {
+                    error!(error = %e, %path, "Failed to write audit event to Vault");
+                }
+            }
+        }
+    }
+
+    /// Returns the version of a secret written within the read-after-write window, if reads wait
+    /// for replicas to serve it
+    fn written_version(&self, path: &str) -> Option<u64> {
+        let written = self.written.as_ref()?.lock().unwrap();
+        written
+            .get(path)
+            .filter(|(_, at)| at.elapsed() < self.read_after_write)
+            .map(|(version, _)| *version)
+    }
+
+    /// Logs in with the auth method and switches all servers to the new token. If another
+    /// request already logged in after `failed_since`, its token is used instead
+    async fn reauthenticate(
+        &self,
+        auth: &AuthMethod,
+        failed_since: Instant,
+    ) -> Result<(), VaultError> {
+        let mut last_login = self.last_login.lock().await;
+        if last_login.is_some_and(|at| at > failed_since) {
+            return Ok(());
+        }
+        let mut res = None;
+        for node in self.nodes.candidates() {
+            match auth.login(&node.client()).await {
+                Err(e) if failover::should_failover(&e) => {
+                    warn!(addr = %node.addr, error = %e, "Vault server unavailable");
+                    node.mark_unhealthy(&e);
+                    res = Some(Err(e));
+                }
+                other => {
+                    res = Some(other);
+                    break;
+                }
+            }
+        }
+        let info = match res.expect("a client always has at least one node") {
+            Ok(info) => info,
+            Err(e) => {
+                self.renewals.record("login", false);
+                return Err(e.into());
+            }
+        };
+        self.nodes.set_token(&info.client_token)?;
+        if let Some(read_nodes) = &self.read_nodes {
+            read_nodes.set_token(&info.client_token)?;
+        }
+        *last_login = Some(Instant::now());
+        self.renewals.record("login", true);
+        debug!(path = %auth.login_path(), "Logged in to Vault with a new token");
+        Ok(())
+    }
+
+    /// Switches requests from the token in use to the other of the primary and secondary tokens,
+    /// unless that happened after `failed_since`
+    async fn switch_token(
+        &self,
+        tokens: &(String, String),
+        failed_since: Instant,
+    ) -> Result<(), VaultError> {
+        let mut last_switch = self.last_login.lock().await;
+        if last_switch.is_some_and(|at| at > failed_since) {
+            return Ok(());
+        }
+        let current = self
+            .nodes
+            .candidates()
+            .first()
+            .map(|node| node.client().settings.token.clone())
+            .unwrap_or_default();
+        let (token, name) = if current == tokens.0 {
+            (&tokens.1, "secondary")
+        } else {
+            (&tokens.0, "primary")
+        };
+        self.nodes.set_token(token)?;
+        if let Some(read_nodes) = &self.read_nodes {
+            read_nodes.set_token(token)?;
+        }
+        *last_switch = Some(Instant::now());
+        self.renewals.record("switch", true);
+        warn!("Vault token was denied, switched to the {name} token");
+        Ok(())
+    }
+
+    /// Runs a single Vault HTTP request inside a span describing it. The span is a child of the
+    /// current span, so it is exported as part of the invoking actor's trace.
+    ///
+    /// The request is built by `request` for the Vault server it is sent to. If a server can't
+    /// handle it, the request is retried against the next configured server. If all servers are
+    /// sealed, the request is retried with backoff until `sealed_retry` has passed, and likewise
+    /// for `rate_limit_retry` if it is rate limited
+    async fn traced<'a, T, F, Fut>(
+        &'a self,
+        method: &'static str,
+        path: &str,
+        request: F,
+    ) -> Result<T, VaultError>
+    where
+        F: Fn(Connection) -> Fut + 'a,
+        Fut: Future<Output = Result<T, ClientError>> + 'a,
+    {
+        let span = tracing::debug_span!(
+            "vault_request",
+            http.method = method,
+            vault.path = %sanitize_path(path),
+            http.status_code = field::Empty,
+            vault.request_id = field::Empty,
+            retries = 0u32,
+        );
+        async move {
+            let begun = Instant::now();
+            let mut permits = Vec::with_capacity(self.limiters.len());
+            for limiter in self.limiters.iter() {
+                match limiter.acquire().await {
+                    Ok(permit) => permits.push(permit),
+                    Err(e) => {
+                        self.errors.record(
+                            metrics::request_kind(method, path),
+                            metrics::error_class(&e),
+                        );
+                        return Err(e);
+                    }
+                }
+            }
+            let candidates: Vec<Arc<Node>> = match &self.read_nodes {
+                Some(read_nodes) if is_read(method) => read_nodes
+                    .candidates()
+                    .into_iter()
+                    .chain(self.nodes.candidates())
+                    .collect(),
+                _ => self.nodes.candidates(),
+            };
+            let started = Instant::now();
+            let mut attempts = 0u32;
+            let mut response = api::Response::default();
+            let index = is_read(method)
+                .then(|| self.replication_index.lock().unwrap().clone())
+                .flatten();
+            let index = index.as_deref();
+            let injected = match &self.faults {
+                Some(faults) => faults.before_request(method, path).await,
+                None => None,
+            };
+            let mut res = match injected {
+                Some(e) => Err(e),
+                None => {
+                    self.send_hedged(
+                        method,
+                        &candidates,
+                        &request,
+                        index,
+                        &mut attempts,
+                        &mut response,
+                    )
+                    .await
+                }
+            };
+            if let Err(ClientError::APIError { code: 403, .. }) = &res {
+                // The token may have expired or been revoked, so get or switch to another one and
+                // try once more
+                let renewed = match (&self.auth, &self.tokens) {
+                    (Some(auth), _) => Some(self.reauthenticate(auth, started).await),
+                    (None, Some(tokens)) => Some(self.switch_token(tokens, started).await),
+                    (None, None) => None,
+                };
+                match renewed {
+                    Some(Ok(())) => {
+                        res = self
+                            .send_hedged(
+                                method,
+                                &candidates,
+                                &request,
+                                index,
+                                &mut attempts,
+                                &mut response,
+                            )
+                            .await
+                    }
+                    Some(Err(e)) => error!(error = %e, "Failed to get a new Vault token"),
+                    None => (),
+                }
+            }
+            // vaultrs doesn't expose the status of the responses to the requests it sends, but it
+            // only treats 2xx as success and only deletes return no content
+            let status = response.status.or(match &res {
+                Ok(_) if method == "DELETE" => Some(204),
+                Ok(_) => Some(200),
+                Err(ClientError::APIError { code, .. }) => Some(*code),
+                Err(_) => None,
+            });
+            if let Some(status) = status {
+                Span::current().record("http.status_code", status);
+            }
+            if let Some(request_id) = &response.request_id {
+                Span::current().record("vault.request_id", request_id.as_str());
+            }
+            if let (Ok(_), Some(index)) = (&res, response.index.take()) {
+                *self.replication_index.lock().unwrap() = Some(index);
+            }
+            let elapsed = begun.elapsed();
+            if self.slow_request.is_some_and(|slow| elapsed > slow) {
+                warn!(
+                    method,
+                    path = %sanitize_path(path),
+                    ?elapsed,
+                    retries = attempts.saturating_sub(1),
+                    actor_id = self.actor_id.as_deref().unwrap_or_default(),
+                    status,
+                    request_id = response.request_id.as_deref().unwrap_or_default(),
+                    "Slow Vault request"
+                );
+            }
+            res.map_err(|e| {
+                let err = if failover::is_sealed(&e) {
+                    VaultError::Sealed
+                } else {
+                    VaultError::Client {
+                        source: e,
+                        request_id: response.request_id.clone(),
+                    }
+                };
+                self.errors.record(
+                    metrics::request_kind(method, path),
+                    metrics::error_class(&err),
+                );
+                err
+            })
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Sends a request like [`Client::send`]. Reads are hedged if enabled: when the request
+    /// isn't answered within the hedge delay, it is sent again starting with the next candidate,
+    /// and the first answer is used unless it is an error
+    async fn send_hedged<T, F, Fut>(
+        &self,
+        method: &str,
+        candidates: &[Arc<Node>],
+        request: &F,
+        index: Option<&str>,
+        attempts: &mut u32,
+        response: &mut api::Response,
+    ) -> Result<T, ClientError>
+    where
+        F: Fn(Connection) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let Some(hedge) = self.hedge.as_ref().filter(|_| is_read(method)) else {
+            return self
+                .send(candidates, request, index, attempts, response)
+                .await;
+        };
+        let started = Instant::now();
+        let res = match hedge.delay() {
+            None => {
+                self.send(candidates, request, index, attempts, response)
+                    .await
+            }
+            Some(delay) => {
+                let mut rotated = candidates.to_vec();
+                let shift = rotated.len().min(1);
+                rotated.rotate_left(shift);
+                let mut hedged_attempts = 0;
+                let mut hedged_response = api::Response::default();
+                let (res, hedged) = {
+                    let first = std::pin::pin!(self.send(
+                        candidates,
+                        request,
+                        index,
+                        attempts,
+                        &mut *response
+                    ));
+                    let second = std::pin::pin!(async {
+                        tokio::time::sleep(delay).await;
+                        debug!(?delay, "Hedging slow Vault read");
+                        self.send(
+                            &rotated,
+                            request,
+                            index,
+                            &mut hedged_attempts,
+                            &mut hedged_response,
+                        )
+                        .await
+                    });
+                    match futures::future::select(first, second).await {
+                        Either::Left((Err(_), second)) => (second.await, true),
+                        Either::Right((Err(_), first)) => (first.await, false),
+                        Either::Left((res, _)) => (res, false),
+                        Either::Right((res, _)) => (res, true),
+                    }
+                };
+                *attempts += hedged_attempts;
+                if hedged {
+                    *response = hedged_response;
+                }
+                res
+            }
+        };
+        if res.is_ok() {
+            hedge.record(started.elapsed());
+        }
+        res
+    }
+
+    /// Sends a request to the first of the candidates able to handle it, retrying with backoff
+    /// while all of them are sealed or the request is rate limited, but not past the
+    /// [deadline](crate::deadline) of the invocation. Rate limited (429) requests, and those
+    /// refused with 503 and a `Retry-After`, are retried after the delay the server asked for if
+    /// it gave one. Reads carry the replication
+    /// `index` they must observe if given. `attempts` counts the requests sent, and `response` is
+    /// set to the response to the last one
+    async fn send<T, F, Fut>(
+        &self,
+        candidates: &[Arc<Node>],
+        request: &F,
+        index: Option<&str>,
+        attempts: &mut u32,
+        response: &mut api::Response,
+    ) -> Result<T, ClientError>
+    where
+        F: Fn(Connection) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let sealed_deadline = deadline::cap(Instant::now() + self.sealed_retry);
+        let mut backoff = SEALED_INITIAL_BACKOFF;
+        let rate_limit_deadline = deadline::cap(Instant::now() + self.rate_limit_retry);
+        let mut rate_limit_backoff = RATE_LIMIT_INITIAL_BACKOFF;
+        let mut res = None;
+        loop {
+            for node in candidates.iter() {
+                if *attempts > 0 {
+                    Span::current().record("retries", *attempts);
+                }
+                *attempts += 1;
+                let conn = node.connection().with_index(index);
+                let sent = request(conn.clone()).await;
+                *response = conn.response();
+                match sent {
+                    Err(e) if failover::should_failover(&e) => {
+                        warn!(addr = %node.addr, error = %e, "Vault server unavailable");
+                        node.mark_unhealthy(&e);
+                        res = Some(Err(e));
+                    }
+                    other => {
+                        node.mark_healthy();
+                        res = Some(other);
+                        break;
+                    }
+                }
+            }
+            let retry_after = response.retry_after.unwrap_or(rate_limit_backoff);
+            match &res {
+                Some(Err(e))
+                    if failover::is_sealed(e) && Instant::now() + backoff < sealed_deadline =>
+                {
+                    warn!(?backoff, "Vault is sealed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(SEALED_MAX_BACKOFF);
+                }
+                Some(Err(ClientError::APIError { code, .. }))
+                    if (*code == 429 || *code == 503 && response.retry_after.is_some())
+                        && Instant::now() + retry_after < rate_limit_deadline =>
+                {
+                    warn!(code, backoff = ?retry_after, "Rate limited by Vault, retrying");
+                    tokio::time::sleep(retry_after).await;
+                    rate_limit_backoff = (rate_limit_backoff * 2).min(RATE_LIMIT_MAX_BACKOFF);
+                }
+                _ => return res.expect("a client always has at least one node"),
+            }
+        }
+    }
+}
+
+/// Looks up the token requests through the connection are sent with
+async fn lookup_token(conn: &Connection) -> Result<TokenLookup, ClientError> {
+    conn.send("GET", &format!("v{API_VERSION}/auth/token/lookup-self"))
+        .await
+        .and_then(api::required)
+}
+
+/// Creates a node for each of the given addresses using the rest of the config
+/// Returns the cell holding the KV version of a mount, set already if it is configured or the
+/// mount is the cubbyhole, or left to be detected before the first request to the mount otherwise
+fn known_kv_version(mount: &str, configured: Option<u8>) -> Arc<tokio::sync::OnceCell<Option<u8>>> {
+    let known = match (mount.trim_matches('/'), configured) {
+        (_, Some(_)) | ("cubbyhole", _) => Some(Engine::new(mount, configured).kv_version()),
+        _ => None,
+    };
+    Arc::new(tokio::sync::OnceCell::new_with(known.map(Some)))
+}
+
+fn build_nodes(config: &Config, addrs: &[url::Url]) -> Result<Nodes, VaultError> {
+    let http = http_client(config)?;
+    addrs
+        .iter()
+        .map(|addr| {
+            VaultClient::new(VaultClientSettings {
+                token: config.token.clone(),
+                address: addr.clone(),
+                ca_certs: config.certs.clone(),
+                verify: !config.tls_skip_verify,
+                version: API_VERSION,
+                wrapping: false,
+                timeout: None,
+                namespace: Some(config.mount.clone()),
+            })
+            .map(|mut client| {
+                if let Some(http) = &http {
+                    client.http.http = http.clone();
+                }
+                Node::new(addr.clone(), client).with_headers(config.headers.clone())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Nodes::new)
+        .map_err(VaultError::from)
+}
+
+/// Builds the HTTP client of the nodes the same way vaultrs does when the link sets options
+/// vaultrs has no setting for: a minimum TLS version, cipher suites, a client certificate, extra
+/// headers or connection pool settings. Returns None if it sets none of them, leaving the one
+/// vaultrs builds
+fn http_client(config: &Config) -> Result<Option<reqwest::Client>, ClientError> {
+    if config.tls_min_version.is_none()
+        && config.tls_cipher_suites.is_empty()
+        && config.tls_client_cert.is_none()
+        && config.headers.is_empty()
+        && config.pool_max_idle_per_host.is_none()
+        && config.pool_idle_timeout.is_none()
+        && config.tcp_keepalive.is_none()
+    {
+        return Ok(None);
+    }
+    let read = |path: &String| {
+        std::fs::read(path).map_err(|source| ClientError::FileReadError {
+            source,
+            path: path.clone(),
+        })
+    };
+    let mut builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(config.tls_skip_verify)
+        .default_headers(config.headers.clone());
+    if let Some(max_idle) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(timeout) = config.pool_idle_timeout {
+        builder = builder.pool_idle_timeout((!timeout.is_zero()).then_some(timeout));
+    }
+    builder = builder.tcp_keepalive(config.tcp_keepalive);
+    // The rustls configuration has the roots, client certificate and minimum version of the link
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    if !config.tls_cipher_suites.is_empty() {
+        return builder
+            .use_preconfigured_tls(crate::tls::client_config(config)?)
+            .build()
+            .map(Some)
+            .map_err(|source| ClientError::RestClientBuildError { source });
+    }
+    if let Some(min_version) = config.tls_min_version {
+        builder = builder.min_tls_version(min_version);
+    }
+    for path in &config.certs {
+        let cert = reqwest::Certificate::from_pem(&read(path)?).map_err(|source| {
+            ClientError::ParseCertificateError {
+                source,
+                path: path.clone(),
+            }
+        })?;
+        builder = builder.add_root_certificate(cert);
+    }
+    if let (Some(cert_path), Some(key_path)) = (&config.tls_client_cert, &config.tls_client_key) {
+        let (cert, key) = (read(cert_path)?, read(key_path)?);
+        // reqwest uses native-tls when both backends are enabled
+        #[cfg(feature = "native-tls")]
+        let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key);
+        #[cfg(not(feature = "native-tls"))]
+        let identity = reqwest::Identity::from_pem(&[cert, key].concat());
+        let identity = identity.map_err(|source| ClientError::ParseCertificateError {
+            source,
+            path: cert_path.clone(),
+        })?;
+        builder = builder.identity(identity);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|source| ClientError::RestClientBuildError { source })
+}
+
+/// Checks the client certificate files every `tls_client_reload`, and rebuilds the HTTP client
+/// of the nodes when they changed so requests present the rotated certificate. Stops once the
+/// link's nodes are dropped
+async fn reload_client_cert(config: Config, nodes: Vec<Weak<Nodes>>) {
+    let modified = |config: &Config| {
+        [&config.tls_client_cert, &config.tls_client_key]
+            .into_iter()
+            .flatten()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    let mut loaded = modified(&config);
+    let mut interval = tokio::time::interval(config.tls_client_reload);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let nodes: Vec<_> = nodes.iter().filter_map(Weak::upgrade).collect();
+        if nodes.is_empty() {
+            return;
+        }
+        let current = modified(&config);
+        if current == loaded {
+            continue;
+        }
+        // A file may be caught halfway through being replaced, in which case it is read again
+        // on the next check
+        let http = match http_client(&config) {
+            Ok(Some(http)) => http,
+            Ok(None) => return,
+            Err(e) => {
+                warn!(error = %e, "Failed to reload the client certificate");
+                continue;
+            }
+        };
+        match nodes.iter().try_for_each(|n| n.set_http_client(&http)) {
+            Ok(()) => {
+                loaded = current;
+                info!("Reloaded the client certificate presented to Vault");
+            }
+            Err(e) => warn!(error = %e, "Failed to reload the client certificate"),
+        }
+    }
+}
+
+/// Asks each server whether it is sealed every `interval`, logging when that changes. Servers
+/// that can't be reached are left to requests to mark. Stops once the link's nodes are dropped
+async fn poll_seal_status(interval: Duration, nodes: Vec<Weak<Nodes>>) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let nodes: Vec<_> = nodes.iter().filter_map(Weak::upgrade).collect();
+        if nodes.is_empty() {
+            return;
+        }
+        let servers = nodes.iter().flat_map(|nodes| nodes.candidates());
+        futures::future::join_all(servers.map(|node| async move {
+            match node.seal_status().await {
+                Ok(sealed) if node.set_sealed(sealed) => {
+                    if sealed {
+                        warn!(addr = %node.addr, "Vault server is sealed");
+                    } else {
+                        info!(addr = %node.addr, "Vault server is unsealed");
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => debug!(addr = %node.addr, error = %e, "Failed to poll Vault seal status"),
+            }
+        }))
+        .await;
+    }
+}
+
+/// Returns the current version of a secret, or None if that version was deleted or destroyed
+pub fn live_version(metadata: &ReadSecretMetadataResponse) -> Option<u64> {
+    let version = metadata.current_version;
+    metadata
+        .versions
+        .get(&version.to_string())
+        .filter(|v| v.deletion_time.is_empty() && !v.destroyed)
+        .map(|_| version)
+}
+
+/// Returns the size of the request writing a file sends to Vault. The data is serialized as a
+/// JSON array of numbers, taking 2 to 4 bytes per byte
+fn request_size(file: &File) -> usize {
+    let data: usize = file
+        .data
+        .iter()
+        .map(|b| match b {
+            0..=9 => 2,
+            10..=99 => 3,
+            _ => 4,
+        })
+        .sum();
+    let strings = [&file.content_type, &file.content_encoding, &file.blob]
+        .into_iter()
+        .flatten()
+        .map(String::len)
+        .sum::<usize>();
+    data + strings + REQUEST_OVERHEAD_BYTES
+}
+
+/// Returns true for HTTP methods that don't modify anything in Vault
+fn is_read(method: &str) -> bool {
+    matches!(method, "GET" | "LIST")
+}
+
+/// Strips control characters and bounds the length of a path so object names can't garble or
+/// bloat exported spans
+fn sanitize_path(path: &str) -> String {
+    let mut sanitized: String = path
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TRACED_PATH_LEN)
+        .collect();
+    if path.chars().count() > MAX_TRACED_PATH_LEN {
+        sanitized.push_str("...");
+    }
+    sanitized
 }
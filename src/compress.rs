@@ -0,0 +1,29 @@
+//! Compression of object data sent to actors
+//!
+//! Actors can accept gzip-compressed data in responses, like the `Accept-Encoding` header of
+//! HTTP. Large responses are then compressed before being sent over the lattice, which keeps
+//! compressible objects under the message size limit of NATS more often. Compressed data is
+//! returned with `gzip` as its content encoding.
+use std::io::Write;
+
+use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
+
+/// Content encoding of gzip-compressed data
+pub const GZIP: &str = "gzip";
+
+/// Returns whether a comma-separated list of accepted encodings includes gzip
+pub fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding
+        .split(',')
+        .map(|encoding| encoding.split(';').next().unwrap_or_default().trim())
+        .any(|encoding| encoding.eq_ignore_ascii_case(GZIP))
+}
+
+/// Compresses data with gzip, returning None if that doesn't make it smaller
+pub fn gzip(data: &[u8]) -> Option<Bytes> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::fast());
+    encoder.write_all(data).ok()?;
+    let compressed = encoder.finish().ok()?;
+    (compressed.len() < data.len()).then(|| compressed.into())
+}
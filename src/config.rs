@@ -1,26 +1,294 @@
 //! Configuration for vault blobstore capability provider
 //!
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path, time::Duration};
 use url::Url;
 
+use crate::{
+    audit::AuditSink,
+    auth::AuthMethod,
+    discovery::{Discovery, DEFAULT_DISCOVERY_INTERVAL},
+    fault::FaultInjection,
+};
+
 const DEFAULT_VAULT_ADDR: &str = "http://127.0.0.1:8200";
+/// Default time reads of a recently written secret wait for `read_addrs` to serve it
+const DEFAULT_READ_AFTER_WRITE: Duration = Duration::from_secs(2);
+/// Default time requests are retried for while Vault is sealed
+const DEFAULT_SEALED_RETRY: Duration = Duration::from_secs(10);
+/// Default interval at which the seal status of the servers is polled
+const DEFAULT_SEAL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Default interval at which client certificate files are checked for rotation
+const DEFAULT_TLS_CLIENT_RELOAD: Duration = Duration::from_secs(60);
+/// Default maximum size of a request to Vault, the default `max_request_size` of its listeners
+const DEFAULT_MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+/// Default time the results of writes with idempotency keys are kept
+const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(300);
+/// Number of requests allowed to wait for a free slot when a concurrency limit is set
+pub const DEFAULT_MAX_QUEUED_REQUESTS: usize = 1024;
+/// Number of objects whose metadata is fetched concurrently when listings include metadata
+pub const DEFAULT_LIST_METADATA_CONCURRENCY: usize = 8;
+/// Number of folders listed concurrently when all objects of a container are listed
+pub const DEFAULT_LIST_CONCURRENCY: usize = 4;
+/// Number of objects of a batch written concurrently
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
 
 /// Vault configuration
 #[derive(Clone, Debug)]
 pub struct Config {
     /// Token for connecting to vault, can be set in environment with VAULT_TOKEN.
-    /// Required
+    /// Required unless `auth` is set
     pub token: String,
-    /// Url for connecting to vault, can be set in environment with VAULT_ADDR.
+    /// Second token requests switch to when the current token is denied, and back again, so
+    /// tokens can be rotated blue/green without downtime: a new token is deployed as the
+    /// secondary before the primary is revoked. Can be set with `secondary_token`. Ignored if
+    /// `auth` is set
+    pub secondary_token: Option<String>,
+    /// Auth method used to get a new token when the current one expires or is missing. Set with
+    /// `auth_method`, which is either `approle`, using `role_id` and `secret_id`, or `kubernetes`,
+    /// using `role` and the service account token at `jwt_path`. The method's mount can be set
+    /// with `auth_mount`, and defaults to the method name
+    pub auth: Option<AuthMethod>,
+    /// Urls for connecting to vault, can be set in environment with VAULT_ADDR. Several
+    /// addresses can be given as a comma-separated list, in which case requests go to the first
+    /// healthy address and fail over to the next one when a server is unreachable or sealed.
+    /// A Vault Agent or Vault Proxy listening on a Unix socket is addressed by the path of the
+    /// socket, as in `unix:///run/vault/agent.sock`; its auto-auth token is used, so such
+    /// addresses can't be combined with `auth_method` or `vault_events`.
     /// Defaults to 'http://127.0.0.1:8200'
-    pub addr: Url,
+    pub addrs: Vec<Url>,
+    /// How the servers are discovered if `addr` is a `srv://` or `consul://` address instead,
+    /// see [`discovery`](crate::discovery). `addrs` then only holds the address used until the
+    /// first discovery
+    pub discovery: Option<Discovery>,
+    /// Time between two discoveries of the servers. Can be set with `discovery_interval_secs`.
+    /// Defaults to 30 seconds
+    pub discovery_interval: Duration,
+    /// Urls to send read-only requests (reads, lists and metadata lookups) to, such as
+    /// performance standbys or a load balancer in front of them. Can be set with `read_addr`, as
+    /// a comma-separated list like `addr`. Reads fall back to `addrs` if none of these can serve
+    /// them. Defaults to sending all requests to `addrs`.
+    ///
+    /// Reads carry the `X-Vault-Index` Vault Enterprise returned for the last write, so an actor
+    /// that writes and then reads an object doesn't get stale data from a standby that hasn't
+    /// replicated the write yet: the standby forwards the read to the active node instead
+    pub read_addrs: Vec<Url>,
+    /// How long reads of an object written through the link wait for `read_addrs` to serve the
+    /// written version with `read_your_writes`. Can be set in seconds with
+    /// `read_after_write_secs`. Defaults to 2 seconds
+    pub read_after_write: Duration,
+    /// Whether reads of an object written within `read_after_write` wait until `read_addrs`
+    /// serve at least the version that was written, for replicas that don't return
+    /// `X-Vault-Index`, such as performance secondaries. The metadata of the object is polled
+    /// until the replica caught up or the window passed. Can be set with `read_your_writes`.
+    /// Defaults to false
+    pub read_your_writes: bool,
     /// Vault mount point, can be set with in environment with VAULT_MOUNT.
     /// Defaults to "secret/"
     pub mount: String,
+    /// Additional mounts for containers with a given prefix, as (prefix, mount) pairs. Can be set
+    /// with `mounts` as a comma-separated list of `prefix:mount`, e.g.
+    /// `configs:secret,artifacts:kv-large`. Containers matching no prefix use `mount`, and a
+    /// container matching several uses the longest prefix
+    pub mounts: Vec<(String, String)>,
+    /// Version of the KV secrets engine of the mounts, 1 or 2. Defaults to 2, except for the
+    /// `cubbyhole` mount which has the API of KV v1. Secrets of KV v1 mounts have no versions or
+    /// metadata, so `content_addressed`, `usage_accounting`, locks, snapshots and custom metadata
+    /// need KV v2. When unset, the version of each mount is detected from `sys/mounts` before its
+    /// first request, falling back to 2 if the token may not read the mounts. Can be set with
+    /// `kv_version`
+    pub kv_version: Option<u8>,
     /// certificate files - path to CA certificate file(s). Setting this enables TLS
     /// The linkdef value `certs` and the environment variable `VAULT_CERTS`
     /// are parsed as a comma-separated string of file paths to generate this list.
     pub certs: Vec<String>,
+    /// Minimum TLS version of connections to Vault, either `1.2` or `1.3`. Connections to servers
+    /// that only offer older versions fail. Can be set with `tls_min_version`. Defaults to the
+    /// minimum of the TLS backend, which is TLS 1.2 with rustls
+    pub tls_min_version: Option<reqwest::tls::Version>,
+    /// Cipher suites connections to Vault may use, by their IANA names such as
+    /// `TLS13_AES_256_GCM_SHA384` or `TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384`. Can be set with
+    /// `tls_cipher_suites` as a comma-separated list. Defaults to all the suites of the TLS
+    /// backend. Only the rustls backend can restrict them, with native-tls they are set in the
+    /// OpenSSL configuration
+    pub tls_cipher_suites: Vec<String>,
+    /// Whether certificates of Vault servers are accepted without verification, for test servers
+    /// with self-signed certificates. Prefer adding their CA to `certs`. Can be set with
+    /// `tls_skip_verify`. Defaults to false
+    pub tls_skip_verify: bool,
+    /// PEM file with the certificate chain presented to Vault for mTLS, such as an X.509 SVID
+    /// kept up to date by the SPIFFE helper. Requires `tls_client_key`. Can be set with
+    /// `tls_client_cert`
+    pub tls_client_cert: Option<String>,
+    /// PEM file with the PKCS#8 private key of `tls_client_cert`. Can be set with
+    /// `tls_client_key`
+    pub tls_client_key: Option<String>,
+    /// How often the client certificate files are checked for changes, reloading them so rotated
+    /// certificates are used without recreating the link. Can be set with
+    /// `tls_client_reload_secs`, where 0 disables reloading. Defaults to 60 seconds, well within
+    /// the lifetime of an SVID
+    pub tls_client_reload: Duration,
+    /// Headers added to every request to Vault, such as routing headers required by a gateway in
+    /// front of it. Can be set with `headers` as a comma-separated list of `name:value` pairs,
+    /// or as an object in the config file. `X-Vault-Token` and `X-Vault-Namespace` are set by the
+    /// client and can't be overridden
+    pub headers: reqwest::header::HeaderMap,
+    /// Maximum number of idle connections kept open to each Vault server, so bursts of requests
+    /// reuse connections instead of doing new TLS handshakes. Can be set with
+    /// `pool_max_idle_per_host`. Defaults to no limit
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle connection is kept open. Can be set in seconds with
+    /// `pool_idle_timeout_secs`, where 0 keeps them open until the server closes them. Defaults
+    /// to 90 seconds
+    pub pool_idle_timeout: Option<Duration>,
+    /// Interval of TCP keep-alive probes on connections to Vault, which keep idle connections
+    /// from being dropped by load balancers and firewalls in between. Can be set in seconds with
+    /// `tcp_keepalive_secs`. Disabled by default
+    pub tcp_keepalive: Option<Duration>,
+    /// Audit log sink, can be set with `audit`. Either `stdout` to write each operation as a line
+    /// of JSON, or `vault:<path>` to write each operation as a secret under `path` in
+    /// `audit_mount`. Disabled by default
+    pub audit: Option<AuditSink>,
+    /// Mount a `vault:` audit sink writes to, can be set with `audit_mount`. Defaults to the
+    /// link's mount, where actors can't list, read or write the audit path
+    pub audit_mount: Option<String>,
+    /// Maximum number of Vault requests in flight for this link, can be set with
+    /// `max_concurrent_requests`. Unlimited by default
+    pub max_concurrent_requests: Option<usize>,
+    /// Maximum number of Vault requests waiting for a slot once `max_concurrent_requests` is
+    /// reached, can be set with `max_queued_requests`. Requests beyond this are rejected.
+    /// Defaults to 1024
+    pub max_queued_requests: usize,
+    /// Maximum rate in bytes per second at which the link reads object data from Vault, can be
+    /// set with `max_read_bytes_per_sec`. Reads beyond the rate are delayed. Unlimited by default
+    pub max_read_bytes_per_sec: Option<u64>,
+    /// Maximum rate in bytes per second at which the link writes object data to Vault, can be
+    /// set with `max_write_bytes_per_sec`. Unlimited by default
+    pub max_write_bytes_per_sec: Option<u64>,
+    /// Maximum size in bytes of a request to Vault, matching the `max_request_size` of its
+    /// listener. Writes of objects whose request would be larger are rejected with `TooLarge`
+    /// before anything is sent, instead of failing with an opaque error from Vault. Object data
+    /// is sent as a JSON array of numbers, so a request takes up to 4 times the object's size.
+    /// Can be set with `max_request_bytes`, where 0 disables the check. Defaults to Vault's
+    /// default of 32 MiB
+    pub max_request_bytes: usize,
+    /// How long the result of a write with an idempotency key is kept, so retries with the same
+    /// key within it don't write another version. Can be set with `idempotency_window_secs`,
+    /// where 0 ignores idempotency keys. Defaults to 5 minutes
+    pub idempotency_window: Duration,
+    /// Whether to check that the token is valid and the mount can be listed when the link is
+    /// created, rejecting the link if not. Can be set with `validate_on_link`. Defaults to false,
+    /// in which case problems only surface on the first invocation
+    pub validate_on_link: bool,
+    /// Whether to reject the link when none of the Vault servers can be reached when it is
+    /// created. Unlike `validate_on_link` this doesn't check the token or mount, so a link can be
+    /// created before its policy is in place. Can be set with `require_reachable_on_link`.
+    /// Defaults to false, accepting the link and connecting on the first invocation
+    pub require_reachable_on_link: bool,
+    /// Whether to connect to every configured Vault server when the link is created, logging in
+    /// first if needed, so the first invocations don't pay for TLS handshakes. Failures are only
+    /// logged. Can be set with `prewarm_on_link`. Defaults to false
+    pub prewarm_on_link: bool,
+    /// How long requests are retried with backoff while Vault is sealed before failing with a
+    /// `Sealed` error, to ride out unseal windows. Can be set in seconds with
+    /// `sealed_retry_secs`. Defaults to 10 seconds
+    pub sealed_retry: Duration,
+    /// Interval at which each server is asked whether it is sealed, so the provider's health
+    /// reports a sealed Vault, and requests avoid sealed servers, before any request fails. Can
+    /// be set in seconds with `seal_poll_interval_secs`, where 0 disables polling. Defaults to
+    /// 10 seconds
+    pub seal_poll_interval: Duration,
+    /// How long requests rejected by a rate limit quota (429), or refused with 503 and a
+    /// `Retry-After`, are retried for. Retries wait as long as `Retry-After` asks, or else a
+    /// second, the interval of Vault's quotas, doubling up to 8 seconds. Retries never go past
+    /// the deadline of the invocation. Can be set in seconds with `rate_limit_retry_secs`.
+    /// Defaults to 0, failing such requests right away
+    pub rate_limit_retry: Duration,
+    /// Time above which a Vault request, including its retries, logs a warning with its path,
+    /// duration, retries and the linked actor. Can be set in milliseconds with
+    /// `slow_request_ms`. Disabled by default
+    pub slow_request: Option<Duration>,
+    /// Percentile of recent read latencies after which a read that wasn't answered yet is sent a
+    /// second time, to the next server if there are several, taking whichever answer comes
+    /// first. See [`hedge`](crate::hedge). Can be set with `hedge_percentile`, e.g. `95`.
+    /// Disabled by default
+    pub hedge_percentile: Option<f64>,
+    /// UNSAFE, never use in production: faults injected into requests to Vault, for testing
+    /// retries in staging. See [`fault`](crate::fault). Deliberately left out of the documented
+    /// link values, can be set with `unsafe_fault_injection`. Disabled by default
+    pub fault_injection: Option<FaultInjection>,
+    /// Whether object data is stored content-addressed, once per distinct content, with each
+    /// object only pointing to it. This deduplicates identical objects and makes copies cheap.
+    /// Can be set with `content_addressed`. Defaults to false
+    pub content_addressed: bool,
+    /// Whether actors of the link may remove orphaned content-addressed secrets of all its
+    /// mounts with `VaultBlobstore.CollectGarbage`. Can be set with `allow_garbage_collection`.
+    /// Defaults to false, leaving garbage collection to `VAULT_GC_INTERVAL_SECS`
+    pub allow_garbage_collection: bool,
+    /// Whether actors of the link may archive containers into objects with
+    /// `VaultBlobstore.ExportContainer`. Can be set with `allow_export`. Defaults to false
+    pub allow_export: bool,
+    /// Whether a write of the data, content type and encoding an object already has is skipped,
+    /// returning its current version instead of writing a new one. In content-addressed mode the
+    /// hash of the data is compared with the blob the object points to, otherwise the current
+    /// data is read and compared. Can be set with `skip_unchanged_writes`. Defaults to false
+    pub skip_unchanged_writes: bool,
+    /// Whether the number of objects and bytes of each container are kept in a marker secret
+    /// and returned by `get_container_info`, see [`usage`](crate::usage). This costs a read of
+    /// the previous data and two more requests per write or removal. Can be set with
+    /// `usage_accounting`. Defaults to false
+    pub usage_accounting: bool,
+    /// Maximum number of versions Vault keeps of each object in a container, by container, so
+    /// objects rewritten often don't accumulate unbounded version history. Can be set with
+    /// `container_max_versions` as a comma-separated list of `container:count` pairs, or as an
+    /// object in the config file. Containers without an entry use the mount's setting
+    pub container_max_versions: HashMap<String, u64>,
+    /// Minimum size in bytes of object data returned by `get_object` that is gzip-compressed for
+    /// actors that accept it, see [`compress`](crate::compress). Can be set with
+    /// `compress_min_bytes`. Disabled by default
+    pub compress_min_bytes: Option<usize>,
+    /// Size in bytes of the chunks object data is sent to actors in. `get_object` returns only
+    /// the first chunk of larger objects, and sends the rest to the actor's
+    /// `ChunkReceiver.ReceiveChunk` afterwards. Can be set with `download_chunk_bytes`. Disabled
+    /// by default, returning all data in the response
+    pub download_chunk_bytes: Option<usize>,
+    /// Whether `list_objects` fetches the metadata of each listed object to fill in its size,
+    /// content type and modification time. Can be set with `list_metadata`. This costs two Vault
+    /// requests per object, so defaults to false
+    pub list_metadata: bool,
+    /// Maximum number of objects whose metadata is fetched at once when `list_metadata` is set,
+    /// can be set with `list_metadata_concurrency`. Defaults to 8
+    pub list_metadata_concurrency: usize,
+    /// Maximum number of folders listed at once when all objects of a container are listed,
+    /// such as for exports and snapshots. Can be set with `list_concurrency`. Defaults to 4
+    pub list_concurrency: usize,
+    /// Maximum number of keys a folder may have when all objects of a container are listed,
+    /// failing the listing otherwise. Can be set with `list_max_folder_keys`. Unlimited by
+    /// default
+    pub list_max_folder_keys: Option<usize>,
+    /// Maximum number of keys, objects and folders, visited when all objects of a container are
+    /// listed, failing the listing once exceeded. Can be set with `list_max_keys`. Unlimited by
+    /// default
+    pub list_max_keys: Option<usize>,
+    /// Maximum number of objects of a `PutObjects` batch written at once. Can be set with
+    /// `batch_concurrency`. Defaults to 8
+    pub batch_concurrency: usize,
+    /// NATS subject a JSON event is published on after each successful write or delete of an
+    /// object, so other actors and services can react to changes without polling. Can be set
+    /// with `events_subject`. Disabled by default
+    pub events_subject: Option<String>,
+    /// Whether to subscribe to the event notifications of Vault 1.16+ and publish every change to
+    /// the mount on `events_subject`, including changes made outside of this provider. The
+    /// provider then doesn't publish its own events, which Vault reports as well. Can be set with
+    /// `vault_events`. Defaults to false
+    pub vault_events: bool,
+    /// Prefixes of the paths the link may access, can be set with `allowed_prefixes` as a
+    /// comma-separated list. Prefixes are matched against the paths objects are stored at in the
+    /// mount, and the folder for listings, which are filtered to permitted entries. Requests for
+    /// other paths are rejected before reaching Vault. Defaults to allowing all paths
+    pub allowed_prefixes: Vec<String>,
+    /// Prefixes of the paths the link may not access, even if they match `allowed_prefixes`.
+    /// Can be set with `denied_prefixes` as a comma-separated list. Defaults to none
+    pub denied_prefixes: Vec<String>,
 }
 
 impl Default for Config {
@@ -34,32 +302,569 @@ impl Config {
     /// initialize from linkdef values, environment, and defaults
     pub fn from_values(values: &[(String, String)]) -> anyhow::Result<Config> {
         let mut values: HashMap<String, String> = values.iter().cloned().collect();
+        let auth = AuthMethod::from_values(&mut values)?;
+        let addr = values
+            .remove("addr")
+            .or_else(|| values.remove("ADDR"))
+            .unwrap_or_else(|| DEFAULT_VAULT_ADDR.to_string());
+        let discovery = Discovery::from_addr(&addr, &mut values)?;
         let config = Config {
-            addr: values
-                .remove("addr")
-                .or_else(|| values.remove("ADDR"))
-                .unwrap_or_else(|| DEFAULT_VAULT_ADDR.to_string())
-                .parse()
-                .unwrap_or_else(|_| {
+            addrs: match &discovery {
+                Some(discovery) => vec![discovery.initial_addr()],
+                None => parse_addrs("VAULT_ADDR", &addr).unwrap_or_else(|| {
                     eprintln!(
-                        "Could not parse VAULT_ADDR as Url, using default of {}",
+                        "No valid Url in VAULT_ADDR, using default of {}",
                         DEFAULT_VAULT_ADDR
                     );
-                    DEFAULT_VAULT_ADDR.parse().unwrap()
+                    vec![DEFAULT_VAULT_ADDR.parse().unwrap()]
                 }),
-            token: values
-                .remove("token")
-                .or_else(|| values.remove("TOKEN"))
-                .ok_or_else(|| anyhow::anyhow!("missing setting for 'token' or VAULT_TOKEN"))?,
+            },
+            discovery,
+            discovery_interval: values
+                .remove("discovery_interval_secs")
+                .or_else(|| values.remove("DISCOVERY_INTERVAL_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid discovery_interval_secs: {e}"))?
+                .unwrap_or(DEFAULT_DISCOVERY_INTERVAL),
+            read_addrs: values
+                .remove("read_addr")
+                .or_else(|| values.remove("READ_ADDR"))
+                .and_then(|addrs| parse_addrs("read_addr", &addrs))
+                .unwrap_or_default(),
+            read_after_write: values
+                .remove("read_after_write_secs")
+                .or_else(|| values.remove("READ_AFTER_WRITE_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid read_after_write_secs: {e}"))?
+                .unwrap_or(DEFAULT_READ_AFTER_WRITE),
+            read_your_writes: values
+                .remove("read_your_writes")
+                .or_else(|| values.remove("READ_YOUR_WRITES"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid read_your_writes: {e}"))?
+                .unwrap_or_default(),
+            token: match values.remove("token").or_else(|| values.remove("TOKEN")) {
+                Some(token) => token,
+                // The token is fetched with the auth method on the first request
+                None if auth.is_some() => String::new(),
+                None => anyhow::bail!("missing setting for 'token' or VAULT_TOKEN"),
+            },
+            secondary_token: values
+                .remove("secondary_token")
+                .or_else(|| values.remove("SECONDARY_TOKEN"))
+                .filter(|token| !token.is_empty()),
+            auth,
             mount: values
                 .remove("mount")
                 .or_else(|| values.remove("MOUNT"))
                 .unwrap_or_else(|| "secret".to_string()),
+            mounts: values
+                .remove("mounts")
+                .or_else(|| values.remove("MOUNTS"))
+                .map(|mounts| parse_mounts(&mounts))
+                .transpose()?
+                .unwrap_or_default(),
+            kv_version: match values
+                .remove("kv_version")
+                .or_else(|| values.remove("KV_VERSION"))
+                .as_deref()
+            {
+                None => None,
+                Some("1") => Some(1),
+                Some("2") => Some(2),
+                Some(v) => anyhow::bail!("invalid kv_version '{v}', expected 1 or 2"),
+            },
             certs: match values.remove("certs").or_else(|| values.remove("CERTS")) {
                 Some(certs) => certs.split(',').map(|s| s.trim().to_string()).collect(),
                 _ => Vec::new(),
             },
+            tls_min_version: match values
+                .remove("tls_min_version")
+                .or_else(|| values.remove("TLS_MIN_VERSION"))
+                .as_deref()
+                .map(str::trim)
+            {
+                None => None,
+                Some("1.2") => Some(reqwest::tls::Version::TLS_1_2),
+                Some("1.3") => Some(reqwest::tls::Version::TLS_1_3),
+                Some(v) => anyhow::bail!("invalid tls_min_version '{v}', expected 1.2 or 1.3"),
+            },
+            tls_cipher_suites: values
+                .remove("tls_cipher_suites")
+                .or_else(|| values.remove("TLS_CIPHER_SUITES"))
+                .map(|suites| parse_list(&suites))
+                .unwrap_or_default(),
+            tls_skip_verify: values
+                .remove("tls_skip_verify")
+                .or_else(|| values.remove("TLS_SKIP_VERIFY"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid tls_skip_verify: {e}"))?
+                .unwrap_or_default(),
+            tls_client_cert: values
+                .remove("tls_client_cert")
+                .or_else(|| values.remove("TLS_CLIENT_CERT"))
+                .filter(|path| !path.trim().is_empty()),
+            tls_client_key: values
+                .remove("tls_client_key")
+                .or_else(|| values.remove("TLS_CLIENT_KEY"))
+                .filter(|path| !path.trim().is_empty()),
+            tls_client_reload: values
+                .remove("tls_client_reload_secs")
+                .or_else(|| values.remove("TLS_CLIENT_RELOAD_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid tls_client_reload_secs: {e}"))?
+                .unwrap_or(DEFAULT_TLS_CLIENT_RELOAD),
+            headers: values
+                .remove("headers")
+                .or_else(|| values.remove("HEADERS"))
+                .map(|headers| parse_headers(&headers))
+                .transpose()?
+                .unwrap_or_default(),
+            pool_max_idle_per_host: values
+                .remove("pool_max_idle_per_host")
+                .or_else(|| values.remove("POOL_MAX_IDLE_PER_HOST"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid pool_max_idle_per_host: {e}"))?,
+            pool_idle_timeout: values
+                .remove("pool_idle_timeout_secs")
+                .or_else(|| values.remove("POOL_IDLE_TIMEOUT_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid pool_idle_timeout_secs: {e}"))?,
+            tcp_keepalive: values
+                .remove("tcp_keepalive_secs")
+                .or_else(|| values.remove("TCP_KEEPALIVE_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid tcp_keepalive_secs: {e}"))?
+                .filter(|interval| !interval.is_zero()),
+            audit: values
+                .remove("audit")
+                .or_else(|| values.remove("AUDIT"))
+                .map(|sink| sink.parse())
+                .transpose()?,
+            audit_mount: values
+                .remove("audit_mount")
+                .or_else(|| values.remove("AUDIT_MOUNT"))
+                .map(|mount| mount.trim_matches('/').to_string())
+                .filter(|mount| !mount.is_empty()),
+            max_concurrent_requests: values
+                .remove("max_concurrent_requests")
+                .or_else(|| values.remove("MAX_CONCURRENT_REQUESTS"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid max_concurrent_requests: {e}"))?,
+            max_queued_requests: values
+                .remove("max_queued_requests")
+                .or_else(|| values.remove("MAX_QUEUED_REQUESTS"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid max_queued_requests: {e}"))?
+                .unwrap_or(DEFAULT_MAX_QUEUED_REQUESTS),
+            max_read_bytes_per_sec: values
+                .remove("max_read_bytes_per_sec")
+                .or_else(|| values.remove("MAX_READ_BYTES_PER_SEC"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid max_read_bytes_per_sec: {e}"))?,
+            max_write_bytes_per_sec: values
+                .remove("max_write_bytes_per_sec")
+                .or_else(|| values.remove("MAX_WRITE_BYTES_PER_SEC"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid max_write_bytes_per_sec: {e}"))?,
+            max_request_bytes: values
+                .remove("max_request_bytes")
+                .or_else(|| values.remove("MAX_REQUEST_BYTES"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid max_request_bytes: {e}"))?
+                .unwrap_or(DEFAULT_MAX_REQUEST_BYTES),
+            idempotency_window: values
+                .remove("idempotency_window_secs")
+                .or_else(|| values.remove("IDEMPOTENCY_WINDOW_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid idempotency_window_secs: {e}"))?
+                .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW),
+            validate_on_link: values
+                .remove("validate_on_link")
+                .or_else(|| values.remove("VALIDATE_ON_LINK"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid validate_on_link: {e}"))?
+                .unwrap_or_default(),
+            require_reachable_on_link: values
+                .remove("require_reachable_on_link")
+                .or_else(|| values.remove("REQUIRE_REACHABLE_ON_LINK"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid require_reachable_on_link: {e}"))?
+                .unwrap_or_default(),
+            prewarm_on_link: values
+                .remove("prewarm_on_link")
+                .or_else(|| values.remove("PREWARM_ON_LINK"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid prewarm_on_link: {e}"))?
+                .unwrap_or_default(),
+            sealed_retry: values
+                .remove("sealed_retry_secs")
+                .or_else(|| values.remove("SEALED_RETRY_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid sealed_retry_secs: {e}"))?
+                .unwrap_or(DEFAULT_SEALED_RETRY),
+            seal_poll_interval: values
+                .remove("seal_poll_interval_secs")
+                .or_else(|| values.remove("SEAL_POLL_INTERVAL_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid seal_poll_interval_secs: {e}"))?
+                .unwrap_or(DEFAULT_SEAL_POLL_INTERVAL),
+            rate_limit_retry: values
+                .remove("rate_limit_retry_secs")
+                .or_else(|| values.remove("RATE_LIMIT_RETRY_SECS"))
+                .map(|secs| secs.parse().map(Duration::from_secs))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid rate_limit_retry_secs: {e}"))?
+                .unwrap_or_default(),
+            slow_request: values
+                .remove("slow_request_ms")
+                .or_else(|| values.remove("SLOW_REQUEST_MS"))
+                .map(|ms| ms.parse().map(Duration::from_millis))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid slow_request_ms: {e}"))?,
+            hedge_percentile: values
+                .remove("hedge_percentile")
+                .or_else(|| values.remove("HEDGE_PERCENTILE"))
+                .map(|p| match p.parse::<f64>() {
+                    Ok(p) if p > 0.0 && p < 100.0 => Ok(p),
+                    _ => Err(anyhow::anyhow!(
+                        "invalid hedge_percentile '{p}', expected a number between 0 and 100"
+                    )),
+                })
+                .transpose()?,
+            fault_injection: values
+                .remove("unsafe_fault_injection")
+                .or_else(|| values.remove("UNSAFE_FAULT_INJECTION"))
+                .map(|faults| faults.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid unsafe_fault_injection: {e}"))?,
+            skip_unchanged_writes: values
+                .remove("skip_unchanged_writes")
+                .or_else(|| values.remove("SKIP_UNCHANGED_WRITES"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid skip_unchanged_writes: {e}"))?
+                .unwrap_or_default(),
+            usage_accounting: values
+                .remove("usage_accounting")
+                .or_else(|| values.remove("USAGE_ACCOUNTING"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid usage_accounting: {e}"))?
+                .unwrap_or_default(),
+            container_max_versions: values
+                .remove("container_max_versions")
+                .or_else(|| values.remove("CONTAINER_MAX_VERSIONS"))
+                .map(|limits| parse_max_versions(&limits))
+                .transpose()?
+                .unwrap_or_default(),
+            compress_min_bytes: values
+                .remove("compress_min_bytes")
+                .or_else(|| values.remove("COMPRESS_MIN_BYTES"))
+                .map(|min| min.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid compress_min_bytes: {e}"))?,
+            download_chunk_bytes: values
+                .remove("download_chunk_bytes")
+                .or_else(|| values.remove("DOWNLOAD_CHUNK_BYTES"))
+                .map(|size| size.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid download_chunk_bytes: {e}"))?
+                .filter(|size| *size > 0),
+            content_addressed: values
+                .remove("content_addressed")
+                .or_else(|| values.remove("CONTENT_ADDRESSED"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid content_addressed: {e}"))?
+                .unwrap_or_default(),
+            allow_garbage_collection: values
+                .remove("allow_garbage_collection")
+                .or_else(|| values.remove("ALLOW_GARBAGE_COLLECTION"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid allow_garbage_collection: {e}"))?
+                .unwrap_or_default(),
+            allow_export: values
+                .remove("allow_export")
+                .or_else(|| values.remove("ALLOW_EXPORT"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid allow_export: {e}"))?
+                .unwrap_or_default(),
+            list_metadata: values
+                .remove("list_metadata")
+                .or_else(|| values.remove("LIST_METADATA"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid list_metadata: {e}"))?
+                .unwrap_or_default(),
+            list_metadata_concurrency: values
+                .remove("list_metadata_concurrency")
+                .or_else(|| values.remove("LIST_METADATA_CONCURRENCY"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid list_metadata_concurrency: {e}"))?
+                .unwrap_or(DEFAULT_LIST_METADATA_CONCURRENCY)
+                .max(1),
+            list_concurrency: values
+                .remove("list_concurrency")
+                .or_else(|| values.remove("LIST_CONCURRENCY"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid list_concurrency: {e}"))?
+                .unwrap_or(DEFAULT_LIST_CONCURRENCY)
+                .max(1),
+            list_max_folder_keys: values
+                .remove("list_max_folder_keys")
+                .or_else(|| values.remove("LIST_MAX_FOLDER_KEYS"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid list_max_folder_keys: {e}"))?,
+            list_max_keys: values
+                .remove("list_max_keys")
+                .or_else(|| values.remove("LIST_MAX_KEYS"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid list_max_keys: {e}"))?,
+            batch_concurrency: values
+                .remove("batch_concurrency")
+                .or_else(|| values.remove("BATCH_CONCURRENCY"))
+                .map(|max| max.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid batch_concurrency: {e}"))?
+                .unwrap_or(DEFAULT_BATCH_CONCURRENCY)
+                .max(1),
+            events_subject: values
+                .remove("events_subject")
+                .or_else(|| values.remove("EVENTS_SUBJECT"))
+                .filter(|subject| !subject.trim().is_empty()),
+            vault_events: values
+                .remove("vault_events")
+                .or_else(|| values.remove("VAULT_EVENTS"))
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid vault_events: {e}"))?
+                .unwrap_or_default(),
+            allowed_prefixes: values
+                .remove("allowed_prefixes")
+                .or_else(|| values.remove("ALLOWED_PREFIXES"))
+                .map(|prefixes| parse_list(&prefixes))
+                .unwrap_or_default(),
+            denied_prefixes: values
+                .remove("denied_prefixes")
+                .or_else(|| values.remove("DENIED_PREFIXES"))
+                .map(|prefixes| parse_list(&prefixes))
+                .unwrap_or_default(),
         };
+        if config.tls_client_cert.is_some() != config.tls_client_key.is_some() {
+            anyhow::bail!("tls_client_cert and tls_client_key must be set together");
+        }
+        if matches!(config.auth, Some(AuthMethod::Cert { .. })) && config.tls_client_cert.is_none()
+        {
+            anyhow::bail!("auth_method cert requires tls_client_cert and tls_client_key");
+        }
+        if config
+            .addrs
+            .iter()
+            .chain(&config.read_addrs)
+            .any(|addr| addr.scheme() == "unix")
+        {
+            // Logins and the event stream are sent by HTTP clients that only connect over TCP
+            if config.auth.is_some() || config.vault_events {
+                anyhow::bail!(
+                    "unix:// addresses can't be combined with auth_method or vault_events"
+                );
+            }
+        }
+        if !config.tls_cipher_suites.is_empty() {
+            if config.tls_skip_verify {
+                anyhow::bail!("tls_cipher_suites can't be combined with tls_skip_verify");
+            }
+            // reqwest uses native-tls when both backends are enabled
+            #[cfg(any(not(feature = "rustls"), feature = "native-tls"))]
+            anyhow::bail!(
+                "tls_cipher_suites needs the rustls TLS backend, set the ciphers of native-tls in \
+                 the OpenSSL configuration"
+            );
+            #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+            crate::tls::check(&config)?;
+        }
+        let unversioned = config.kv_version == Some(1)
+            || std::iter::once(&config.mount)
+                .chain(config.mounts.iter().map(|(_, mount)| mount))
+                .any(|mount| mount.trim_matches('/') == "cubbyhole");
+        if unversioned && (config.content_addressed || config.usage_accounting) {
+            anyhow::bail!(
+                "content_addressed and usage_accounting need check-and-set writes, which KV v1 \
+                 and cubbyhole mounts don't support"
+            );
+        }
         Ok(config)
     }
 }
+
+/// Reads a provider-level configuration file with defaults for the values of every link. The file
+/// is a JSON object keyed by the same settings as linkdef values. Strings, numbers and booleans are
+/// taken as is, arrays are joined into comma-separated lists and objects into comma-separated
+/// `key:value` pairs, e.g.
+/// `{"certs": ["/etc/vault/ca.pem"], "sealed_retry_secs": 30, "validate_on_link": true}`
+pub fn load_defaults(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("cannot read config file {}: {e}", path.display()))?;
+    let settings: HashMap<String, serde_json::Value> = serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("invalid config file {}: {e}", path.display()))?;
+    settings
+        .into_iter()
+        .map(|(key, value)| {
+            let value = setting_value(&value)
+                .ok_or_else(|| anyhow::anyhow!("invalid value for '{key}' in config file"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Converts a JSON value of the config file to the string a linkdef value would hold
+fn setting_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(setting_value)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        serde_json::Value::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| setting_value(value).map(|value| format!("{key}:{value}")))
+            .collect::<Option<Vec<_>>>()
+            .map(|entries| entries.join(",")),
+        _ => None,
+    }
+}
+
+/// Combines provider-level defaults with the values of a link. A link value overrides the default
+/// of the same setting, in either case
+pub fn with_defaults(
+    defaults: &[(String, String)],
+    values: &[(String, String)],
+) -> Vec<(String, String)> {
+    defaults
+        .iter()
+        .filter(|(key, _)| {
+            !values
+                .iter()
+                .any(|(link_key, _)| link_key.eq_ignore_ascii_case(key))
+        })
+        .chain(values)
+        .cloned()
+        .collect()
+}
+
+/// Parses a comma-separated list, skipping empty entries
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Parses a comma-separated list of `prefix:mount` pairs
+fn parse_mounts(mounts: &str) -> anyhow::Result<Vec<(String, String)>> {
+    mounts
+        .split(',')
+        .map(str::trim)
+        .filter(|route| !route.is_empty())
+        .map(|route| match route.split_once(':') {
+            Some((prefix, mount)) if !prefix.is_empty() && !mount.is_empty() => {
+                Ok((prefix.to_string(), mount.to_string()))
+            }
+            _ => Err(anyhow::anyhow!(
+                "invalid mounts entry '{route}', expected 'prefix:mount'"
+            )),
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `name:value` headers
+fn parse_headers(headers: &str) -> anyhow::Result<reqwest::header::HeaderMap> {
+    headers
+        .split(',')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .map(|header| {
+            let invalid =
+                || anyhow::anyhow!("invalid headers entry '{header}', expected 'name:value'");
+            let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+            let name: reqwest::header::HeaderName = name.trim().parse().map_err(|_| invalid())?;
+            if matches!(name.as_str(), "x-vault-token" | "x-vault-namespace") {
+                anyhow::bail!("header '{name}' is set by the client and can't be configured");
+            }
+            Ok((name, value.trim().parse().map_err(|_| invalid())?))
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of `container:count` version limits
+fn parse_max_versions(limits: &str) -> anyhow::Result<HashMap<String, u64>> {
+    limits
+        .split(',')
+        .map(str::trim)
+        .filter(|limit| !limit.is_empty())
+        .map(|limit| {
+            let invalid = || {
+                anyhow::anyhow!(
+                    "invalid container_max_versions entry '{limit}', expected 'container:count'"
+                )
+            };
+            let (container, count) = limit.rsplit_once(':').ok_or_else(invalid)?;
+            let count = count.trim().parse().map_err(|_| invalid())?;
+            Ok((container.trim().to_string(), count))
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of http(s) or unix Urls, skipping any that are invalid. Returns None if
+/// none are valid
+fn parse_addrs(setting: &str, addrs: &str) -> Option<Vec<Url>> {
+    let parsed: Vec<Url> = addrs
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .filter_map(|addr| {
+            addr.parse()
+                .map_err(|_| eprintln!("Could not parse '{addr}' in {setting} as Url, skipping"))
+                .ok()
+        })
+        .filter(|addr: &Url| match addr.scheme() {
+            "http" | "https" => true,
+            "unix" if cfg!(unix) => true,
+            scheme => {
+                eprintln!("Unsupported scheme '{scheme}' of '{addr}' in {setting}, skipping");
+                false
+            }
+        })
+        .collect();
+    (!parsed.is_empty()).then_some(parsed)
+}
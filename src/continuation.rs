@@ -0,0 +1,82 @@
+//! Continuation tokens of paged listings
+//!
+//! A token records the container and prefix a listing was for and the last name it returned,
+//! and is signed with HMAC-SHA256 under a key of the provider. Actors can't read or forge
+//! tokens, so a token can't make a listing continue in another container or outside the prefix
+//! it was issued for. The key is random unless set with `VAULT_CONTINUATION_KEY`, which
+//! providers sharing a lattice need so each accepts the tokens of the others.
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::wasmcloud_interface_blobstore::ListSort;
+
+/// Where a listing continues
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Continuation {
+    pub container: String,
+    pub prefix: String,
+    /// Order of the listing
+    #[serde(default)]
+    pub sort: ListSort,
+    /// Last name returned, the listing continues with the names after it
+    pub after: String,
+    /// Seconds and nanoseconds of the modification time of the last name returned, if the
+    /// listing is ordered by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_modified: Option<(u64, u32)>,
+}
+
+impl Continuation {
+    /// Returns whether the continuation is of a listing of the prefix in the container, in the
+    /// order given, so a token can't be used to page through another listing
+    pub fn is_for(&self, container: &str, prefix: &str, sort: ListSort) -> bool {
+        self.container == container && self.prefix == prefix && self.sort == sort
+    }
+}
+
+/// Key continuation tokens are signed with
+#[derive(Clone)]
+pub struct ContinuationKey {
+    mac: Hmac<Sha256>,
+}
+
+impl Default for ContinuationKey {
+    /// Creates a random key, so tokens are only accepted by the provider that issued them
+    fn default() -> Self {
+        ContinuationKey::new(rand::random::<[u8; 32]>())
+    }
+}
+
+impl ContinuationKey {
+    pub fn new(key: impl AsRef<[u8]>) -> ContinuationKey {
+        ContinuationKey {
+            mac: Hmac::new_from_slice(key.as_ref()).expect("HMAC accepts keys of any length"),
+        }
+    }
+
+    /// Returns the signed token of a continuation
+    pub fn encode(&self, continuation: &Continuation) -> String {
+        let payload = serde_json::to_vec(continuation).expect("continuations serialize to JSON");
+        let mut mac = self.mac.clone();
+        mac.update(&payload);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    /// Returns the continuation of a token, or None if it wasn't signed with this key
+    pub fn decode(&self, token: &str) -> Option<Continuation> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = self.mac.clone();
+        mac.update(&payload);
+        // Compared in constant time, so the signature can't be guessed byte by byte
+        mac.verify_slice(&signature).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+}
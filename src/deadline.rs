@@ -0,0 +1,45 @@
+//! Deadlines of invocations
+//!
+//! The provider runs each invocation within [`scope`], with the time by which the caller needs
+//! the result. Requests retried while Vault is sealed or rate limiting give up at the deadline
+//! rather than sleeping past it, and operations visiting many secrets, such as exports, stop
+//! with [`VaultError::DeadlineExceeded`] instead of being cancelled halfway without a reason.
+//! Work outside of an invocation, like background garbage collection, has no deadline.
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::error::VaultError;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Runs `f` with a deadline. Tasks spawned by `f` don't inherit it
+pub async fn scope<F: Future>(deadline: Instant, f: F) -> F::Output {
+    DEADLINE.scope(deadline, f).await
+}
+
+/// Returns the deadline of the current invocation, if there is one
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Returns the time left until the deadline of the current invocation, if there is one
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Returns the earlier of `instant` and the deadline of the current invocation
+pub fn cap(instant: Instant) -> Instant {
+    current().map_or(instant, |deadline| deadline.min(instant))
+}
+
+/// Fails with [`VaultError::DeadlineExceeded`] if the deadline of the current invocation passed
+pub fn check() -> Result<(), VaultError> {
+    match current() {
+        Some(deadline) if Instant::now() >= deadline => Err(VaultError::DeadlineExceeded),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,229 @@
+//! Discovery of the Vault servers of a link, for infrastructure where their addresses change
+//!
+//! An `addr` of `srv://<name>` is resolved as the DNS SRV records of `name`, such as
+//! `srv://vault.service.consul`, and the servers are connected to over HTTPS, or over HTTP with
+//! `srv+http://<name>`. The records are resolved again every `discovery_interval_secs`, and the
+//! client fails over between the servers in the order of their priority and weight. Until the
+//! first resolution finishes, requests are sent to `name` itself on Vault's default port.
+//!
+//! An `addr` of `consul://<service>` (or `consul+http://<service>`) queries the health API of
+//! the Consul agent at `consul_addr` for the passing instances of `service` instead, with the
+//! ACL token `consul_token` if set. The active server, which Vault tags `active` when it
+//! registers itself, is tried first so writes don't fail over from standbys. Until the first
+//! query finishes, requests are sent to `<service>.service.consul` on Vault's default port.
+use std::{collections::HashMap, sync::Weak, time::Duration};
+
+use hickory_resolver::{error::ResolveError, TokioAsyncResolver};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::failover::Nodes;
+
+/// Default time between two discoveries of the servers
+pub const DEFAULT_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+/// Port Vault listens on by default
+const DEFAULT_VAULT_PORT: u16 = 8200;
+/// Default address of the Consul agent
+pub const DEFAULT_CONSUL_ADDR: &str = "http://127.0.0.1:8500";
+/// Tag Vault registers its active server with in Consul
+const ACTIVE_TAG: &str = "active";
+
+/// How the servers of a link are discovered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Discovery {
+    /// From the SRV records of a DNS name
+    Srv {
+        name: String,
+        /// Scheme the servers are connected to with
+        scheme: &'static str,
+    },
+    /// From the passing instances of a service in the Consul catalog
+    Consul {
+        /// Address of the Consul agent
+        consul_addr: Url,
+        /// ACL token for the Consul API, if required
+        token: Option<String>,
+        service: String,
+        /// Scheme the servers are connected to with
+        scheme: &'static str,
+    },
+}
+
+/// An instance of a service in a Consul health response
+#[derive(Deserialize)]
+struct ServiceEntry {
+    #[serde(rename = "Node")]
+    node: ConsulNode,
+    #[serde(rename = "Service")]
+    service: ConsulService,
+}
+
+#[derive(Deserialize)]
+struct ConsulNode {
+    #[serde(rename = "Address")]
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct ConsulService {
+    /// Address of the instance, empty if it is the address of its node
+    #[serde(rename = "Address", default)]
+    address: String,
+    #[serde(rename = "Port")]
+    port: u16,
+    #[serde(rename = "Tags", default)]
+    tags: Option<Vec<String>>,
+}
+
+impl Discovery {
+    /// Returns the discovery an `addr` setting asks for, or None if it is a plain address. The
+    /// settings of Consul discovery are removed from `values`
+    pub fn from_addr(
+        addr: &str,
+        values: &mut HashMap<String, String>,
+    ) -> anyhow::Result<Option<Discovery>> {
+        let (kind, scheme, name) = match addr.trim().split_once("://") {
+            Some(("srv", name)) => ("srv", "https", name),
+            Some(("srv+http", name)) => ("srv", "http", name),
+            Some(("consul", name)) => ("consul", "https", name),
+            Some(("consul+http", name)) => ("consul", "http", name),
+            _ => return Ok(None),
+        };
+        let name = name.trim_end_matches('/');
+        if name.is_empty() || name.contains([',', '/']) {
+            anyhow::bail!("invalid discovery address '{addr}', expected '{kind}://<name>'");
+        }
+        if kind == "srv" {
+            return Ok(Some(Discovery::Srv {
+                name: name.to_string(),
+                scheme,
+            }));
+        }
+        let consul_addr = values
+            .remove("consul_addr")
+            .or_else(|| values.remove("CONSUL_ADDR"))
+            .unwrap_or_else(|| DEFAULT_CONSUL_ADDR.to_string());
+        Ok(Some(Discovery::Consul {
+            consul_addr: consul_addr
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid consul_addr: {e}"))?,
+            token: values
+                .remove("consul_token")
+                .or_else(|| values.remove("CONSUL_TOKEN"))
+                .filter(|token| !token.is_empty()),
+            service: name.to_string(),
+            scheme,
+        }))
+    }
+
+    /// Returns the address requests are sent to until the servers were discovered
+    pub fn initial_addr(&self) -> Url {
+        match self {
+            Discovery::Srv { name, scheme } => format!("{scheme}://{name}:{DEFAULT_VAULT_PORT}")
+                .parse()
+                .expect("SRV names are valid hosts"),
+            Discovery::Consul {
+                service, scheme, ..
+            } => format!("{scheme}://{service}.service.consul:{DEFAULT_VAULT_PORT}")
+                .parse()
+                .expect("service names are valid hosts"),
+        }
+    }
+
+    /// Returns the addresses of the servers in the order they should be tried
+    async fn discover(
+        &self,
+        resolver: &Result<TokioAsyncResolver, ResolveError>,
+        http: &reqwest::Client,
+    ) -> anyhow::Result<Vec<Url>> {
+        match self {
+            Discovery::Srv { name, scheme } => {
+                let mut records: Vec<_> = resolver
+                    .as_ref()
+                    .map_err(Clone::clone)?
+                    .srv_lookup(name.as_str())
+                    .await?
+                    .into_iter()
+                    .collect();
+                // Lower priorities are preferred, and within a priority higher weights
+                records.sort_by_key(|srv| (srv.priority(), std::cmp::Reverse(srv.weight())));
+                records
+                    .iter()
+                    .map(|srv| {
+                        let host = srv.target().to_utf8();
+                        let host = host.trim_end_matches('.');
+                        Ok(format!("{scheme}://{host}:{}", srv.port()).parse()?)
+                    })
+                    .collect()
+            }
+            Discovery::Consul {
+                consul_addr,
+                token,
+                service,
+                scheme,
+            } => {
+                let mut request = http
+                    .get(consul_addr.join(&format!("v1/health/service/{service}"))?)
+                    .query(&[("passing", "true")]);
+                if let Some(token) = token {
+                    request = request.header("X-Consul-Token", token);
+                }
+                let body = request.send().await?.error_for_status()?.bytes().await?;
+                let mut entries: Vec<ServiceEntry> = serde_json::from_slice(&body)?;
+                // The sort is stable, so instances keep the catalog's order otherwise
+                entries.sort_by_key(|entry| {
+                    !entry
+                        .service
+                        .tags
+                        .iter()
+                        .flatten()
+                        .any(|tag| tag == ACTIVE_TAG)
+                });
+                entries
+                    .iter()
+                    .map(|entry| {
+                        let host = match entry.service.address.as_str() {
+                            "" => &entry.node.address,
+                            address => address,
+                        };
+                        Ok(format!("{scheme}://{host}:{}", entry.service.port).parse()?)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Discovers the servers every `interval`, starting right away, and replaces the servers of
+/// `nodes` with them. Stops once the link's nodes are dropped
+pub async fn run(discovery: Discovery, interval: Duration, nodes: Weak<Nodes>) {
+    // Only SRV discovery needs the resolver, so failing to create it is reported when it is
+    // used
+    let resolver = TokioAsyncResolver::tokio_from_system_conf();
+    let http = reqwest::Client::new();
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let Some(nodes) = nodes.upgrade() else {
+            return;
+        };
+        let addrs = match discovery.discover(&resolver, &http).await {
+            Ok(addrs) if addrs.is_empty() => {
+                warn!("Discovered no Vault servers, keeping the current ones");
+                continue;
+            }
+            Ok(addrs) => addrs,
+            Err(e) => {
+                warn!(error = %e, "Failed to discover Vault servers");
+                continue;
+            }
+        };
+        match nodes.set_addrs(&addrs) {
+            Ok(true) => info!(?addrs, "Discovered new Vault servers"),
+            Ok(false) => debug!(?addrs, "Vault servers unchanged"),
+            Err(e) => warn!(error = %e, "Failed to update the Vault servers"),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Delivery of the chunks of large downloads to actors
+//!
+//! Objects larger than a link's `download_chunk_bytes` are returned by `get_object` with only
+//! their first chunk, and the rest is sent to the actor's `ChunkReceiver.ReceiveChunk` operation.
+//! Chunks of a download are sent one at a time in the order of their offsets, each only after the
+//! actor acknowledged the previous one. A chunk that fails to be delivered is retried rather than
+//! skipped, so the actor never sees a gap, but may see a chunk twice if an acknowledgement was
+//! lost. Concurrent downloads of the same object by the same actor are delivered one after the
+//! other, so their chunks don't interleave.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tracing::{debug, error, warn};
+use wasmcloud_provider_sdk::core::WasmCloudEntity;
+
+use crate::wasmcloud_interface_blobstore::{Chunk, ChunkResponse};
+
+/// Operation of actors chunks are sent to
+const RECEIVE_CHUNK: &str = "ChunkReceiver.ReceiveChunk";
+/// Times sending a chunk is attempted before the download is abandoned
+const SEND_ATTEMPTS: u32 = 3;
+/// Delay before sending a chunk again, doubling with every attempt
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Actor, container and object of a download
+type DownloadKey = (String, String, String);
+
+/// Sends the chunks of downloads to actors
+#[derive(Default)]
+pub struct ChunkSender {
+    /// Downloads being sent, so later downloads of the same object wait for them
+    active: Mutex<HashMap<DownloadKey, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ChunkSender {
+    /// Sends `rest` to the actor in chunks of `chunk_bytes`, after any earlier download of the
+    /// same object by the actor was sent. Stops once the actor sets `cancel_download`, or a chunk
+    /// can't be delivered, dropping the data that wasn't sent yet
+    pub async fn send(&self, actor_id: String, rest: Chunk, chunk_bytes: usize) {
+        let key = (
+            actor_id.clone(),
+            rest.container_id.clone(),
+            rest.object_id.clone(),
+        );
+        let turn = self
+            .active
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        {
+            let _turn = turn.lock().await;
+            send_chunks(actor_id, rest, chunk_bytes).await;
+        }
+        // Only the map and this download hold the lock if no other download is waiting for it
+        let mut active = self.active.lock().unwrap();
+        drop(turn);
+        if active
+            .get(&key)
+            .is_some_and(|turn| Arc::strong_count(turn) == 1)
+        {
+            active.remove(&key);
+        }
+    }
+}
+
+async fn send_chunks(actor_id: String, mut rest: Chunk, chunk_bytes: usize) {
+    let origin = match wasmcloud_provider_sdk::load_host_data() {
+        Ok(host_data) => WasmCloudEntity {
+            public_key: host_data.provider_key.clone(),
+            link_name: host_data.link_name.clone(),
+            contract_id: "wasmcloud:blobstore".to_string(),
+        },
+        Err(e) => {
+            error!(error = %e, "Failed to load host data, can't send chunks");
+            return;
+        }
+    };
+    let target = WasmCloudEntity {
+        public_key: actor_id,
+        ..Default::default()
+    };
+    while !rest.bytes.is_empty() {
+        let bytes = rest.bytes.split_to(chunk_bytes.min(rest.bytes.len()));
+        let chunk = Chunk {
+            object_id: rest.object_id.clone(),
+            container_id: rest.container_id.clone(),
+            offset: rest.offset,
+            is_last: rest.bytes.is_empty(),
+            bytes,
+        };
+        rest.offset += chunk.bytes.len() as u64;
+        match send_chunk(&origin, &target, &chunk).await {
+            Ok(response) if response.cancel_download => {
+                debug!(
+                    actor_id = %target.public_key,
+                    object_id = %chunk.object_id,
+                    offset = chunk.offset,
+                    "Actor cancelled download"
+                );
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!(
+                    actor_id = %target.public_key,
+                    object_id = %chunk.object_id,
+                    offset = chunk.offset,
+                    error = %e,
+                    "Failed to send chunk, abandoning download"
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Sends a chunk to the actor, retrying failed attempts
+async fn send_chunk(
+    origin: &WasmCloudEntity,
+    target: &WasmCloudEntity,
+    chunk: &Chunk,
+) -> Result<ChunkResponse, wasmcloud_provider_sdk::error::InvocationError> {
+    let payload = wasmcloud_provider_sdk::serialize(chunk)?;
+    let mut delay = SEND_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let res = wasmcloud_provider_sdk::get_connection()
+            .get_rpc_client()
+            .send(
+                origin.clone(),
+                target.clone(),
+                RECEIVE_CHUNK,
+                payload.clone(),
+            )
+            .await
+            .and_then(|response| wasmcloud_provider_sdk::deserialize(&response));
+        match res {
+            Err(e) if attempt < SEND_ATTEMPTS => {
+                debug!(
+                    actor_id = %target.public_key,
+                    offset = chunk.offset,
+                    attempt,
+                    error = %e,
+                    "Failed to send chunk, retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
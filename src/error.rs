@@ -1,3 +1,8 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use vaultrs::error::ClientError;
+
 #[derive(thiserror::Error, Debug)]
 pub enum VaultError {
     /// Key not found error.
@@ -6,7 +11,232 @@ pub enum VaultError {
     #[error("Key not found: namespace/key {namespace}/{path}")]
     NotFound { namespace: String, path: String },
 
-    /// All other errors
-    #[error("An error occurred with the request")]
-    Client(#[from] vaultrs::error::ClientError),
+    /// The link's path rules don't allow accessing the path
+    #[error("Access denied to {path}")]
+    Denied { path: String },
+
+    /// A conditional write found a different version of the secret than expected
+    #[error("Version of {path} doesn't match the expected version")]
+    VersionMismatch { path: String },
+
+    /// An idempotency key was used again within its window for a different write
+    #[error("Idempotency key {key} was already used for a different write")]
+    IdempotencyKeyReused { key: String },
+
+    /// Writing the object would exceed the maximum size of a Vault request
+    #[error(
+        "Object {path} is too large: writing it sends {size} bytes to Vault, the limit is {max}"
+    )]
+    TooLarge {
+        path: String,
+        size: usize,
+        max: usize,
+    },
+
+    /// Listing a container visited more keys than the link allows
+    #[error("Listing {path} exceeds the limit of {max} keys")]
+    TooManyKeys { path: String, max: usize },
+
+    /// Too many requests are already in flight or queued
+    #[error("Too many concurrent requests ({scope} limit), try again later")]
+    Backpressure { scope: &'static str },
+
+    /// Vault is sealed, and didn't get unsealed while the request was retried
+    #[error("Vault is sealed, try again once it is unsealed")]
+    Sealed,
+
+    /// The invocation ran out of time before the operation completed
+    #[error("The operation did not complete before the deadline of the invocation")]
+    DeadlineExceeded,
+
+    /// Data read back from Vault doesn't match the checksum recorded when it was written
+    #[error("{path} doesn't match the checksum recorded when it was written")]
+    Corrupted { path: String },
+
+    /// The operation needs a setting that isn't enabled for the link
+    #[error("{operation} requires {setting} to be enabled")]
+    NotEnabled {
+        operation: &'static str,
+        setting: &'static str,
+    },
+
+    /// All other errors, with the ID Vault logged the request under if it returned one
+    #[error(
+        "An error occurred with the request{}",
+        request_id.as_ref().map(|id| format!(" (Vault request ID {id})")).unwrap_or_default()
+    )]
+    Client {
+        source: ClientError,
+        request_id: Option<String>,
+    },
+
+    /// An error from a request whose result was shared by several callers
+    #[error(transparent)]
+    Shared(Arc<VaultError>),
+}
+
+impl From<ClientError> for VaultError {
+    fn from(source: ClientError) -> Self {
+        VaultError::Client {
+            source,
+            request_id: None,
+        }
+    }
+}
+
+impl VaultError {
+    /// Returns the ID Vault logged the failed request under, if it is known
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            VaultError::Client { request_id, .. } => request_id.as_deref(),
+            VaultError::Shared(err) => err.request_id(),
+            _ => None,
+        }
+    }
+
+    /// Returns the category of the error reported to actors
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            VaultError::NotFound { .. } => ErrorCode::NotFound,
+            VaultError::Denied { .. } => ErrorCode::Unauthorized,
+            VaultError::VersionMismatch { .. } => ErrorCode::Conflict,
+            VaultError::TooLarge { .. } => ErrorCode::TooLarge,
+            VaultError::TooManyKeys { .. } => ErrorCode::TooLarge,
+            VaultError::IdempotencyKeyReused { .. } => ErrorCode::Conflict,
+            VaultError::Backpressure { .. } => ErrorCode::Unavailable,
+            VaultError::Sealed => ErrorCode::Sealed,
+            VaultError::DeadlineExceeded => ErrorCode::Unavailable,
+            VaultError::NotEnabled { .. } => ErrorCode::Unauthorized,
+            VaultError::Corrupted { .. } => ErrorCode::Internal,
+            VaultError::Client {
+                source: ClientError::APIError { code, .. },
+                ..
+            } => match code {
+                404 => ErrorCode::NotFound,
+                401 | 403 => ErrorCode::Unauthorized,
+                409 | 412 => ErrorCode::Conflict,
+                413 => ErrorCode::TooLarge,
+                429 | 502 | 503 | 504 => ErrorCode::Unavailable,
+                _ => ErrorCode::Internal,
+            },
+            VaultError::Client {
+                source: ClientError::RestClientError { .. },
+                ..
+            } => ErrorCode::Unavailable,
+            VaultError::Client { .. } => ErrorCode::Internal,
+            VaultError::Shared(err) => err.code(),
+        }
+    }
+
+    /// Formats the error for returning to an actor, see [`ErrorCode`]
+    pub fn to_rpc_string(&self) -> String {
+        self.code().message(self)
+    }
+
+    /// Recovers an error that was shared between callers. Variants callers are expected to match
+    /// on are recreated, anything else is wrapped in [`VaultError::Shared`]
+    pub fn from_shared(err: Arc<VaultError>) -> VaultError {
+        Arc::try_unwrap(err).unwrap_or_else(|err| match err.as_ref() {
+            VaultError::NotFound { namespace, path } => VaultError::NotFound {
+                namespace: namespace.clone(),
+                path: path.clone(),
+            },
+            VaultError::Denied { path } => VaultError::Denied { path: path.clone() },
+            VaultError::VersionMismatch { path } => {
+                VaultError::VersionMismatch { path: path.clone() }
+            }
+            VaultError::IdempotencyKeyReused { key } => {
+                VaultError::IdempotencyKeyReused { key: key.clone() }
+            }
+            VaultError::TooLarge { path, size, max } => VaultError::TooLarge {
+                path: path.clone(),
+                size: *size,
+                max: *max,
+            },
+            VaultError::TooManyKeys { path, max } => VaultError::TooManyKeys {
+                path: path.clone(),
+                max: *max,
+            },
+            VaultError::Backpressure { scope } => VaultError::Backpressure { scope },
+            VaultError::Sealed => VaultError::Sealed,
+            VaultError::DeadlineExceeded => VaultError::DeadlineExceeded,
+            VaultError::NotEnabled { operation, setting } => {
+                VaultError::NotEnabled { operation, setting }
+            }
+            VaultError::Corrupted { path } => VaultError::Corrupted { path: path.clone() },
+            _ => VaultError::Shared(err),
+        })
+    }
+}
+
+/// Category of an error returned to actors.
+///
+/// Errors sent across the RPC boundary are formatted as `<code>: <message>`, e.g.
+/// `NotFound: Key not found: namespace/key secret/foo`, so actors can branch on the code with
+/// [`ErrorCode::parse`] instead of matching on the message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// The object or container doesn't exist
+    NotFound,
+    /// The actor isn't linked, or the token isn't allowed to perform the operation
+    Unauthorized,
+    /// The object is too large to be stored
+    TooLarge,
+    /// The object was modified concurrently
+    Conflict,
+    /// Vault can't currently handle the request, it may succeed if retried later
+    Unavailable,
+    /// Vault is sealed, requests will fail until an operator unseals it
+    Sealed,
+    /// Any other error
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "NotFound",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::TooLarge => "TooLarge",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::Unavailable => "Unavailable",
+            ErrorCode::Sealed => "Sealed",
+            ErrorCode::Internal => "Internal",
+        }
+    }
+
+    /// Formats an error message with this code for returning to an actor
+    pub fn message(self, message: impl fmt::Display) -> String {
+        format!("{self}: {message}")
+    }
+
+    /// Splits an error returned by the provider into its code and message. Returns None if the
+    /// error doesn't start with a known code
+    pub fn parse(error: &str) -> Option<(ErrorCode, &str)> {
+        let (code, message) = error.split_once(": ")?;
+        Some((code.parse().ok()?, message))
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "NotFound" => ErrorCode::NotFound,
+            "Unauthorized" => ErrorCode::Unauthorized,
+            "TooLarge" => ErrorCode::TooLarge,
+            "Conflict" => ErrorCode::Conflict,
+            "Unavailable" => ErrorCode::Unavailable,
+            "Sealed" => ErrorCode::Sealed,
+            "Internal" => ErrorCode::Internal,
+            _ => anyhow::bail!("unknown error code '{s}'"),
+        })
+    }
 }
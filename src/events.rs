@@ -0,0 +1,53 @@
+//! Events published to the lattice when objects change
+//!
+use serde::Serialize;
+
+use crate::wasmcloud_interface_blobstore::Timestamp;
+
+/// Kind of change made to an object
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOperation {
+    /// The object was written, or copied over
+    Put,
+    /// The object was deleted
+    Remove,
+}
+
+/// A change made to an object by an actor, published after the change succeeded
+#[derive(Clone, Debug, Serialize)]
+pub struct ObjectEvent {
+    pub timestamp: Timestamp,
+    /// Actor that made the change. Empty for changes reported by Vault events
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub actor_id: String,
+    pub operation: ChangeOperation,
+    pub container_id: String,
+    pub object_id: String,
+    /// Version of the secret written by a put
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+impl ObjectEvent {
+    pub fn new(
+        actor_id: impl Into<String>,
+        operation: ChangeOperation,
+        container_id: impl Into<String>,
+        object_id: impl Into<String>,
+    ) -> ObjectEvent {
+        ObjectEvent {
+            timestamp: Timestamp::now(),
+            actor_id: actor_id.into(),
+            operation,
+            container_id: container_id.into(),
+            object_id: object_id.into(),
+            version: None,
+        }
+    }
+
+    pub fn version(mut self, version: u64) -> ObjectEvent {
+        self.version = Some(version);
+        self
+    }
+}
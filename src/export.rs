@@ -0,0 +1,254 @@
+//! Export of a container to a gzipped tar archive, run with
+//! `blobstore_vault --export <container> <archive>` or the `VaultBlobstore.ExportContainer`
+//! operation
+//!
+//! Each object becomes an entry named after its ID relative to the container, with the time it
+//! was last modified. Its content type and encoding are kept in PAX extended headers. Objects are
+//! read and appended one at a time, so only the archive writer buffers more than one object. The
+//! operation writes the archive to a container in parts, each stored once it is complete.
+use std::{future::Future, io::Write};
+
+use flate2::{write::GzEncoder, Compression};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::Client, deadline, error::VaultError, object_path, progress::Progress,
+    wasmcloud_interface_blobstore::Timestamp,
+};
+
+/// PAX header holding the content type of an object
+pub const CONTENT_TYPE_HEADER: &str = "VAULTBLOBSTORE.content_type";
+/// PAX header holding the content encoding of an object
+pub const CONTENT_ENCODING_HEADER: &str = "VAULTBLOBSTORE.content_encoding";
+
+/// Bounds of listings of all objects of a container, so a huge container can't flood Vault with
+/// LIST requests or hold the provider busy
+#[derive(Clone, Copy, Debug)]
+pub struct ListLimits {
+    /// Number of folders listed at once
+    pub concurrency: usize,
+    /// Number of keys a single folder may have
+    pub max_folder_keys: Option<usize>,
+    /// Number of keys, objects and folders, visited in total
+    pub max_keys: Option<usize>,
+}
+
+/// Size of the parts the archive of a `VaultBlobstore.ExportContainer` operation is written in,
+/// well below Vault's default request size even once sent as a JSON array of numbers
+pub const PART_BYTES: usize = 1024 * 1024;
+
+/// Body of the `VaultBlobstore.ExportContainer` operation, which writes the archive of a
+/// container to objects. Returns the IDs of the objects written, see [`part_id`]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExportContainerRequest {
+    /// Container to export
+    #[serde(rename = "containerId")]
+    pub container_id: String,
+    /// Container the archive is written to
+    #[serde(rename = "targetContainerId")]
+    pub target_container_id: String,
+    /// Object the archive is written to, as parts of [`PART_BYTES`] named by [`part_id`]
+    #[serde(rename = "targetObjectId")]
+    pub target_object_id: String,
+}
+
+/// Returns the ID of the part of an archive with the given index. Concatenating the parts in the
+/// order of their IDs gives the archive, e.g. `cat backup.tar.gz.* > backup.tar.gz`
+pub fn part_id(object_id: &str, index: usize) -> String {
+    format!("{object_id}.{index:06}")
+}
+
+/// Writes all objects of a container to `writer` as a gzipped tar archive, returning the writer
+/// and the number of objects exported
+pub async fn archive<W: Write>(
+    client: &Client,
+    container: &str,
+    writer: W,
+    progress: &Progress,
+) -> anyhow::Result<(W, usize)> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    let ids = walk(client, container).await?;
+    progress.start(ids.len());
+    for id in &ids {
+        deadline::check()?;
+        let size = append(&mut builder, client, container, id).await?;
+        progress.advance(size);
+    }
+    Ok((builder.into_inner()?.finish()?, ids.len()))
+}
+
+/// Writes all objects of a container as a gzipped tar archive in parts of `part_bytes`, passing
+/// each to `store` with its index as soon as it is complete, so no more than a part and the
+/// object being archived are held in memory. Returns the number of objects exported and the
+/// number of parts stored
+pub async fn archive_parts<F, Fut>(
+    client: &Client,
+    container: &str,
+    part_bytes: usize,
+    progress: &Progress,
+    mut store: F,
+) -> anyhow::Result<(usize, usize)>
+where
+    F: FnMut(usize, Vec<u8>) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        Parts::new(part_bytes),
+        Compression::default(),
+    ));
+    let mut stored = 0;
+    let ids = walk(client, container).await?;
+    progress.start(ids.len());
+    for id in &ids {
+        deadline::check()?;
+        let size = append(&mut builder, client, container, id).await?;
+        for part in builder.get_mut().get_mut().take_full() {
+            store(stored, part).await?;
+            stored += 1;
+        }
+        progress.advance(size);
+    }
+    let mut parts = builder.into_inner()?.finish()?;
+    for part in parts.take_full().into_iter().chain(parts.take_rest()) {
+        store(stored, part).await?;
+        stored += 1;
+    }
+    Ok((ids.len(), stored))
+}
+
+/// Appends an object of a container to an archive as an entry named after its ID, returning the
+/// size of its data
+async fn append<W: Write>(
+    builder: &mut tar::Builder<GzEncoder<W>>,
+    client: &Client,
+    container: &str,
+    id: &str,
+) -> anyhow::Result<u64> {
+    let (metadata, file) = client
+        .read_with_metadata(object_path::join(container, id))
+        .await?;
+    let extensions: Vec<(&str, &[u8])> = [
+        (CONTENT_TYPE_HEADER, &file.content_type),
+        (CONTENT_ENCODING_HEADER, &file.content_encoding),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.as_deref().map(|v| (key, v.as_bytes())))
+    .collect();
+    if !extensions.is_empty() {
+        builder.append_pax_extensions(extensions)?;
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_size(file.data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        Timestamp::parse_rfc3339(&metadata.updated_time)
+            .map(|t| t.sec)
+            .unwrap_or_default(),
+    );
+    builder.append_data(&mut header, id, file.data.as_ref())?;
+    Ok(file.data.len() as u64)
+}
+
+/// Collects the bytes of an archive until they can be taken as parts of a fixed size
+struct Parts {
+    buffer: Vec<u8>,
+    part_bytes: usize,
+}
+
+impl Parts {
+    fn new(part_bytes: usize) -> Parts {
+        Parts {
+            buffer: Vec::new(),
+            part_bytes: part_bytes.max(1),
+        }
+    }
+
+    /// Removes and returns the complete parts collected so far
+    fn take_full(&mut self) -> Vec<Vec<u8>> {
+        let full = self.buffer.len() / self.part_bytes * self.part_bytes;
+        self.buffer
+            .drain(..full)
+            .as_slice()
+            .chunks(self.part_bytes)
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+
+    /// Removes and returns the last, incomplete part, if there is one
+    fn take_rest(&mut self) -> Option<Vec<u8>> {
+        (!self.buffer.is_empty()).then(|| std::mem::take(&mut self.buffer))
+    }
+}
+
+impl Write for Parts {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the IDs of all objects in a container, relative to it
+pub(crate) async fn walk(client: &Client, container: &str) -> Result<Vec<String>, VaultError> {
+    walk_folder(client, container, "").await
+}
+
+/// Returns the IDs of all objects in a folder of a container and its subfolders, relative to the
+/// container. The folder is empty or ends in `/`. The folders of each level are listed
+/// concurrently within the link's [`ListLimits`], failing with [`VaultError::TooManyKeys`] if a
+/// limit is exceeded
+pub async fn walk_folder(
+    client: &Client,
+    container: &str,
+    folder: &str,
+) -> Result<Vec<String>, VaultError> {
+    let limits = client.list_limits();
+    let mut ids = Vec::new();
+    let mut visited = 0;
+    let mut folders = vec![folder.to_string()];
+    while !folders.is_empty() {
+        deadline::check()?;
+        let listed: Vec<_> = futures::stream::iter(std::mem::take(&mut folders))
+            .map(|folder| async move {
+                let path = object_path::join(container, &folder);
+                (folder, client.list_files(&path).await)
+            })
+            .buffer_unordered(limits.concurrency.max(1))
+            .collect()
+            .await;
+        for (folder, res) in listed {
+            let keys = match res {
+                Ok(keys) => keys,
+                // An empty container has nothing to list
+                Err(VaultError::NotFound { .. }) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(max) = limits.max_folder_keys.filter(|max| keys.len() > *max) {
+                return Err(VaultError::TooManyKeys {
+                    path: object_path::join(container, &folder),
+                    max,
+                });
+            }
+            visited += keys.len();
+            if let Some(max) = limits.max_keys.filter(|max| visited > *max) {
+                return Err(VaultError::TooManyKeys {
+                    path: container.to_string(),
+                    max,
+                });
+            }
+            for key in keys {
+                if key.ends_with('/') {
+                    folders.push(format!("{folder}{key}"));
+                } else {
+                    ids.push(format!("{folder}{key}"));
+                }
+            }
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
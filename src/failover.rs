@@ -0,0 +1,265 @@
+//! Failover between multiple Vault addresses
+//!
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::header::HeaderMap;
+use url::Url;
+use vaultrs::{
+    client::{VaultClient, VaultClientSettings},
+    error::ClientError,
+};
+
+use crate::api::Connection;
+
+/// How long an address that failed is only tried after all healthy addresses
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// A Vault server and its health as observed from requests sent to it
+pub struct Node {
+    pub addr: Url,
+    client: RwLock<Arc<VaultClient>>,
+    /// Headers configured for the link. The HTTP client adds them to requests sent over TCP, but
+    /// requests to a `unix://` address are sent without it
+    headers: Arc<HeaderMap>,
+    unhealthy_until: Mutex<Option<Instant>>,
+    /// Whether the last request failed because the server is sealed
+    sealed: AtomicBool,
+}
+
+impl Node {
+    pub fn new(addr: Url, client: VaultClient) -> Node {
+        Node {
+            addr,
+            client: RwLock::new(Arc::new(client)),
+            headers: Arc::default(),
+            unhealthy_until: Mutex::new(None),
+            sealed: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the headers configured for the link
+    pub fn with_headers(mut self, headers: HeaderMap) -> Node {
+        self.headers = Arc::new(headers);
+        self
+    }
+
+    /// Returns the client for sending requests to the server
+    pub fn client(&self) -> Arc<VaultClient> {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Returns a connection for sending a request to the server
+    pub fn connection(&self) -> Connection {
+        Connection::new(self.client()).with_headers(self.headers.clone())
+    }
+
+    /// Replaces the token requests to the server are sent with. Requests already in flight keep
+    /// using the old one
+    pub fn set_token(&self, token: &str) -> Result<(), ClientError> {
+        self.rebuild(|settings| settings.token = token.to_string(), None)
+    }
+
+    /// Replaces the HTTP client requests to the server are sent with, such as after the client
+    /// certificate was rotated. Requests already in flight keep using the old one
+    pub fn set_http_client(&self, http: reqwest::Client) -> Result<(), ClientError> {
+        self.rebuild(|_| (), Some(http))
+    }
+
+    /// Creates a node for another address of the same cluster, with the token and HTTP client of
+    /// this one
+    pub fn with_addr(&self, addr: Url) -> Result<Node, ClientError> {
+        let client = self.client();
+        let mut settings = client.settings.clone();
+        settings.address = addr.clone();
+        let mut built = VaultClient::new(settings)?;
+        built.http.http = client.http.http.clone();
+        let mut node = Node::new(addr, built);
+        node.headers = self.headers.clone();
+        Ok(node)
+    }
+
+    /// Replaces the client with one with updated settings, keeping the HTTP client unless a new
+    /// one is given. vaultrs builds a default HTTP client, which would lose TLS settings it has
+    /// no setting for
+    fn rebuild(
+        &self,
+        update: impl FnOnce(&mut VaultClientSettings),
+        http: Option<reqwest::Client>,
+    ) -> Result<(), ClientError> {
+        let mut client = self.client.write().unwrap();
+        let mut settings = client.settings.clone();
+        update(&mut settings);
+        let mut rebuilt = VaultClient::new(settings)?;
+        rebuilt.http.http = http.unwrap_or_else(|| client.http.http.clone());
+        *client = Arc::new(rebuilt);
+        Ok(())
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .map(|until| until <= Instant::now())
+            .unwrap_or(true)
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_healthy(&self) {
+        self.unhealthy_until.lock().unwrap().take();
+        self.sealed.store(false, Ordering::Relaxed);
+    }
+
+    /// Records whether the server reported being sealed when asked, keeping requests away from it
+    /// while it is. Returns whether that changed
+    pub fn set_sealed(&self, sealed: bool) -> bool {
+        let was_sealed = self.sealed.swap(sealed, Ordering::Relaxed);
+        let mut unhealthy_until = self.unhealthy_until.lock().unwrap();
+        if sealed {
+            *unhealthy_until = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+        } else if was_sealed {
+            unhealthy_until.take();
+        }
+        was_sealed != sealed
+    }
+
+    /// Asks the server whether it is sealed. The endpoint needs no token
+    pub async fn seal_status(&self) -> anyhow::Result<bool> {
+        #[derive(serde::Deserialize)]
+        struct SealStatus {
+            sealed: bool,
+        }
+        #[cfg(unix)]
+        if self.addr.scheme() == "unix" {
+            let response = crate::unix::send(
+                self.addr.path(),
+                reqwest::Method::GET,
+                "v1/sys/seal-status",
+                (*self.headers).clone(),
+                None,
+                None,
+            )
+            .await?;
+            anyhow::ensure!(
+                response.status().is_success(),
+                "seal status request failed with {}",
+                response.status()
+            );
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            return Ok(serde_json::from_slice::<SealStatus>(&body)?.sealed);
+        }
+        let url = format!(
+            "{}/v1/sys/seal-status",
+            self.addr.as_str().trim_end_matches('/')
+        );
+        let response = self.client().http.http.get(url).send().await?;
+        let status: SealStatus =
+            serde_json::from_slice(&response.error_for_status()?.bytes().await?)?;
+        Ok(status.sealed)
+    }
+
+    /// Marks the node as unable to handle requests because of the given error
+    pub fn mark_unhealthy(&self, err: &ClientError) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+        self.sealed.store(is_sealed(err), Ordering::Relaxed);
+    }
+}
+
+/// The set of Vault servers a client can send requests to. It always has at least one server,
+/// and can be replaced when the servers are discovered rather than configured
+pub struct Nodes(RwLock<Vec<Arc<Node>>>);
+
+impl Nodes {
+    pub fn new(nodes: Vec<Node>) -> Nodes {
+        Nodes(RwLock::new(nodes.into_iter().map(Arc::new).collect()))
+    }
+
+    /// Returns true if at least one of the servers is healthy
+    pub fn any_healthy(&self) -> bool {
+        self.0.read().unwrap().iter().any(|node| node.is_healthy())
+    }
+
+    /// Replaces the token requests to all of the servers are sent with
+    pub fn set_token(&self, token: &str) -> Result<(), ClientError> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .try_for_each(|node| node.set_token(token))
+    }
+
+    /// Replaces the HTTP client requests to all of the servers are sent with
+    pub fn set_http_client(&self, http: &reqwest::Client) -> Result<(), ClientError> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .try_for_each(|node| node.set_http_client(http.clone()))
+    }
+
+    /// Replaces the servers with the given addresses in that order. Servers that remain keep
+    /// their health, new ones get the token and HTTP client of the current ones. Returns whether
+    /// the addresses changed. An empty list is ignored, as there would be no server left to try
+    pub fn set_addrs(&self, addrs: &[Url]) -> Result<bool, ClientError> {
+        let mut nodes = self.0.write().unwrap();
+        if addrs.is_empty() || nodes.iter().map(|node| &node.addr).eq(addrs) {
+            return Ok(false);
+        }
+        let template = nodes[0].clone();
+        *nodes = addrs
+            .iter()
+            .map(|addr| match nodes.iter().find(|node| node.addr == *addr) {
+                Some(node) => Ok(node.clone()),
+                None => template.with_addr(addr.clone()).map(Arc::new),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(true)
+    }
+
+    /// Returns true if all of the servers are sealed
+    pub fn all_sealed(&self) -> bool {
+        self.0.read().unwrap().iter().all(|node| node.is_sealed())
+    }
+
+    /// Returns the nodes in the order they should be tried: healthy nodes in the configured
+    /// order, followed by the unhealthy ones in case they recovered
+    pub fn candidates(&self) -> Vec<Arc<Node>> {
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = self
+            .0
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .partition(|n| n.is_healthy());
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+}
+
+/// Returns true if the error means the server can't currently handle requests, so the request
+/// should be retried against the next address. This covers connection failures and sealed or
+/// standby servers, which respond with 503
+pub fn should_failover(err: &ClientError) -> bool {
+    matches!(
+        err,
+        ClientError::RestClientError { .. } | ClientError::APIError { code: 503, .. }
+    )
+}
+
+/// Returns true if the error means the server is sealed. Sealed servers respond with 503 like
+/// standbys that can't serve requests, but say so in the error message
+pub fn is_sealed(err: &ClientError) -> bool {
+    match err {
+        ClientError::APIError { code: 503, errors } => {
+            errors.iter().any(|e| e.to_lowercase().contains("sealed"))
+        }
+        _ => false,
+    }
+}
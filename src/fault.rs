@@ -0,0 +1,116 @@
+//! Injection of faults into requests to Vault, for testing how actors and the provider cope with
+//! an unreliable Vault in staging
+//!
+//! UNSAFE: never enable this in production. Injected errors fail operations that would have
+//! succeeded, and truncated reads return corrupt data to actors.
+//!
+//! Enabled with the undocumented `unsafe_fault_injection` link value, a comma separated list of
+//! `name=value` pairs such as `delay=0.1,max_delay_ms=500,error=0.05,truncate=0.01`:
+//! - `delay`: probability of delaying a request by up to `max_delay_ms`, 1000 by default
+//! - `error`: probability of failing a request with a 500 or 503 error instead of sending it
+//! - `truncate`: probability of cutting the data of a file read short
+use std::{str::FromStr, time::Duration};
+
+use bytes::Bytes;
+use rand::Rng;
+use tracing::debug;
+use vaultrs::error::ClientError;
+
+/// Longest delay injected unless configured
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Probabilities of the faults injected into requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultInjection {
+    /// Probability of delaying a request
+    pub delay: f64,
+    /// Longest delay of a delayed request
+    pub max_delay: Duration,
+    /// Probability of failing a request
+    pub error: f64,
+    /// Probability of truncating the data of a file read
+    pub truncate: f64,
+}
+
+impl Default for FaultInjection {
+    fn default() -> Self {
+        FaultInjection {
+            delay: 0.0,
+            max_delay: DEFAULT_MAX_DELAY,
+            error: 0.0,
+            truncate: 0.0,
+        }
+    }
+}
+
+impl FromStr for FaultInjection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let probability = |name: &str, value: &str| -> anyhow::Result<f64> {
+            match value.parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => anyhow::bail!("{name} must be a probability between 0 and 1, got {value}"),
+            }
+        };
+        let mut faults = FaultInjection::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected name=value, got {pair}"))?;
+            match name.trim() {
+                "delay" => faults.delay = probability(name, value.trim())?,
+                "error" => faults.error = probability(name, value.trim())?,
+                "truncate" => faults.truncate = probability(name, value.trim())?,
+                "max_delay_ms" => {
+                    faults.max_delay = Duration::from_millis(value.trim().parse()?);
+                }
+                other => anyhow::bail!("unknown fault {other}"),
+            }
+        }
+        Ok(faults)
+    }
+}
+
+impl FaultInjection {
+    /// Delays a request or returns the error it fails with instead of being sent, at random
+    pub async fn before_request(&self, method: &str, path: &str) -> Option<ClientError> {
+        let (delay, error) = {
+            let mut rng = rand::thread_rng();
+            let delay = rng
+                .gen_bool(self.delay)
+                .then(|| self.max_delay.mul_f64(rng.gen::<f64>()));
+            let error = rng
+                .gen_bool(self.error)
+                .then(|| if rng.gen_bool(0.5) { 500 } else { 503 });
+            (delay, error)
+        };
+        if let Some(delay) = delay {
+            debug!(method, path, ?delay, "Injecting delay");
+            tokio::time::sleep(delay).await;
+        }
+        error.map(|code| {
+            debug!(method, path, code, "Injecting error");
+            ClientError::APIError {
+                code,
+                errors: vec!["injected fault".to_string()],
+            }
+        })
+    }
+
+    /// Cuts the data of a file read short at random
+    pub fn truncate(&self, path: &str, data: &mut Bytes) {
+        let mut rng = rand::thread_rng();
+        if data.is_empty() || !rng.gen_bool(self.truncate) {
+            return;
+        }
+        let len = rng.gen_range(0..data.len());
+        debug!(
+            path,
+            len,
+            original = data.len(),
+            "Injecting truncated response"
+        );
+        data.truncate(len);
+    }
+}
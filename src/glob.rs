@@ -0,0 +1,72 @@
+//! Glob patterns selecting the objects of listings by ID, such as `reports/2024-*/summary.json`
+//!
+//! `*` matches any characters other than `/`, so it stays within a folder, `**` matches any
+//! characters including `/`, and `?` matches a single character other than `/`. Any other
+//! character matches itself.
+
+/// Part of a pattern
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    /// `*`
+    Star,
+    /// `**`
+    DoubleStar,
+    /// `?`
+    Any,
+    Char(char),
+}
+
+/// A parsed glob pattern
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    /// Folder every match is in, the part of the pattern up to the last `/` before a wildcard
+    folder: String,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' if chars.next_if_eq(&'*').is_some() => Token::DoubleStar,
+                '*' => Token::Star,
+                '?' => Token::Any,
+                c => Token::Char(c),
+            });
+        }
+        let literal = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+        let folder = literal[..literal.rfind('/').map_or(0, |i| i + 1)].to_string();
+        Pattern { tokens, folder }
+    }
+
+    /// Returns the folder all matches are in, ending in `/`, or an empty string if matches can
+    /// be anywhere
+    pub fn folder(&self) -> &str {
+        &self.folder
+    }
+
+    /// Returns whether the pattern matches the whole name
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        // matched[j] is whether the tokens from the current one on match name[j..]. Filled from
+        // the last token to the first, so matching takes time proportional to the product of
+        // the lengths rather than backtracking
+        let mut matched = vec![false; name.len() + 1];
+        matched[name.len()] = true;
+        for token in self.tokens.iter().rev() {
+            let next = matched.clone();
+            for j in (0..=name.len()).rev() {
+                let c = name.get(j).copied();
+                matched[j] = match token {
+                    Token::DoubleStar => next[j] || (c.is_some() && matched[j + 1]),
+                    Token::Star => next[j] || (c.is_some_and(|c| c != '/') && matched[j + 1]),
+                    Token::Any => c.is_some_and(|c| c != '/') && next[j + 1],
+                    Token::Char(expected) => c == Some(*expected) && next[j + 1],
+                };
+            }
+        }
+        matched[0]
+    }
+}
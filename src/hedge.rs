@@ -0,0 +1,54 @@
+//! Hedging of reads, trading extra Vault requests for lower tail latency
+//!
+//! A read that hasn't been answered within a percentile of the latencies of recent reads is sent
+//! a second time, to the next server if there are several, and the first answer is used. With
+//! the 95th percentile about one read in twenty is sent twice.
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+/// Number of recent read latencies the threshold is derived from
+const WINDOW: usize = 200;
+/// Reads observed before any are hedged, so the threshold isn't derived from a handful of reads
+const MIN_SAMPLES: usize = 20;
+/// Lower bound of the threshold, so reads served from a warm cache aren't all hedged
+const MIN_DELAY: Duration = Duration::from_millis(5);
+
+/// Tracks the latencies of recent reads and decides when to hedge them
+#[derive(Debug)]
+pub struct Hedge {
+    /// Percentile of the latencies after which a read is hedged, between 0 and 100
+    percentile: f64,
+    samples: Mutex<VecDeque<Duration>>,
+}
+
+impl Hedge {
+    pub fn new(percentile: f64) -> Hedge {
+        Hedge {
+            percentile: percentile.clamp(0.0, 100.0),
+            samples: Mutex::new(VecDeque::with_capacity(WINDOW)),
+        }
+    }
+
+    /// Records the latency of a read that succeeded
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    /// Returns how long a read waits before it is hedged, or None while too few reads were
+    /// observed
+    pub fn delay(&self) -> Option<Duration> {
+        let mut sorted: Vec<_> = {
+            let samples = self.samples.lock().unwrap();
+            if samples.len() < MIN_SAMPLES {
+                return None;
+            }
+            samples.iter().copied().collect()
+        };
+        sorted.sort_unstable();
+        let rank = (self.percentile / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+        Some(sorted[rank].max(MIN_DELAY))
+    }
+}
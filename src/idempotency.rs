@@ -0,0 +1,93 @@
+//! Deduplication of retried writes
+//!
+//! Actors can send an idempotency key with a write, so that retrying it after a timeout doesn't
+//! write another version of the secret. A write with a key already used for the same object and
+//! content within the window gets the result of the first write instead, waiting for it if it is
+//! still in flight. Failed writes are forgotten, so retrying them writes again.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+use crate::error::VaultError;
+
+type SharedWrite<T> = Shared<BoxFuture<'static, Result<T, Arc<VaultError>>>>;
+
+struct Entry<T> {
+    /// Identifies the object and content written with the key
+    fingerprint: String,
+    started: Instant,
+    write: SharedWrite<T>,
+}
+
+/// Results of writes made with idempotency keys, kept for a window after they started
+pub struct Writes<T: Clone> {
+    window: Duration,
+    entries: Arc<Mutex<HashMap<String, Entry<T>>>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Writes<T> {
+    pub fn new(window: Duration) -> Writes<T> {
+        Writes {
+            window,
+            entries: Default::default(),
+        }
+    }
+
+    /// Runs `write` unless a write with `key` and the same `fingerprint` started within the
+    /// window, in which case its result is returned instead. Fails with
+    /// [`VaultError::IdempotencyKeyReused`] if the key was used for a different write
+    pub async fn write<F>(&self, key: &str, fingerprint: String, write: F) -> Result<T, VaultError>
+    where
+        F: Future<Output = Result<T, VaultError>> + Send + 'static,
+    {
+        let shared = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|_, entry| entry.started.elapsed() < self.window);
+            match entries.get(key) {
+                Some(entry) if entry.fingerprint != fingerprint => {
+                    return Err(VaultError::IdempotencyKeyReused {
+                        key: key.to_string(),
+                    })
+                }
+                Some(entry) => entry.write.clone(),
+                None => {
+                    let entries_ref = self.entries.clone();
+                    let owned_key = key.to_string();
+                    let started = Instant::now();
+                    // Like singleflight calls, the write cleans up after itself so a failure is
+                    // forgotten even if the caller that started it was cancelled
+                    let shared = async move {
+                        let res = write.await.map_err(Arc::new);
+                        if res.is_err() {
+                            let mut entries = entries_ref.lock().unwrap();
+                            if entries
+                                .get(&owned_key)
+                                .is_some_and(|e| e.started == started)
+                            {
+                                entries.remove(&owned_key);
+                            }
+                        }
+                        res
+                    }
+                    .boxed()
+                    .shared();
+                    entries.insert(
+                        key.to_string(),
+                        Entry {
+                            fingerprint,
+                            started,
+                            write: shared.clone(),
+                        },
+                    );
+                    shared
+                }
+            }
+        };
+        shared.await.map_err(VaultError::from_shared)
+    }
+}
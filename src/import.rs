@@ -0,0 +1,84 @@
+//! Bulk import of a local directory into a container, run with
+//! `blobstore_vault --import <directory> <container>`
+//!
+//! Every file below the directory becomes an object named after its path relative to the
+//! directory, written through the same client as the provider so IDs are encoded and routed to
+//! mounts the same way. Settings are taken like for `--selfcheck`. To migrate from an
+//! S3-compatible store, sync the bucket to a local directory first (e.g. with `aws s3 sync`).
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+
+use crate::{client::Client, config::Config, object_path, progress::Progress};
+
+/// Number of files written to Vault at the same time
+const CONCURRENCY: usize = 8;
+
+/// Imports all files below `dir` into `container`, printing each failure. Returns the number of
+/// files imported and the number that failed
+pub async fn run(
+    settings: &[(String, String)],
+    dir: &Path,
+    container: &str,
+) -> anyhow::Result<(usize, usize)> {
+    let client = Client::new(Config::from_values(settings)?)?;
+    let client = client.for_container(container);
+    let files = walk(dir)?;
+    println!("Importing {} files into '{container}'", files.len());
+    let progress = &Progress::printed("import", container);
+    progress.start(files.len());
+
+    let results: Vec<bool> = futures::stream::iter(files)
+        .map(|file| async move {
+            let id = object_id(dir, &file);
+            let res = match tokio::fs::read(&file).await {
+                Ok(data) => {
+                    let len = data.len() as u64;
+                    client
+                        .write_file(object_path::join(container, &id), data)
+                        .await
+                        .map(|_| progress.advance(len))
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = &res {
+                println!("FAILED  {}: {e}", file.display());
+            }
+            res.is_ok()
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect()
+        .await;
+    let imported = results.iter().filter(|ok| **ok).count();
+    Ok((imported, results.len() - imported))
+}
+
+/// Returns the paths of all files below a directory, following symlinks
+fn walk(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the object ID of a file, its path relative to the imported directory with `/` as
+/// separator
+fn object_id(dir: &Path, file: &Path) -> String {
+    file.strip_prefix(dir)
+        .unwrap_or(file)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,177 @@
+//! JSON bodies of requests and responses, encoded and decoded while they are transferred
+//!
+//! Vault stores the bytes of objects as arrays of numbers, so the JSON of an object is several
+//! times its size. Bodies up to [`CHUNK_BYTES`] are handled whole, but larger ones are encoded on
+//! a blocking thread into a bounded channel the request body is read from, and decoded on a
+//! blocking thread from a bounded channel the response body is written to. Each request then
+//! holds at most a few chunks of JSON besides the value itself, however large the object.
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    pin::Pin,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::mpsc;
+
+/// Size of the chunks bodies are transferred in, and of the largest body handled whole
+pub const CHUNK_BYTES: usize = 64 * 1024;
+/// Number of chunks buffered between the thread encoding or decoding a body and its transfer
+const BUFFERED_CHUNKS: usize = 4;
+
+/// Chunks of a body
+pub type Chunks<E> = Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>;
+
+/// Body of a request
+pub enum Body {
+    /// The whole of a body up to [`CHUNK_BYTES`]
+    Whole(Vec<u8>),
+    /// Chunks of a larger body, sent as they are encoded
+    Encoding(mpsc::Receiver<io::Result<Bytes>>),
+}
+
+impl Body {
+    /// Returns the chunks of the body
+    pub fn into_chunks(self) -> Chunks<io::Error> {
+        match self {
+            Body::Whole(body) => Box::pin(futures::stream::once(async { Ok(body.into()) })),
+            Body::Encoding(chunks) => {
+                Box::pin(futures::stream::unfold(chunks, |mut chunks| async move {
+                    chunks.recv().await.map(|chunk| (chunk, chunks))
+                }))
+            }
+        }
+    }
+}
+
+/// A response body, read whole if it is up to [`CHUNK_BYTES`] or decoded as it was received
+pub enum Received<D> {
+    Whole(Bytes),
+    Decoded(serde_json::Result<D>),
+}
+
+/// Collects writes up to a limit, failing once it is exceeded
+struct Limited(Vec<u8>);
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0.len() + buf.len() > CHUNK_BYTES {
+            return Err(io::Error::other("body exceeds a chunk"));
+        }
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends writes to a channel, blocking while it is full
+struct ChunkWriter(mpsc::Sender<io::Result<Bytes>>);
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "request was dropped"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the chunks received from a channel, blocking while it is empty
+struct ChunkReader {
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Encodes a value as the body of a request. Values whose JSON is larger than a chunk are
+/// encoded on a blocking thread while the body is sent
+pub fn encode<T: Serialize + Send + 'static>(value: T) -> serde_json::Result<Body> {
+    let mut whole = Limited(Vec::new());
+    match serde_json::to_writer(&mut whole, &value) {
+        Ok(()) => return Ok(Body::Whole(whole.0)),
+        // Only writes fail with I/O errors, so the JSON doesn't fit in a chunk
+        Err(e) if e.is_io() => (),
+        Err(e) => return Err(e),
+    }
+    let (sender, chunks) = mpsc::channel(BUFFERED_CHUNKS);
+    tokio::task::spawn_blocking(move || {
+        let mut writer = BufWriter::with_capacity(CHUNK_BYTES, ChunkWriter(sender.clone()));
+        let encoded = serde_json::to_writer(&mut writer, &value)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.flush());
+        if let Err(e) = encoded {
+            // The request is dropped if the channel is closed, so there's no one to tell
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+    Ok(Body::Encoding(chunks))
+}
+
+/// Reads a response body, decoding bodies larger than a chunk on a blocking thread while they
+/// are received. Fails if the body can't be received
+pub async fn decode<D, E>(mut body: Chunks<E>) -> Result<Received<D>, E>
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    let mut start = BytesMut::new();
+    while start.len() <= CHUNK_BYTES {
+        match body.next().await {
+            Some(chunk) => start.extend_from_slice(&chunk?),
+            None => return Ok(Received::Whole(start.freeze())),
+        }
+    }
+    let (sender, chunks) = mpsc::channel(BUFFERED_CHUNKS);
+    let decoding = tokio::task::spawn_blocking(move || {
+        let reader = ChunkReader {
+            chunks,
+            chunk: Bytes::new(),
+        };
+        serde_json::from_reader(BufReader::with_capacity(CHUNK_BYTES, reader))
+    });
+    let receiving = async move {
+        let mut chunk = Some(Ok(start.freeze()));
+        while let Some(received) = chunk {
+            match received {
+                // The decoder stops reading after the end of the value, or at an error
+                Ok(received) => {
+                    if sender.send(Ok(received)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    let aborted = io::ErrorKind::ConnectionAborted.into();
+                    let _ = sender.send(Err(aborted)).await;
+                    return Err(e);
+                }
+            }
+            chunk = body.next().await;
+        }
+        Ok(())
+    };
+    let (received, decoded): (Result<(), E>, _) = futures::join!(receiving, decoding);
+    received?;
+    Ok(Received::Decoded(decoded.unwrap_or_else(|e| {
+        std::panic::resume_unwind(e.into_panic())
+    })))
+}
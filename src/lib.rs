@@ -1,5 +1,52 @@
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("one of the `rustls` or `native-tls` features must be enabled for TLS to Vault");
+#[cfg(all(feature = "fips", feature = "rustls"))]
+compile_error!(
+    "the `fips` feature uses OpenSSL for TLS and can't be combined with `rustls`, build with \
+     `--no-default-features --features fips,smithy`"
+);
+
 // TODO: These types should be defined via WIT
+pub mod access;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod backend;
+pub mod cas;
 pub mod client;
+pub mod compress;
 pub mod config;
+pub mod continuation;
+pub mod deadline;
+pub mod discovery;
+#[cfg(feature = "smithy")]
+pub mod download;
 pub mod error;
+pub mod events;
+pub mod export;
+pub mod failover;
+pub mod fault;
+pub mod glob;
+pub mod hedge;
+pub mod idempotency;
+pub mod import;
+pub mod json;
+pub mod limit;
+pub mod lock;
+pub mod metrics;
+pub mod object_path;
+pub mod objects;
+pub mod progress;
+pub mod recording;
+pub mod selfcheck;
+pub mod singleflight;
+pub mod snapshot;
+pub mod throttle;
+#[cfg(feature = "rustls")]
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
+pub mod upload;
+pub mod usage;
+pub mod vault_events;
 pub mod wasmcloud_interface_blobstore;
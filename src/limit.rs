@@ -0,0 +1,86 @@
+//! Concurrency limiting for Vault requests
+//!
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::error::VaultError;
+
+/// Limits the number of requests in flight, queueing up to a bounded number of requests beyond
+/// that. Requests that would exceed the queue bound are rejected with
+/// [`VaultError::Backpressure`] instead of piling up
+#[derive(Debug)]
+pub struct Limiter {
+    scope: &'static str,
+    permits: Arc<Semaphore>,
+    max_queued: usize,
+    queued: AtomicUsize,
+}
+
+impl Limiter {
+    /// Creates a limiter allowing `max_concurrent` requests in flight and `max_queued` more
+    /// waiting for a slot. `scope` names the limiter in backpressure errors
+    pub fn new(scope: &'static str, max_concurrent: usize, max_queued: usize) -> Limiter {
+        Limiter {
+            scope,
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            max_queued,
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Waits for a slot, returning a permit that frees the slot when dropped
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, VaultError> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        if self.queued.fetch_add(1, Ordering::AcqRel) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            return Err(VaultError::Backpressure { scope: self.scope });
+        }
+        let permit = self.permits.clone().acquire_owned().await;
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+        // The semaphore is never closed
+        Ok(permit.expect("limiter semaphore closed"))
+    }
+}
+
+/// Unit the memory budget is counted in, so budgets of many gigabytes fit the semaphore
+const BUDGET_UNIT: usize = 1024;
+
+/// Bounds the object data being written at the same time, across all links. Serializing an
+/// object for Vault takes a multiple of its size, so without a bound many concurrent large
+/// writes can exhaust the provider's memory. Writes wait for room instead
+#[derive(Debug)]
+pub struct MemoryBudget {
+    units: Arc<Semaphore>,
+    max_units: u32,
+}
+
+impl MemoryBudget {
+    /// Creates a budget allowing `max_bytes` of object data to be written at once
+    pub fn new(max_bytes: usize) -> MemoryBudget {
+        let max_units = max_bytes.div_ceil(BUDGET_UNIT).clamp(1, u32::MAX as usize) as u32;
+        MemoryBudget {
+            units: Arc::new(Semaphore::new(max_units as usize)),
+            max_units,
+        }
+    }
+
+    /// Waits until `bytes` fit in the budget, returning a permit that frees them when dropped.
+    /// Objects larger than the whole budget wait for all of it, so they are written alone
+    pub async fn reserve(&self, bytes: usize) -> OwnedSemaphorePermit {
+        let units = bytes
+            .div_ceil(BUDGET_UNIT)
+            .clamp(1, self.max_units as usize) as u32;
+        // The semaphore is never closed
+        self.units
+            .clone()
+            .acquire_many_owned(units)
+            .await
+            .expect("memory budget semaphore closed")
+    }
+}
@@ -0,0 +1,63 @@
+//! Advisory locks over objects, for actors that need mutual exclusion such as only one actor
+//! regenerating an artifact at a time
+//!
+//! The lock of an object is a secret under [`LOCK_PREFIX`] holding its owner and expiry. It is
+//! taken and released with check-and-set writes, so of several actors racing for it only one
+//! succeeds. The version of the lock secret written when taking it is the lock's token: releasing
+//! requires it, and it increases with every acquisition, so it can also fence off writes of an
+//! owner whose lock expired.
+//!
+//! NOTE: Expiry is checked against the clock of the provider, so providers sharing a mount need
+//! synchronized clocks. Locks are advisory, objects can still be written without holding them.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Folder of the mount holding the locks of objects. It is hidden from listings
+pub const LOCK_PREFIX: &str = "_locks";
+/// Time a lock is held for if the request doesn't say
+pub const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(30);
+
+/// Returns the path of the lock secret of an object
+pub fn lock_path(path: &str) -> String {
+    format!("{LOCK_PREFIX}/{path}")
+}
+
+/// Content of a lock secret
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Lock {
+    /// Actor holding the lock, empty once released
+    pub owner: String,
+    /// Seconds since the Unix epoch at which the lock expires
+    pub expires_at: u64,
+}
+
+impl Lock {
+    /// Creates a lock held by `owner` for `ttl` from now
+    pub fn new(owner: impl Into<String>, ttl: Duration) -> Lock {
+        Lock {
+            owner: owner.into(),
+            expires_at: (now() + ttl).as_secs(),
+        }
+    }
+
+    /// Returns whether the lock is held by anyone
+    pub fn is_held(&self) -> bool {
+        !self.owner.is_empty() && now().as_secs() < self.expires_at
+    }
+}
+
+/// Result of trying to take a lock
+#[derive(Clone, Debug)]
+pub enum LockOutcome {
+    /// The lock was taken, and must be released with `token`
+    Acquired { token: u64, expires_at: u64 },
+    /// Another owner holds the lock
+    Held(Lock),
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+}
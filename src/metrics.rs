@@ -0,0 +1,326 @@
+//! Metrics of the operations actors invoke on the provider
+//!
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use opentelemetry::{
+    metrics::{Counter, Histogram, ObservableGauge, Unit},
+    KeyValue,
+};
+use sha2::{Digest, Sha256};
+use vaultrs::error::ClientError;
+
+use crate::error::VaultError;
+
+/// Name of the counter of failed operations, by kind of operation and class of error
+const ERRORS_COUNTER: &str = "blobstore_vault.errors";
+/// Name of the counter of new Vault tokens links got, by how and whether it succeeded
+const TOKEN_RENEWALS_COUNTER: &str = "blobstore_vault.token.renewals";
+
+/// How the invoking actor is labeled in metrics. Every distinct label value is a separate time
+/// series, so lattices with many actors can truncate or bucket the IDs to cap their number
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActorLabel {
+    /// Metrics aren't labeled with the actor
+    #[default]
+    None,
+    /// The full actor ID
+    Full,
+    /// The first characters of the actor ID
+    Prefix(usize),
+    /// One of a number of buckets the actor ID hashes to
+    Hash(u64),
+}
+
+impl FromStr for ActorLabel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "invalid actor label '{s}', expected 'none', 'full', 'prefix:<chars>' or \
+                 'hash:<buckets>'"
+            )
+        };
+        match s.trim().split_once(':') {
+            None if s.trim() == "none" => Ok(ActorLabel::None),
+            None if s.trim() == "full" => Ok(ActorLabel::Full),
+            Some(("prefix", chars)) => match chars.parse() {
+                Ok(chars) if chars > 0 => Ok(ActorLabel::Prefix(chars)),
+                _ => Err(invalid()),
+            },
+            Some(("hash", buckets)) => match buckets.parse() {
+                Ok(buckets) if buckets > 0 => Ok(ActorLabel::Hash(buckets)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl ActorLabel {
+    /// Returns the label value for an actor, or None if actors aren't labeled
+    pub fn value(&self, actor_id: &str) -> Option<String> {
+        match self {
+            ActorLabel::None => None,
+            ActorLabel::Full => Some(actor_id.to_string()),
+            ActorLabel::Prefix(chars) => Some(actor_id.chars().take(*chars).collect()),
+            ActorLabel::Hash(buckets) => {
+                let digest = Sha256::digest(actor_id.as_bytes());
+                let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+                Some(format!("bucket-{}", hash % buckets))
+            }
+        }
+    }
+}
+
+/// Records how long operations take, and operations abandoned after timing out
+#[derive(Clone, Debug)]
+pub struct OperationMetrics {
+    actor_label: ActorLabel,
+    duration: Histogram<f64>,
+    errors: ErrorCounter,
+}
+
+impl Default for OperationMetrics {
+    fn default() -> Self {
+        OperationMetrics::new(ActorLabel::default())
+    }
+}
+
+impl OperationMetrics {
+    pub fn new(actor_label: ActorLabel) -> OperationMetrics {
+        OperationMetrics {
+            actor_label,
+            duration: opentelemetry::global::meter("blobstore-vault")
+                .f64_histogram("blobstore_vault.operation.duration")
+                .with_description("Time taken by operations invoked by actors")
+                .with_unit(Unit::new("s"))
+                .init(),
+            errors: ErrorCounter::default(),
+        }
+    }
+
+    /// Records that an invocation of `method` was abandoned because it took too long
+    pub fn record_timeout(&self, method: &str) {
+        self.errors.record(operation_kind(method), "timeout");
+    }
+
+    /// Records the duration of an invocation of `method` by an actor and whether it succeeded
+    pub fn record(&self, method: &str, actor_id: &str, elapsed: Duration, ok: bool) {
+        let mut attributes = vec![
+            KeyValue::new("operation", operation_kind(method)),
+            KeyValue::new("outcome", if ok { "ok" } else { "error" }),
+        ];
+        if let Some(actor) = self.actor_label.value(actor_id) {
+            attributes.push(KeyValue::new("actor", actor));
+        }
+        self.duration.record(elapsed.as_secs_f64(), &attributes);
+    }
+}
+
+/// Groups the methods of both interfaces into the kinds of operation metrics are labeled with,
+/// so actors invoking unknown methods can't add labels
+pub fn operation_kind(method: &str) -> &'static str {
+    let name = method
+        .rsplit(['.', '/'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let has = |words: &[&str]| words.iter().any(|word| name.contains(word));
+    if has(&["list"]) {
+        "list"
+    } else if has(&["delete", "remove", "clear"]) {
+        "delete"
+    } else if has(&[
+        "info",
+        "exists",
+        "has-object",
+        "versions",
+        "watch",
+        "get-container",
+    ]) {
+        "metadata"
+    } else if has(&["get"]) {
+        "get"
+    } else if has(&["put", "write", "copy", "move", "create", "restore"]) {
+        "put"
+    } else {
+        "other"
+    }
+}
+
+/// Counts failed operations by kind and error class, so alerts can tell actors asking for
+/// missing objects apart from Vault being unavailable. Failed Vault requests are counted by the
+/// client, invocations that time out by the provider
+#[derive(Clone, Debug)]
+pub struct ErrorCounter {
+    errors: Counter<u64>,
+}
+
+impl Default for ErrorCounter {
+    fn default() -> Self {
+        ErrorCounter {
+            errors: opentelemetry::global::meter("blobstore-vault")
+                .u64_counter(ERRORS_COUNTER)
+                .with_description("Failed operations by kind of operation and class of error")
+                .init(),
+        }
+    }
+}
+
+impl ErrorCounter {
+    pub fn record(&self, operation: &'static str, class: &'static str) {
+        self.errors.add(
+            1,
+            &[
+                KeyValue::new("operation", operation),
+                KeyValue::new("class", class),
+            ],
+        );
+    }
+}
+
+/// Counts the times links got a new Vault token after theirs was denied, by logging in again
+/// (`login`) or switching between the primary and secondary token (`switch`), and whether it
+/// succeeded
+#[derive(Clone, Debug)]
+pub struct RenewalCounter {
+    renewals: Counter<u64>,
+}
+
+impl Default for RenewalCounter {
+    fn default() -> Self {
+        RenewalCounter {
+            renewals: opentelemetry::global::meter("blobstore-vault")
+                .u64_counter(TOKEN_RENEWALS_COUNTER)
+                .with_description("New Vault tokens links got after theirs was denied")
+                .init(),
+        }
+    }
+}
+
+impl RenewalCounter {
+    pub fn record(&self, method: &'static str, ok: bool) {
+        self.renewals.add(
+            1,
+            &[
+                KeyValue::new("method", method),
+                KeyValue::new("outcome", if ok { "ok" } else { "error" }),
+            ],
+        );
+    }
+}
+
+/// Reports the seconds left before the Vault token of each link expires, as of its last lookup.
+/// Links whose actors share a label report the token expiring first. Tokens that don't expire
+/// aren't reported
+#[derive(Clone)]
+pub struct TokenTtlGauge {
+    actor_label: ActorLabel,
+    /// Time to live of each link's token when it was looked up, and when that was, by actor
+    ttls: Arc<Mutex<HashMap<String, (Duration, Instant)>>>,
+    _gauge: ObservableGauge<u64>,
+}
+
+impl Default for TokenTtlGauge {
+    fn default() -> Self {
+        TokenTtlGauge::new(ActorLabel::default())
+    }
+}
+
+impl TokenTtlGauge {
+    pub fn new(actor_label: ActorLabel) -> TokenTtlGauge {
+        let ttls: Arc<Mutex<HashMap<String, (Duration, Instant)>>> = Default::default();
+        let observed = ttls.clone();
+        let gauge = opentelemetry::global::meter("blobstore-vault")
+            .u64_observable_gauge("blobstore_vault.token.ttl")
+            .with_description("Time left before the Vault token of a link expires")
+            .with_unit(Unit::new("s"))
+            .with_callback(move |gauge| {
+                let mut remaining: HashMap<Option<String>, u64> = HashMap::new();
+                for (actor_id, (ttl, looked_up)) in observed.lock().unwrap().iter() {
+                    let left = ttl.saturating_sub(looked_up.elapsed()).as_secs();
+                    remaining
+                        .entry(actor_label.value(actor_id))
+                        .and_modify(|min| *min = left.min(*min))
+                        .or_insert(left);
+                }
+                for (actor, left) in remaining {
+                    let attributes: Vec<_> = actor
+                        .map(|actor| KeyValue::new("actor", actor))
+                        .into_iter()
+                        .collect();
+                    gauge.observe(left, &attributes);
+                }
+            })
+            .init();
+        TokenTtlGauge {
+            actor_label,
+            ttls,
+            _gauge: gauge,
+        }
+    }
+
+    /// Records the time to live of a link's token that was just looked up, None if it doesn't
+    /// expire
+    pub fn record(&self, actor_id: &str, ttl: Option<Duration>) {
+        let mut ttls = self.ttls.lock().unwrap();
+        match ttl {
+            Some(ttl) => ttls.insert(actor_id.to_string(), (ttl, Instant::now())),
+            None => ttls.remove(actor_id),
+        };
+    }
+
+    /// Stops reporting the token of a link that was deleted
+    pub fn remove(&self, actor_id: &str) {
+        self.ttls.lock().unwrap().remove(actor_id);
+    }
+}
+
+impl std::fmt::Debug for TokenTtlGauge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenTtlGauge")
+            .field("actor_label", &self.actor_label)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the kind of operation a Vault request is for, matching [`operation_kind`]
+pub fn request_kind(method: &str, path: &str) -> &'static str {
+    match method {
+        "LIST" => "list",
+        "DELETE" => "delete",
+        "GET" if path.contains("/metadata/") => "metadata",
+        "GET" => "get",
+        _ => "put",
+    }
+}
+
+/// Returns the class of an error: `not_found`, `unauthorized`, `sealed`, `timeout`,
+/// `rate_limited` or `other`
+pub fn error_class(err: &VaultError) -> &'static str {
+    match err {
+        VaultError::NotFound { .. } => "not_found",
+        VaultError::Denied { .. } => "unauthorized",
+        VaultError::Sealed => "sealed",
+        VaultError::DeadlineExceeded => "timeout",
+        VaultError::Backpressure { .. } => "rate_limited",
+        VaultError::Client {
+            source: ClientError::APIError { code, .. },
+            ..
+        } => match code {
+            404 => "not_found",
+            401 | 403 => "unauthorized",
+            408 | 504 => "timeout",
+            429 => "rate_limited",
+            _ => "other",
+        },
+        VaultError::Shared(err) => error_class(err),
+        _ => "other",
+    }
+}
@@ -0,0 +1,38 @@
+//! Encoding of object IDs into Vault secret paths
+//!
+//! Object IDs may contain characters that can't appear in a Vault path or that Vault treats
+//! specially, such as spaces, `%`, `?` or unicode. Each `/`-separated segment of an ID is
+//! percent-encoded, keeping `/` so IDs still map onto Vault's folder hierarchy. IDs made of only
+//! ASCII letters, digits and `-._~` are stored unchanged.
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Characters that are encoded in a path segment. Everything except unreserved characters
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Returns the path in the mount of an object of a container, `{container}/{object}`. Listings of
+/// a container return object IDs relative to it, so they can be joined with it again
+pub fn join(container: &str, object: &str) -> String {
+    format!("{}/{object}", container.trim_end_matches('/'))
+}
+
+/// Returns the Vault path an object ID is stored at
+pub fn encode(id: &str) -> String {
+    id.split('/')
+        .map(|segment| match segment {
+            // Dot segments would be resolved away by Vault's path handling
+            "." => "%2E".to_string(),
+            ".." => "%2E%2E".to_string(),
+            _ => utf8_percent_encode(segment, SEGMENT).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the object ID stored at a Vault path, reversing [`encode`]
+pub fn decode(path: &str) -> String {
+    percent_decode_str(path).decode_utf8_lossy().into_owned()
+}
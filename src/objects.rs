@@ -0,0 +1,249 @@
+//! Writes of the objects of containers
+//!
+//! Objects are stored at `{container}/{object}` in the mount of their container, the paths
+//! [`object_path::join`] returns, so listings, exports, snapshots and imports see the objects
+//! written by every operation. Batches of objects are written independently of each other by
+//! [`put_objects`], while the objects of a group written by [`put_object_group`] are all written
+//! or rolled back together.
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use tracing::warn;
+use vaultrs::api::kv2::responses::SecretVersionMetadata;
+
+use crate::{
+    client::{live_version, Client, File},
+    error::VaultError,
+    object_path, usage,
+    wasmcloud_interface_blobstore::{
+        ItemResult, ObjectWrite, ObjectWriteResult, PutObjectGroupResponse,
+    },
+};
+
+/// Writes an object of a container with the conditions of a `PutObject` request, then replaces
+/// its user metadata if given
+pub async fn write_object(
+    client: &Client,
+    container_id: &str,
+    object_id: &str,
+    file: File,
+    if_version: Option<u64>,
+    idempotency_key: Option<&str>,
+    metadata: Option<HashMap<String, String>>,
+) -> Result<SecretVersionMetadata, VaultError> {
+    let path = object_path::join(container_id, object_id);
+    let before = usage::size_before(client, &path).await;
+    let size = file.data.len() as u64;
+    let written = match (idempotency_key, if_version) {
+        (Some(key), version) => client.write_file_once(&path, file, version, key).await,
+        (None, Some(version)) => client.write_file_if_version(&path, file, version).await,
+        (None, None) => client.write_file(&path, file).await,
+    }?;
+    usage::record(client, container_id, before, Some(size)).await;
+    if let Some(metadata) = metadata {
+        client.set_custom_metadata(&path, metadata).await?;
+    }
+    Ok(written)
+}
+
+/// An object as read before changing it
+pub struct Current {
+    /// Current version of the object's secret, which writes are made conditional on. 0 if it was
+    /// never written
+    pub version: u64,
+    /// The object, None if it doesn't exist or was deleted
+    pub file: Option<File>,
+    /// User metadata of the object
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Reads an object of a container before changing it. The metadata is read first, so the data
+/// read is at least as new as the version writes are made conditional on
+pub async fn read_current(
+    client: &Client,
+    container_id: &str,
+    object_id: &str,
+) -> Result<Current, VaultError> {
+    let path = object_path::join(container_id, object_id);
+    match client.get_metadata(&path).await {
+        Ok(metadata) if live_version(&metadata).is_some() => Ok(Current {
+            version: metadata.current_version,
+            file: Some(client.read_file(&path).await?),
+            metadata: metadata.custom_metadata,
+        }),
+        Ok(metadata) => Ok(Current {
+            version: metadata.current_version,
+            file: None,
+            metadata: None,
+        }),
+        Err(VaultError::NotFound { .. }) => Ok(Current {
+            version: 0,
+            file: None,
+            metadata: None,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the objects of a batch, the link's `batch_concurrency` at a time. A failed write doesn't
+/// stop the others, and the results are in the order of the objects
+pub async fn put_objects(
+    client: &Client,
+    container_id: &str,
+    objects: Vec<ObjectWrite>,
+) -> Vec<ObjectWriteResult> {
+    futures::stream::iter(objects)
+        .map(|object| async move {
+            let file = File {
+                data: object.bytes,
+                content_type: object.content_type,
+                content_encoding: object.content_encoding,
+                ..Default::default()
+            };
+            let res = write_object(
+                client,
+                container_id,
+                &object.object_id,
+                file,
+                object.if_version,
+                None,
+                object.metadata,
+            )
+            .await;
+            match res {
+                Ok(written) => ObjectWriteResult {
+                    object_id: object.object_id,
+                    success: true,
+                    version: Some(written.version),
+                    error: None,
+                },
+                Err(e) => ObjectWriteResult {
+                    object_id: object.object_id,
+                    success: false,
+                    version: None,
+                    error: Some(e.to_rpc_string()),
+                },
+            }
+        })
+        .buffered(client.batch_concurrency())
+        .collect()
+        .await
+}
+
+/// Writes the objects of a group one after the other, rolling back those already written if a
+/// write fails. Objects without an `ifVersion` are written only if they are still at the version
+/// read before, so a concurrent change is never overwritten and then lost by the rollback
+pub async fn put_object_group(
+    client: &Client,
+    container_id: &str,
+    objects: Vec<ObjectWrite>,
+) -> PutObjectGroupResponse {
+    let mut results = Vec::with_capacity(objects.len());
+    // Objects written so far, with what they were before and the version they were written with
+    let mut written = Vec::new();
+    for object in objects {
+        let keeps_metadata = object.metadata.is_none();
+        let res = match read_current(client, container_id, &object.object_id).await {
+            Ok(current) => {
+                let file = File {
+                    data: object.bytes,
+                    content_type: object.content_type,
+                    content_encoding: object.content_encoding,
+                    ..Default::default()
+                };
+                write_object(
+                    client,
+                    container_id,
+                    &object.object_id,
+                    file,
+                    Some(object.if_version.unwrap_or(current.version)),
+                    None,
+                    object.metadata,
+                )
+                .await
+                .map(|version| (current, version))
+            }
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok((mut current, version)) => {
+                results.push(ObjectWriteResult {
+                    object_id: object.object_id.clone(),
+                    success: true,
+                    version: Some(version.version),
+                    error: None,
+                });
+                // The previous metadata only needs restoring if the write replaced it, and is
+                // cleared if there was none
+                current.metadata = if keeps_metadata {
+                    None
+                } else {
+                    Some(current.metadata.unwrap_or_default())
+                };
+                written.push((object.object_id, current, version.version));
+            }
+            Err(e) => {
+                results.push(ObjectWriteResult {
+                    object_id: object.object_id,
+                    success: false,
+                    version: None,
+                    error: Some(e.to_rpc_string()),
+                });
+                break;
+            }
+        }
+    }
+    let committed = results.iter().all(|result| result.success);
+    let mut rolled_back = Vec::new();
+    if !committed {
+        for (object_id, previous, version) in written.into_iter().rev() {
+            let res = roll_back(client, container_id, &object_id, previous, version).await;
+            if let Err(e) = &res {
+                warn!(%object_id, error = %e, "Failed to roll back write of object group");
+            }
+            rolled_back.push(ItemResult {
+                key: object_id,
+                success: res.is_ok(),
+                error: res.err().map(|e| e.to_rpc_string()),
+            });
+        }
+    }
+    PutObjectGroupResponse {
+        committed,
+        results,
+        rolled_back,
+    }
+}
+
+/// Undoes the write of an object of a group, if it is still at the version written: restores
+/// its previous data, or removes it if it didn't exist
+async fn roll_back(
+    client: &Client,
+    container_id: &str,
+    object_id: &str,
+    previous: Current,
+    version: u64,
+) -> Result<(), VaultError> {
+    let Some(file) = previous.file else {
+        let path = object_path::join(container_id, object_id);
+        let current = client.get_metadata(&path).await?;
+        if current.current_version != version {
+            return Err(VaultError::VersionMismatch { path });
+        }
+        let before = usage::size_before(client, &path).await;
+        client.delete_file(&path).await?;
+        usage::record(client, container_id, before, None).await;
+        return Ok(());
+    };
+    write_object(
+        client,
+        container_id,
+        object_id,
+        file,
+        Some(version),
+        None,
+        previous.metadata,
+    )
+    .await
+    .map(|_| ())
+}
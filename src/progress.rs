@@ -0,0 +1,95 @@
+//! Progress of long transfers such as container exports and imports
+//!
+//! Transfers of many objects can take minutes, so their progress is reported periodically rather
+//! than only once they finish. The provider reports it as log events, the command line
+//! subcommands print it.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+/// Minimum time between two reports of the same transfer
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Counts the objects and bytes a transfer has moved, reporting them every [`REPORT_INTERVAL`]
+pub struct Progress {
+    /// Kind of transfer, e.g. `export`
+    operation: &'static str,
+    /// What is transferred, e.g. the container
+    target: String,
+    /// Whether reports are printed instead of logged
+    print: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    total: usize,
+    objects: usize,
+    bytes: u64,
+    last_report: Instant,
+}
+
+impl Progress {
+    /// Creates a tracker whose reports are logged
+    pub fn logged(operation: &'static str, target: impl Into<String>) -> Progress {
+        Progress::new(operation, target.into(), false)
+    }
+
+    /// Creates a tracker whose reports are printed to stdout
+    pub fn printed(operation: &'static str, target: impl Into<String>) -> Progress {
+        Progress::new(operation, target.into(), true)
+    }
+
+    fn new(operation: &'static str, target: String, print: bool) -> Progress {
+        Progress {
+            operation,
+            target,
+            print,
+            state: Mutex::new(State {
+                total: 0,
+                objects: 0,
+                bytes: 0,
+                last_report: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sets the number of objects the transfer moves once it is known
+    pub fn start(&self, total: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.total = total;
+        state.last_report = Instant::now();
+    }
+
+    /// Records an object of `bytes` bytes as transferred, reporting the progress if the last
+    /// report is older than the report interval
+    pub fn advance(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.objects += 1;
+        state.bytes += bytes;
+        if state.last_report.elapsed() >= REPORT_INTERVAL && state.objects < state.total {
+            state.last_report = Instant::now();
+            self.report(&state);
+        }
+    }
+
+    fn report(&self, state: &State) {
+        if self.print {
+            println!(
+                "{} of '{}': {}/{} objects, {} bytes",
+                self.operation, self.target, state.objects, state.total, state.bytes
+            );
+        } else {
+            info!(
+                operation = self.operation,
+                target = %self.target,
+                objects = state.objects,
+                total = state.total,
+                bytes = state.bytes,
+                "Transfer in progress"
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Recording of Vault interactions to fixtures, to replay them in tests without a Vault server
+//!
+//! `blobstore_vault --record <listen address> <fixtures> [--<setting> <value>...]` runs a proxy
+//! in front of the Vault server of the settings. Every request sent to the listen address, e.g.
+//! by `--selfcheck` or a provider linked with it as `addr`, is forwarded and appended with its
+//! response to the fixtures file as a line of JSON. Tests then serve the recorded responses with
+//! a mock server, so they run against realistic responses, such as those of Vault Enterprise with
+//! namespaces and replication headers, without standing up such a server.
+//!
+//! Tokens, passwords and other credentials are redacted from the fixtures, but the data of the
+//! secrets read and written isn't, so only record against test data.
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::config::Config;
+
+/// Value credentials are replaced with
+pub const REDACTED: &str = "<redacted>";
+/// Headers holding credentials
+const SECRET_HEADERS: [&str; 2] = ["x-vault-token", "authorization"];
+/// Fields of request and response bodies holding credentials
+const SECRET_FIELDS: [&str; 7] = [
+    "client_token",
+    "accessor",
+    "password",
+    "secret_id",
+    "jwt",
+    "token",
+    "signature",
+];
+/// Headers that describe a single connection or transfer rather than the interaction
+const TRANSFER_HEADERS: [&str; 6] = [
+    "connection",
+    "content-length",
+    "date",
+    "host",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+/// A request to Vault and the response it got
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path of the request, with its query if it had one
+    pub path: String,
+    /// Headers of the request, by lowercase name
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Body of the request, if it had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    /// Headers of the response, by lowercase name
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Body of the response, if it had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+/// Reads the interactions of a fixtures file, in the order they were recorded
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Vec<Interaction>> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                anyhow::anyhow!("invalid interaction at {}:{}: {e}", path.display(), i + 1)
+            })
+        })
+        .collect()
+}
+
+/// Forwards the requests sent to `listen` to the first Vault server of the config, appending
+/// each with its response to the fixtures file. Runs until the listener fails
+pub async fn record(config: &Config, listen: SocketAddr, fixtures: PathBuf) -> anyhow::Result<()> {
+    let upstream = config
+        .addrs
+        .first()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no Vault address configured"))?;
+    if upstream.scheme() == "unix" {
+        anyhow::bail!("recording from a unix:// Vault address isn't supported");
+    }
+    // Redirects of standby servers are recorded rather than followed
+    let mut builder = reqwest::Client::builder()
+        .danger_accept_invalid_certs(config.tls_skip_verify)
+        .redirect(reqwest::redirect::Policy::none());
+    for path in &config.certs {
+        builder =
+            builder.add_root_certificate(reqwest::Certificate::from_pem(&std::fs::read(path)?)?);
+    }
+    let http = builder.build()?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&fixtures)?;
+    let file = Arc::new(Mutex::new(file));
+    let listener = TcpListener::bind(listen).await?;
+    info!(%listen, %upstream, fixtures = %fixtures.display(), "Recording Vault interactions");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (http, upstream, file) = (http.clone(), upstream.clone(), file.clone());
+        tokio::spawn(async move {
+            if let Err(e) = proxy(stream, &http, &upstream, &file).await {
+                warn!(%peer, error = %e, "Failed to proxy connection");
+            }
+        });
+    }
+}
+
+/// Proxies the requests of a connection one after the other until it is closed
+async fn proxy(
+    stream: TcpStream,
+    http: &reqwest::Client,
+    upstream: &Url,
+    file: &Mutex<std::fs::File>,
+) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            anyhow::bail!("invalid request line '{}'", line.trim_end());
+        };
+        let (method, path) = (method.to_string(), path.to_string());
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).await?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("invalid header '{header}'"))?;
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+        let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+        if header("transfer-encoding").is_some() {
+            anyhow::bail!("chunked request bodies aren't supported");
+        }
+        let length: usize = header("content-length").map_or(Ok(0), |l| l.parse())?;
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+
+        let mut request = http.request(
+            reqwest::Method::from_bytes(method.as_bytes())?,
+            upstream.join(&path)?,
+        );
+        for (name, value) in headers.iter().filter(|(n, _)| !is_transfer_header(n)) {
+            request = request.header(name, value);
+        }
+        let response = request.body(body.clone()).send().await?;
+        let status = response.status();
+        let response_headers: Vec<_> = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !is_transfer_header(name.as_str()))
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let response_body = response.bytes().await?;
+
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default()
+        );
+        for (name, value) in &response_headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!("content-length: {}\r\n\r\n", response_body.len()));
+        write.write_all(head.as_bytes()).await?;
+        write.write_all(&response_body).await?;
+        write.flush().await?;
+
+        let interaction = Interaction {
+            request: RecordedRequest {
+                method,
+                path,
+                headers: redact_headers(headers.into_iter()),
+                body: redact_body(&body),
+            },
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: redact_headers(response_headers.into_iter()),
+                body: redact_body(&response_body),
+            },
+        };
+        debug!(
+            method = %interaction.request.method,
+            path = %interaction.request.path,
+            status = interaction.response.status,
+            "Recorded Vault interaction"
+        );
+        let mut line = serde_json::to_vec(&interaction)?;
+        line.push(b'\n');
+        file.lock().unwrap().write_all(&line)?;
+    }
+}
+
+fn is_transfer_header(name: &str) -> bool {
+    TRANSFER_HEADERS.contains(&name.to_lowercase().as_str())
+}
+
+/// Returns the headers to record, with credentials redacted
+fn redact_headers(headers: impl Iterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    headers
+        .filter(|(name, _)| !is_transfer_header(name))
+        .map(|(name, value)| {
+            let name = name.to_lowercase();
+            if SECRET_HEADERS.contains(&name.as_str()) {
+                (name, REDACTED.to_string())
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+/// Returns the body to record, with credentials redacted. Bodies that aren't JSON are recorded
+/// as strings
+fn redact_body(body: &[u8]) -> Option<serde_json::Value> {
+    if body.is_empty() {
+        return None;
+    }
+    let Ok(mut value) = serde_json::from_slice(body) else {
+        return Some(String::from_utf8_lossy(body).into_owned().into());
+    };
+    redact_value(&mut value);
+    Some(value)
+}
+
+fn redact_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&name.as_str()) && !field.is_null() {
+                    *field = REDACTED.into();
+                } else {
+                    redact_value(field);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
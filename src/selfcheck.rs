@@ -0,0 +1,131 @@
+//! Connectivity self-check, run with `blobstore_vault --selfcheck`
+//!
+//! Settings are the same as the linkdef values of the provider, taken from `VAULT_*` environment
+//! variables (e.g. `VAULT_ADDR` for `addr`) and overridden by `--<setting> <value>` flags.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    client::Client,
+    config::Config,
+    error::{ErrorCode, VaultError},
+};
+
+/// Folder the probe secret is written to
+const PROBE_FOLDER: &str = "_selfcheck";
+
+/// Collects settings from the environment and the command line arguments following
+/// `--selfcheck`
+pub fn settings(args: impl Iterator<Item = String>) -> anyhow::Result<Vec<(String, String)>> {
+    let mut settings: Vec<(String, String)> = std::env::vars()
+        .filter_map(|(key, value)| {
+            key.strip_prefix("VAULT_")
+                .map(|setting| (setting.to_lowercase(), value))
+        })
+        .collect();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let flag = arg
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow::anyhow!("unexpected argument '{arg}'"))?;
+        let (setting, value) = match flag.split_once('=') {
+            Some((setting, value)) => (setting.to_string(), value.to_string()),
+            None => (
+                flag.to_string(),
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for --{flag}"))?,
+            ),
+        };
+        settings.push((setting.replace('-', "_"), value));
+    }
+    Ok(settings)
+}
+
+/// Connects to Vault and writes, reads, lists and deletes a probe secret, printing the outcome
+/// of each step and a diagnosis of the first failure. Returns true if all steps succeeded
+pub async fn run(settings: &[(String, String)]) -> bool {
+    let config = match Config::from_values(settings) {
+        Ok(config) => config,
+        Err(e) => return fail("parse configuration", e, "check the settings and flags"),
+    };
+    let addrs: Vec<String> = config.addrs.iter().map(ToString::to_string).collect();
+    println!("Vault: {}, mount: {}", addrs.join(", "), config.mount);
+    let client = match Client::new(config) {
+        Ok(client) => client,
+        Err(e) => return fail("create client", e, "check the addresses and certificates"),
+    };
+    if let Err(e) = client.validate().await {
+        return fail("validate token and mount", e, "");
+    }
+    pass("validate token and mount");
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let name = format!("probe-{}-{nanos}", std::process::id());
+    let path = format!("{PROBE_FOLDER}/{name}");
+    let data = path.as_bytes().to_vec();
+
+    if let Err(e) = client.write_file(&path, data.clone()).await {
+        return fail("write probe", &e, hint(&e, "write"));
+    }
+    pass("write probe");
+    let ok = check(&client, &path, &name, &data).await;
+    match client.purge_file(&path).await {
+        Ok(()) => pass("delete probe"),
+        Err(e) => return fail("delete probe", &e, hint(&e, "delete")),
+    }
+    ok
+}
+
+/// Reads and lists the probe, returning true if both match what was written
+async fn check(client: &Client, path: &str, name: &str, data: &[u8]) -> bool {
+    match client.read_file(path).await {
+        Ok(file) if file.data == data => pass("read probe"),
+        Ok(_) => {
+            return fail(
+                "read probe",
+                "data differs from what was written",
+                "another writer may be using the same path",
+            )
+        }
+        Err(e) => return fail("read probe", &e, hint(&e, "read")),
+    }
+    match client.list_files(PROBE_FOLDER).await {
+        Ok(names) if names.iter().any(|n| n == name) => pass("list probe"),
+        Ok(_) => {
+            return fail(
+                "list probe",
+                "probe missing from listing",
+                "reads may be served by a replica that hasn't caught up",
+            )
+        }
+        Err(e) => return fail("list probe", &e, hint(&e, "list")),
+    }
+    true
+}
+
+/// Returns a likely cause of an error
+fn hint(err: &VaultError, capability: &str) -> String {
+    match err.code() {
+        ErrorCode::Unauthorized => {
+            format!("the token's policy needs the '{capability}' capability on the mount")
+        }
+        ErrorCode::Sealed => "unseal Vault".to_string(),
+        ErrorCode::Unavailable => "check that Vault is running and reachable from here".to_string(),
+        ErrorCode::NotFound => "check that the mount is a KV v2 engine".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn pass(step: &str) {
+    println!("ok      {step}");
+}
+
+fn fail(step: &str, err: impl std::fmt::Debug, hint: impl AsRef<str>) -> bool {
+    println!("FAILED  {step}: {err:?}");
+    if !hint.as_ref().is_empty() {
+        println!("        hint: {}", hint.as_ref());
+    }
+    false
+}
@@ -0,0 +1,59 @@
+//! Deduplication of identical concurrent requests
+//!
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+use crate::error::VaultError;
+
+type SharedCall<T> = Shared<BoxFuture<'static, Result<T, Arc<VaultError>>>>;
+
+/// Coalesces concurrent calls for the same key into a single call whose result is shared by all
+/// callers. Once the call completes, the next call for the key starts a new one
+pub struct Group<T: Clone> {
+    calls: Arc<Mutex<HashMap<String, SharedCall<T>>>>,
+}
+
+impl<T: Clone> Default for Group<T> {
+    fn default() -> Self {
+        Group {
+            calls: Default::default(),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Group<T> {
+    /// Runs `call` unless a call for `key` is already in flight, in which case its result is
+    /// awaited instead
+    pub async fn work<F>(&self, key: &str, call: F) -> Result<T, VaultError>
+    where
+        F: Future<Output = Result<T, VaultError>> + Send + 'static,
+    {
+        let shared = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(key) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let calls_ref = self.calls.clone();
+                    let owned_key = key.to_string();
+                    // The entry is removed by the call itself rather than by the caller that
+                    // started it, so it is cleaned up even if that caller is cancelled
+                    let shared = async move {
+                        let res = call.await.map_err(Arc::new);
+                        calls_ref.lock().unwrap().remove(&owned_key);
+                        res
+                    }
+                    .boxed()
+                    .shared();
+                    calls.insert(key.to_string(), shared.clone());
+                    shared
+                }
+            }
+        };
+        shared.await.map_err(VaultError::from_shared)
+    }
+}
@@ -0,0 +1,117 @@
+//! Snapshots of containers, recording the version every object had at one point in time
+//!
+//! Vault keeps the versions of each secret, so a snapshot only needs to remember which version
+//! of every object was current. Restoring it writes those versions as the latest versions
+//! again, like `Blobstore.RestoreObject` does for single objects.
+//!
+//! The manifest of a snapshot is a secret under [`SNAPSHOT_PREFIX`], holding the versions by
+//! object ID relative to the container. Manifests are written once, so a snapshot name can't be
+//! reused for another snapshot of the container.
+//!
+//! NOTE: Objects are read one after the other, so writes made while a snapshot is taken may or
+//! may not be part of it. Versions pruned by `max_versions` can no longer be restored.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{live_version, Client},
+    config::DEFAULT_LIST_METADATA_CONCURRENCY,
+    deadline,
+    error::VaultError,
+    export, object_path,
+    wasmcloud_interface_blobstore::Timestamp,
+};
+
+/// Folder of the mount holding the manifests of snapshots. It is hidden from listings
+pub const SNAPSHOT_PREFIX: &str = "_snapshots";
+
+/// Returns the path of the manifest of a container's snapshot
+pub fn snapshot_path(container: &str, name: &str) -> String {
+    format!(
+        "{SNAPSHOT_PREFIX}/{}/{name}",
+        container.trim_end_matches('/')
+    )
+}
+
+/// Content of a snapshot manifest secret
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    /// Seconds since the Unix epoch at which the snapshot was taken
+    pub created_at: u64,
+    /// Version of each object, by its ID relative to the container
+    pub objects: BTreeMap<String, u64>,
+}
+
+/// Records the current version of every object of a container as the snapshot `name`. Objects
+/// whose latest version is deleted aren't part of it. Fails with
+/// [`VaultError::VersionMismatch`] if the container already has a snapshot with that name
+pub async fn take(client: &Client, container: &str, name: &str) -> Result<Manifest, VaultError> {
+    let container = container.trim_end_matches('/');
+    let ids = export::walk(client, container).await?;
+    let concurrency = client
+        .list_metadata()
+        .unwrap_or(DEFAULT_LIST_METADATA_CONCURRENCY);
+    let paths = ids.into_iter().map(|id| object_path::join(container, &id));
+    let mut manifest = Manifest {
+        created_at: Timestamp::now().sec,
+        objects: BTreeMap::new(),
+    };
+    for (path, res) in client.get_metadata_many(paths, concurrency).await {
+        let version = match res {
+            Ok(metadata) => live_version(&metadata),
+            // Removed since the container was listed
+            Err(VaultError::NotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(version) = version {
+            let id = path[container.len() + 1..].to_string();
+            manifest.objects.insert(id, version);
+        }
+    }
+    client.write_snapshot(container, name, &manifest).await?;
+    Ok(manifest)
+}
+
+/// Writes the version every object had in the snapshot `name` of a container as its latest
+/// version, returning the IDs of the objects rewritten. Objects already at that version are
+/// left alone, as are objects created after the snapshot was taken. With `dry_run` nothing is
+/// written, and the IDs of the objects that would be are returned
+pub async fn restore(
+    client: &Client,
+    container: &str,
+    name: &str,
+    dry_run: bool,
+) -> Result<Vec<String>, VaultError> {
+    let container = container.trim_end_matches('/');
+    let manifest = client.read_snapshot(container, name).await?;
+    let concurrency = client
+        .list_metadata()
+        .unwrap_or(DEFAULT_LIST_METADATA_CONCURRENCY);
+    let paths: Vec<_> = manifest
+        .objects
+        .keys()
+        .map(|id| object_path::join(container, id))
+        .collect();
+    let mut changed = Vec::new();
+    for ((id, version), (path, res)) in manifest
+        .objects
+        .iter()
+        .zip(client.get_metadata_many(paths, concurrency).await)
+    {
+        let current = match res {
+            Ok(metadata) => live_version(&metadata),
+            Err(VaultError::NotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        if current == Some(*version) {
+            continue;
+        }
+        if !dry_run {
+            deadline::check()?;
+            client.restore_file(&path, *version).await?;
+        }
+        changed.push(id.clone());
+    }
+    Ok(changed)
+}
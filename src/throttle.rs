@@ -0,0 +1,63 @@
+//! Bandwidth limiting for object data sent to and received from Vault
+//!
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use opentelemetry::{
+    metrics::{Counter, Unit},
+    KeyValue,
+};
+
+/// Limits the rate of bytes transferred with a token bucket holding up to one second worth of
+/// bytes. Transfers larger than the bucket are let through once it is full and leave it in debt,
+/// so later transfers wait until the rate is met again
+#[derive(Debug)]
+pub struct Throttle {
+    direction: &'static str,
+    bytes_per_sec: f64,
+    /// Bytes that can be transferred without waiting, negative when in debt, as of the instant
+    bucket: Mutex<(f64, Instant)>,
+    throttled: Counter<f64>,
+}
+
+impl Throttle {
+    /// Creates a throttle for transfers in `direction`, which names it in metrics
+    pub fn new(direction: &'static str, bytes_per_sec: u64) -> Throttle {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Throttle {
+            direction,
+            bytes_per_sec,
+            bucket: Mutex::new((bytes_per_sec, Instant::now())),
+            throttled: opentelemetry::global::meter("blobstore-vault")
+                .f64_counter("blobstore_vault.throttled_time")
+                .with_description("Time transfers waited for the bandwidth limit of their link")
+                .with_unit(Unit::new("s"))
+                .init(),
+        }
+    }
+
+    /// Waits until `bytes` can be transferred without exceeding the rate, returning the time
+    /// waited
+    pub async fn consume(&self, bytes: u64) -> Duration {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refilled = bucket.1.elapsed().as_secs_f64() * self.bytes_per_sec;
+            let available = (bucket.0 + refilled).min(self.bytes_per_sec);
+            // Wait until the bucket is full when the transfer is larger than it
+            let wait = (bytes as f64).min(self.bytes_per_sec) - available;
+            *bucket = (available - bytes as f64, now);
+            Duration::from_secs_f64(wait.max(0.0) / self.bytes_per_sec)
+        };
+        if !wait.is_zero() {
+            self.throttled.add(
+                wait.as_secs_f64(),
+                &[KeyValue::new("direction", self.direction)],
+            );
+            tokio::time::sleep(wait).await;
+        }
+        wait
+    }
+}
@@ -0,0 +1,99 @@
+//! rustls configuration of connections to Vault
+//!
+//! reqwest can't restrict the cipher suites of rustls, so when a link does the HTTP client is
+//! given a configuration built here instead, with the roots, client certificate and minimum TLS
+//! version it would otherwise have set itself.
+use std::io::BufReader;
+
+use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, SupportedCipherSuite};
+use vaultrs::error::ClientError;
+
+use crate::config::Config;
+
+/// Returns the cipher suites with the given IANA names, in the same order
+pub fn cipher_suites(names: &[String]) -> anyhow::Result<Vec<SupportedCipherSuite>> {
+    names
+        .iter()
+        .map(|name| {
+            rustls::ALL_CIPHER_SUITES
+                .iter()
+                .find(|suite| suite.suite().as_str() == Some(name.to_uppercase().as_str()))
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("unsupported cipher suite '{name}'"))
+        })
+        .collect()
+}
+
+/// TLS versions connections may use when the link requires TLS 1.3
+static TLS13_ONLY: &[&rustls::SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Returns the TLS versions connections may use given the minimum version of the link
+fn versions(config: &Config) -> &'static [&'static rustls::SupportedProtocolVersion] {
+    match config.tls_min_version {
+        Some(reqwest::tls::Version::TLS_1_3) => TLS13_ONLY,
+        _ => rustls::ALL_VERSIONS,
+    }
+}
+
+/// Checks that some of the cipher suites of the link can be used with its minimum TLS version
+pub fn check(config: &Config) -> anyhow::Result<()> {
+    ClientConfig::builder()
+        .with_cipher_suites(&cipher_suites(&config.tls_cipher_suites)?)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions(config))
+        .map(drop)
+        .map_err(|e| anyhow::anyhow!("invalid tls_cipher_suites: {e}"))
+}
+
+/// Builds the rustls configuration of a link restricting its cipher suites. The config must
+/// have passed [`check`]
+pub fn client_config(config: &Config) -> Result<ClientConfig, ClientError> {
+    let read_pem = |path: &String, parse: fn(&mut dyn std::io::BufRead) -> std::io::Result<_>| {
+        std::fs::File::open(path)
+            .and_then(|file| parse(&mut BufReader::new(file)))
+            .map_err(|source| ClientError::FileReadError {
+                source,
+                path: path.clone(),
+            })
+    };
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    for path in &config.certs {
+        roots.add_parsable_certificates(&read_pem(path, rustls_pemfile::certs)?);
+    }
+    let suites = cipher_suites(&config.tls_cipher_suites).unwrap_or_default();
+    let builder = ClientConfig::builder()
+        .with_cipher_suites(&suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions(config))
+        .expect("cipher suites were checked with the config")
+        .with_root_certificates(roots);
+    let (Some(cert_path), Some(key_path)) = (&config.tls_client_cert, &config.tls_client_key)
+    else {
+        return Ok(builder.with_no_client_auth());
+    };
+    let chain = read_pem(cert_path, rustls_pemfile::certs)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let key = read_pem(key_path, rustls_pemfile::pkcs8_private_keys)?
+        .into_iter()
+        .next()
+        .map(rustls::PrivateKey);
+    key.ok_or_else(|| "no PKCS#8 private key found".to_string())
+        .and_then(|key| {
+            builder
+                .with_client_auth_cert(chain, key)
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e| ClientError::FileReadError {
+            source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            path: cert_path.clone(),
+        })
+}
@@ -0,0 +1,146 @@
+//! Requests to Vault servers listening on a Unix socket
+//!
+//! Vault Agent and Vault Proxy can listen on a Unix socket instead of a TCP port, for hosts that
+//! don't allow loopback listeners. reqwest only connects over TCP, so the requests of a
+//! [`Connection`](crate::api::Connection) to an address such as `unix:///run/vault/agent.sock`
+//! are sent with hyper over a connection to the socket instead. The socket is named by the
+//! hex-encoded host of the URIs given to hyper, so the connections to each socket are pooled.
+use std::{
+    ffi::OsString,
+    future::Future,
+    io,
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+    pin::Pin,
+    sync::OnceLock,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::{
+    client::connect::{Connected, Connection},
+    header::HeaderMap,
+    Body, Method, Request, Response, Uri,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UnixStream,
+};
+
+use crate::json;
+
+/// A connection to a Unix socket
+struct Stream(UnixStream);
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Connects to the socket named by the host of a URI
+#[derive(Clone)]
+struct Connector;
+
+impl tower_service::Service<Uri> for Connector {
+    type Response = Stream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Stream>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(async move {
+            let socket = uri.host().and_then(decode).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("no socket in {uri}"))
+            })?;
+            UnixStream::connect(socket).await.map(Stream)
+        })
+    }
+}
+
+/// Hex-encodes the path of a socket into a URI host
+fn encode(socket: &str) -> String {
+    socket.bytes().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decodes the path of a socket from a URI host
+fn decode(host: &str) -> Option<PathBuf> {
+    let bytes = (0..host.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(host.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(OsString::from_vec(bytes).into())
+}
+
+/// Adds the headers of `defaults` that `headers` doesn't set, the way reqwest adds the default
+/// headers of its client. Requests sent over a socket don't go through that client, so the headers
+/// of the link are added with this instead
+pub fn add_defaults(headers: &mut HeaderMap, defaults: &HeaderMap) {
+    for name in defaults.keys() {
+        if !headers.contains_key(name) {
+            for value in defaults.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Sends a request to an API path such as `v1/secret/data/key` of the server listening on the
+/// socket at `socket`, failing if its response doesn't start within `timeout`. The body of the
+/// response is left to be received
+pub async fn send(
+    socket: &str,
+    method: Method,
+    path: &str,
+    headers: HeaderMap,
+    body: Option<json::Body>,
+    timeout: Option<Duration>,
+) -> anyhow::Result<Response<Body>> {
+    static CLIENT: OnceLock<hyper::Client<Connector>> = OnceLock::new();
+    let client = CLIENT.get_or_init(|| hyper::Client::builder().build(Connector));
+    let mut request = Request::builder()
+        .method(method)
+        .uri(format!("http://{}/{path}", encode(socket)))
+        .body(match body {
+            Some(json::Body::Whole(body)) => Body::from(body),
+            Some(body) => Body::wrap_stream(body.into_chunks()),
+            None => Body::empty(),
+        })?;
+    *request.headers_mut() = headers;
+    let response = client.request(request);
+    Ok(match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, response).await??,
+        None => response.await?,
+    })
+}
@@ -0,0 +1,245 @@
+//! In-progress multipart uploads
+//!
+//! Chunks of an upload are buffered in its [`UploadSession`] until the last one arrived, but each
+//! is also written to Vault as it arrives, under [`UPLOAD_PREFIX`] in the mount of the container,
+//! with a [`Manifest`] of the upload recording the offset received so far and the checksum of
+//! every chunk. When a chunk arrives for an upload the provider doesn't know, e.g. because it
+//! restarted, the session is read back from Vault, so the upload resumes at the offset it
+//! reached instead of starting over. The secrets of an upload are purged once it completes or
+//! is cancelled, and those of abandoned uploads only if the janitor is told to.
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+use crate::cas;
+
+/// Folder of the mount holding the manifests and chunks of multipart uploads. It is hidden from
+/// listings
+pub const UPLOAD_PREFIX: &str = "_uploads";
+
+/// Returns the path of the manifest of an upload
+pub fn manifest_path(stream_id: &str) -> String {
+    format!("{UPLOAD_PREFIX}/{stream_id}/manifest")
+}
+
+/// Returns the path of the chunk of an upload with the given index
+pub fn chunk_path(stream_id: &str, index: usize) -> String {
+    format!("{UPLOAD_PREFIX}/{stream_id}/chunks/{index}")
+}
+
+/// A chunk of an upload written to Vault
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Chunk {
+    /// Offset of the chunk in the object
+    pub offset: u64,
+    /// Hex-encoded SHA-256 hash of the data of the chunk
+    pub sha256: String,
+}
+
+/// Content of the manifest secret of an upload, everything needed to resume it
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    pub actor_id: String,
+    pub container_id: String,
+    pub object_id: String,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub if_version: Option<u64>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub idempotency_key: Option<String>,
+    /// Offset the next chunk starts at
+    pub received: u64,
+    /// Chunks received so far, in order
+    pub chunks: Vec<Chunk>,
+}
+
+/// Default time after which an upload that hasn't received a chunk is abandoned
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Settings for abandoning stalled uploads
+#[derive(Clone, Debug)]
+pub struct JanitorSettings {
+    /// Time after which an upload that hasn't received a chunk is abandoned
+    pub timeout: Duration,
+    /// Whether to delete the secrets an abandoned upload already wrote
+    pub delete_partial: bool,
+    /// How often to remove orphaned content-addressed secrets. Disabled if None
+    pub gc_interval: Option<Duration>,
+}
+
+impl Default for JanitorSettings {
+    fn default() -> Self {
+        JanitorSettings {
+            timeout: DEFAULT_UPLOAD_TIMEOUT,
+            delete_partial: false,
+            gc_interval: None,
+        }
+    }
+}
+
+/// State of a single multipart upload
+#[derive(Debug)]
+pub struct UploadSession {
+    pub actor_id: String,
+    pub container_id: String,
+    pub object_id: String,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    /// Version the object must have when the upload completes, as in `PutObject`
+    pub if_version: Option<u64>,
+    /// User metadata written with the object once the upload completes
+    pub metadata: Option<HashMap<String, String>>,
+    /// Idempotency key of the write once the upload completes
+    pub idempotency_key: Option<String>,
+    /// Data received so far, which is written to the object once the last chunk arrived
+    pub buffer: BytesMut,
+    /// Chunks received so far, which were written to Vault with the manifest of the upload
+    pub chunks: Vec<Chunk>,
+    last_activity: Instant,
+}
+
+impl UploadSession {
+    pub fn new(
+        actor_id: impl Into<String>,
+        container_id: impl Into<String>,
+        object_id: impl Into<String>,
+    ) -> UploadSession {
+        UploadSession {
+            actor_id: actor_id.into(),
+            container_id: container_id.into(),
+            object_id: object_id.into(),
+            content_type: None,
+            content_encoding: None,
+            if_version: None,
+            metadata: None,
+            idempotency_key: None,
+            buffer: BytesMut::new(),
+            chunks: Vec::new(),
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Returns the session of an upload read back from Vault, with the data of its chunks
+    pub fn resume(manifest: Manifest, buffer: BytesMut) -> UploadSession {
+        UploadSession {
+            actor_id: manifest.actor_id,
+            container_id: manifest.container_id,
+            object_id: manifest.object_id,
+            content_type: manifest.content_type,
+            content_encoding: manifest.content_encoding,
+            if_version: manifest.if_version,
+            metadata: manifest.metadata,
+            idempotency_key: manifest.idempotency_key,
+            buffer,
+            chunks: manifest.chunks,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Returns the manifest of the upload once `data` was added at its current offset, with the
+    /// index of the new chunk. The session itself is only changed by [`UploadSession::append`],
+    /// once the chunk was written to Vault
+    pub fn manifest_with(&self, data: &[u8]) -> (Manifest, usize) {
+        let mut chunks = self.chunks.clone();
+        chunks.push(Chunk {
+            offset: self.received(),
+            sha256: cas::hash(data),
+        });
+        let manifest = Manifest {
+            actor_id: self.actor_id.clone(),
+            container_id: self.container_id.clone(),
+            object_id: self.object_id.clone(),
+            content_type: self.content_type.clone(),
+            content_encoding: self.content_encoding.clone(),
+            if_version: self.if_version,
+            metadata: self.metadata.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+            received: self.received() + data.len() as u64,
+            chunks,
+        };
+        (manifest, self.chunks.len())
+    }
+
+    /// Adds a chunk at the current offset
+    pub fn append(&mut self, data: &Bytes) {
+        self.chunks.push(Chunk {
+            offset: self.received(),
+            sha256: cas::hash(data),
+        });
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns whether `data` is the chunk already received at `offset`, as when a chunk is sent
+    /// again because the response to it was lost
+    pub fn has_chunk(&self, offset: u64, data: &[u8]) -> bool {
+        self.chunks
+            .iter()
+            .any(|chunk| chunk.offset == offset && chunk.sha256 == cas::hash(data))
+    }
+
+    /// Returns the number of bytes received so far
+    pub fn received(&self) -> u64 {
+        self.buffer.len() as u64
+    }
+}
+
+/// All in-progress uploads, keyed by stream ID
+#[derive(Default)]
+pub struct UploadSessions {
+    sessions: Mutex<HashMap<String, UploadSession>>,
+}
+
+impl UploadSessions {
+    /// Registers a new upload, returning its stream ID. Stream IDs are random rather than
+    /// counted, so they aren't reused by the uploads of a restarted provider
+    pub fn start(&self, session: UploadSession) -> String {
+        let stream_id = format!("{}-{:016x}", session.actor_id, rand::random::<u64>());
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(stream_id.clone(), session);
+        stream_id
+    }
+
+    /// Registers an upload read back from Vault, unless it was already resumed meanwhile
+    pub fn resume(&self, stream_id: &str, session: UploadSession) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .entry(stream_id.to_string())
+            .or_insert(session);
+    }
+
+    /// Runs `f` on the session of the given upload, marking it as active. Returns None if there is
+    /// no such upload, e.g. because it expired
+    pub fn update<T>(&self, stream_id: &str, f: impl FnOnce(&mut UploadSession) -> T) -> Option<T> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(stream_id)?;
+        session.last_activity = Instant::now();
+        Some(f(session))
+    }
+
+    /// Removes an upload, e.g. once it completed or was cancelled
+    pub fn remove(&self, stream_id: &str) -> Option<UploadSession> {
+        self.sessions.lock().unwrap().remove(stream_id)
+    }
+
+    /// Removes and returns all uploads that have been idle for longer than `timeout`
+    pub fn expire(&self, timeout: Duration) -> Vec<(String, UploadSession)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, s)| s.last_activity.elapsed() > timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| sessions.remove(&id).map(|s| (id, s)))
+            .collect()
+    }
+}
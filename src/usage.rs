@@ -0,0 +1,99 @@
+//! Usage of containers, kept up to date as objects are written and removed
+//!
+//! With `usage_accounting` set, each container has a marker secret under [`CONTAINER_PREFIX`]
+//! holding the number of objects in it, the bytes of their data and the time of the last change,
+//! which `GetContainerInfo` returns so capacity dashboards don't need to list every object. The
+//! marker is updated with check-and-set writes after each write or removal, retried when several
+//! changes race.
+//!
+//! NOTE: Vault metadata doesn't record the size of a secret, so the previous data of an object is
+//! read before it is overwritten or removed. Only changes made through this provider while
+//! accounting is enabled are counted, and a change whose marker update fails is only logged.
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{client::Client, error::VaultError, wasmcloud_interface_blobstore::Timestamp};
+
+/// Folder of the mount holding the markers of containers. It is hidden from listings
+pub const CONTAINER_PREFIX: &str = "_containers";
+/// Times the marker of a container is read and written again when another change raced it
+pub const UPDATE_ATTEMPTS: usize = 5;
+
+/// Returns the path of the marker of a container
+pub fn container_path(container: &str) -> String {
+    format!("{CONTAINER_PREFIX}/{}", container.trim_end_matches('/'))
+}
+
+/// Content of a container marker secret
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Usage {
+    /// Seconds since the Unix epoch at which the first change to the container was counted
+    pub created_at: u64,
+    pub object_count: u64,
+    /// Bytes of the data of all objects
+    pub total_bytes: u64,
+    /// Seconds since the Unix epoch of the last change to an object
+    pub last_write: u64,
+}
+
+impl Usage {
+    /// Creates the usage of a container nothing was counted for yet
+    pub fn new() -> Usage {
+        let now = Timestamp::now().sec;
+        Usage {
+            created_at: now,
+            last_write: now,
+            ..Default::default()
+        }
+    }
+
+    /// Counts the change of an object from `before` to `after` bytes, None meaning it didn't
+    /// exist
+    pub fn apply(&mut self, before: Option<u64>, after: Option<u64>) {
+        if let Some(size) = before {
+            self.object_count = self.object_count.saturating_sub(1);
+            self.total_bytes = self.total_bytes.saturating_sub(size);
+        }
+        if let Some(size) = after {
+            self.object_count += 1;
+            self.total_bytes += size;
+        }
+        self.last_write = Timestamp::now().sec;
+    }
+}
+
+/// Returns the size of an object's data before it is changed, for [`record`]. None if the link
+/// doesn't account usage or the size couldn't be read, in which case the change isn't counted
+pub async fn size_before(client: &Client, path: &str) -> Option<Option<u64>> {
+    if !client.usage_accounting() {
+        return None;
+    }
+    match client.read_file(path).await {
+        Ok(file) => Some(Some(file.data.len() as u64)),
+        Err(VaultError::NotFound { .. }) => Some(None),
+        Err(e) => {
+            warn!(error = %e, %path, "Failed to read size of object, not counting its change");
+            None
+        }
+    }
+}
+
+/// Counts the change of an object of a container to `after` bytes in the container's marker,
+/// with `before` as returned by [`size_before`]. Failures are only logged, as the change itself
+/// succeeded
+pub async fn record(
+    client: &Client,
+    container: &str,
+    before: Option<Option<u64>>,
+    after: Option<u64>,
+) {
+    let Some(before) = before else {
+        return;
+    };
+    if before.is_none() && after.is_none() {
+        return;
+    }
+    if let Err(e) = client.update_usage(container, before, after).await {
+        warn!(error = %e, container, "Failed to update usage of container");
+    }
+}
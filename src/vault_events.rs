@@ -0,0 +1,125 @@
+//! Subscription to the event notifications of Vault 1.16+ for changes to secrets of a mount
+//!
+//! Vault streams events over a WebSocket at `sys/events/subscribe`. Unlike the events the provider
+//! publishes itself, these include changes made by other providers and Vault clients sharing the
+//! mount, but not the actor that made them.
+use futures::{Stream, StreamExt};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+use tracing::debug;
+
+use crate::{
+    cas::CAS_PREFIX, events::ChangeOperation, lock::LOCK_PREFIX, object_path,
+    snapshot::SNAPSHOT_PREFIX, usage::CONTAINER_PREFIX,
+};
+
+/// Event types subscribed to, covering all KV v2 operations
+const EVENT_TYPES: &str = "kv-v2/*";
+
+/// A change to a secret reported by Vault
+#[derive(Clone, Debug)]
+pub struct SecretChange {
+    pub operation: ChangeOperation,
+    /// Decoded path of the secret within the mount
+    pub path: String,
+    /// Version of the secret after a put, if Vault reported it
+    pub version: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    data: EventData,
+}
+
+#[derive(Deserialize)]
+struct EventData {
+    event: Event,
+    plugin_info: PluginInfo,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    metadata: EventMetadata,
+}
+
+#[derive(Deserialize)]
+struct EventMetadata {
+    operation: String,
+    path: String,
+    #[serde(default)]
+    current_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PluginInfo {
+    mount_path: String,
+}
+
+/// Subscribes to changes of secrets in `mount`, returning them as a stream that ends when Vault
+/// closes the connection. `headers` are added to the WebSocket handshake
+pub async fn subscribe(
+    addr: &url::Url,
+    token: &str,
+    headers: &HeaderMap,
+    mount: &str,
+) -> anyhow::Result<impl Stream<Item = SecretChange>> {
+    let mut url = addr.join(&format!("v1/sys/events/subscribe/{EVENT_TYPES}?json=true"))?;
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow::anyhow!("cannot use {url} as a WebSocket address"))?;
+    let mut request = url.as_str().into_client_request()?;
+    request.headers_mut().extend(headers.clone());
+    request
+        .headers_mut()
+        .insert("X-Vault-Token", token.parse()?);
+    let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+    let mount = format!("{}/", mount.trim_matches('/'));
+    Ok(socket
+        .take_while(|msg| futures::future::ready(msg.is_ok()))
+        .filter_map(move |msg| {
+            let change = match msg {
+                Ok(Message::Text(text)) => parse(&text, &mount),
+                _ => None,
+            };
+            futures::future::ready(change)
+        }))
+}
+
+/// Parses an event, returning the change it reports if it's a change to an object in the mount
+fn parse(text: &str, mount: &str) -> Option<SecretChange> {
+    let envelope: Envelope = match serde_json::from_str(text) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            debug!(error = %e, "Ignoring malformed Vault event");
+            return None;
+        }
+    };
+    if envelope.data.plugin_info.mount_path != mount {
+        return None;
+    }
+    let metadata = envelope.data.event.metadata;
+    let operation = match metadata.operation.as_str() {
+        "data-write" | "data-patch" | "undelete" => ChangeOperation::Put,
+        "data-delete" | "delete" | "destroy" | "metadata-delete" => ChangeOperation::Remove,
+        _ => return None,
+    };
+    let key = metadata
+        .path
+        .strip_prefix(mount)
+        .and_then(|rest| rest.split_once('/'))
+        .map(|(_, key)| key)?;
+    let path = object_path::decode(key);
+    if [CAS_PREFIX, LOCK_PREFIX, SNAPSHOT_PREFIX, CONTAINER_PREFIX]
+        .iter()
+        .any(|prefix| path.starts_with(&format!("{prefix}/")))
+    {
+        return None;
+    }
+    Some(SecretChange {
+        operation,
+        path,
+        version: metadata.current_version.and_then(|v| v.parse().ok()),
+    })
+}
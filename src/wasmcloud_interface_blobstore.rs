@@ -1,5 +1,11 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+#[cfg(feature = "provider")]
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "provider")]
 use wasmcloud_provider_sdk::Context;
 
 pub type ContainerId = String;
@@ -30,6 +36,18 @@ impl Timestamp {
             nsec: since_epoch.subsec_nanos(),
         }
     }
+
+    /// Parses an RFC 3339 timestamp in UTC, as returned by Vault
+    pub fn parse_rfc3339(s: &str) -> Option<Timestamp> {
+        let since_epoch = humantime::parse_rfc3339(s)
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+        Some(Timestamp {
+            sec: since_epoch.as_secs(),
+            nsec: since_epoch.subsec_nanos(),
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -39,9 +57,8 @@ pub struct Chunk {
     #[serde(rename = "containerId")]
     pub container_id: ContainerId,
     /// bytes in this chunk
-    #[serde(with = "serde_bytes")]
     #[serde(default)]
-    pub bytes: Vec<u8>,
+    pub bytes: Bytes,
     /// The byte offset within the object for this chunk
     #[serde(default)]
     pub offset: u64,
@@ -68,6 +85,21 @@ pub struct ContainerMetadata {
     #[serde(rename = "createdAt")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub created_at: Option<Timestamp>,
+    /// Number of objects in the container, if the link accounts usage. Extension of the
+    /// interface, not set by other providers
+    #[serde(rename = "objectCount")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_count: Option<u64>,
+    /// Bytes of the data of all objects in the container, if the link accounts usage. Extension
+    /// of the interface, not set by other providers
+    #[serde(rename = "totalBytes")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// Time of the last change to an object in the container, if the link accounts usage.
+    /// Extension of the interface, not set by other providers
+    #[serde(rename = "lastModified")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<Timestamp>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -78,6 +110,18 @@ pub struct ContainerObject {
     pub object_id: ObjectId,
 }
 
+/// Request of the `Blobstore.GetObjectIfChanged` extension, which only returns the object's data
+/// if its version differs from the one the caller has
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct GetObjectIfChangedRequest {
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Version of the object the caller has
+    pub version: u64,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct GetObjectRequest {
     /// object to download
@@ -99,6 +143,25 @@ pub struct GetObjectRequest {
     #[serde(rename = "rangeEnd")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub range_end: Option<u64>,
+    /// Comma-separated content encodings the caller can decode, like the `Accept-Encoding` header
+    /// of HTTP. If it includes `gzip`, large objects may be returned compressed with
+    /// `contentEncoding` set to `gzip`. Extension of the interface, ignored by other providers
+    #[serde(rename = "acceptEncoding")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept_encoding: Option<String>,
+}
+
+impl GetObjectRequest {
+    /// Returns the bytes of an object of `len` bytes the request asks for, following the rules
+    /// of `rangeStart` and `rangeEnd`: a start beyond the end selects nothing, and an end beyond
+    /// it stops at the end
+    pub fn range(&self, len: u64) -> std::ops::Range<u64> {
+        let start = self.range_start.unwrap_or(0).min(len);
+        let end = self
+            .range_end
+            .map_or(len, |end| end.saturating_add(1).min(len));
+        start..end.max(start)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -127,6 +190,32 @@ pub struct GetObjectResponse {
     #[serde(rename = "contentEncoding")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub content_encoding: Option<String>,
+    /// Version of the object, which changes on every write. Can be used like an ETag to detect
+    /// changes. Extension of the interface, not set by other providers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    /// Set by GetObjectIfChanged when the object still has the version the caller has, in which
+    /// case no data is returned. Extension of the interface
+    #[serde(rename = "notModified")]
+    #[serde(default)]
+    pub not_modified: bool,
+}
+
+/// A version of an object, returned by the `Blobstore.GetObjectVersions` extension
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ObjectVersion {
+    pub version: u64,
+    /// When the version was written
+    #[serde(rename = "createdAt")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<Timestamp>,
+    /// When the version was deleted, if it was. Deleted versions can still be restored
+    #[serde(rename = "deletedAt")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<Timestamp>,
+    /// Whether the data of the version was permanently removed
+    #[serde(default)]
+    pub destroyed: bool,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -141,6 +230,21 @@ pub struct ItemResult {
     pub error: Option<String>,
 }
 
+/// Request of the `Blobstore.ListObjectsByTag` extension, which lists the objects whose user
+/// metadata contains all of the given tags
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ListObjectsByTagRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Only list objects whose names start with this prefix. Like for ListObjects, only objects
+    /// in the folder the prefix is in are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Key-value pairs that must all be in the user metadata of a listed object
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ListObjectsRequest {
     /// Name of the container to search
@@ -174,12 +278,68 @@ pub struct ListObjectsRequest {
     #[serde(rename = "maxItems")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_items: Option<u32>,
+    /// Only return objects whose names start with this value. (Optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Group objects whose names contain this value after `prefix` into
+    /// `commonPrefixes` instead of returning them, like folders in a
+    /// file system. Only "/" is supported. (Optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    /// Order the objects are returned in, applied before the listing is split into pages.
+    /// Defaults to ascending names. Extension of the interface, ignored by other providers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<ListSort>,
+    /// Only return objects modified after this time. Folders are returned regardless. Requires
+    /// the link to set `list_metadata`. Extension of the interface, ignored by other providers
+    #[serde(rename = "modifiedAfter")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_after: Option<Timestamp>,
+    /// Only return objects modified before this time. Folders are returned regardless. Requires
+    /// the link to set `list_metadata`. Extension of the interface, ignored by other providers
+    #[serde(rename = "modifiedBefore")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_before: Option<Timestamp>,
+    /// Only return objects whose IDs match this glob pattern, such as
+    /// `reports/2024-*/summary.json`, including objects in subfolders. `*` matches within a
+    /// folder, `**` across folders and `?` a single character. Extension of the interface,
+    /// ignored by other providers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+}
+
+/// Order of the objects of a listing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ListSort {
+    #[default]
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "nameDesc")]
+    NameDesc,
+    /// Least recently modified first. Requires the link to set `list_metadata`
+    #[serde(rename = "lastModified")]
+    LastModified,
+    /// Most recently modified first. Requires the link to set `list_metadata`
+    #[serde(rename = "lastModifiedDesc")]
+    LastModifiedDesc,
+}
+
+impl ListSort {
+    /// Returns whether the order depends on the modification times of objects
+    pub fn by_modified(&self) -> bool {
+        matches!(self, ListSort::LastModified | ListSort::LastModifiedDesc)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ListObjectsResponse {
     /// set of objects returned
     pub objects: ObjectsInfo,
+    /// Names ending in the `delimiter` of the request that group the
+    /// objects below them. Empty if the request didn't set `delimiter`
+    #[serde(rename = "commonPrefixes")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub common_prefixes: Vec<String>,
     /// Indicates if the item list is complete, or the last item
     /// in a multi-part response.
     #[serde(rename = "isLast")]
@@ -227,6 +387,15 @@ pub struct ObjectMetadata {
     #[serde(rename = "contentEncoding")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub content_encoding: Option<String>,
+    /// Version of the object, which changes on every write. Can be used like an ETag to detect
+    /// changes. Extension of the interface, not set by other providers. Set by GetObjectInfo, and
+    /// by ListObjects if the link has `list_metadata` enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    /// User metadata set when the object was written. Extension of the interface, filled in
+    /// like `version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -261,6 +430,106 @@ pub struct PutObjectRequest {
     #[serde(rename = "contentEncoding")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub content_encoding: Option<String>,
+    /// Only write the object if its current version is this one, or if it doesn't exist yet when
+    /// 0, failing with a `Conflict` error otherwise. Extension of the interface, ignored by other
+    /// providers
+    #[serde(rename = "ifVersion")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_version: Option<u64>,
+    /// User metadata to store with the object, such as tags, replacing any it had. Existing
+    /// metadata is kept if not set. Vault limits it to 64 entries, with keys of up to 128 bytes
+    /// and values of up to 512 bytes. Extension of the interface, ignored by other providers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+    /// Key identifying this write across retries. A retry with the same key and content within
+    /// the link's idempotency window returns the result of the first write instead of writing
+    /// another version. Extension of the interface, ignored by other providers
+    #[serde(rename = "idempotencyKey")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+/// Request of the `Blobstore.PutObjects` extension, which writes several small objects of a
+/// container in one invocation. The response has the result of each write, in the order of
+/// `objects`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PutObjectsRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Objects to write
+    #[serde(default)]
+    pub objects: Vec<ObjectWrite>,
+}
+
+/// Write of a whole object, as part of a batch
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ObjectWrite {
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Data of the object
+    #[serde(default)]
+    pub bytes: Bytes,
+    /// A MIME type of the object. (Optional)
+    #[serde(rename = "contentType")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Content encodings applied to the object. (Optional)
+    #[serde(rename = "contentEncoding")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// Only write the object if its current version is this one, or if it doesn't exist yet when
+    /// 0. (Optional)
+    #[serde(rename = "ifVersion")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_version: Option<u64>,
+    /// User metadata to store with the object, replacing any it had. (Optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Result of the write of an object of a batch
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ObjectWriteResult {
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Whether the object was written
+    #[serde(default)]
+    pub success: bool,
+    /// Version the object was written with, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    /// Why the object wasn't written, if it wasn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Request of the `Blobstore.PutObjectGroup` extension, which writes all objects of a group or
+/// none of them. The objects are written one after the other, and if a write fails those already
+/// written are rolled back: restored to their previous data, or removed if they didn't exist.
+/// Other actors can see the objects already written until they are rolled back
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PutObjectGroupRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Objects to write, in the order they are written
+    #[serde(default)]
+    pub objects: Vec<ObjectWrite>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PutObjectGroupResponse {
+    /// Whether all objects were written
+    #[serde(default)]
+    pub committed: bool,
+    /// Result of the write of each object attempted, in order. Writing stops at the first
+    /// failure, so objects after it have no result
+    #[serde(default)]
+    pub results: Vec<ObjectWriteResult>,
+    /// Result of the rollback of each object written before a failure. An object whose rollback
+    /// failed, e.g. because another actor changed it in the meantime, keeps the data written
+    #[serde(rename = "rolledBack")]
+    #[serde(default)]
+    pub rolled_back: Vec<ItemResult>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -272,6 +541,131 @@ pub struct RemoveObjectsRequest {
     pub objects: ObjectIds,
 }
 
+/// Request of the `Blobstore.RestoreObject` extension, which writes an older version of an object
+/// as its latest version. The response is the new version
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RestoreObjectRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Version to restore
+    pub version: u64,
+}
+
+/// Request of the `Blobstore.AcquireLock` extension, which takes the advisory lock of an object
+/// unless another actor holds it
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AcquireLockRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// How long the lock is held for in milliseconds unless released sooner, rounded up to whole
+    /// seconds. 0 holds it for 30 seconds
+    #[serde(rename = "ttlMs")]
+    #[serde(default)]
+    pub ttl_ms: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AcquireLockResponse {
+    /// Whether the caller now holds the lock
+    #[serde(default)]
+    pub acquired: bool,
+    /// Token to release the lock with, if acquired. Tokens of an object's lock increase with
+    /// every acquisition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<u64>,
+    /// Actor holding the lock, the caller if acquired
+    #[serde(default)]
+    pub owner: String,
+    /// When the lock expires
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Timestamp,
+}
+
+/// Request of the `Blobstore.ReleaseLock` extension. Fails with a `Conflict` error if the lock
+/// was already released, or taken by another actor after it expired
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ReleaseLockRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Token returned when the lock was acquired
+    pub token: u64,
+}
+
+/// Request of the `Blobstore.SnapshotContainer` extension, which records the current version of
+/// every object of a container under a name. Fails with a `Conflict` error if the container
+/// already has a snapshot with that name
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SnapshotContainerRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Name of the snapshot, which can't contain `/`
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SnapshotContainerResponse {
+    /// Number of objects recorded in the snapshot
+    #[serde(rename = "objectCount")]
+    #[serde(default)]
+    pub object_count: u64,
+    /// When the snapshot was taken
+    #[serde(rename = "createdAt")]
+    pub created_at: Timestamp,
+}
+
+/// Request of the `Blobstore.RestoreSnapshot` extension, which writes the version every object
+/// had in a snapshot as its latest version. Objects created after the snapshot are kept
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RestoreSnapshotRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    /// Name the snapshot was taken with
+    pub name: String,
+    /// Only list the objects that would be rewritten, without writing them
+    #[serde(rename = "dryRun")]
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RestoreSnapshotResponse {
+    /// Objects rewritten to their version in the snapshot, or that would be with `dryRun`
+    #[serde(default)]
+    pub changed: ObjectIds,
+}
+
+/// Request of the `Blobstore.AppendObject` extension, which adds bytes to the end of an object,
+/// creating it if it doesn't exist
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AppendObjectRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Bytes to append
+    #[serde(default)]
+    pub bytes: Bytes,
+    /// Content type of the object if it is created. (Optional)
+    #[serde(rename = "contentType")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AppendObjectResponse {
+    /// Version of the object written with the appended bytes
+    pub version: u64,
+    /// Length of the object after the append
+    #[serde(rename = "contentLength")]
+    pub content_length: u64,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct PutObjectResponse {
     /// If this is a multipart upload, `streamId` must be returned
@@ -281,6 +675,35 @@ pub struct PutObjectResponse {
     pub stream_id: Option<String>,
 }
 
+/// Request of the `Blobstore.WatchObject` extension, which waits for an object to change
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WatchObjectRequest {
+    #[serde(rename = "containerId")]
+    pub container_id: ContainerId,
+    #[serde(rename = "objectId")]
+    pub object_id: ObjectId,
+    /// Version of the object the caller has. None if the caller hasn't seen the object, or it
+    /// doesn't exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    /// How long to wait for a change in milliseconds. The provider may answer sooner, and 0 waits
+    /// as long as the provider allows
+    #[serde(rename = "timeoutMs")]
+    #[serde(default)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WatchObjectResponse {
+    /// Whether the version of the object differs from the one in the request
+    #[serde(default)]
+    pub changed: bool,
+    /// Current version of the object. None if it doesn't exist or was deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+#[cfg(feature = "provider")]
 #[async_trait]
 pub trait Blobstore {
     /// returns the capability contract id for this interface
@@ -0,0 +1,129 @@
+//! Checks where a Vault audit sink writes events, and that actors can't list, read or overwrite
+//! them when they share the link's mount
+
+use blobstore_vault::{audit::AuditEvent, client::Client, config::Config, error::VaultError};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+
+/// Starts a mock Vault server accepting all writes, and returns it with a client connected to it
+/// with the given additional values. The server must be kept alive for the duration of the test
+async fn serve(values: &[(&str, &str)]) -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(response(serde_json::json!({
+            "created_time": "2024-03-01T12:00:00.000000Z",
+            "custom_metadata": null,
+            "deletion_time": "",
+            "destroyed": false,
+            "version": 1
+        })))
+        .mount(&server)
+        .await;
+    let mut config = vec![
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ];
+    config.extend(values.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let config = Config::from_values(&config).expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+/// Returns the paths of the writes received
+async fn writes(server: &MockServer) -> Vec<String> {
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|r| (r.method.to_string(), r.url.path().to_string()))
+        .filter(|(method, _)| method == "POST")
+        .map(|(_, path)| path)
+        .collect()
+}
+
+#[tokio::test]
+async fn writes_events_to_audit_mount() {
+    let (server, client) = serve(&[
+        ("audit", "vault:blobstore/events"),
+        ("audit_mount", "audit"),
+    ])
+    .await;
+
+    client
+        .audit(AuditEvent::new("actor-a", "PutObject", "docs").object("readme.md"))
+        .await;
+
+    let writes = writes(&server).await;
+    assert_eq!(writes.len(), 1);
+    assert!(
+        writes[0].starts_with("/v1/audit/data/blobstore/events/actor-a/"),
+        "{}",
+        writes[0]
+    );
+
+    // The audit path is only reserved in the mount events are written to
+    client
+        .write_file("blobstore/events/actor-a/1", b"{}".to_vec())
+        .await
+        .expect("write should succeed");
+}
+
+#[tokio::test]
+async fn hides_audit_path_in_link_mount() {
+    let (server, client) = serve(&[("audit", "vault:logs/audit")]).await;
+    Mock::given(method("LIST"))
+        .and(path(format!("/v1/{MOUNT}/metadata/")))
+        .respond_with(response(serde_json::json!({ "keys": ["docs/", "logs/"] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("LIST"))
+        .and(path(format!("/v1/{MOUNT}/metadata/logs/")))
+        .respond_with(response(
+            serde_json::json!({ "keys": ["audit/", "app/", "audit.txt"] }),
+        ))
+        .mount(&server)
+        .await;
+
+    client
+        .audit(AuditEvent::new("actor-a", "PutObject", "docs"))
+        .await;
+    assert!(writes(&server).await[0].starts_with(&format!("/v1/{MOUNT}/data/logs/audit/actor-a/")));
+
+    assert_eq!(
+        client.list_files("").await.unwrap(),
+        vec!["docs/".to_string(), "logs/".to_string()]
+    );
+    assert_eq!(
+        client.list_files("logs/").await.unwrap(),
+        vec!["app/".to_string(), "audit.txt".to_string()]
+    );
+    for path in ["logs/audit", "logs/audit/actor-a/1"] {
+        let err = client.write_file(path, b"{}".to_vec()).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+        let err = client.read_file(path).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+    }
+    let err = client.list_files("logs/audit/").await.unwrap_err();
+    assert!(matches!(err, VaultError::Denied { .. }), "{err:?}");
+
+    // Only the audit path itself is reserved, not paths it is a prefix of
+    client
+        .write_file("logs/audit.txt", b"{}".to_vec())
+        .await
+        .expect("write should succeed");
+}
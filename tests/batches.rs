@@ -0,0 +1,99 @@
+//! Checks that the objects of a `PutObjects` batch are written independently of each other, with a
+//! result per object in the order they were given
+
+mod common;
+
+use blobstore_vault::{
+    client::Client, objects::put_objects, wasmcloud_interface_blobstore::ObjectWrite,
+};
+use bytes::Bytes;
+use common::Vault;
+
+fn object(object_id: &str, data: &'static [u8]) -> ObjectWrite {
+    ObjectWrite {
+        object_id: object_id.to_string(),
+        bytes: Bytes::from_static(data),
+        ..Default::default()
+    }
+}
+
+async fn read(client: &Client, object_id: &str) -> Option<Bytes> {
+    client
+        .read_file(format!("config/{object_id}"))
+        .await
+        .ok()
+        .map(|file| file.data)
+}
+
+#[tokio::test]
+async fn writes_batches_in_order() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[("batch_concurrency", "3")]);
+    let objects: Vec<_> = (0..10)
+        .map(|i| ObjectWrite {
+            object_id: format!("{i}.json"),
+            bytes: Bytes::from(format!("{{\"n\":{i}}}")),
+            content_type: Some("application/json".to_string()),
+            ..Default::default()
+        })
+        .collect();
+
+    let results = put_objects(&client, "config", objects).await;
+    assert_eq!(results.len(), 10);
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(result.object_id, format!("{i}.json"));
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.version, Some(1));
+        let file = client
+            .read_file(format!("config/{i}.json"))
+            .await
+            .expect("object should be written");
+        assert_eq!(file.data, format!("{{\"n\":{i}}}"));
+        assert_eq!(file.content_type.as_deref(), Some("application/json"));
+    }
+}
+
+#[tokio::test]
+async fn reports_failures_per_object() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    vault.put("config/b.json", serde_json::json!({ "data": b"old" }));
+    vault.fail_writes("config/c.json", true);
+
+    let mut stale = object("b.json", b"new");
+    stale.if_version = Some(7);
+    let results = put_objects(
+        &client,
+        "config",
+        vec![
+            object("a.json", b"a"),
+            stale,
+            object("c.json", b"c"),
+            object("d.json", b"d"),
+        ],
+    )
+    .await;
+
+    let outcome: Vec<_> = results
+        .iter()
+        .map(|result| (result.object_id.as_str(), result.success))
+        .collect();
+    assert_eq!(
+        outcome,
+        [
+            ("a.json", true),
+            ("b.json", false),
+            ("c.json", false),
+            ("d.json", true)
+        ]
+    );
+    assert!(results[1].version.is_none());
+    assert!(results[1].error.is_some());
+    assert!(results[2].error.is_some());
+
+    // The failed writes left their objects as they were, and didn't stop the others
+    assert_eq!(read(&client, "a.json").await.as_deref(), Some(&b"a"[..]));
+    assert_eq!(read(&client, "b.json").await.as_deref(), Some(&b"old"[..]));
+    assert_eq!(read(&client, "c.json").await, None);
+    assert_eq!(read(&client, "d.json").await.as_deref(), Some(&b"d"[..]));
+}
@@ -0,0 +1,212 @@
+//! Checks how `Client` maps the responses of a mock Vault server to `VaultError`s
+
+use blobstore_vault::{
+    client::Client,
+    config::Config,
+    error::{ErrorCode, VaultError},
+};
+use vaultrs::error::ClientError;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "object";
+
+/// Starts a mock Vault server answering reads of `OBJECT` with the given response, and returns it
+/// with a client connected to it. The server must be kept alive for the duration of the test
+async fn serve(response: ResponseTemplate) -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response)
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        // Fail straight away instead of waiting for the mock to be unsealed
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+fn errors(errors: &[&str]) -> serde_json::Value {
+    serde_json::json!({ "errors": errors })
+}
+
+#[tokio::test]
+async fn not_found() {
+    let (_server, client) = serve(ResponseTemplate::new(404).set_body_json(errors(&[]))).await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(&err, VaultError::NotFound { namespace, path } if namespace == MOUNT && path == OBJECT),
+        "expected NotFound, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::NotFound);
+}
+
+#[tokio::test]
+async fn permission_denied() {
+    let (_server, client) =
+        serve(ResponseTemplate::new(403).set_body_json(errors(&["permission denied"]))).await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 403, .. },
+                ..
+            }
+        ),
+        "expected a 403 APIError, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Unauthorized);
+}
+
+#[tokio::test]
+async fn rate_limited() {
+    let (_server, client) = serve(ResponseTemplate::new(429).set_body_json(errors(&[
+        "request path \"secret/data/object\": rate limit quota exceeded",
+    ])))
+    .await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 429, .. },
+                ..
+            }
+        ),
+        "expected a 429 APIError, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Unavailable);
+}
+
+#[tokio::test]
+async fn internal_server_error() {
+    let (_server, client) =
+        serve(ResponseTemplate::new(500).set_body_json(errors(&["internal error"]))).await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 500, .. },
+                ..
+            }
+        ),
+        "expected a 500 APIError, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Internal);
+}
+
+#[tokio::test]
+async fn sealed() {
+    let (_server, client) =
+        serve(ResponseTemplate::new(503).set_body_json(errors(&["Vault is sealed"]))).await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(err, VaultError::Sealed),
+        "expected Sealed, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Sealed);
+    assert!(client.is_sealed());
+    assert!(!client.is_healthy());
+}
+
+#[tokio::test]
+async fn standby_unavailable_is_not_sealed() {
+    let (_server, client) =
+        serve(ResponseTemplate::new(503).set_body_json(errors(&["Vault is in standby mode"])))
+            .await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 503, .. },
+                ..
+            }
+        ),
+        "expected a 503 APIError, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Unavailable);
+    assert!(!client.is_sealed());
+}
+
+#[tokio::test]
+async fn malformed_body() {
+    let (_server, client) = serve(
+        ResponseTemplate::new(200)
+            .insert_header("content-type", "application/json")
+            .set_body_string("{\"data\": not json"),
+    )
+    .await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(&err, VaultError::Client { source, .. } if !matches!(source, ClientError::APIError { .. })),
+        "expected a non-API client error, got {err:?}"
+    );
+    assert_eq!(err.code(), ErrorCode::Internal);
+}
+
+#[tokio::test]
+async fn unexpected_data_has_request_id() {
+    let (_server, client) = serve(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "3b2c0c1e-5d6f-4a7b-8c9d-0e1f2a3b4c5d",
+        "data": { "data": "not a file", "metadata": null }
+    })))
+    .await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert_eq!(
+        err.request_id(),
+        Some("3b2c0c1e-5d6f-4a7b-8c9d-0e1f2a3b4c5d")
+    );
+    assert!(err
+        .to_string()
+        .contains("Vault request ID 3b2c0c1e-5d6f-4a7b-8c9d-0e1f2a3b4c5d"));
+    assert_eq!(err.code(), ErrorCode::Internal);
+}
+
+#[tokio::test]
+async fn reads_file() {
+    let (_server, client) = serve(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "lease_id": "",
+        "renewable": false,
+        "lease_duration": 0,
+        "data": {
+            "data": { "data": [104, 105], "content_type": "text/plain" },
+            "metadata": {
+                "created_time": "2023-01-01T00:00:00.000000Z",
+                "custom_metadata": null,
+                "deletion_time": "",
+                "destroyed": false,
+                "version": 1
+            }
+        },
+        "wrap_info": null,
+        "warnings": null,
+        "auth": null
+    })))
+    .await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"hi"[..]);
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+    assert!(client.is_healthy());
+}
@@ -0,0 +1,254 @@
+//! A KV v2 engine kept in memory, for tests that need Vault to remember what was written rather
+//! than answer fixed responses
+// Each test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use blobstore_vault::{client::Client, config::Config};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use wiremock::{matchers::path_regex, Mock, MockServer, Request, ResponseTemplate};
+
+const CREATED: &str = "2024-03-01T12:00:00.000000Z";
+
+/// A version of a secret
+#[derive(Clone, Debug)]
+pub struct Version {
+    pub data: Value,
+    pub deleted: bool,
+}
+
+/// A secret with its versions, the first at index 0
+#[derive(Clone, Debug, Default)]
+pub struct Secret {
+    pub versions: Vec<Version>,
+    pub custom_metadata: Option<HashMap<String, String>>,
+}
+
+impl Secret {
+    /// Returns the current version unless it is deleted
+    pub fn current(&self) -> Option<&Version> {
+        self.versions.last().filter(|version| !version.deleted)
+    }
+
+    fn version_metadata(&self, version: usize) -> Value {
+        json!({
+            "created_time": CREATED,
+            "custom_metadata": self.custom_metadata,
+            "deletion_time": if self.versions[version - 1].deleted { CREATED } else { "" },
+            "destroyed": false,
+            "version": version,
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    secrets: BTreeMap<String, Secret>,
+    /// Keys whose writes fail with a 500
+    failing: HashSet<String>,
+}
+
+/// A mock Vault server with a KV v2 engine at `mount`
+pub struct Vault {
+    pub server: MockServer,
+    pub mount: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl Vault {
+    pub async fn start(mount: &str) -> Vault {
+        let server = MockServer::start().await;
+        let state = Arc::new(Mutex::new(State::default()));
+        let engine = state.clone();
+        let prefix = format!("/v1/{mount}/");
+        Mock::given(path_regex(format!("^/v1/{mount}/")))
+            .respond_with(move |request: &Request| {
+                let rest = &request.url.path()[prefix.len()..];
+                let rest = percent_decode_str(rest).decode_utf8_lossy().to_string();
+                answer(&mut engine.lock().unwrap(), request, &rest)
+            })
+            .mount(&server)
+            .await;
+        Mock::given(path_regex("^/v1/sys/seal-status$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "sealed": false })))
+            .mount(&server)
+            .await;
+        Vault {
+            server,
+            mount: mount.to_string(),
+            state,
+        }
+    }
+
+    /// Returns a client of the mount with the given extra settings
+    pub fn client(&self, settings: &[(&str, &str)]) -> Client {
+        let mut values = vec![
+            ("addr".to_string(), self.server.uri()),
+            ("token".to_string(), "test-token".to_string()),
+            ("mount".to_string(), self.mount.clone()),
+            ("sealed_retry_secs".to_string(), "0".to_string()),
+        ];
+        values.extend(settings.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        let config = Config::from_values(&values).expect("config should be valid");
+        Client::new(config).expect("client should be created")
+    }
+
+    /// Returns the secret at a key of the mount, if any
+    pub fn secret(&self, key: &str) -> Option<Secret> {
+        self.state.lock().unwrap().secrets.get(key).cloned()
+    }
+
+    /// Returns the keys of the mount holding a secret
+    pub fn keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().secrets.keys().cloned().collect()
+    }
+
+    /// Writes a new version of the secret at a key, bypassing the client
+    pub fn put(&self, key: &str, data: Value) {
+        let mut state = self.state.lock().unwrap();
+        let secret = state.secrets.entry(key.to_string()).or_default();
+        secret.versions.push(Version {
+            data,
+            deleted: false,
+        });
+    }
+
+    /// Makes writes to the secret at a key fail, or succeed again
+    pub fn fail_writes(&self, key: &str, fail: bool) {
+        let mut state = self.state.lock().unwrap();
+        if fail {
+            state.failing.insert(key.to_string());
+        } else {
+            state.failing.remove(key);
+        }
+    }
+}
+
+fn data(data: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn not_found() -> ResponseTemplate {
+    ResponseTemplate::new(404).set_body_json(json!({ "errors": [] }))
+}
+
+fn error(status: u16, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({ "errors": [message] }))
+}
+
+/// Answers a request to the path `rest` of the mount the way Vault's KV v2 engine does
+fn answer(state: &mut State, request: &Request, rest: &str) -> ResponseTemplate {
+    let (endpoint, key) = rest.split_once('/').unwrap_or((rest, ""));
+    let body: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+    let query: HashMap<_, _> = request.url.query_pairs().into_owned().collect();
+    match (request.method.as_str(), endpoint) {
+        ("GET", "data") => {
+            let Some(secret) = state.secrets.get(key) else {
+                return not_found();
+            };
+            let version = match query.get("version") {
+                Some(version) => version.parse().unwrap(),
+                None => secret.versions.len(),
+            };
+            match secret.versions.get(version.wrapping_sub(1)) {
+                Some(found) if !found.deleted => data(json!({
+                    "data": found.data,
+                    "metadata": secret.version_metadata(version),
+                })),
+                _ => not_found(),
+            }
+        }
+        ("POST" | "PUT", "data") => {
+            if state.failing.contains(key) {
+                return error(500, "internal error");
+            }
+            let secret = state.secrets.entry(key.to_string()).or_default();
+            if let Some(cas) = body["options"]["cas"].as_u64() {
+                if cas != secret.versions.len() as u64 {
+                    return error(
+                        400,
+                        "check-and-set parameter did not match the current version",
+                    );
+                }
+            }
+            secret.versions.push(Version {
+                data: body["data"].clone(),
+                deleted: false,
+            });
+            data(secret.version_metadata(secret.versions.len()))
+        }
+        ("DELETE", "data") => {
+            if let Some(version) = state
+                .secrets
+                .get_mut(key)
+                .and_then(|secret| secret.versions.last_mut())
+            {
+                version.deleted = true;
+            }
+            ResponseTemplate::new(204)
+        }
+        ("GET", "subkeys") => match state.secrets.get(key).and_then(Secret::current) {
+            Some(_) => data(json!({ "subkeys": { "data": null } })),
+            None => not_found(),
+        },
+        ("GET", "metadata") => {
+            let Some(secret) = state.secrets.get(key) else {
+                return not_found();
+            };
+            let versions: HashMap<_, _> = (1..=secret.versions.len())
+                .map(|version| (version.to_string(), secret.version_metadata(version)))
+                .collect();
+            data(json!({
+                "cas_required": false,
+                "created_time": CREATED,
+                "current_version": secret.versions.len(),
+                "delete_version_after": "0s",
+                "max_versions": 0,
+                "oldest_version": 0,
+                "updated_time": CREATED,
+                "custom_metadata": secret.custom_metadata,
+                "versions": versions,
+            }))
+        }
+        ("POST" | "PUT", "metadata") => {
+            let secret = state.secrets.entry(key.to_string()).or_default();
+            if let Some(custom) = body.get("custom_metadata") {
+                secret.custom_metadata = serde_json::from_value(custom.clone()).unwrap();
+            }
+            ResponseTemplate::new(204)
+        }
+        ("DELETE", "metadata") => {
+            state.secrets.remove(key);
+            ResponseTemplate::new(204)
+        }
+        ("LIST", "metadata") => {
+            let folder = match key.trim_end_matches('/') {
+                "" => String::new(),
+                folder => format!("{folder}/"),
+            };
+            let mut keys: Vec<String> = state
+                .secrets
+                .keys()
+                .filter_map(|k| k.strip_prefix(&folder))
+                .map(|rest| match rest.split_once('/') {
+                    Some((sub, _)) => format!("{sub}/"),
+                    None => rest.to_string(),
+                })
+                .collect();
+            keys.dedup();
+            if keys.is_empty() {
+                return not_found();
+            }
+            data(json!({ "keys": keys }))
+        }
+        _ => not_found(),
+    }
+}
@@ -0,0 +1,192 @@
+//! Checks that objects written like `PutObject` writes them are stored at `{container}/{object}`,
+//! so listings and exports of their container find them under the same ID
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+use blobstore_vault::{
+    client::{Client, File},
+    config::Config,
+    export, objects,
+    progress::Progress,
+};
+use wiremock::{matchers::path_regex, Mock, MockServer, Request, ResponseTemplate};
+
+const MOUNT: &str = "secret";
+const CONTAINER: &str = "docs";
+const OBJECT: &str = "reports/2024.json";
+
+/// Starts a mock Vault server keeping the data written to it in memory, and returns it with a
+/// client connected to it. The server must be kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let store = Arc::new(Mutex::new(BTreeMap::<String, serde_json::Value>::new()));
+    Mock::given(path_regex(format!("^/v1/{MOUNT}/")))
+        .respond_with(move |r: &Request| kv(&store, r))
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Answers a request to the KV v2 engine from the secrets in `store`
+fn kv(store: &Mutex<BTreeMap<String, serde_json::Value>>, request: &Request) -> ResponseTemplate {
+    let mut store = store.lock().unwrap();
+    let path = request.url.path();
+    let method = request.method.as_str();
+    if let Some(key) = path.strip_prefix(&format!("/v1/{MOUNT}/data/")) {
+        return match method {
+            "POST" | "PUT" => {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                store.insert(key.to_string(), body["data"].clone());
+                response(version_metadata())
+            }
+            _ => match store.get(key) {
+                Some(data) => response(serde_json::json!({
+                    "data": data,
+                    "metadata": version_metadata()
+                })),
+                None => ResponseTemplate::new(404),
+            },
+        };
+    }
+    let Some(key) = path.strip_prefix(&format!("/v1/{MOUNT}/metadata/")) else {
+        return ResponseTemplate::new(404);
+    };
+    if method == "LIST" {
+        // Vault lists a folder whether or not its path ends in `/`
+        let folder = format!("{}/", key.trim_end_matches('/'));
+        let keys: Vec<_> = store
+            .keys()
+            .filter_map(|stored| stored.strip_prefix(&folder))
+            .map(|rest| match rest.find('/') {
+                Some(i) => rest[..=i].to_string(),
+                None => rest.to_string(),
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        if keys.is_empty() {
+            return ResponseTemplate::new(404);
+        }
+        return response(serde_json::json!({ "keys": keys }));
+    }
+    if !store.contains_key(key) {
+        return ResponseTemplate::new(404);
+    }
+    response(serde_json::json!({
+        "cas_required": false,
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "current_version": 1,
+        "custom_metadata": null,
+        "delete_version_after": "0s",
+        "max_versions": 0,
+        "oldest_version": 0,
+        "updated_time": "2024-03-01T12:00:00.000000Z",
+        "versions": { "1": version_metadata() }
+    }))
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn version_metadata() -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": 1
+    })
+}
+
+#[tokio::test]
+async fn round_trips_put_object_through_listing_and_export() {
+    let (_server, client) = serve().await;
+    let file = File {
+        data: b"{}".to_vec().into(),
+        content_type: Some("application/json".to_string()),
+        ..Default::default()
+    };
+    objects::write_object(&client, CONTAINER, OBJECT, file, None, None, None)
+        .await
+        .expect("write should succeed");
+
+    // Listed under the container with the ID it was written with
+    assert_eq!(
+        client.list_files(CONTAINER).await.unwrap(),
+        vec!["reports/".to_string()]
+    );
+    assert_eq!(
+        export::walk_folder(&client, CONTAINER, "").await.unwrap(),
+        vec![OBJECT.to_string()]
+    );
+    let current = objects::read_current(&client, CONTAINER, OBJECT)
+        .await
+        .expect("read should succeed");
+    assert_eq!(current.version, 1);
+    assert_eq!(current.file.unwrap().data, &b"{}"[..]);
+
+    let (archive, count) = export::archive(
+        &client,
+        CONTAINER,
+        Vec::new(),
+        &Progress::logged("export", CONTAINER),
+    )
+    .await
+    .expect("export should succeed");
+    assert_eq!(count, 1);
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive.as_slice()));
+    let mut entries = archive.entries().unwrap();
+    let mut entry = entries.next().unwrap().unwrap();
+    assert_eq!(entry.path().unwrap().to_str(), Some(OBJECT));
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"{}");
+    assert!(entries.next().is_none());
+}
+
+#[tokio::test]
+async fn keeps_containers_apart() {
+    let (_server, client) = serve().await;
+    for container in ["docs", "drafts"] {
+        objects::write_object(
+            &client,
+            container,
+            "readme.md",
+            File::from(container.as_bytes().to_vec()),
+            None,
+            None,
+            None,
+        )
+        .await
+        .expect("write should succeed");
+    }
+
+    for container in ["docs", "drafts"] {
+        let current = objects::read_current(&client, container, "readme.md")
+            .await
+            .expect("read should succeed");
+        assert_eq!(current.file.unwrap().data, container.as_bytes());
+    }
+    let missing = objects::read_current(&client, "notes", "readme.md")
+        .await
+        .expect("read should succeed");
+    assert!(missing.file.is_none());
+    assert_eq!(missing.version, 0);
+}
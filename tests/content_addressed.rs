@@ -0,0 +1,496 @@
+//! Checks that identical objects share a content-addressed blob deleted with its last reference,
+//! that reference counts are updated with check-and-set writes, so providers sharing a mount don't
+//! lose references or delete data still pointed to, that only one of them collects garbage at a
+//! time, and that actors can't reach the folders the provider keeps its own secrets in
+
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use blobstore_vault::{cas, client::Client, config::Config, error::VaultError};
+use common::Vault;
+use wiremock::{
+    matchers::{body_json, method, path, query_param},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "docs/readme.md";
+const DATA: &[u8] = b"hello";
+
+/// Starts a mock Vault server and returns it with a content-addressed client connected to it.
+/// The server must be kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    serve_with(true).await
+}
+
+async fn serve_with(content_addressed: bool) -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+        (
+            "content_addressed".to_string(),
+            content_addressed.to_string(),
+        ),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn version_metadata(version: u64) -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": version
+    })
+}
+
+fn metadata(version: u64) -> serde_json::Value {
+    serde_json::json!({
+        "cas_required": false,
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "current_version": version,
+        "custom_metadata": null,
+        "delete_version_after": "0s",
+        "max_versions": 0,
+        "oldest_version": 0,
+        "updated_time": "2024-03-01T12:00:00.000000Z",
+        "versions": { version.to_string(): version_metadata(version) }
+    })
+}
+
+/// Answers writes with the metadata of the version after their check-and-set version
+fn written(request: &Request) -> ResponseTemplate {
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    response(version_metadata(
+        body["options"]["cas"].as_u64().unwrap_or_default() + 1,
+    ))
+}
+
+fn refs_path() -> String {
+    format!("/v1/{MOUNT}/data/{}", cas::refs_path(&cas::hash(DATA)))
+}
+
+/// Serves the reference count of the blob of `DATA` as `refs` at `version`. With `times`, the
+/// count is only served that many times, before counts mounted later
+async fn serve_refs(
+    server: &MockServer,
+    version: u64,
+    refs: serde_json::Value,
+    times: Option<u64>,
+) {
+    let hash = cas::hash(DATA);
+    let mut current = Mock::given(method("GET"))
+        .and(path(format!(
+            "/v1/{MOUNT}/metadata/{}",
+            cas::refs_path(&hash)
+        )))
+        .respond_with(response(metadata(version)))
+        .with_priority(1);
+    if let Some(times) = times {
+        current = current.up_to_n_times(times);
+    }
+    current.mount(server).await;
+    Mock::given(method("GET"))
+        .and(path(refs_path()))
+        .and(query_param("version", version.to_string()))
+        .respond_with(response(serde_json::json!({
+            "data": refs,
+            "metadata": version_metadata(version)
+        })))
+        .mount(server)
+        .await;
+}
+
+/// Accepts writes of the reference count with the given content and check-and-set version
+async fn accept_refs(server: &MockServer, refs: serde_json::Value, cas: u64) {
+    Mock::given(method("POST"))
+        .and(path(refs_path()))
+        .and(body_json(
+            serde_json::json!({ "data": refs, "options": { "cas": cas } }),
+        ))
+        .respond_with(response(version_metadata(cas + 1)))
+        .mount(server)
+        .await;
+}
+
+/// Returns the requests received, as method and path
+async fn requests(server: &MockServer) -> Vec<(String, String)> {
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|r: &Request| (r.method.to_string(), r.url.path().to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn retries_raced_reference() {
+    let (server, client) = serve().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({ "errors": [] })))
+        .mount(&server)
+        .await;
+    // Another provider adds a reference between the first read of the count and its write
+    serve_refs(&server, 1, serde_json::json!({ "count": 1 }), Some(1)).await;
+    serve_refs(&server, 2, serde_json::json!({ "count": 2 }), None).await;
+    Mock::given(method("POST"))
+        .and(path(refs_path()))
+        .and(body_json(
+            serde_json::json!({ "data": { "count": 2 }, "options": { "cas": 1 } }),
+        ))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "errors": ["check-and-set parameter did not match the current version"]
+        })))
+        .mount(&server)
+        .await;
+    accept_refs(&server, serde_json::json!({ "count": 3 }), 2).await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(version_metadata(1)))
+        .mount(&server)
+        .await;
+
+    client
+        .write_file(OBJECT, DATA.to_vec())
+        .await
+        .expect("write should succeed");
+
+    let requests = requests(&server).await;
+    let refs_writes = requests
+        .iter()
+        .filter(|(method, path)| method == "POST" && *path == refs_path())
+        .count();
+    assert_eq!(refs_writes, 2);
+    // The blob already existed, so its data isn't written again
+    let blob_path = format!("/v1/{MOUNT}/data/{}", cas::blob_path(&cas::hash(DATA)));
+    assert!(!requests.iter().any(|(_, path)| *path == blob_path));
+}
+
+#[tokio::test]
+async fn marks_last_reference_deleting_before_deleting_blob() {
+    let (server, client) = serve().await;
+    let hash = cas::hash(DATA);
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(serde_json::json!({
+            "data": { "data": [], "blob": hash },
+            "metadata": version_metadata(1)
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    serve_refs(&server, 4, serde_json::json!({ "count": 1 }), None).await;
+    // The count is marked as deleting at a time that differs between runs, then released
+    Mock::given(method("POST"))
+        .and(path(refs_path()))
+        .respond_with(|request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            match body["options"]["cas"].as_u64() {
+                Some(4) if body["data"]["deleting_since"].is_u64() => response(version_metadata(5)),
+                Some(5) if body["data"] == serde_json::json!({ "count": 0 }) => {
+                    response(version_metadata(6))
+                }
+                _ => ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                    "errors": ["check-and-set parameter did not match the current version"]
+                })),
+            }
+        })
+        .mount(&server)
+        .await;
+
+    client
+        .delete_file(OBJECT)
+        .await
+        .expect("delete should succeed");
+
+    let requests = requests(&server).await;
+    let position = |method: &str, path: &str| {
+        requests
+            .iter()
+            .position(|r| r.0 == method && r.1 == path)
+            .unwrap_or_else(|| panic!("expected {method} {path}"))
+    };
+    let marked = position("POST", &refs_path());
+    let deleted = position(
+        "DELETE",
+        &format!("/v1/{MOUNT}/metadata/{}", cas::blob_path(&hash)),
+    );
+    let refs_writes: Vec<_> = requests
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.0 == "POST" && r.1 == refs_path())
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(refs_writes.len(), 2);
+    assert!(marked < deleted && deleted < refs_writes[1]);
+}
+
+#[tokio::test]
+async fn waits_for_deletion_of_blob() {
+    let (server, client) = serve().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({ "errors": [] })))
+        .mount(&server)
+        .await;
+    // Another provider is deleting the blob the first time the count is read
+    serve_refs(
+        &server,
+        5,
+        serde_json::json!({ "count": 0, "deleting_since": now() }),
+        Some(1),
+    )
+    .await;
+    serve_refs(&server, 6, serde_json::json!({ "count": 0 }), None).await;
+    accept_refs(&server, serde_json::json!({ "count": 1 }), 6).await;
+    Mock::given(method("POST"))
+        .respond_with(response(version_metadata(1)))
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    client
+        .write_file(OBJECT, DATA.to_vec())
+        .await
+        .expect("write should succeed");
+
+    // The data is written again once the deletion is done
+    let blob_path = format!("/v1/{MOUNT}/data/{}", cas::blob_path(&cas::hash(DATA)));
+    let requests = requests(&server).await;
+    let blob_written = requests
+        .iter()
+        .position(|r| r.0 == "POST" && r.1 == blob_path)
+        .expect("blob should be written");
+    let retained = requests
+        .iter()
+        .position(|r| r.0 == "POST" && r.1 == refs_path())
+        .expect("count should be written");
+    assert!(blob_written < retained);
+}
+
+#[tokio::test]
+async fn rejects_reserved_folders() {
+    let (server, client) = serve().await;
+
+    for path in [
+        "_locks/docs/readme.md",
+        "_snapshots/docs/nightly",
+        "_containers/docs",
+        "_cas/blobs/0000",
+    ] {
+        let err = client.write_file(path, DATA.to_vec()).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+        let err = client.read_file(path).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+        let err = client.delete_file(path).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+        let err = client.copy_file(OBJECT, path).await.unwrap_err();
+        assert!(matches!(err, VaultError::Denied { .. }), "{path}: {err:?}");
+    }
+    let err = client.list_files("_locks/docs").await.unwrap_err();
+    assert!(matches!(err, VaultError::Denied { .. }), "{err:?}");
+
+    assert!(requests(&server).await.is_empty());
+}
+
+#[tokio::test]
+async fn garbage_collection_requires_content_addressed() {
+    let (server, client) = serve_with(false).await;
+
+    let err = client.collect_garbage().await.unwrap_err();
+    assert!(
+        matches!(err, VaultError::NotEnabled { .. }),
+        "expected NotEnabled, got {err:?}"
+    );
+    assert!(requests(&server).await.is_empty());
+}
+
+#[tokio::test]
+async fn collects_garbage_holding_lock() {
+    let (server, client) = serve().await;
+    let lock_path = format!("/v1/{MOUNT}/data/{}", cas::gc_lock_path());
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/v1/{MOUNT}/metadata/{}",
+            cas::gc_lock_path()
+        )))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({ "errors": [] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(lock_path.clone()))
+        .respond_with(written)
+        .mount(&server)
+        .await;
+    Mock::given(method("LIST"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{}", cas::blob_path(""))))
+        .respond_with(response(serde_json::json!({ "keys": ["orphan", "fresh"] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("LIST"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{}", cas::refs_path(""))))
+        .respond_with(response(serde_json::json!({ "keys": [] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/v1/{MOUNT}/metadata/{}",
+            cas::blob_path("orphan")
+        )))
+        .respond_with(response(metadata(1)))
+        .mount(&server)
+        .await;
+    // Written just now, so its reference count may still be on its way
+    let mut fresh = metadata(1);
+    fresh["updated_time"] = humantime::format_rfc3339(SystemTime::now())
+        .to_string()
+        .into();
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/v1/{MOUNT}/metadata/{}",
+            cas::blob_path("fresh")
+        )))
+        .respond_with(response(fresh))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+
+    let removed = client
+        .collect_garbage()
+        .await
+        .expect("garbage collection should succeed");
+    assert_eq!(removed, vec![cas::blob_path("orphan")]);
+
+    let requests = requests(&server).await;
+    let lock_writes: Vec<_> = requests
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.0 == "POST" && r.1 == lock_path)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(lock_writes.len(), 2, "lock should be taken and released");
+    let listed = requests.iter().position(|r| r.0 == "LIST").unwrap();
+    assert!(lock_writes[0] < listed && listed < lock_writes[1]);
+}
+
+#[tokio::test]
+async fn skips_garbage_collection_running_elsewhere() {
+    let (server, client) = serve().await;
+    let lock_path = cas::gc_lock_path();
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{lock_path}")))
+        .respond_with(response(metadata(3)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{lock_path}")))
+        .and(query_param("version", "3"))
+        .respond_with(response(serde_json::json!({
+            "data": { "owner": "gc-other", "expires_at": now() + 600 },
+            "metadata": version_metadata(3)
+        })))
+        .mount(&server)
+        .await;
+
+    let removed = client
+        .collect_garbage()
+        .await
+        .expect("garbage collection should succeed");
+    assert!(removed.is_empty());
+    assert!(!requests(&server)
+        .await
+        .iter()
+        .any(|r| r.0 == "LIST" || r.0 == "DELETE"));
+}
+
+/// Returns the reference count of the blob holding `data`, None if it has none
+fn refs(vault: &Vault, data: &[u8]) -> Option<u64> {
+    let secret = vault.secret(&cas::refs_path(&cas::hash(data)))?;
+    secret.current()?.data["count"].as_u64()
+}
+
+/// Returns whether the blob holding `data` exists
+fn has_blob(vault: &Vault, data: &[u8]) -> bool {
+    vault
+        .secret(&cas::blob_path(&cas::hash(data)))
+        .is_some_and(|secret| secret.current().is_some())
+}
+
+#[tokio::test]
+async fn counts_references_to_shared_blobs() {
+    let vault = Vault::start(MOUNT).await;
+    let client = vault.client(&[("content_addressed", "true")]);
+
+    // Identical objects share a blob, and a copy only adds a reference
+    client.write_file("docs/a.md", DATA.to_vec()).await.unwrap();
+    client.write_file("docs/b.md", DATA.to_vec()).await.unwrap();
+    client.copy_file("docs/a.md", "docs/c.md").await.unwrap();
+    assert_eq!(refs(&vault, DATA), Some(3));
+    let blobs = vault
+        .keys()
+        .into_iter()
+        .filter(|key| key.starts_with(&cas::blob_path("")))
+        .count();
+    assert_eq!(blobs, 1);
+    let pointer = vault
+        .secret("docs/a.md")
+        .unwrap()
+        .current()
+        .unwrap()
+        .data
+        .clone();
+    assert_eq!(pointer["blob"], cas::hash(DATA));
+    assert_eq!(pointer["data"], serde_json::json!([]));
+
+    // Replacing an object moves its reference to the blob of the new data
+    client
+        .write_file("docs/b.md", b"bye".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(refs(&vault, DATA), Some(2));
+    assert_eq!(refs(&vault, b"bye"), Some(1));
+    assert_eq!(
+        client.read_file("docs/b.md").await.unwrap().data,
+        &b"bye"[..]
+    );
+
+    // The blob outlives all but the last object pointing to it
+    client.delete_file("docs/a.md").await.unwrap();
+    assert_eq!(refs(&vault, DATA), Some(1));
+    assert!(has_blob(&vault, DATA));
+    assert_eq!(client.read_file("docs/c.md").await.unwrap().data, DATA);
+    client.delete_file("docs/c.md").await.unwrap();
+    assert_eq!(refs(&vault, DATA), Some(0));
+    assert!(!has_blob(&vault, DATA));
+    assert!(has_blob(&vault, b"bye"));
+}
@@ -0,0 +1,103 @@
+//! Checks that continuation tokens can't be forged or used to page through another listing
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use blobstore_vault::{
+    continuation::{Continuation, ContinuationKey},
+    wasmcloud_interface_blobstore::ListSort,
+};
+
+fn continuation() -> Continuation {
+    Continuation {
+        container: "reports".to_string(),
+        prefix: "2024/".to_string(),
+        sort: ListSort::Name,
+        after: "2024/03.json".to_string(),
+        after_modified: None,
+    }
+}
+
+/// Returns the token with its payload replaced by the JSON of another continuation, keeping the
+/// signature
+fn with_payload(token: &str, continuation: &Continuation) -> String {
+    let (_, signature) = token.split_once('.').unwrap();
+    let payload = serde_json::to_vec(continuation).unwrap();
+    format!("{}.{signature}", URL_SAFE_NO_PAD.encode(payload))
+}
+
+#[test]
+fn round_trips() {
+    let key = ContinuationKey::new("shared key");
+    let token = key.encode(&continuation());
+    assert_eq!(key.decode(&token), Some(continuation()));
+    // Providers configured with the same key accept each other's tokens
+    assert_eq!(
+        ContinuationKey::new("shared key").decode(&token),
+        Some(continuation())
+    );
+}
+
+#[test]
+fn rejects_other_keys() {
+    let token = ContinuationKey::new("shared key").encode(&continuation());
+    assert_eq!(ContinuationKey::new("other key").decode(&token), None);
+    assert_eq!(ContinuationKey::default().decode(&token), None);
+    // Keys longer than a block are hashed rather than truncated
+    let long = "k".repeat(100);
+    let token = ContinuationKey::new(&long).encode(&continuation());
+    assert_eq!(ContinuationKey::new(&long[..64]).decode(&token), None);
+    assert!(ContinuationKey::new(&long).decode(&token).is_some());
+}
+
+#[test]
+fn rejects_tampered_tokens() {
+    let key = ContinuationKey::new("shared key");
+    let token = key.encode(&continuation());
+
+    let mut forged = continuation();
+    forged.after = "2024/99.json".to_string();
+    assert_eq!(key.decode(&with_payload(&token, &forged)), None);
+
+    let (payload, signature) = token.split_once('.').unwrap();
+    let mut signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    signature[0] ^= 1;
+    let flipped = format!("{payload}.{}", URL_SAFE_NO_PAD.encode(&signature));
+    assert_eq!(key.decode(&flipped), None);
+    let short = format!("{payload}.{}", URL_SAFE_NO_PAD.encode(&signature[..16]));
+    assert_eq!(key.decode(&short), None);
+
+    assert_eq!(key.decode(payload), None);
+    assert_eq!(key.decode(&format!("{payload}.")), None);
+    assert_eq!(key.decode("not a token"), None);
+}
+
+#[test]
+fn rejects_tokens_of_other_containers() {
+    let key = ContinuationKey::new("shared key");
+    let token = key.encode(&continuation());
+
+    // Rewriting the container breaks the signature
+    let mut forged = continuation();
+    forged.container = "secrets".to_string();
+    assert_eq!(key.decode(&with_payload(&token, &forged)), None);
+
+    // A genuine token of one container doesn't continue a listing of another
+    let decoded = key.decode(&token).unwrap();
+    assert!(decoded.is_for("reports", "2024/", ListSort::Name));
+    assert!(!decoded.is_for("secrets", "2024/", ListSort::Name));
+}
+
+#[test]
+fn rejects_tokens_of_other_prefixes() {
+    let key = ContinuationKey::new("shared key");
+    let token = key.encode(&continuation());
+
+    let mut forged = continuation();
+    forged.prefix = String::new();
+    forged.after = "../".to_string();
+    assert_eq!(key.decode(&with_payload(&token, &forged)), None);
+
+    let decoded = key.decode(&token).unwrap();
+    assert!(!decoded.is_for("reports", "", ListSort::Name));
+    assert!(!decoded.is_for("reports", "2024/private/", ListSort::Name));
+    assert!(!decoded.is_for("reports", "2024/", ListSort::NameDesc));
+}
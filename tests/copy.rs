@@ -0,0 +1,129 @@
+//! Checks that copies within a mount carry the user metadata of the source along with its data
+
+use blobstore_vault::{client::Client, config::Config};
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const SRC: &str = "reports/2024.json";
+const DEST: &str = "archive/2024.json";
+
+/// Starts a mock Vault server and returns it with a client connected to it. The server must be
+/// kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn version_metadata() -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": 1
+    })
+}
+
+/// Serves `SRC` with the given user metadata, and accepts writes of `DEST`
+async fn serve_copy(server: &MockServer, custom_metadata: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{SRC}")))
+        .respond_with(response(serde_json::json!({
+            "cas_required": false,
+            "created_time": "2024-03-01T12:00:00.000000Z",
+            "current_version": 1,
+            "custom_metadata": custom_metadata,
+            "delete_version_after": "0s",
+            "max_versions": 0,
+            "oldest_version": 0,
+            "updated_time": "2024-03-01T12:00:00.000000Z",
+            "versions": { "1": version_metadata() }
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{SRC}")))
+        .respond_with(response(serde_json::json!({
+            "data": { "data": [123, 125], "content_type": "application/json" },
+            "metadata": version_metadata()
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/data/{DEST}")))
+        .and(body_json(serde_json::json!({
+            "data": { "data": [123, 125], "content_type": "application/json" }
+        })))
+        .respond_with(response(version_metadata()))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{DEST}")))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(server)
+        .await;
+}
+
+/// Returns the bodies of the requests that set the metadata of `DEST`
+async fn metadata_writes(server: &MockServer) -> Vec<serde_json::Value> {
+    let metadata_path = format!("/v1/{MOUNT}/metadata/{DEST}");
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        // Only writes go to the metadata of the destination
+        .filter(|r| r.url.path() == metadata_path)
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn copies_custom_metadata() {
+    let (server, client) = serve().await;
+    serve_copy(&server, serde_json::json!({ "owner": "team-a" })).await;
+
+    let written = client
+        .copy_file(SRC, DEST)
+        .await
+        .expect("copy should succeed");
+    assert_eq!(written.version, 1);
+
+    let writes = metadata_writes(&server).await;
+    assert_eq!(writes.len(), 1);
+    assert_eq!(
+        writes[0]["custom_metadata"],
+        serde_json::json!({ "owner": "team-a" })
+    );
+}
+
+#[tokio::test]
+async fn leaves_metadata_of_plain_files_alone() {
+    let (server, client) = serve().await;
+    serve_copy(&server, serde_json::Value::Null).await;
+
+    client
+        .copy_file(SRC, DEST)
+        .await
+        .expect("copy should succeed");
+    assert!(metadata_writes(&server).await.is_empty());
+}
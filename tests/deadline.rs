@@ -0,0 +1,113 @@
+//! Checks that retries and walks of an operation stop at the deadline of its invocation
+
+use std::time::{Duration, Instant};
+
+use blobstore_vault::{
+    client::Client,
+    config::Config,
+    deadline,
+    error::{ErrorCode, VaultError},
+    export,
+    progress::Progress,
+};
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+const MOUNT: &str = "secret";
+
+/// Starts a mock Vault server and returns it with a client connected to it, retrying rate
+/// limited requests for half a minute. The server must be kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+        ("rate_limit_retry_secs".to_string(), "30".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+#[test]
+fn has_no_deadline_outside_of_invocations() {
+    assert!(deadline::current().is_none());
+    assert!(deadline::remaining().is_none());
+    assert!(deadline::check().is_ok());
+    let later = Instant::now() + Duration::from_secs(60);
+    assert_eq!(deadline::cap(later), later);
+}
+
+#[tokio::test]
+async fn caps_to_deadline_of_invocation() {
+    let end = Instant::now() + Duration::from_secs(5);
+    deadline::scope(end, async {
+        assert_eq!(deadline::current(), Some(end));
+        assert_eq!(deadline::cap(end + Duration::from_secs(1)), end);
+        let earlier = end - Duration::from_secs(1);
+        assert_eq!(deadline::cap(earlier), earlier);
+        assert!(deadline::remaining().unwrap() <= Duration::from_secs(5));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn stops_retrying_rate_limited_request_at_deadline() {
+    let (server, client) = serve().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429))
+        .mount(&server)
+        .await;
+
+    let started = Instant::now();
+    let res = deadline::scope(
+        started + Duration::from_millis(1500),
+        client.read_file("docs/readme.md"),
+    )
+    .await;
+    let err = res.expect_err("read should be rate limited");
+    assert_eq!(err.code(), ErrorCode::Unavailable, "{err:?}");
+    // Without the deadline it would have been retried for the configured half minute
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn stops_export_at_deadline() {
+    let (server, client) = serve().await;
+    Mock::given(method("LIST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "data": { "keys": ["readme.md"] }
+        })))
+        .mount(&server)
+        .await;
+
+    let err = deadline::scope(Instant::now(), export::walk_folder(&client, "docs", ""))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VaultError::DeadlineExceeded), "{err:?}");
+
+    let err = deadline::scope(
+        Instant::now(),
+        export::archive(
+            &client,
+            "docs",
+            Vec::new(),
+            &Progress::logged("export", "docs"),
+        ),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<VaultError>(),
+            Some(VaultError::DeadlineExceeded)
+        ),
+        "{err:?}"
+    );
+    assert!(server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .is_empty());
+}
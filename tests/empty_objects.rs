@@ -0,0 +1,162 @@
+//! Checks that empty objects, commonly used as markers, are written, found and read like any
+//! other object rather than being mistaken for missing data
+
+use blobstore_vault::{
+    client::{Client, File},
+    config::Config,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "marker";
+
+/// Starts a mock Vault server and returns it with a client connected to it. The server must be
+/// kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "lease_id": "",
+        "renewable": false,
+        "lease_duration": 0,
+        "data": data,
+        "wrap_info": null,
+        "warnings": null,
+        "auth": null
+    }))
+}
+
+fn version_metadata() -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2023-01-01T00:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": 1
+    })
+}
+
+/// Serves reads of `OBJECT` with the given content of its secret
+async fn serve_secret(server: &MockServer, secret: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(serde_json::json!({
+            "data": secret,
+            "metadata": version_metadata()
+        })))
+        .mount(server)
+        .await;
+}
+
+#[test]
+fn empty_file_round_trips_through_json() {
+    let json = serde_json::to_value(File::from(Vec::new())).unwrap();
+    assert_eq!(json, serde_json::json!({ "data": [] }));
+
+    let file: File = serde_json::from_value(json).unwrap();
+    assert!(file.data.is_empty());
+    assert!(file.blob.is_none());
+}
+
+#[tokio::test]
+async fn writes_empty_file() {
+    let (server, client) = serve().await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(version_metadata()))
+        .mount(&server)
+        .await;
+
+    let written = client
+        .write_file(OBJECT, Vec::new())
+        .await
+        .expect("write should succeed");
+    assert_eq!(written.version, 1);
+
+    // The client may check the seal status of the server in the background, so the write isn't
+    // necessarily the last request
+    let requests = server.received_requests().await.unwrap_or_default();
+    let write = requests
+        .iter()
+        .find(|r| r.url.path() == format!("/v1/{MOUNT}/data/{OBJECT}"))
+        .expect("the secret should be written");
+    let body: serde_json::Value = serde_json::from_slice(&write.body).unwrap();
+    assert_eq!(body["data"]["data"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn reads_empty_file() {
+    let (server, client) = serve().await;
+    serve_secret(
+        &server,
+        serde_json::json!({ "data": [], "content_type": "text/plain" }),
+    )
+    .await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert!(file.data.is_empty());
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+}
+
+#[tokio::test]
+async fn reads_secret_without_data_as_empty_file() {
+    let (server, client) = serve().await;
+    serve_secret(&server, serde_json::json!({ "content_type": "text/plain" })).await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert!(file.data.is_empty());
+    assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+}
+
+#[tokio::test]
+async fn empty_file_exists() {
+    let (server, client) = serve().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{OBJECT}")))
+        .respond_with(response(serde_json::json!({
+            "cas_required": false,
+            "created_time": "2023-01-01T00:00:00.000000Z",
+            "current_version": 1,
+            "custom_metadata": null,
+            "delete_version_after": "0s",
+            "max_versions": 0,
+            "oldest_version": 0,
+            "updated_time": "2023-01-01T00:00:00.000000Z",
+            "versions": { "1": version_metadata() }
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/subkeys/{OBJECT}")))
+        .respond_with(response(serde_json::json!({
+            "subkeys": { "data": null },
+            "metadata": version_metadata()
+        })))
+        .mount(&server)
+        .await;
+    serve_secret(&server, serde_json::json!({ "data": [] })).await;
+
+    assert!(client.exists(OBJECT).await.expect("lookup should succeed"));
+    let (metadata, file) = client
+        .read_with_metadata(OBJECT)
+        .await
+        .expect("read should succeed");
+    assert_eq!(metadata.current_version, 1);
+    assert_eq!(file.data.len(), 0);
+}
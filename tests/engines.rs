@@ -0,0 +1,206 @@
+//! Checks that KV v1 and cubbyhole mounts are sent the requests of their engine, which has no
+//! `data/` or `metadata/` paths, including mounts whose version is detected rather than
+//! configured, and that requests needing versions are refused
+
+use blobstore_vault::{
+    client::{Client, File},
+    config::Config,
+    error::VaultError,
+};
+use vaultrs::error::ClientError;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const OBJECT: &str = "docs/readme.md";
+
+/// Starts a mock Vault server and returns it with a client of `mount` connected to it, with the
+/// given extra settings. The server must be kept alive for the duration of the test
+async fn serve(mount: &str, settings: &[(&str, &str)]) -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let mut values = vec![
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), mount.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ];
+    values.extend(settings.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let config = Config::from_values(&values).expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+/// Serves `OBJECT` of `mount` the way KV v1 stores it, as the secret itself
+async fn serve_secret(server: &MockServer, mount: &str) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{mount}/{OBJECT}")))
+        .respond_with(response(serde_json::json!({ "data": [111, 107] })))
+        .mount(server)
+        .await;
+}
+
+/// Returns the paths of the requests received for secrets of `mount`, with their methods.
+/// Requests the client sends in the background, like checks of the seal status, are left out
+async fn requests(server: &MockServer, mount: &str) -> Vec<String> {
+    let prefix = format!("/v1/{mount}/");
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.url.path().starts_with(&prefix))
+        .map(|r| format!("{} {}", r.method, r.url.path()))
+        .collect()
+}
+
+#[tokio::test]
+async fn reads_and_writes_kv1_secrets_in_place() {
+    let (server, client) = serve("kv", &[("kv_version", "1")]).await;
+    serve_secret(&server, "kv").await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/kv/{OBJECT}")))
+        .and(body_json(serde_json::json!({ "data": [111, 107] })))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    Mock::given(method("LIST"))
+        .and(path("/v1/kv/docs"))
+        .respond_with(response(serde_json::json!({ "keys": ["readme.md"] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(format!("/v1/kv/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+
+    let written = client
+        .write_file(OBJECT, File::from(b"ok".to_vec()))
+        .await
+        .expect("write should succeed");
+    assert_eq!(written.version, 1);
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    assert!(client.exists(OBJECT).await.unwrap());
+    let metadata = client
+        .get_metadata(OBJECT)
+        .await
+        .expect("metadata should be reported");
+    assert_eq!(metadata.current_version, 1);
+    assert_eq!(
+        client.list_files("docs").await.unwrap(),
+        vec!["readme.md".to_string()]
+    );
+    client
+        .delete_file(OBJECT)
+        .await
+        .expect("delete should succeed");
+
+    let requests = requests(&server, "kv").await;
+    assert!(
+        requests.iter().all(|r| !r.contains("/data/")
+            && !r.contains("/metadata/")
+            && !r.contains("/subkeys/")),
+        "{requests:?}"
+    );
+}
+
+#[tokio::test]
+async fn detects_kv1_mounts() {
+    let (server, client) = serve("kv", &[]).await;
+    Mock::given(method("GET"))
+        .and(path("/v1/sys/mounts"))
+        .respond_with(response(serde_json::json!({
+            "kv/": { "type": "kv", "options": { "version": "1" } }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    serve_secret(&server, "kv").await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/kv/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    client
+        .write_file(OBJECT, File::from(b"ok".to_vec()))
+        .await
+        .expect("write should succeed");
+    assert_eq!(client.kv_version().await, Some(1));
+    assert_eq!(
+        requests(&server, "kv").await,
+        vec![
+            format!("GET /v1/kv/{OBJECT}"),
+            format!("POST /v1/kv/{OBJECT}")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn uses_kv1_api_for_cubbyhole() {
+    let (server, client) = serve("cubbyhole", &[]).await;
+    serve_secret(&server, "cubbyhole").await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    assert!(!client.exists("docs/missing.md").await.unwrap());
+    assert_eq!(
+        requests(&server, "cubbyhole").await,
+        vec![
+            format!("GET /v1/cubbyhole/{OBJECT}"),
+            "GET /v1/cubbyhole/docs/missing.md".to_string()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn refuses_writes_of_given_version() {
+    let (server, client) = serve("kv", &[("kv_version", "1")]).await;
+
+    let err = client
+        .write_file_if_version(OBJECT, File::from(b"ok".to_vec()), 1)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 400, errors },
+                ..
+            } if errors[0].contains("given version")
+        ),
+        "{err:?}"
+    );
+    assert!(requests(&server, "kv").await.is_empty());
+}
+
+#[test]
+fn rejects_features_needing_versions() {
+    for (mount, kv_version) in [("kv", "1"), ("cubbyhole", "2")] {
+        let err = Config::from_values(&[
+            ("token".to_string(), "test-token".to_string()),
+            ("mount".to_string(), mount.to_string()),
+            ("kv_version".to_string(), kv_version.to_string()),
+            ("content_addressed".to_string(), "true".to_string()),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("check-and-set"), "{err}");
+    }
+    assert!(Config::from_values(&[
+        ("token".to_string(), "test-token".to_string()),
+        ("kv_version".to_string(), "3".to_string()),
+    ])
+    .is_err());
+}
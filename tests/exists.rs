@@ -0,0 +1,148 @@
+//! Checks that existence checks ask the KV v2 `subkeys` endpoint, which doesn't return any data,
+//! and read the metadata of secrets instead where the endpoint can't be used
+
+use blobstore_vault::{client::Client, config::Config};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "docs/readme.md";
+
+/// Starts a mock Vault server and returns it with a client connected to it. The server must be
+/// kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn version_metadata() -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2024-03-01T12:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": 1
+    })
+}
+
+async fn serve_subkeys(server: &MockServer, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/subkeys/{OBJECT}")))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+async fn serve_metadata(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/metadata/{OBJECT}")))
+        .respond_with(response(serde_json::json!({
+            "cas_required": false,
+            "created_time": "2024-03-01T12:00:00.000000Z",
+            "current_version": 1,
+            "custom_metadata": null,
+            "delete_version_after": "0s",
+            "max_versions": 0,
+            "oldest_version": 0,
+            "updated_time": "2024-03-01T12:00:00.000000Z",
+            "versions": { "1": version_metadata() }
+        })))
+        .mount(server)
+        .await;
+}
+
+/// Returns the number of requests received to the given endpoint of the mount, e.g. `subkeys`
+async fn requests_to(server: &MockServer, endpoint: &str) -> usize {
+    let prefix = format!("/v1/{MOUNT}/{endpoint}/");
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.url.path().starts_with(&prefix))
+        .count()
+}
+
+#[tokio::test]
+async fn checks_subkeys_only() {
+    let (server, client) = serve().await;
+    serve_subkeys(
+        &server,
+        response(serde_json::json!({
+            "subkeys": { "data": null, "content_type": null },
+            "metadata": version_metadata()
+        })),
+    )
+    .await;
+
+    assert!(client.exists(OBJECT).await.unwrap());
+    assert_eq!(requests_to(&server, "subkeys").await, 1);
+    assert_eq!(requests_to(&server, "metadata").await, 0);
+    assert_eq!(requests_to(&server, "data").await, 0);
+}
+
+#[tokio::test]
+async fn missing_or_deleted_secret_doesnt_exist() {
+    let (server, client) = serve().await;
+    // Vault answers with 404 for deleted versions too
+    serve_subkeys(
+        &server,
+        ResponseTemplate::new(404).set_body_json(serde_json::json!({ "errors": [] })),
+    )
+    .await;
+
+    assert!(!client.exists(OBJECT).await.unwrap());
+    assert_eq!(requests_to(&server, "metadata").await, 0);
+}
+
+#[tokio::test]
+async fn reads_metadata_on_servers_without_subkeys() {
+    let (server, client) = serve().await;
+    serve_subkeys(
+        &server,
+        ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "errors": ["1 error occurred:\n\t* unsupported path\n\n"]
+        })),
+    )
+    .await;
+    serve_metadata(&server).await;
+
+    assert!(client.exists(OBJECT).await.unwrap());
+    assert!(client.exists(OBJECT).await.unwrap());
+    // The server isn't asked again once it is known not to support the endpoint
+    assert_eq!(requests_to(&server, "subkeys").await, 1);
+    assert_eq!(requests_to(&server, "metadata").await, 2);
+}
+
+#[tokio::test]
+async fn reads_metadata_when_subkeys_are_denied() {
+    let (server, client) = serve().await;
+    serve_subkeys(
+        &server,
+        ResponseTemplate::new(403)
+            .set_body_json(serde_json::json!({ "errors": ["permission denied"] })),
+    )
+    .await;
+    serve_metadata(&server).await;
+
+    assert!(client.exists(OBJECT).await.unwrap());
+    assert_eq!(requests_to(&server, "metadata").await, 1);
+}
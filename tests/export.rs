@@ -0,0 +1,109 @@
+//! Checks that the archive of a container is stored in parts as soon as they are complete, and
+//! that exporting containers must be allowed by the link
+
+mod common;
+
+use std::{io::Read, sync::Mutex};
+
+use blobstore_vault::{client::File, export, progress::Progress};
+use common::Vault;
+
+const CONTAINER: &str = "docs";
+const OBJECTS: [&str; 3] = ["a.bin", "b.bin", "c.bin"];
+const PART_BYTES: usize = 16 * 1024;
+
+/// Returns data gzip can't compress, so the archive is at least as large as the objects
+fn noise(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// Returns the number of objects of the container read so far
+async fn objects_read(vault: &Vault) -> usize {
+    vault
+        .server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| {
+            r.method.as_str() == "GET"
+                && r.url.path().starts_with("/v1/secret/data/docs/")
+                && r.url.query().is_none()
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn stores_parts_while_archiving() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    for (seed, id) in OBJECTS.iter().enumerate() {
+        client
+            .write_file(
+                format!("{CONTAINER}/{id}"),
+                File::from(noise(seed as u32, 4 * PART_BYTES)),
+            )
+            .await
+            .expect("write should succeed");
+    }
+
+    let mut parts = Vec::new();
+    let reads_before_parts = Mutex::new(Vec::new());
+    let (count, stored) = export::archive_parts(
+        &client,
+        CONTAINER,
+        PART_BYTES,
+        &Progress::logged("export", CONTAINER),
+        |index, data| {
+            assert_eq!(index, parts.len());
+            parts.push(data);
+            let reads = &reads_before_parts;
+            let vault = &vault;
+            async move {
+                let read = objects_read(vault).await;
+                reads.lock().unwrap().push(read);
+                Ok(())
+            }
+        },
+    )
+    .await
+    .expect("export should succeed");
+    assert_eq!(count, OBJECTS.len());
+    assert_eq!(stored, parts.len());
+
+    // Parts of the first object were stored before the last one was read
+    let reads_before_parts = reads_before_parts.into_inner().unwrap();
+    assert!(
+        reads_before_parts[0] < OBJECTS.len(),
+        "{reads_before_parts:?}"
+    );
+    let (last, full) = parts.split_last().unwrap();
+    assert!(full.iter().all(|part| part.len() == PART_BYTES));
+    assert!(!last.is_empty() && last.len() <= PART_BYTES);
+
+    let archive = parts.concat();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive.as_slice()));
+    for (seed, entry) in archive.entries().unwrap().enumerate() {
+        let mut entry = entry.unwrap();
+        assert_eq!(entry.path().unwrap().to_str(), Some(OBJECTS[seed]));
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        assert_eq!(data, noise(seed as u32, 4 * PART_BYTES));
+    }
+    assert_eq!(export::part_id("backup.tar.gz", 2), "backup.tar.gz.000002");
+}
+
+#[tokio::test]
+async fn exports_only_if_allowed() {
+    let vault = Vault::start("secret").await;
+    assert!(!vault.client(&[]).allow_export());
+    assert!(vault.client(&[("allow_export", "true")]).allow_export());
+}
@@ -0,0 +1,83 @@
+//! Checks which object IDs listing patterns select, and the folders listings of them start in
+
+use blobstore_vault::glob::Pattern;
+
+#[test]
+fn star_stays_within_folder() {
+    let pattern = Pattern::new("reports/2024-*/summary.json");
+    assert!(pattern.matches("reports/2024-01/summary.json"));
+    assert!(pattern.matches("reports/2024-/summary.json"));
+    assert!(!pattern.matches("reports/2024-01/q1/summary.json"));
+    assert!(!pattern.matches("reports/2023-01/summary.json"));
+
+    let pattern = Pattern::new("*.json");
+    assert!(pattern.matches("config.json"));
+    assert!(!pattern.matches("app/config.json"));
+}
+
+#[test]
+fn double_star_crosses_folders() {
+    let pattern = Pattern::new("reports/**/summary.json");
+    assert!(pattern.matches("reports/2024/01/summary.json"));
+    assert!(pattern.matches("reports/2024/summary.json"));
+    assert!(!pattern.matches("reports/summary.json"));
+    assert!(!pattern.matches("archive/reports/2024/summary.json"));
+
+    let pattern = Pattern::new("**");
+    assert!(pattern.matches(""));
+    assert!(pattern.matches("a/b/c"));
+}
+
+#[test]
+fn question_mark_matches_one_character() {
+    let pattern = Pattern::new("logs/day-?.txt");
+    assert!(pattern.matches("logs/day-1.txt"));
+    assert!(!pattern.matches("logs/day-.txt"));
+    assert!(!pattern.matches("logs/day-10.txt"));
+    assert!(!Pattern::new("a?b").matches("a/b"));
+    assert!(Pattern::new("?").matches("é"));
+}
+
+#[test]
+fn literal_characters_match_themselves() {
+    let pattern = Pattern::new("reports/2024.json");
+    assert!(pattern.matches("reports/2024.json"));
+    assert!(!pattern.matches("reports/2024xjson"));
+    assert!(!pattern.matches("reports/2024.json.bak"));
+}
+
+#[test]
+fn many_stars_match_without_backtracking() {
+    let pattern = Pattern::new(&"*a".repeat(32));
+    assert!(!pattern.matches(&"a".repeat(31)));
+    assert!(pattern.matches(&"a".repeat(64)));
+}
+
+#[test]
+fn folder_is_literal_part_before_wildcard() {
+    assert_eq!(
+        Pattern::new("reports/2024-*/summary.json").folder(),
+        "reports/"
+    );
+    assert_eq!(
+        Pattern::new("reports/2024/*.json").folder(),
+        "reports/2024/"
+    );
+    assert_eq!(Pattern::new("reports/**").folder(), "reports/");
+    assert_eq!(Pattern::new("reports/?/a").folder(), "reports/");
+}
+
+#[test]
+fn folder_without_wildcard() {
+    let pattern = Pattern::new("reports/2024/summary.json");
+    assert_eq!(pattern.folder(), "reports/2024/");
+    assert!(pattern.matches("reports/2024/summary.json"));
+    assert_eq!(Pattern::new("summary.json").folder(), "");
+}
+
+#[test]
+fn folder_with_leading_wildcard() {
+    assert_eq!(Pattern::new("*/summary.json").folder(), "");
+    assert_eq!(Pattern::new("**/summary.json").folder(), "");
+    assert_eq!(Pattern::new("?eports/a").folder(), "");
+}
@@ -0,0 +1,186 @@
+//! Checks that the objects of a group are all written, or that those already written are rolled
+//! back to what they were when a write fails
+
+mod common;
+
+use std::collections::HashMap;
+
+use blobstore_vault::{
+    client::File,
+    objects::{put_object_group, write_object},
+    wasmcloud_interface_blobstore::ObjectWrite,
+};
+use bytes::Bytes;
+use common::Vault;
+use serde_json::{json, Value};
+
+fn object(object_id: &str, data: &[u8]) -> ObjectWrite {
+    ObjectWrite {
+        object_id: object_id.to_string(),
+        bytes: Bytes::copy_from_slice(data),
+        ..Default::default()
+    }
+}
+
+/// Returns the data stored for an object, None if it doesn't exist or was deleted
+fn stored(vault: &Vault, object_id: &str) -> Option<Value> {
+    vault
+        .secret(&format!("config/{object_id}"))
+        .and_then(|secret| secret.current().map(|version| version.data["data"].clone()))
+}
+
+#[tokio::test]
+async fn commits_groups() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    vault.put("config/a.json", json!({ "data": b"old" }));
+
+    let group = put_object_group(
+        &client,
+        "config",
+        vec![object("a.json", b"a"), object("b.json", b"b")],
+    )
+    .await;
+    assert!(group.committed);
+    assert!(group.rolled_back.is_empty());
+    let versions: Vec<_> = group.results.iter().map(|result| result.version).collect();
+    assert_eq!(versions, [Some(2), Some(1)]);
+    assert_eq!(stored(&vault, "a.json"), Some(json!(b"a")));
+    assert_eq!(stored(&vault, "b.json"), Some(json!(b"b")));
+}
+
+#[tokio::test]
+async fn rolls_back_after_failed_write() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    let metadata = HashMap::from([("owner".to_string(), "ops".to_string())]);
+    write_object(
+        &client,
+        "config",
+        "a.json",
+        File::from(b"old".to_vec()),
+        None,
+        None,
+        Some(metadata.clone()),
+    )
+    .await
+    .unwrap();
+    vault.fail_writes("config/c.json", true);
+
+    let mut replaced = object("a.json", b"a");
+    replaced.metadata = Some(HashMap::from([("owner".to_string(), "dev".to_string())]));
+    let group = put_object_group(
+        &client,
+        "config",
+        vec![
+            replaced,
+            object("b.json", b"b"),
+            object("c.json", b"c"),
+            object("d.json", b"d"),
+        ],
+    )
+    .await;
+
+    assert!(!group.committed);
+    let outcome: Vec<_> = group
+        .results
+        .iter()
+        .map(|result| (result.object_id.as_str(), result.success))
+        .collect();
+    // The group stops at the failed write
+    assert_eq!(
+        outcome,
+        [("a.json", true), ("b.json", true), ("c.json", false)]
+    );
+    assert!(group.results[2].error.is_some());
+    // Objects are rolled back in the reverse order they were written in
+    let rolled_back: Vec<_> = group
+        .rolled_back
+        .iter()
+        .map(|item| (item.key.as_str(), item.success))
+        .collect();
+    assert_eq!(rolled_back, [("b.json", true), ("a.json", true)]);
+
+    // The replaced object has its previous data and metadata again, and the new one is removed
+    assert_eq!(stored(&vault, "a.json"), Some(json!(b"old")));
+    let restored = vault.secret("config/a.json").unwrap();
+    assert_eq!(restored.custom_metadata, Some(metadata));
+    assert_eq!(restored.versions.len(), 3);
+    assert_eq!(stored(&vault, "b.json"), None);
+    assert_eq!(stored(&vault, "c.json"), None);
+    assert!(vault.secret("config/d.json").is_none());
+}
+
+#[tokio::test]
+async fn rolls_back_after_version_mismatch() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    vault.put("config/b.json", json!({ "data": b"old" }));
+
+    let mut stale = object("b.json", b"b");
+    stale.if_version = Some(4);
+    let group = put_object_group(&client, "config", vec![object("a.json", b"a"), stale]).await;
+
+    assert!(!group.committed);
+    assert!(!group.results[1].success);
+    assert_eq!(group.rolled_back.len(), 1);
+    assert!(group.rolled_back[0].success);
+    assert_eq!(stored(&vault, "a.json"), None);
+    assert_eq!(stored(&vault, "b.json"), Some(json!(b"old")));
+}
+
+/// With requests failing at random, every group is either written whole or left as it was,
+/// except for objects whose rollback is reported to have failed
+#[tokio::test]
+async fn groups_are_all_or_nothing_under_injected_faults() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[
+        ("unsafe_fault_injection", "error=0.2"),
+        ("rate_limit_retry_secs", "0"),
+    ]);
+    vault.put("config/a.json", json!({ "data": b"old" }));
+
+    let mut rolled_back = 0;
+    for round in 0..30 {
+        let before: Vec<_> = ["a.json", "b.json", "c.json"]
+            .iter()
+            .map(|object_id| stored(&vault, object_id))
+            .collect();
+        let data = format!("round {round}").into_bytes();
+        let group = put_object_group(
+            &client,
+            "config",
+            vec![
+                object("a.json", &data),
+                object("b.json", &data),
+                object("c.json", &data),
+            ],
+        )
+        .await;
+
+        for (i, object_id) in ["a.json", "b.json", "c.json"].iter().enumerate() {
+            let now = stored(&vault, object_id);
+            if group.committed {
+                assert_eq!(now, Some(json!(data)), "round {round}: {object_id}");
+                continue;
+            }
+            let failed_rollback = group
+                .rolled_back
+                .iter()
+                .any(|item| item.key == *object_id && !item.success);
+            if !failed_rollback {
+                assert_eq!(now, before[i], "round {round}: {object_id}");
+            }
+        }
+        if !group.committed {
+            rolled_back += 1;
+        }
+        // Start the next round from a known state if a rollback failed
+        if group.rolled_back.iter().any(|item| !item.success) {
+            for object_id in ["a.json", "b.json", "c.json"] {
+                vault.put(&format!("config/{object_id}"), json!({ "data": b"reset" }));
+            }
+        }
+    }
+    assert!(rolled_back > 0, "faults should have failed some groups");
+}
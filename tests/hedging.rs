@@ -0,0 +1,101 @@
+//! Checks that reads slower than a percentile of recent read latencies are sent again, and that
+//! the first answer is used
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use blobstore_vault::{client::Client, config::Config, hedge::Hedge};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn hedges_after_percentile_of_recent_reads() {
+    let hedge = Hedge::new(50.0);
+    for latency in 1..20 {
+        hedge.record(ms(latency));
+    }
+    // Too few reads were observed to derive a threshold
+    assert_eq!(hedge.delay(), None);
+    for latency in 20..=100 {
+        hedge.record(ms(latency));
+    }
+    assert_eq!(hedge.delay(), Some(ms(51)));
+    assert_eq!(Hedge::new(100.0).delay(), None);
+
+    // Only the most recent reads count
+    for _ in 0..200 {
+        hedge.record(ms(10));
+    }
+    assert_eq!(hedge.delay(), Some(ms(10)));
+}
+
+#[test]
+fn waits_at_least_the_minimum_delay() {
+    let hedge = Hedge::new(95.0);
+    for _ in 0..50 {
+        hedge.record(Duration::from_micros(100));
+    }
+    assert_eq!(hedge.delay(), Some(ms(5)));
+}
+
+#[tokio::test]
+async fn answers_slow_reads_with_the_hedged_request() {
+    let server = MockServer::start().await;
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted = reads.clone();
+    Mock::given(method("GET"))
+        .and(path_regex("^/v1/secret/data/"))
+        .respond_with(move |_: &Request| {
+            let response = ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "data": { "data": b"meow" },
+                    "metadata": {
+                        "created_time": "2024-03-01T12:00:00.000000Z",
+                        "custom_metadata": null,
+                        "deletion_time": "",
+                        "destroyed": false,
+                        "version": 1
+                    }
+                }
+            }));
+            // The first request of the read after the threshold is known hangs
+            match counted.fetch_add(1, Ordering::SeqCst) {
+                20 => response.set_delay(Duration::from_secs(5)),
+                _ => response,
+            }
+        })
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("hedge_percentile".to_string(), "50".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+
+    for i in 0..20 {
+        client.read_file(format!("photos/{i}.png")).await.unwrap();
+    }
+    assert_eq!(reads.load(Ordering::SeqCst), 20);
+
+    let started = Instant::now();
+    let file = client
+        .read_file("photos/slow.png")
+        .await
+        .expect("hedged read should succeed");
+    assert_eq!(file.data, &b"meow"[..]);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(reads.load(Ordering::SeqCst), 22);
+}
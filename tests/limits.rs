@@ -0,0 +1,151 @@
+//! Checks that concurrency limits bound the Vault requests in flight, queue a bounded number of
+//! requests and reject the rest with backpressure
+
+use std::time::Duration;
+
+use blobstore_vault::{client::Client, config::Config, error::VaultError, limit::Limiter};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn queues_then_rejects() {
+    let limiter = Limiter::new("link", 1, 1);
+    let first = limiter.acquire().await.expect("a slot should be free");
+
+    // The second request waits for the slot, and a third doesn't fit in the queue
+    let second = tokio::spawn(async move {
+        let permit = limiter.acquire().await;
+        (limiter, permit)
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!second.is_finished());
+    drop(first);
+    let (limiter, permit) = second.await.unwrap();
+    let permit = permit.expect("queued request should get the slot");
+
+    let queued = limiter.acquire();
+    tokio::pin!(queued);
+    assert!(tokio::time::timeout(Duration::from_millis(50), &mut queued)
+        .await
+        .is_err());
+    match limiter.acquire().await {
+        Err(VaultError::Backpressure { scope }) => assert_eq!(scope, "link"),
+        res => panic!("unexpected result {:?}", res.map(|_| ())),
+    }
+    drop(permit);
+    let _permit = queued.await.expect("queued request should get the slot");
+}
+
+#[tokio::test]
+async fn rejects_requests_beyond_the_link_limit() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/v1/secret/data/"))
+        .respond_with(
+            ResponseTemplate::new(404)
+                .set_body_json(serde_json::json!({ "errors": [] }))
+                .set_delay(Duration::from_millis(300)),
+        )
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("max_concurrent_requests".to_string(), "1".to_string()),
+        ("max_queued_requests".to_string(), "1".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+
+    // Reads of different paths aren't coalesced, so each needs a slot
+    let reads = futures::future::join_all(
+        ["a", "b", "c"].map(|name| client.read_file(format!("photos/{name}.png"))),
+    )
+    .await;
+    let rejected = reads
+        .iter()
+        .filter(|res| matches!(res, Err(VaultError::Backpressure { .. })))
+        .count();
+    let answered = reads
+        .iter()
+        .filter(|res| matches!(res, Err(VaultError::NotFound { .. })))
+        .count();
+    assert_eq!((answered, rejected), (2, 1));
+}
+
+#[tokio::test]
+async fn shares_limits_across_links() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/v1/secret/data/"))
+        .respond_with(
+            ResponseTemplate::new(404)
+                .set_body_json(serde_json::json!({ "errors": [] }))
+                .set_delay(Duration::from_millis(300)),
+        )
+        .mount(&server)
+        .await;
+    let limiter = std::sync::Arc::new(Limiter::new("provider", 1, 0));
+    let client = || {
+        let config = Config::from_values(&[
+            ("addr".to_string(), server.uri()),
+            ("token".to_string(), "test-token".to_string()),
+        ])
+        .expect("config should be valid");
+        Client::new(config)
+            .expect("client should be created")
+            .with_shared_limiter(limiter.clone())
+    };
+    let (first, second) = (client(), client());
+
+    let (a, b) = futures::join!(
+        first.read_file("photos/a.png"),
+        second.read_file("photos/b.png")
+    );
+    let scopes: Vec<_> = [a, b]
+        .into_iter()
+        .filter_map(|res| match res {
+            Err(VaultError::Backpressure { scope }) => Some(scope),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(scopes, ["provider"]);
+}
+
+#[tokio::test]
+async fn counts_routed_containers_against_shared_limits() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/v1/(secret|archive)/data/"))
+        .respond_with(
+            ResponseTemplate::new(404)
+                .set_body_json(serde_json::json!({ "errors": [] }))
+                .set_delay(Duration::from_millis(300)),
+        )
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mounts".to_string(), "logs-:archive".to_string()),
+    ])
+    .expect("config should be valid");
+    let limiter = std::sync::Arc::new(Limiter::new("provider", 1, 0));
+    let client = Client::new(config)
+        .expect("client should be created")
+        .with_shared_limiter(limiter);
+    let routed = client.for_container("logs-2024");
+    assert_eq!(routed.mount(), "archive");
+
+    let (a, b) = futures::join!(
+        client.read_file("photos/a.png"),
+        routed.read_file("logs-2024/b.log")
+    );
+    let rejected = [a, b]
+        .iter()
+        .filter(|res| matches!(res, Err(VaultError::Backpressure { scope: "provider" })))
+        .count();
+    assert_eq!(rejected, 1);
+}
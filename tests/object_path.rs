@@ -0,0 +1,81 @@
+//! Checks that object IDs survive being stored at encoded Vault paths and listed back
+
+use std::collections::BTreeSet;
+
+use blobstore_vault::object_path::{decode, encode};
+use proptest::prelude::*;
+
+/// A path segment, which may contain anything but `/`
+fn segment() -> impl Strategy<Value = String> {
+    "[^/]{1,16}"
+}
+
+/// An object ID made of one or more segments
+fn object_id() -> impl Strategy<Value = String> {
+    prop::collection::vec(segment(), 1..4).prop_map(|segments| segments.join("/"))
+}
+
+/// Lists a folder of the store like Vault's LIST does: the keys directly in it, and the names of
+/// subfolders ending in `/`
+fn list(store: &BTreeSet<String>, folder: &str) -> BTreeSet<String> {
+    store
+        .iter()
+        .filter_map(|key| key.strip_prefix(folder))
+        .map(|rest| match rest.find('/') {
+            Some(i) => rest[..=i].to_string(),
+            None => rest.to_string(),
+        })
+        .collect()
+}
+
+/// Lists all keys below a folder, decoding each listed name like `Client::list_files` does, and
+/// returns the decoded IDs relative to the folder
+fn walk(store: &BTreeSet<String>, folder: &str, decoded_folder: &str) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    for entry in list(store, folder) {
+        let decoded = format!("{decoded_folder}{}", decode(&entry));
+        if entry.ends_with('/') {
+            found.extend(walk(store, &format!("{folder}{entry}"), &decoded));
+        } else {
+            found.insert(decoded);
+        }
+    }
+    found
+}
+
+proptest! {
+    #[test]
+    fn encode_round_trips(id in "\\PC*") {
+        prop_assert_eq!(decode(&encode(&id)), id);
+    }
+
+    #[test]
+    fn encoded_paths_are_vault_safe(id in "\\PC*") {
+        let encoded = encode(&id);
+        prop_assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~%/".contains(c)),
+            "{encoded} contains unsafe characters"
+        );
+        prop_assert!(
+            encoded.split('/').all(|segment| segment != "." && segment != ".."),
+            "{encoded} contains dot segments"
+        );
+        prop_assert_eq!(encoded.split('/').count(), id.split('/').count());
+    }
+
+    #[test]
+    fn listing_round_trips(
+        container in segment(),
+        ids in prop::collection::btree_set(object_id(), 1..16),
+    ) {
+        let store: BTreeSet<String> = ids
+            .iter()
+            .map(|id| encode(&format!("{container}/{id}")))
+            .collect();
+
+        let listed = walk(&store, &format!("{}/", encode(&container)), "");
+        prop_assert_eq!(listed, ids);
+    }
+}
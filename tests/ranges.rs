@@ -0,0 +1,32 @@
+//! Checks the bytes `GetObject` returns for `rangeStart` and `rangeEnd`
+
+use blobstore_vault::wasmcloud_interface_blobstore::GetObjectRequest;
+
+fn range(start: Option<u64>, end: Option<u64>, len: u64) -> std::ops::Range<u64> {
+    GetObjectRequest {
+        range_start: start,
+        range_end: end,
+        ..Default::default()
+    }
+    .range(len)
+}
+
+#[test]
+fn selects_inclusive_ranges() {
+    assert_eq!(range(None, None, 10), 0..10);
+    assert_eq!(range(Some(2), Some(4), 10), 2..5);
+    assert_eq!(range(Some(2), None, 10), 2..10);
+    assert_eq!(range(None, Some(0), 10), 0..1);
+}
+
+#[test]
+fn clamps_ranges_to_the_object() {
+    // An end beyond the object stops at its end
+    assert_eq!(range(Some(8), Some(100), 10), 8..10);
+    assert_eq!(range(Some(0), Some(u64::MAX), 10), 0..10);
+    // A start beyond the object selects nothing
+    assert_eq!(range(Some(10), None, 10), 10..10);
+    assert_eq!(range(Some(20), Some(30), 10), 10..10);
+    assert_eq!(range(Some(5), Some(2), 10), 5..5);
+    assert_eq!(range(None, None, 0), 0..0);
+}
@@ -0,0 +1,110 @@
+//! Checks that reads after a write carry the replication state Vault Enterprise returned for the
+//! write, so performance standbys don't serve data older than the write
+
+use blobstore_vault::{client::Client, config::Config};
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "config";
+const INDEX: &str = "djE6Y2x1c3Rlcjo0Mjo3";
+
+/// Starts a mock Vault server and returns it with a client connected to it, sending reads to the
+/// same server as writes. The server must be kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("read_addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Wraps data in the envelope of a Vault response
+fn response(data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": data,
+    }))
+}
+
+fn version_metadata() -> serde_json::Value {
+    serde_json::json!({
+        "created_time": "2023-01-01T00:00:00.000000Z",
+        "custom_metadata": null,
+        "deletion_time": "",
+        "destroyed": false,
+        "version": 2
+    })
+}
+
+/// Serves reads of `OBJECT` that carry `INDEX`, and fails the others like a standby that can't
+/// tell whether it is up to date
+async fn serve_reads(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .and(header("X-Vault-Index", INDEX))
+        .and(header("X-Vault-Inconsistent", "forward-active-node"))
+        .respond_with(response(serde_json::json!({
+            "data": { "data": [110, 101, 119] },
+            "metadata": version_metadata()
+        })))
+        .with_priority(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(
+            ResponseTemplate::new(412).set_body_json(
+                serde_json::json!({ "errors": ["required index state not present"] }),
+            ),
+        )
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn reads_carry_index_of_last_write() {
+    let (server, client) = serve().await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(version_metadata()).insert_header("X-Vault-Index", INDEX))
+        .mount(&server)
+        .await;
+    serve_reads(&server).await;
+
+    // Without a write there is no state to ask for
+    assert!(client.read_file(OBJECT).await.is_err());
+
+    client
+        .write_file(OBJECT, b"new".to_vec())
+        .await
+        .expect("write should succeed");
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"new"[..]);
+}
+
+#[tokio::test]
+async fn index_is_shared_by_clones() {
+    let (server, client) = serve().await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(response(version_metadata()).insert_header("X-Vault-Index", INDEX))
+        .mount(&server)
+        .await;
+    serve_reads(&server).await;
+
+    let reader = client.clone().with_actor("reader");
+    client
+        .write_file(OBJECT, b"new".to_vec())
+        .await
+        .expect("write should succeed");
+    assert!(reader.read_file(OBJECT).await.is_ok());
+}
@@ -0,0 +1,147 @@
+//! Checks that requests Vault refuses with a `Retry-After` are retried after the delay it asked
+//! for, within the retry window of the link and the deadline of the invocation
+
+use std::time::{Duration, Instant};
+
+use blobstore_vault::{
+    client::Client,
+    config::Config,
+    deadline,
+    error::{ErrorCode, VaultError},
+};
+use vaultrs::error::ClientError;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const MOUNT: &str = "secret";
+const OBJECT: &str = "object";
+
+/// Starts a mock Vault server and returns it with a client connected to it, retrying rate
+/// limited requests for a minute. The server must be kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("mount".to_string(), MOUNT.to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+        ("rate_limit_retry_secs".to_string(), "60".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Refuses the first read of `OBJECT` with `refusal`, and serves it afterwards
+async fn refuse_once(server: &MockServer, refusal: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(refusal)
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/{MOUNT}/data/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "data": {
+                "data": { "data": [111, 107] },
+                "metadata": {
+                    "created_time": "2024-03-01T12:00:00.000000Z",
+                    "custom_metadata": null,
+                    "deletion_time": "",
+                    "destroyed": false,
+                    "version": 1
+                }
+            }
+        })))
+        .mount(server)
+        .await;
+}
+
+fn errors(errors: &[&str]) -> serde_json::Value {
+    serde_json::json!({ "errors": errors })
+}
+
+/// Returns the number of reads of `OBJECT` received
+async fn reads(server: &MockServer) -> usize {
+    let object_path = format!("/v1/{MOUNT}/data/{OBJECT}");
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.url.path() == object_path)
+        .count()
+}
+
+#[tokio::test]
+async fn waits_as_long_as_rate_limit_asks() {
+    let (server, client) = serve().await;
+    let refusal = ResponseTemplate::new(429)
+        .set_body_json(errors(&["rate limit quota exceeded"]))
+        .insert_header("Retry-After", "2");
+    refuse_once(&server, refusal).await;
+
+    let started = Instant::now();
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    // Without the header, the first retry is after a second
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert_eq!(reads(&server).await, 2);
+}
+
+#[tokio::test]
+async fn retries_unavailable_server_that_says_when() {
+    let (server, client) = serve().await;
+    let refusal = ResponseTemplate::new(503)
+        .set_body_json(errors(&["overloaded"]))
+        .insert_header("Retry-After", "1");
+    refuse_once(&server, refusal).await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    assert_eq!(reads(&server).await, 2);
+}
+
+#[tokio::test]
+async fn fails_unavailable_server_that_doesnt_say_when() {
+    let (server, client) = serve().await;
+    refuse_once(
+        &server,
+        ResponseTemplate::new(503).set_body_json(errors(&["overloaded"])),
+    )
+    .await;
+
+    let err = client.read_file(OBJECT).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            VaultError::Client {
+                source: ClientError::APIError { code: 503, .. },
+                ..
+            }
+        ),
+        "expected a 503 APIError, got {err:?}"
+    );
+    assert_eq!(reads(&server).await, 1);
+}
+
+#[tokio::test]
+async fn gives_up_when_delay_passes_deadline() {
+    let (server, client) = serve().await;
+    let refusal = ResponseTemplate::new(429)
+        .set_body_json(errors(&["rate limit quota exceeded"]))
+        .insert_header("Retry-After", "30");
+    refuse_once(&server, refusal).await;
+
+    let started = Instant::now();
+    let err = deadline::scope(started + Duration::from_secs(5), client.read_file(OBJECT))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::Unavailable, "{err:?}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(reads(&server).await, 1);
+}
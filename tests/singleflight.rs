@@ -0,0 +1,118 @@
+//! Checks that identical concurrent reads share a single request to Vault and its result
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use blobstore_vault::{client::Client, config::Config, error::VaultError, singleflight::Group};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// Returns a call counting how often it runs, answering `value` after a short wait
+fn counted(
+    runs: &Arc<AtomicUsize>,
+    value: Result<u32, VaultError>,
+) -> impl std::future::Future<Output = Result<u32, VaultError>> + Send + 'static {
+    let runs = runs.clone();
+    async move {
+        runs.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        value
+    }
+}
+
+#[tokio::test]
+async fn coalesces_concurrent_calls() {
+    let group = Group::default();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let results = futures::future::join_all(
+        (0..5).map(|i| group.work("photos/cat.png", counted(&runs, Ok(i)))),
+    )
+    .await;
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    // Every caller gets the result of the call that ran
+    assert!(results.iter().all(|res| matches!(res, Ok(0))));
+
+    // Other keys run their own call, and a finished call isn't reused
+    let (other, again) =
+        futures::join!(group.work("photos/dog.png", counted(&runs, Ok(7))), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            group.work("photos/cat.png", counted(&runs, Ok(8))).await
+        },);
+    assert!(matches!(other, Ok(7)));
+    assert!(matches!(again, Ok(8)));
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn shares_errors() {
+    let group: Group<u32> = Group::default();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let not_found = || VaultError::NotFound {
+        namespace: String::new(),
+        path: "photos/cat.png".to_string(),
+    };
+    let (first, second) = futures::join!(
+        group.work("photos/cat.png", counted(&runs, Err(not_found()))),
+        group.work("photos/cat.png", counted(&runs, Err(not_found()))),
+    );
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert!(matches!(first, Err(VaultError::NotFound { .. })));
+    assert!(matches!(second, Err(VaultError::NotFound { .. })));
+}
+
+#[tokio::test]
+async fn concurrent_reads_send_one_request() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/v1/secret/data/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({
+                    "data": {
+                        "data": { "data": b"meow" },
+                        "metadata": {
+                            "created_time": "2024-03-01T12:00:00.000000Z",
+                            "custom_metadata": null,
+                            "deletion_time": "",
+                            "destroyed": false,
+                            "version": 1
+                        }
+                    }
+                }))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&server)
+        .await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+
+    let reads = futures::future::join_all((0..4).map(|_| client.read_file("photos/cat.png"))).await;
+    for read in reads {
+        assert_eq!(read.expect("read should succeed").data, &b"meow"[..]);
+    }
+    client.read_file("photos/dog.png").await.unwrap();
+    let requests = server.received_requests().await.unwrap();
+    let paths: Vec<_> = requests
+        .iter()
+        .map(|request| request.url.path())
+        .filter(|path| path.starts_with("/v1/secret/"))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "/v1/secret/data/photos/cat.png",
+            "/v1/secret/data/photos/dog.png"
+        ]
+    );
+}
@@ -0,0 +1,181 @@
+//! Checks that bodies larger than a chunk are encoded and decoded while they are transferred, and
+//! arrive the same as bodies handled whole
+
+use blobstore_vault::{
+    client::Client,
+    config::Config,
+    error::VaultError,
+    json::{self, Body, Received, CHUNK_BYTES},
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use vaultrs::error::ClientError;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+const OBJECT: &str = "docs/large.bin";
+
+/// Returns data whose JSON spans many chunks
+fn large_data() -> Vec<u8> {
+    (0..512 * 1024).map(|i| (i % 251) as u8).collect()
+}
+
+/// Starts a mock Vault server and returns it with a client connected to it. The server must be
+/// kept alive for the duration of the test
+async fn serve() -> (MockServer, Client) {
+    let server = MockServer::start().await;
+    let config = Config::from_values(&[
+        ("addr".to_string(), server.uri()),
+        ("token".to_string(), "test-token".to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+    ])
+    .expect("config should be valid");
+    let client = Client::new(config).expect("client should be created");
+    (server, client)
+}
+
+/// Returns the response to a read of a secret with the given data
+fn read_response(data: &[u8]) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "request_id": "00000000-0000-0000-0000-000000000000",
+        "data": {
+            "data": { "data": data },
+            "metadata": {
+                "created_time": "2024-03-01T12:00:00.000000Z",
+                "custom_metadata": null,
+                "deletion_time": "",
+                "destroyed": false,
+                "version": 1
+            }
+        }
+    }))
+}
+
+#[tokio::test]
+async fn writes_large_objects() {
+    let (server, client) = serve().await;
+    Mock::given(method("POST"))
+        .and(path(format!("/v1/secret/data/{OBJECT}")))
+        .respond_with(|request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let data: Vec<u8> = serde_json::from_value(body["data"]["data"].clone()).unwrap();
+            let status = if data == large_data() { 200 } else { 400 };
+            ResponseTemplate::new(status).set_body_json(serde_json::json!({
+                "data": {
+                    "created_time": "2024-03-01T12:00:00.000000Z",
+                    "custom_metadata": null,
+                    "deletion_time": "",
+                    "destroyed": false,
+                    "version": 1
+                }
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let written = client
+        .write_file(OBJECT, large_data())
+        .await
+        .expect("write should succeed");
+    assert_eq!(written.version, 1);
+}
+
+#[tokio::test]
+async fn reads_large_objects() {
+    let (server, client) = serve().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/secret/data/{OBJECT}")))
+        .respond_with(read_response(&large_data()))
+        .mount(&server)
+        .await;
+
+    let file = client.read_file(OBJECT).await.expect("read should succeed");
+    assert_eq!(file.data, large_data());
+}
+
+#[tokio::test]
+async fn fails_on_invalid_large_responses() {
+    let (server, client) = serve().await;
+    let mut body = serde_json::to_vec(&serde_json::json!({
+        "data": { "data": { "data": large_data() } }
+    }))
+    .unwrap();
+    body.truncate(body.len() - 10);
+    Mock::given(method("GET"))
+        .and(path(format!("/v1/secret/data/{OBJECT}")))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+        .mount(&server)
+        .await;
+
+    match client.read_file(OBJECT).await {
+        Err(VaultError::Client {
+            source: ClientError::JsonParseError { .. },
+            ..
+        }) => (),
+        res => panic!("unexpected result {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn encodes_in_chunks() {
+    let data = Bytes::from(large_data());
+    let Body::Encoding(_) = json::encode(data.clone()).unwrap() else {
+        panic!("large values should be encoded while they are sent");
+    };
+    let chunks: Vec<_> = json::encode(data.clone())
+        .unwrap()
+        .into_chunks()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_BYTES));
+    assert_eq!(chunks.concat(), serde_json::to_vec(&data).unwrap());
+
+    let Body::Whole(body) = json::encode(Bytes::from_static(b"ok")).unwrap() else {
+        panic!("small values should be encoded whole");
+    };
+    assert_eq!(body, b"[111,107]");
+}
+
+#[tokio::test]
+async fn decodes_in_chunks() {
+    let chunks = |body: Vec<u8>| -> json::Chunks<()> {
+        let chunks: Vec<_> = body
+            .chunks(1000)
+            .map(Bytes::copy_from_slice)
+            .map(Ok)
+            .collect();
+        Box::pin(futures::stream::iter(chunks))
+    };
+    let data = large_data();
+    let body = serde_json::to_vec(&data).unwrap();
+    match json::decode::<Vec<u8>, _>(chunks(body.clone())).await {
+        Ok(Received::Decoded(Ok(decoded))) => assert_eq!(decoded, data),
+        _ => panic!("large bodies should be decoded while they are received"),
+    }
+    match json::decode::<Vec<u8>, _>(chunks(body[..body.len() - 1].to_vec())).await {
+        Ok(Received::Decoded(Err(_))) => (),
+        _ => panic!("truncated bodies should fail to decode"),
+    }
+    match json::decode::<Vec<u8>, _>(chunks(b"[111,107]".to_vec())).await {
+        Ok(Received::Whole(body)) => assert_eq!(body, &b"[111,107]"[..]),
+        _ => panic!("small bodies should be read whole"),
+    }
+
+    // A failure to receive the body is reported rather than the decoding failing
+    let received: Vec<_> = body[..100_000]
+        .chunks(1000)
+        .map(Bytes::copy_from_slice)
+        .map(Ok)
+        .collect();
+    let failing: json::Chunks<&str> = Box::pin(
+        futures::stream::iter(received)
+            .chain(futures::stream::once(async { Err("connection reset") })),
+    );
+    assert!(matches!(
+        json::decode::<Vec<u8>, _>(failing).await,
+        Err("connection reset")
+    ));
+}
@@ -0,0 +1,172 @@
+//! Checks that connections to Vault verify its certificate unless told not to, and only use the
+//! TLS versions and cipher suites the link allows
+
+use std::sync::Arc;
+
+use blobstore_vault::{client::Client, config::Config, error::VaultError};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+/// Test CA, and a certificate and key it signed for `localhost` valid for a hundred years
+const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs");
+
+/// Starts a TLS server for `localhost` answering every request with a 404, using only the given
+/// TLS versions and cipher suites, and returns its address
+async fn serve(
+    versions: &[&'static rustls::SupportedProtocolVersion],
+    suites: &[rustls::SupportedCipherSuite],
+) -> String {
+    let read = |name: &str| std::fs::read(format!("{CERTS}/{name}")).unwrap();
+    let certs = rustls_pemfile::certs(&mut read("server.pem").as_slice())
+        .unwrap()
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut read("server.key").as_slice())
+        .unwrap()
+        .remove(0);
+    let config = rustls::ServerConfig::builder()
+        .with_cipher_suites(suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, rustls::PrivateKey(key))
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut buf = vec![0; 8192];
+                while matches!(stream.read(&mut buf).await, Ok(n) if n > 0) {
+                    let body = r#"{"errors":[]}"#;
+                    let response = format!(
+                        "HTTP/1.1 404 Not Found\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\n\r\n{body}",
+                        body.len()
+                    );
+                    if stream.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    format!("https://localhost:{port}")
+}
+
+/// Starts a TLS server allowing every version and cipher suite
+async fn serve_any() -> String {
+    serve(rustls::ALL_VERSIONS, rustls::ALL_CIPHER_SUITES).await
+}
+
+/// Returns a client of the server at `addr` with the given extra settings
+fn client(addr: &str, settings: &[(&str, &str)]) -> Client {
+    let mut values = vec![
+        ("addr".to_string(), addr.to_string()),
+        ("token".to_string(), "test-token".to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+        // So the HTTP client is built by the provider rather than vaultrs
+        ("tls_min_version".to_string(), "1.2".to_string()),
+    ];
+    values.extend(settings.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let config = Config::from_values(&values).expect("config should be valid");
+    Client::new(config).expect("client should be created")
+}
+
+/// Returns whether a request of the client reached the server, which answers it with a 404
+async fn connects(client: &Client) -> bool {
+    match client.read_file("object").await {
+        Err(VaultError::NotFound { .. }) => true,
+        Err(VaultError::Client { .. }) => false,
+        res => panic!("unexpected result {res:?}"),
+    }
+}
+
+fn ca() -> String {
+    format!("{CERTS}/ca.pem")
+}
+
+#[tokio::test]
+async fn verifies_server_certificate() {
+    let addr = serve_any().await;
+    assert!(!connects(&client(&addr, &[])).await);
+    assert!(connects(&client(&addr, &[("certs", &ca())])).await);
+}
+
+#[tokio::test]
+async fn skips_verification_when_asked() {
+    let addr = serve_any().await;
+    assert!(connects(&client(&addr, &[("tls_skip_verify", "true")])).await);
+}
+
+#[tokio::test]
+async fn requires_minimum_version() {
+    let addr = serve(&[&rustls::version::TLS12], rustls::ALL_CIPHER_SUITES).await;
+    assert!(connects(&client(&addr, &[("certs", &ca())])).await);
+    assert!(
+        !connects(&client(
+            &addr,
+            &[("certs", &ca()), ("tls_min_version", "1.3")]
+        ))
+        .await
+    );
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+#[tokio::test]
+async fn uses_only_configured_cipher_suites() {
+    let addr = serve(
+        rustls::ALL_VERSIONS,
+        &[rustls::cipher_suite::TLS13_AES_128_GCM_SHA256],
+    )
+    .await;
+    let with_suites = |suites| client(&addr, &[("certs", &ca()), ("tls_cipher_suites", suites)]);
+    assert!(!connects(&with_suites("TLS13_AES_256_GCM_SHA384")).await);
+    assert!(
+        connects(&with_suites(
+            "TLS13_AES_256_GCM_SHA384,TLS13_AES_128_GCM_SHA256"
+        ))
+        .await
+    );
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+#[test]
+fn rejects_unusable_cipher_suites() {
+    let config = |settings: &[(&str, &str)]| {
+        let mut values = vec![("token".to_string(), "test-token".to_string())];
+        values.extend(settings.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        Config::from_values(&values)
+    };
+    assert!(config(&[("tls_cipher_suites", "TLS_RSA_WITH_NULL_SHA")]).is_err());
+    // A TLS 1.2 suite can't be used by TLS 1.3 connections
+    assert!(config(&[
+        (
+            "tls_cipher_suites",
+            "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"
+        ),
+        ("tls_min_version", "1.3"),
+    ])
+    .is_err());
+    assert!(config(&[
+        ("tls_cipher_suites", "TLS13_AES_256_GCM_SHA384"),
+        ("tls_skip_verify", "true"),
+    ])
+    .is_err());
+    assert!(config(&[(
+        "tls_cipher_suites",
+        "tls13_aes_256_gcm_sha384, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"
+    )])
+    .is_ok());
+}
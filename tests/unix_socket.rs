@@ -0,0 +1,195 @@
+//! Checks that a Vault Agent listening on a Unix socket is sent requests over the socket,
+//! including the token lookups and mount listing of the link validation, with the headers
+//! configured for the link
+#![cfg(unix)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use blobstore_vault::{client::Client, config::Config};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+/// Requests received by the server, as their request line, token and `X-Tenant` header
+type Received = Arc<Mutex<Vec<(String, Option<String>, Option<String>)>>>;
+
+/// Starts a server answering like Vault Agent on a new socket, and returns the path of the
+/// socket with the requests it received
+fn serve(name: &str) -> (PathBuf, Received) {
+    let socket = std::env::temp_dir().join(format!(
+        "blobstore-vault-{}-{name}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    let received = Received::default();
+    let log = received.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(answer(stream, log.clone()));
+        }
+    });
+    (socket, received)
+}
+
+/// Answers the requests of a connection until it is closed
+async fn answer(stream: UnixStream, received: Received) {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let (mut length, mut token, mut tenant) = (0, None, None);
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            match name.to_lowercase().as_str() {
+                "content-length" => length = value.trim().parse().unwrap(),
+                "x-vault-token" => token = Some(value.trim().to_string()),
+                "x-tenant" => tenant = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.unwrap();
+        let request = request_line
+            .rsplit_once(' ')
+            .map_or("", |(request, _)| request)
+            .to_string();
+        let (status, body) = match request.as_str() {
+            "GET /v1/secret/data/docs/readme.md" => (
+                "200 OK",
+                serde_json::json!({ "data": {
+                    "data": { "data": [111, 107] },
+                    "metadata": {
+                        "created_time": "2024-03-01T12:00:00.000000Z",
+                        "custom_metadata": null,
+                        "deletion_time": "",
+                        "destroyed": false,
+                        "version": 1
+                    }
+                }}),
+            ),
+            "GET /v1/auth/token/lookup-self" => (
+                "200 OK",
+                serde_json::json!({ "data": { "expire_time": null, "ttl": 0 } }),
+            ),
+            "GET /v1/sys/mounts" => (
+                "200 OK",
+                serde_json::json!({ "data": {
+                    "secret/": { "type": "kv", "options": { "version": "2" } }
+                }}),
+            ),
+            "LIST /v1/secret/metadata/" => (
+                "200 OK",
+                serde_json::json!({ "data": { "keys": ["docs/"] } }),
+            ),
+            _ => ("404 Not Found", serde_json::json!({ "errors": [] })),
+        };
+        received.lock().unwrap().push((request, token, tenant));
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream
+            .get_mut()
+            .write_all(response.as_bytes())
+            .await
+            .unwrap();
+    }
+}
+
+/// Returns a client of the server listening on `socket`
+fn client(socket: &Path) -> Client {
+    let config = Config::from_values(&[
+        ("addr".to_string(), format!("unix://{}", socket.display())),
+        ("token".to_string(), "test-token".to_string()),
+        ("sealed_retry_secs".to_string(), "0".to_string()),
+        ("headers".to_string(), "X-Tenant:blue".to_string()),
+    ])
+    .expect("config should be valid");
+    Client::new(config).expect("client should be created")
+}
+
+#[tokio::test]
+async fn reads_secrets_over_socket() {
+    let (socket, received) = serve("read");
+    let client = client(&socket);
+
+    let file = client
+        .read_file("docs/readme.md")
+        .await
+        .expect("read should succeed");
+    assert_eq!(file.data, &b"ok"[..]);
+    assert!(!client.exists("docs/missing.md").await.unwrap());
+    let received = received.lock().unwrap();
+    assert!(received.contains(&(
+        "GET /v1/secret/data/docs/readme.md".to_string(),
+        Some("test-token".to_string()),
+        Some("blue".to_string())
+    )));
+    let _ = std::fs::remove_file(socket);
+}
+
+#[tokio::test]
+async fn validates_link_over_socket() {
+    let (socket, received) = serve("validate");
+    let client = client(&socket);
+
+    client.validate().await.expect("link should be valid");
+    assert_eq!(client.token_ttl().await.unwrap(), None);
+    let requests: Vec<_> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(request, _, _)| request.clone())
+        .collect();
+    let tenants: Vec<_> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, _, tenant)| tenant.clone())
+        .collect();
+    assert!(tenants
+        .iter()
+        .all(|tenant| tenant.as_deref() == Some("blue")));
+    for request in [
+        "GET /v1/auth/token/lookup-self",
+        "GET /v1/sys/mounts",
+        "LIST /v1/secret/metadata/",
+    ] {
+        assert!(
+            requests.contains(&request.to_string()),
+            "{request} not in {requests:?}"
+        );
+    }
+    let _ = std::fs::remove_file(socket);
+}
+
+#[test]
+fn parses_socket_addresses() {
+    let config = |settings: &[(&str, &str)]| {
+        let mut values = vec![
+            (
+                "addr".to_string(),
+                "unix:///run/vault/agent.sock".to_string(),
+            ),
+            ("token".to_string(), "test-token".to_string()),
+        ];
+        values.extend(settings.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        Config::from_values(&values)
+    };
+    let addrs = config(&[]).expect("config should be valid").addrs;
+    assert_eq!(addrs[0].path(), "/run/vault/agent.sock");
+    assert!(config(&[("vault_events", "true")]).is_err());
+}
@@ -0,0 +1,129 @@
+//! Checks that the chunks of multipart uploads are stored in Vault with a manifest, so an upload
+//! can be read back and resumed after a restart
+
+mod common;
+
+use blobstore_vault::{
+    error::VaultError,
+    upload::{self, UploadSession, UploadSessions},
+};
+use bytes::Bytes;
+use common::Vault;
+
+const STREAM: &str = "actor-0123456789abcdef";
+
+/// Stores the given chunks of an upload of `photos/cat.png` like the provider does as they
+/// arrive, and returns the session holding them
+async fn store(client: &blobstore_vault::client::Client, chunks: &[&[u8]]) -> UploadSession {
+    let mut session = UploadSession::new("actor", "photos", "cat.png");
+    session.content_type = Some("image/png".to_string());
+    for data in chunks {
+        let data = Bytes::copy_from_slice(data);
+        let (manifest, index) = session.manifest_with(&data);
+        client
+            .write_upload_chunk(STREAM, &manifest, index, data.clone())
+            .await
+            .expect("chunk should be stored");
+        session.append(&data);
+    }
+    session
+}
+
+#[tokio::test]
+async fn resumes_uploads_from_vault() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    let stored = store(&client, &[b"hello ", b"wor"]).await;
+
+    // A restarted provider only has what is in Vault
+    let resumed = client
+        .read_upload(STREAM)
+        .await
+        .expect("upload should resume");
+    assert_eq!(resumed.received(), 9);
+    assert_eq!(&resumed.buffer[..], b"hello wor");
+    assert_eq!(resumed.chunks, stored.chunks);
+    assert_eq!(resumed.actor_id, "actor");
+    assert_eq!(resumed.object_id, "cat.png");
+    assert_eq!(resumed.content_type.as_deref(), Some("image/png"));
+    let manifest = client.read_upload_manifest(STREAM).await.unwrap();
+    assert_eq!(manifest.received, 9);
+    assert_eq!(manifest.chunks[1].offset, 6);
+
+    // A chunk sent again is recognized by its offset and checksum
+    assert!(resumed.has_chunk(6, b"wor"));
+    assert!(!resumed.has_chunk(6, b"war"));
+    assert!(!resumed.has_chunk(9, b"ld"));
+}
+
+#[tokio::test]
+async fn detects_corrupted_chunks() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    store(&client, &[b"hello ", b"wor"]).await;
+    vault.put(
+        &upload::chunk_path(STREAM, 1),
+        serde_json::json!({ "data": b"war" }),
+    );
+
+    match client.read_upload(STREAM).await {
+        Err(VaultError::Corrupted { path }) => assert_eq!(path, upload::chunk_path(STREAM, 1)),
+        res => panic!("unexpected result {:?}", res.map(|s| s.received())),
+    }
+}
+
+#[tokio::test]
+async fn purges_uploads() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    let session = store(&client, &[b"hello ", b"wor"]).await;
+    assert_eq!(vault.keys().len(), 3);
+
+    client
+        .purge_upload(STREAM, session.chunks.len())
+        .await
+        .expect("upload should be purged");
+    assert!(vault.keys().is_empty());
+    assert!(matches!(
+        client.read_upload(STREAM).await,
+        Err(VaultError::NotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn hides_uploads_from_actors() {
+    let vault = Vault::start("secret").await;
+    let client = vault.client(&[]);
+    store(&client, &[b"hello "]).await;
+    client
+        .write_file("photos/dog.png", b"woof".to_vec())
+        .await
+        .unwrap();
+
+    assert_eq!(client.list_containers().await.unwrap(), vec!["photos"]);
+    assert!(matches!(
+        client
+            .write_file(upload::chunk_path(STREAM, 0), b"evil".to_vec())
+            .await,
+        Err(VaultError::Denied { .. })
+    ));
+}
+
+#[test]
+fn stream_ids_differ_across_restarts() {
+    let start = || UploadSessions::default().start(UploadSession::new("actor", "photos", "a"));
+    let (first, second) = (start(), start());
+    assert!(first.starts_with("actor-"));
+    assert_ne!(first, second);
+
+    // A session read back from Vault doesn't replace one that was resumed meanwhile
+    let sessions = UploadSessions::default();
+    let mut resumed = UploadSession::new("actor", "photos", "a");
+    resumed.append(&Bytes::from_static(b"abc"));
+    sessions.resume(STREAM, resumed);
+    sessions.resume(STREAM, UploadSession::new("actor", "photos", "a"));
+    assert_eq!(
+        sessions.update(STREAM, |session| session.received()),
+        Some(3)
+    );
+}